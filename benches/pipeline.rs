@@ -0,0 +1,68 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use htop::{app::{filter_processes, merge_processes, sort_processes, NameDisplay, ProcInfo, ProcSample, SortBy}, columns::Column, theme::Theme, ui::{process_rows, NameLayout, TreeSum}};
+use sysinfo::Pid;
+
+const PROCESSES: usize = 5000;
+
+fn synthetic_names() -> Vec<String> {
+    (0..PROCESSES).map(|i| format!("worker-{}-{}", i % 37, i)).collect()
+}
+
+// Each "refresh" moves the CPU values a little, like a live system would
+fn samples(names: &[String], tick: usize) -> impl Iterator<Item = ProcSample<'_>> {
+    names.iter().enumerate().map(move |(i, name)| ProcSample {
+        pid: Pid::from(i + 1),
+        name,
+        cpu: ((i * 7 + tick) % 1000) as f32 / 10.0,
+        mem: (i as u64 * 4096) + (tick as u64 % 3) * 4096,
+        parent: Some(Pid::from(i / 8)),
+        kernel_thread: false,
+        user: Some("postgres"),
+        state: 'S',
+        start_time: 0,
+        fds: None,
+        conns: None,
+        net: None,
+        swap: None,
+        voluntary_switches: None,
+        involuntary_switches: None,
+        minor_faults: None,
+        major_faults: None,
+        processor: None,
+        scheduling: None,
+        nice: None,
+        container: None,
+        exe: None,
+        cmd: &[],
+    })
+}
+
+fn pipeline(c: &mut Criterion) {
+    let names = synthetic_names();
+    let mut procs: Vec<ProcInfo> = Vec::new();
+    let mut order = Vec::new();
+    merge_processes(&mut procs, samples(&names, 0));
+
+    let mut tick = 0;
+    c.bench_function("refresh_5000", |b| b.iter(|| {
+        tick += 1;
+        merge_processes(&mut procs, samples(&names, tick));
+        filter_processes(&procs, "", false, &mut order);
+        sort_processes(SortBy::Cpu, true, &procs, &mut order);
+        black_box(process_rows(&procs, &order, &[], &[], &Column::ALL, NameLayout { display: NameDisplay::Basename, width: Column::Name.width(), sum: TreeSum::Off, realtime: true }, &Theme::default()).len())
+    }));
+
+    c.bench_function("filter_sort_5000", |b| b.iter(|| {
+        filter_processes(&procs, black_box("worker-3"), false, &mut order);
+        sort_processes(SortBy::Name, false, &procs, &mut order);
+        black_box(order.len())
+    }));
+
+    filter_processes(&procs, "", false, &mut order);
+    c.bench_function("rows_5000", |b| b.iter(|| black_box(process_rows(&procs, &order, &[], &[], &Column::ALL, NameLayout { display: NameDisplay::Basename, width: Column::Name.width(), sum: TreeSum::Off, realtime: true }, &Theme::default()).len())));
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+use std::{fs, process::Command};
+
+// Embed the git commit so `--version` identifies the exact build in bug reports
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env=RUST_HTOP_GIT_HASH={}", hash);
+
+    // Rebuild when HEAD moves, either to another branch or to a new commit on this one
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Ok(head) = fs::read_to_string(".git/HEAD")
+        && let Some(reference) = head.trim().strip_prefix("ref: ")
+    {
+        println!("cargo:rerun-if-changed=.git/{}", reference);
+    }
+    println!("cargo:rerun-if-changed=.git/packed-refs");
+}
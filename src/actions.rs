@@ -0,0 +1,152 @@
+// What came of changing a process: shown as a toast that goes away by itself, and kept in the
+// action log ('R') to look back on. Every process action runs into the same few problems, so
+// they are told apart here rather than by each caller.
+
+use std::{collections::VecDeque, fmt, io, time::{Duration, Instant}};
+use chrono::{DateTime, Local};
+use sysinfo::Pid;
+
+// How long a toast stays up, rounded up to the tick rate
+pub const TOAST_TIME: Duration = Duration::from_secs(4);
+
+// Toasts shown at once; a fourth pushes out the oldest
+pub const MAX_TOASTS: usize = 3;
+
+// Results kept for the action log
+pub const LOG_LEN: usize = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ActionError {
+    // It exited after it was selected
+    Gone,
+    Denied,
+    // What was typed, or a value the kernel refused
+    Invalid(String),
+    // A remote agent or a replay has no processes to change
+    Unsupported,
+    Failed(String),
+}
+
+impl ActionError {
+    pub fn from_io(error: &io::Error) -> Self {
+        match error.raw_os_error() {
+            // ESRCH
+            Some(3) => ActionError::Gone,
+            // EINVAL
+            Some(22) => ActionError::Invalid("the kernel refused the value".into()),
+            _ if error.kind() == io::ErrorKind::PermissionDenied => ActionError::Denied,
+            _ => ActionError::Failed(error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActionError::Gone => write!(f, "the process has exited"),
+            ActionError::Denied => write!(f, "permission denied; changing another user's process needs root"),
+            ActionError::Invalid(why) | ActionError::Failed(why) => write!(f, "{}", why),
+            ActionError::Unsupported => write!(f, "only processes on this machine can be changed"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ActionResult {
+    pub time: DateTime<Local>,
+    pub pid: Pid,
+    pub name: String,
+    // What was changed, as in "CPU affinity"
+    pub what: &'static str,
+    // The value it has now
+    pub outcome: Result<String, ActionError>,
+}
+
+impl ActionResult {
+    pub fn text(&self) -> String {
+        match &self.outcome {
+            Ok(value) => format!("{} ({}) now has {} {}", self.name, self.pid, self.what, value),
+            Err(ActionError::Gone) => format!("{} ({}) no longer exists", self.name, self.pid),
+            Err(e) => format!("Could not set the {} of {} ({}): {}", self.what, self.name, self.pid, e),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ActionLog {
+    // Oldest first
+    results: VecDeque<ActionResult>,
+    // When each of the newest results went up as a toast, oldest first
+    toasts: VecDeque<Instant>,
+}
+
+impl ActionLog {
+    pub fn push(&mut self, result: ActionResult, now: Instant) {
+        log::info!("{}", result.text());
+        self.results.push_back(result);
+        if self.results.len() > LOG_LEN {
+            self.results.pop_front();
+        }
+        self.toasts.push_back(now);
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+    }
+
+    pub fn results(&self) -> &VecDeque<ActionResult> {
+        &self.results
+    }
+
+    // The results still up as toasts, oldest first
+    pub fn toasts(&self) -> impl Iterator<Item = &ActionResult> {
+        self.results.iter().skip(self.results.len() - self.toasts.len())
+    }
+
+    // Takes down the toasts that have been up for TOAST_TIME; true if any went
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let before = self.toasts.len();
+        self.toasts.retain(|&shown| now < shown + TOAST_TIME);
+        self.toasts.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(pid: usize, outcome: Result<String, ActionError>) -> ActionResult {
+        ActionResult { time: Local::now(), pid: Pid::from(pid), name: "postgres".into(), what: "CPU affinity", outcome }
+    }
+
+    #[test]
+    fn failures_are_told_apart() {
+        assert_eq!(ActionError::from_io(&io::Error::from_raw_os_error(3)), ActionError::Gone);
+        assert_eq!(ActionError::from_io(&io::Error::from_raw_os_error(1)), ActionError::Denied);
+        assert!(matches!(ActionError::from_io(&io::Error::from_raw_os_error(22)), ActionError::Invalid(_)));
+        assert_eq!(result(42, Err(ActionError::Gone)).text(), "postgres (42) no longer exists");
+        assert_eq!(result(42, Err(ActionError::Denied)).text(), "Could not set the CPU affinity of postgres (42): permission denied; changing another user's process needs root");
+        assert_eq!(result(42, Ok("0-3".into())).text(), "postgres (42) now has CPU affinity 0-3");
+    }
+
+    #[test]
+    fn toasts_stack_and_expire_while_the_log_keeps_everything() {
+        let mut log = ActionLog::default();
+        let start = Instant::now();
+        for pid in 0..LOG_LEN + 5 {
+            log.push(result(pid, Ok("0".into())), start + Duration::from_secs(pid as u64));
+        }
+        assert_eq!(log.results().len(), LOG_LEN);
+        assert_eq!(log.results()[0].pid, Pid::from(5));
+        let shown: Vec<Pid> = log.toasts().map(|r| r.pid).collect();
+        assert_eq!(shown, [Pid::from(LOG_LEN + 2), Pid::from(LOG_LEN + 3), Pid::from(LOG_LEN + 4)]);
+
+        // The oldest of the three went up at LOG_LEN + 2 seconds
+        let last = start + Duration::from_secs(LOG_LEN as u64 + 2);
+        assert!(!log.expire(last + TOAST_TIME - Duration::from_millis(1)));
+        assert!(log.expire(last + TOAST_TIME));
+        assert_eq!(log.toasts().count(), 2);
+        assert!(log.expire(last + TOAST_TIME * 10));
+        assert_eq!(log.toasts().count(), 0);
+        assert_eq!(log.results().len(), LOG_LEN);
+    }
+}
@@ -0,0 +1,712 @@
+use std::{collections::HashMap, fmt, time::{Duration, Instant}};
+use serde::{Deserialize, Serialize};
+use sysinfo::Pid;
+
+use crate::{app::{format_mem, ProcInfo}, system::SystemTotals};
+
+// A rule has to clear by this fraction of its threshold before it can fire again, so a value
+// hovering around the line doesn't fire on every wobble
+const HYSTERESIS: f64 = 0.05;
+
+// However short a rule's cooldown, its `on_fire` command starts at most this often, so a
+// flapping condition can't fill the machine with processes
+pub const COMMAND_INTERVAL: Duration = Duration::from_secs(60);
+
+// One [[alert]] table. The condition is either a `when` rule or spelled out in `metric`,
+// `match`, `threshold` and `for_seconds`; empty fields are left out when saving.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    // Shown as the notification title; the rule itself when empty
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub when: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub metric: String,
+    // Process metrics only look at processes matching this, like the search box
+    #[serde(rename = "match", skip_serializing_if = "String::is_empty")]
+    pub pattern: String,
+    // Percent, except process_mem in MB and load as is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub for_seconds: u64,
+    // The least time between two notifications of this rule
+    pub cooldown: String,
+    // "notify", "banner" or "bell"
+    pub action: String,
+    // A shell command to start when the rule fires; needs `allow_exec = true`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub on_fire: String,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            when: String::new(),
+            metric: String::new(),
+            pattern: String::new(),
+            threshold: None,
+            for_seconds: 0,
+            cooldown: "5m".into(),
+            action: "notify".into(),
+            on_fire: String::new(),
+        }
+    }
+}
+
+// Where a fired rule goes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sink {
+    // A desktop notification, or a banner when there is no notification daemon
+    Notify,
+    // A highlighted banner above the table
+    Banner,
+    // The terminal bell and a short flash of the table header; `quiet` keeps only the flash
+    Bell,
+}
+
+impl Sink {
+    pub const ALL: [Sink; 3] = [Sink::Notify, Sink::Banner, Sink::Bell];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sink::Notify => "notify",
+            Sink::Banner => "banner",
+            Sink::Bell => "bell",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Sink> {
+        Sink::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Metric {
+    // Per process, in percent of one core and in bytes
+    ProcessCpu,
+    ProcessMem,
+    // CPU averaged over the cores, memory and swap in percent used, 1-minute load
+    Cpu,
+    Memory,
+    Swap,
+    Load,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 6] = [Metric::ProcessCpu, Metric::ProcessMem, Metric::Cpu, Metric::Memory, Metric::Swap, Metric::Load];
+
+    // The `metric` field of an [[alert]] table
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::ProcessCpu => "process_cpu",
+            Metric::ProcessMem => "process_mem",
+            Metric::Cpu => "system_cpu",
+            Metric::Memory => "system_mem",
+            Metric::Swap => "system_swap",
+            Metric::Load => "load",
+        }
+    }
+
+    fn is_process(self) -> bool {
+        matches!(self, Metric::ProcessCpu | Metric::ProcessMem)
+    }
+}
+
+// What a rule watches for
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    pub metric: Metric,
+    // Process rules only look at processes matching this, like the search box; lowercase
+    pub query: String,
+    // Fires above the threshold, or below it when false
+    pub above: bool,
+    pub threshold: f64,
+    // How long the condition has to hold
+    pub duration: Duration,
+}
+
+// Written back in the `when` syntax, e.g. "process:postgres cpu > 80% for 30s"
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.metric.is_process(), self.query.as_str()) {
+            (true, "") => write!(f, "process ")?,
+            (true, query) => write!(f, "process:{} ", query)?,
+            (false, _) => {},
+        }
+        let metric = match self.metric {
+            Metric::ProcessCpu | Metric::Cpu => "cpu",
+            Metric::ProcessMem => "mem",
+            Metric::Memory => "memory",
+            Metric::Swap => "swap",
+            Metric::Load => "load",
+        };
+        write!(f, "{} {} ", metric, if self.above { ">" } else { "<" })?;
+        match self.metric {
+            Metric::ProcessMem => write!(f, "{}MB", self.threshold / (1024.0 * 1024.0))?,
+            Metric::Load => write!(f, "{}", self.threshold)?,
+            _ => write!(f, "{}%", self.threshold)?,
+        }
+        let secs = self.duration.as_secs();
+        match secs {
+            0 => Ok(()),
+            _ if secs.is_multiple_of(3600) => write!(f, " for {}h", secs / 3600),
+            _ if secs.is_multiple_of(60) => write!(f, " for {}m", secs / 60),
+            _ => write!(f, " for {}s", secs),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub condition: Condition,
+    pub cooldown: Duration,
+    pub sink: Sink,
+    pub on_fire: Option<String>,
+}
+
+// Rules read like "process cpu > 90% for 30s", "process:postgres mem > 4GB", "memory > 95%"
+// or "load > 8 for 5m": an optional `process` or `process:QUERY`, a metric, > or <, a value
+// and an optional `for DURATION`. Without `process` the metric is one of the system totals.
+pub fn parse_rule(text: &str) -> Result<Condition, String> {
+    let mut words = text.split_whitespace().peekable();
+    let query = match words.peek().copied() {
+        Some("process") => {
+            words.next();
+            Some(String::new())
+        },
+        Some(word) if word.starts_with("process:") => {
+            words.next();
+            Some(word["process:".len()..].to_lowercase())
+        },
+        _ => None,
+    };
+    let metric_name = words.next().ok_or("empty rule")?;
+    let metric = match (query.is_some(), metric_name) {
+        (true, "cpu") => Metric::ProcessCpu,
+        (true, "mem" | "memory") => Metric::ProcessMem,
+        (true, other) => return Err(format!("unknown process metric `{}` (expected cpu or mem)", other)),
+        (false, "cpu") => Metric::Cpu,
+        (false, "mem" | "memory") => Metric::Memory,
+        (false, "swap") => Metric::Swap,
+        (false, "load") => Metric::Load,
+        (false, other) => return Err(format!("unknown metric `{}` (expected cpu, memory, swap or load, or process cpu / process mem)", other)),
+    };
+    let above = match words.next() {
+        Some(">") => true,
+        Some("<") => false,
+        Some(other) => return Err(format!("expected > or < after `{}`, found `{}`", metric_name, other)),
+        None => return Err(format!("expected > or < after `{}`", metric_name)),
+    };
+    let value = words.next().ok_or("missing the threshold value")?;
+    let threshold = parse_value(metric, value)?;
+    let duration = match words.next() {
+        None => Duration::ZERO,
+        Some("for") => parse_duration(words.next().ok_or("missing the duration after `for`")?)?,
+        Some(other) => return Err(format!("unexpected `{}`", other)),
+    };
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected `{}`", extra));
+    }
+    Ok(Condition { metric, query: query.unwrap_or_default(), above, threshold, duration })
+}
+
+// The spelled-out form: `metric`, an optional `match`, `threshold` and `for_seconds`. These
+// rules always fire above the threshold.
+fn structured_rule(config: &AlertConfig) -> Result<Condition, String> {
+    let metric = Metric::ALL.into_iter().find(|m| m.name() == config.metric).ok_or_else(|| {
+        let known: Vec<&str> = Metric::ALL.iter().map(|m| m.name()).collect();
+        format!("unknown metric `{}` (expected one of {})", config.metric, known.join(", "))
+    })?;
+    if !config.pattern.is_empty() && !metric.is_process() {
+        return Err(format!("`match` only applies to process_cpu and process_mem, not {}", metric.name()));
+    }
+    let threshold = match config.threshold {
+        Some(n) if n >= 0.0 && n.is_finite() => n,
+        Some(n) => return Err(format!("`threshold = {}` is not a valid threshold", n)),
+        None => return Err("missing `threshold`".into()),
+    };
+    Ok(Condition {
+        metric,
+        query: config.pattern.to_lowercase(),
+        above: true,
+        threshold: if metric == Metric::ProcessMem { threshold * 1024.0 * 1024.0 } else { threshold },
+        duration: Duration::from_secs(config.for_seconds),
+    })
+}
+
+// A table sets either `when` or `metric`
+fn condition(config: &AlertConfig) -> Result<Condition, String> {
+    match (config.when.is_empty(), config.metric.is_empty()) {
+        (false, true) => parse_rule(&config.when),
+        (true, false) => structured_rule(config),
+        (false, false) => Err("set either `when` or `metric`, not both".into()),
+        (true, true) => Err("missing `when` or `metric`".into()),
+    }
+}
+
+fn parse_value(metric: Metric, text: &str) -> Result<f64, String> {
+    let lower = text.to_ascii_lowercase();
+    let (number, scale) = match metric {
+        Metric::ProcessMem => {
+            let split = lower.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(lower.len());
+            let scale = match &lower[split..] {
+                "" | "b" => 1.0,
+                "k" | "kb" => 1024.0,
+                "m" | "mb" => 1024.0 * 1024.0,
+                "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+                unit => return Err(format!("unknown unit `{}` in `{}` (expected KB, MB or GB)", unit, text)),
+            };
+            (&lower[..split], scale)
+        },
+        Metric::Load => (lower.as_str(), 1.0),
+        _ => (lower.strip_suffix('%').unwrap_or(&lower), 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(n * scale),
+        _ => Err(format!("`{}` is not a valid threshold", text)),
+    }
+}
+
+// "30s", "5m", "1h" or a number of seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, scale) = match text.char_indices().last() {
+        Some((i, 's')) => (&text[..i], 1.0),
+        Some((i, 'm')) => (&text[..i], 60.0),
+        Some((i, 'h')) => (&text[..i], 3600.0),
+        _ => (text, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(Duration::from_secs_f64(n * scale)),
+        _ => Err(format!("`{}` is not a duration like 30s, 5m or 1h", text)),
+    }
+}
+
+// A fired rule, ready to be shown
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub title: String,
+    pub message: String,
+    pub sink: Sink,
+    // The rule's `on_fire` command, unless it already ran within COMMAND_INTERVAL
+    pub command: Option<String>,
+}
+
+// Where a rule stands: how many subjects have fired and still hold, how long the longest
+// waiting one has held, and how long ago the rule last fired
+pub struct RuleStatus<'a> {
+    pub rule: &'a Rule,
+    pub firing: usize,
+    pub pending: Option<Duration>,
+    pub last_fired: Option<Duration>,
+}
+
+// How long a condition has held for one subject, and whether it already fired
+struct Track {
+    since: Instant,
+    fired: bool,
+}
+
+// A process by PID and start time, or None for the system totals
+type Subject = Option<(Pid, u64)>;
+
+// A subject's current value, with the process it belongs to
+type Reading<'a> = (Subject, Option<&'a ProcInfo>, f64);
+
+struct RuleState {
+    rule: Rule,
+    tracks: HashMap<Subject, Track>,
+    last_fired: Option<Instant>,
+    last_command: Option<Instant>,
+}
+
+// Checks every rule against each refresh. A subject fires once when its condition has held
+// for the rule's duration and only fires again after it clears past the hysteresis margin,
+// so one runaway process is one notification. Subjects that cross together are reported in
+// one alert, and a rule never fires twice within its cooldown.
+pub struct Alerts {
+    rules: Vec<RuleState>,
+    pub enabled: bool,
+}
+
+impl Alerts {
+    // Rules that don't parse are returned as warnings, one per rule, and skipped
+    pub fn from_config(configs: &[AlertConfig]) -> (Alerts, Vec<String>) {
+        let mut rules = Vec::new();
+        let mut warnings = Vec::new();
+        for (i, config) in configs.iter().enumerate() {
+            let parsed = condition(config).and_then(|condition| {
+                let cooldown = parse_duration(&config.cooldown)?;
+                let sink = Sink::from_name(&config.action).ok_or_else(|| {
+                    let known: Vec<&str> = Sink::ALL.iter().map(|a| a.name()).collect();
+                    format!("unknown action `{}` (expected one of {})", config.action, known.join(", "))
+                })?;
+                Ok((condition, cooldown, sink))
+            });
+            match parsed {
+                Ok((condition, cooldown, sink)) => rules.push(RuleState {
+                    rule: Rule { name: [&config.name, &config.when].into_iter().find(|s| !s.is_empty()).cloned().unwrap_or_else(|| condition.to_string()), condition, cooldown, sink, on_fire: Some(config.on_fire.clone()).filter(|c| !c.trim().is_empty()) },
+                    tracks: HashMap::new(),
+                    last_fired: None,
+                    last_command: None,
+                }),
+                Err(e) => {
+                    let label = [&config.name, &config.when].into_iter().find(|s| !s.is_empty()).cloned().unwrap_or_else(|| format!("#{}", i + 1));
+                    warnings.push(format!("alert `{}` ignored: {}", label, e));
+                },
+            }
+        }
+        (Alerts { rules, enabled: true }, warnings)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().map(|state| &state.rule)
+    }
+
+    // Per rule, for the alerts popup
+    pub fn status(&self, now: Instant) -> Vec<RuleStatus<'_>> {
+        self.rules.iter().map(|state| RuleStatus {
+            rule: &state.rule,
+            firing: state.tracks.values().filter(|t| t.fired).count(),
+            pending: state.tracks.values().filter(|t| !t.fired).map(|t| now.duration_since(t.since)).max(),
+            last_fired: state.last_fired.map(|t| now.duration_since(t)),
+        }).collect()
+    }
+
+    pub fn evaluate(&mut self, procs: &[ProcInfo], totals: &SystemTotals, now: Instant) -> Vec<Alert> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        self.rules.iter_mut().filter_map(|state| state.evaluate(procs, totals, now)).collect()
+    }
+}
+
+impl RuleState {
+    fn evaluate(&mut self, procs: &[ProcInfo], totals: &SystemTotals, now: Instant) -> Option<Alert> {
+        let rule = &self.rule.condition;
+        let subjects: Vec<Reading> = match rule.metric {
+            metric if metric.is_process() => procs.iter()
+                .filter(|p| p.matches(&rule.query))
+                .map(|p| (Some((p.pid, p.start_time)), Some(p), if rule.metric == Metric::ProcessCpu { p.cpu as f64 } else { p.mem as f64 }))
+                .collect(),
+            metric => vec![(None, None, system_value(metric, totals))],
+        };
+
+        let margin = rule.threshold * HYSTERESIS;
+        let mut due = Vec::new();
+        let mut seen = Vec::with_capacity(subjects.len());
+        for (key, process, value) in subjects {
+            seen.push(key);
+            let (met, cleared) = if rule.above {
+                (value > rule.threshold, value < rule.threshold - margin)
+            } else {
+                (value < rule.threshold, value > rule.threshold + margin)
+            };
+            if met {
+                let track = self.tracks.entry(key).or_insert(Track { since: now, fired: false });
+                if !track.fired && now.duration_since(track.since) >= rule.duration {
+                    due.push((key, process, value));
+                }
+            } else if cleared || self.tracks.get(&key).is_some_and(|t| !t.fired) {
+                self.tracks.remove(&key);
+            }
+        }
+        // Exited processes start over if their PID comes back
+        self.tracks.retain(|key, _| seen.contains(key));
+
+        if due.is_empty() || self.last_fired.is_some_and(|t| now.duration_since(t) < self.rule.cooldown) {
+            return None;
+        }
+        for (key, _, _) in &due {
+            if let Some(track) = self.tracks.get_mut(key) {
+                track.fired = true;
+            }
+        }
+        self.last_fired = Some(now);
+        let command = self.rule.on_fire.clone().filter(|_| self.last_command.is_none_or(|t| now.duration_since(t) >= COMMAND_INTERVAL));
+        if command.is_some() {
+            self.last_command = Some(now);
+        }
+        Some(Alert { title: self.rule.name.clone(), message: describe(rule, &due), sink: self.rule.sink, command })
+    }
+}
+
+fn system_value(metric: Metric, totals: &SystemTotals) -> f64 {
+    let percent = |used: u64, total: u64| if total == 0 { 0.0 } else { used as f64 * 100.0 / total as f64 };
+    match metric {
+        Metric::Cpu if totals.cpus.is_empty() => 0.0,
+        Metric::Cpu => totals.cpus.iter().map(|&c| c as f64).sum::<f64>() / totals.cpus.len() as f64,
+        Metric::Memory => percent(totals.mem_used, totals.mem_total),
+        Metric::Swap => percent(totals.swap_used, totals.swap_total),
+        Metric::Load => totals.load[0],
+        Metric::ProcessCpu | Metric::ProcessMem => 0.0,
+    }
+}
+
+// "firefox (PID 4242) at 97.0% CPU for 30s, and 2 more" or "Memory at 96.1%"
+fn describe(rule: &Condition, due: &[Reading]) -> String {
+    let value = |v: f64| match rule.metric {
+        Metric::ProcessCpu => format!("{:.1}% CPU", v),
+        Metric::ProcessMem => format!("{} memory", format_mem(v as u64)),
+        Metric::Cpu => format!("CPU at {:.1}%", v),
+        Metric::Memory => format!("Memory at {:.1}%", v),
+        Metric::Swap => format!("Swap at {:.1}%", v),
+        Metric::Load => format!("Load at {:.2}", v),
+    };
+    let (_, process, first) = due[0];
+    let mut text = match process {
+        Some(p) => format!("{} (PID {}) at {}", p.name, p.pid, value(first)),
+        None => value(first),
+    };
+    if !rule.duration.is_zero() {
+        text.push_str(&format!(" for {}s", rule.duration.as_secs()));
+    }
+    if due.len() > 1 {
+        text.push_str(&format!(", and {} more", due.len() - 1));
+    }
+    text
+}
+
+// Sends a desktop notification; fails without a notification daemon
+#[cfg(feature = "notify")]
+pub fn notify(alert: &Alert) -> Result<(), String> {
+    notify_rust::Notification::new()
+        .appname("rust-htop")
+        .summary(&alert.title)
+        .body(&alert.message)
+        .show()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "notify"))]
+pub fn notify(_alert: &Alert) -> Result<(), String> {
+    Err("built without desktop notifications".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{sample, ProcSample};
+
+    const SEC: Duration = Duration::from_secs(1);
+
+    fn rules(list: &[(&str, &str)]) -> Alerts {
+        let configs: Vec<AlertConfig> = list.iter().map(|&(when, cooldown)| AlertConfig { when: when.into(), cooldown: cooldown.into(), ..AlertConfig::default() }).collect();
+        let (alerts, warnings) = Alerts::from_config(&configs);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        alerts
+    }
+
+    fn procs(data: &[(usize, &str, f32)]) -> Vec<ProcInfo> {
+        data.iter().map(|&(pid, name, cpu)| ProcInfo::new(&ProcSample { cpu, state: 'R', start_time: 100, ..sample(pid, name) })).collect()
+    }
+
+    fn memory(used_percent: u64) -> SystemTotals {
+        SystemTotals { mem_total: 100, mem_used: used_percent, ..SystemTotals::default() }
+    }
+
+    #[test]
+    fn rules_parse() {
+        let condition = |metric, query: &str, above, threshold, duration| Condition { metric, query: query.into(), above, threshold, duration };
+        assert_eq!(parse_rule("process cpu > 90% for 30s"), Ok(condition(Metric::ProcessCpu, "", true, 90.0, 30 * SEC)));
+        assert_eq!(parse_rule("process:Postgres mem > 4GB"), Ok(condition(Metric::ProcessMem, "postgres", true, 4.0 * (1u64 << 30) as f64, Duration::ZERO)));
+        assert_eq!(parse_rule("process mem > 512mb for 1m").map(|c| c.threshold), Ok(512.0 * (1 << 20) as f64));
+        assert_eq!(parse_rule("memory > 95"), Ok(condition(Metric::Memory, "", true, 95.0, Duration::ZERO)));
+        assert_eq!(parse_rule("load > 8.5 for 5m"), Ok(condition(Metric::Load, "", true, 8.5, 300 * SEC)));
+        assert_eq!(parse_rule("swap < 10% for 1h").map(|c| (c.above, c.duration)), Ok((false, 3600 * SEC)));
+        assert_eq!(parse_duration("90"), Ok(90 * SEC));
+    }
+
+    #[test]
+    fn bad_rules_say_why() {
+        for (text, error) in [
+            ("", "empty rule"),
+            ("disk > 90", "unknown metric `disk`"),
+            ("process load > 2", "unknown process metric `load`"),
+            ("cpu = 90", "expected > or <"),
+            ("cpu >", "missing the threshold"),
+            ("cpu > lots", "not a valid threshold"),
+            ("process mem > 4TB", "unknown unit `tb`"),
+            ("cpu > 90 during 30s", "unexpected `during`"),
+            ("cpu > 90 for soon", "not a duration"),
+            ("cpu > 90 for 30s please", "unexpected `please`"),
+        ] {
+            let err = parse_rule(text).unwrap_err();
+            assert!(err.contains(error), "{:?}: {}", text, err);
+        }
+        let (alerts, warnings) = Alerts::from_config(&[AlertConfig { when: "cpu > 90".into(), cooldown: "a while".into(), ..AlertConfig::default() }]);
+        assert!(alerts.is_empty());
+        assert!(warnings[0].starts_with("alert `cpu > 90` ignored: "), "{:?}", warnings);
+        let (alerts, warnings) = Alerts::from_config(&[AlertConfig { when: "cpu > 90".into(), action: "email".into(), ..AlertConfig::default() }]);
+        assert!(alerts.is_empty());
+        assert!(warnings[0].ends_with("unknown action `email` (expected one of notify, banner, bell)"), "{:?}", warnings);
+    }
+
+    fn structured(metric: &str, pattern: &str, threshold: Option<f64>, for_seconds: u64) -> AlertConfig {
+        AlertConfig { metric: metric.into(), pattern: pattern.into(), threshold, for_seconds, ..AlertConfig::default() }
+    }
+
+    #[test]
+    fn structured_rules_parse_and_print_as_when_rules() {
+        let parsed = condition(&structured("process_cpu", "Postgres", Some(80.0), 30)).unwrap();
+        assert_eq!(parsed, Condition { metric: Metric::ProcessCpu, query: "postgres".into(), above: true, threshold: 80.0, duration: 30 * SEC });
+        assert_eq!(parsed.to_string(), "process:postgres cpu > 80% for 30s");
+        assert_eq!(condition(&structured("process_mem", "", Some(512.0), 0)).unwrap().threshold, 512.0 * (1 << 20) as f64);
+        for text in ["process mem > 512MB", "memory > 95%", "load < 0.5 for 10m", "swap > 20% for 1h", "process:java cpu > 150% for 45s"] {
+            let parsed = parse_rule(text).unwrap();
+            assert_eq!(parsed.to_string(), text);
+            assert_eq!(parse_rule(&parsed.to_string()), Ok(parsed));
+        }
+        let (alerts, _) = Alerts::from_config(&[structured("system_mem", "", Some(95.0), 0)]);
+        assert_eq!(alerts.rules().next().unwrap().name, "memory > 95%");
+    }
+
+    #[test]
+    fn each_bad_rule_gets_its_own_warning() {
+        let both = AlertConfig { when: "cpu > 90".into(), ..structured("system_cpu", "", Some(90.0), 0) };
+        let named = AlertConfig { name: "JVM".into(), ..structured("process_mem", "java", None, 0) };
+        let configs = [
+            structured("system_cpu", "", Some(90.0), 0),
+            structured("disk", "", Some(90.0), 0),
+            structured("system_mem", "postgres", Some(90.0), 0),
+            named,
+            structured("load", "", Some(-1.0), 0),
+            both,
+            AlertConfig::default(),
+        ];
+        let (alerts, warnings) = Alerts::from_config(&configs);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(warnings, vec![
+            "alert `#2` ignored: unknown metric `disk` (expected one of process_cpu, process_mem, system_cpu, system_mem, system_swap, load)",
+            "alert `#3` ignored: `match` only applies to process_cpu and process_mem, not system_mem",
+            "alert `JVM` ignored: missing `threshold`",
+            "alert `#5` ignored: `threshold = -1` is not a valid threshold",
+            "alert `cpu > 90` ignored: set either `when` or `metric`, not both",
+            "alert `#7` ignored: missing `when` or `metric`",
+        ]);
+    }
+
+    #[test]
+    fn status_follows_a_sequence_of_snapshots() {
+        let config = AlertConfig { cooldown: "0s".into(), ..structured("process_cpu", "postgres", Some(80.0), 30) };
+        let (mut alerts, _) = Alerts::from_config(&[config]);
+        let start = Instant::now();
+        let totals = SystemTotals::default();
+        // One snapshot every 10s: postgres climbs, holds, dips, climbs again; nginx never counts
+        let sequence = [95.0, 90.0, 85.0, 99.0, 60.0, 90.0, 90.0, 90.0, 90.0];
+        let mut fired_at = Vec::new();
+        for (tick, &cpu) in sequence.iter().enumerate() {
+            let now = start + tick as u32 * 10 * SEC;
+            let list = procs(&[(7, "postgres", cpu), (8, "nginx", 100.0)]);
+            if !alerts.evaluate(&list, &totals, now).is_empty() {
+                fired_at.push(tick);
+            }
+            let status = &alerts.status(now)[0];
+            match tick {
+                2 => assert_eq!((status.firing, status.pending), (0, Some(20 * SEC))),
+                3 => assert_eq!((status.firing, status.pending, status.last_fired), (1, None, Some(Duration::ZERO))),
+                4 => assert_eq!((status.firing, status.pending, status.last_fired), (0, None, Some(10 * SEC))),
+                _ => {},
+            }
+        }
+        assert_eq!(fired_at, vec![3, 8]);
+    }
+
+    #[test]
+    fn a_rule_fires_once_the_condition_has_held_long_enough() {
+        let mut alerts = rules(&[("process cpu > 90 for 30s", "0s")]);
+        let start = Instant::now();
+        let hot = procs(&[(1, "idle", 1.0), (42, "runaway", 99.0)]);
+        let totals = SystemTotals::default();
+        assert!(alerts.evaluate(&hot, &totals, start).is_empty());
+        assert!(alerts.evaluate(&hot, &totals, start + 29 * SEC).is_empty());
+        let fired = alerts.evaluate(&hot, &totals, start + 30 * SEC);
+        assert_eq!(fired, vec![Alert { title: "process cpu > 90 for 30s".into(), message: "runaway (PID 42) at 99.0% CPU for 30s".into(), sink: Sink::Notify, command: None }]);
+        // Still running away: no repeat, however long it lasts
+        for secs in 31..120 {
+            assert!(alerts.evaluate(&hot, &totals, start + secs * SEC).is_empty());
+        }
+    }
+
+    #[test]
+    fn a_dip_restarts_the_clock() {
+        let mut alerts = rules(&[("process cpu > 90 for 30s", "0s")]);
+        let start = Instant::now();
+        let totals = SystemTotals::default();
+        alerts.evaluate(&procs(&[(42, "spiky", 99.0)]), &totals, start);
+        alerts.evaluate(&procs(&[(42, "spiky", 50.0)]), &totals, start + 10 * SEC);
+        assert!(alerts.evaluate(&procs(&[(42, "spiky", 99.0)]), &totals, start + 35 * SEC).is_empty());
+        assert_eq!(alerts.evaluate(&procs(&[(42, "spiky", 99.0)]), &totals, start + 65 * SEC).len(), 1);
+    }
+
+    #[test]
+    fn hysteresis_and_cooldown_limit_repeats() {
+        let mut alerts = rules(&[("memory > 90%", "1m")]);
+        let start = Instant::now();
+        assert_eq!(alerts.evaluate(&[], &memory(95), start)[0].message, "Memory at 95.0%");
+        // Inside the margin (90 - 4.5) the rule doesn't re-arm
+        alerts.evaluate(&[], &memory(88), start + SEC);
+        assert!(alerts.evaluate(&[], &memory(95), start + 2 * SEC).is_empty());
+        // Cleared, but still within the cooldown
+        alerts.evaluate(&[], &memory(80), start + 3 * SEC);
+        assert!(alerts.evaluate(&[], &memory(95), start + 4 * SEC).is_empty());
+        // The cooldown has passed and the condition still holds
+        assert_eq!(alerts.evaluate(&[], &memory(95), start + 61 * SEC).len(), 1);
+    }
+
+    #[test]
+    fn commands_start_at_most_once_a_minute() {
+        let config = AlertConfig { when: "memory > 90%".into(), cooldown: "0s".into(), on_fire: "logger high memory".into(), ..AlertConfig::default() };
+        let (mut alerts, _) = Alerts::from_config(&[config]);
+        let start = Instant::now();
+        let mut commands = Vec::new();
+        // Flapping every second for two minutes
+        for secs in 0..120 {
+            let used = if secs % 2 == 0 { 95 } else { 50 };
+            for alert in alerts.evaluate(&[], &memory(used), start + secs * SEC) {
+                commands.push((secs, alert.command));
+            }
+        }
+        assert_eq!(commands.len(), 60);
+        let started: Vec<u32> = commands.iter().filter(|(_, c)| c.is_some()).map(|&(secs, _)| secs).collect();
+        assert_eq!(started, vec![0, 60]);
+        assert_eq!(commands[0].1.as_deref(), Some("logger high memory"));
+    }
+
+    #[test]
+    fn processes_crossing_together_are_one_alert() {
+        let mut alerts = rules(&[("process:worker cpu > 50", "0s")]);
+        let start = Instant::now();
+        let list = procs(&[(1, "worker-a", 80.0), (2, "worker-b", 70.0), (3, "browser", 99.0)]);
+        let fired = alerts.evaluate(&list, &SystemTotals::default(), start);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].message, "worker-a (PID 1) at 80.0% CPU, and 1 more");
+        // A new process crossing later gets its own alert
+        let list = procs(&[(1, "worker-a", 80.0), (2, "worker-b", 70.0), (4, "worker-c", 60.0)]);
+        assert_eq!(alerts.evaluate(&list, &SystemTotals::default(), start + SEC)[0].message, "worker-c (PID 4) at 60.0% CPU");
+    }
+
+    #[test]
+    fn disabled_alerts_stay_quiet() {
+        let mut alerts = rules(&[("memory > 90%", "0s")]);
+        alerts.enabled = false;
+        assert!(alerts.evaluate(&[], &memory(99), Instant::now()).is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+use std::{cmp::Ordering, sync::Arc};
+use serde::Serialize;
+
+use crate::{app::SortBy, server::{Handler, Request, Response}, snapshot::{CpuFreqRecord, CpuTimeRecord, LoadRecord, MemoryRecord, PressureRecord, ProcessRecord, SharedSnapshot, SystemSnapshot}};
+
+const CONTENT_TYPE: &str = "application/json";
+
+// `/api/system` without the process and network lists
+#[derive(Serialize)]
+struct SystemRecord<'a> {
+    timestamp: &'a str,
+    cpus: &'a [f32],
+    memory: &'a MemoryRecord,
+    load: &'a LoadRecord,
+    boot_time: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_time: Option<&'a CpuTimeRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpufreq: Option<&'a CpuFreqRecord>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pressure: &'a [PressureRecord],
+}
+
+#[derive(Serialize)]
+struct ErrorRecord<'a> {
+    error: &'a str,
+}
+
+// The JSON API, answered from the shared snapshot. The bodies use the same records as
+// `--format json`, so a client can parse both with one set of types.
+pub fn handler(snapshot: SharedSnapshot) -> Handler {
+    Arc::new(move |req: &Request| match snapshot.read() {
+        Ok(snapshot) => respond(&snapshot, req),
+        Err(_) => json(500, &ErrorRecord { error: "snapshot unavailable" }),
+    })
+}
+
+pub fn respond(snapshot: &SystemSnapshot, req: &Request) -> Response {
+    match req.path.as_str() {
+        "/api/processes" => match processes(snapshot, req) {
+            Ok(procs) => json(200, &procs),
+            Err(message) => json(400, &ErrorRecord { error: &message }),
+        },
+        "/api/system" => json(200, &SystemRecord {
+            timestamp: &snapshot.timestamp,
+            cpus: &snapshot.cpus,
+            memory: &snapshot.memory,
+            load: &snapshot.load,
+            boot_time: snapshot.boot_time,
+            cpu_time: snapshot.cpu_time.as_ref(),
+            cpufreq: snapshot.cpufreq.as_ref(),
+            pressure: &snapshot.pressure,
+        }),
+        "/api/networks" => json(200, &snapshot.networks),
+        _ => json(404, &ErrorRecord { error: "not found; try /api/processes, /api/system or /api/networks" }),
+    }
+}
+
+// Query parameters: `filter` (like the search box), `sort` (pid, cpu, mem, name, fds, conn, net, swap, vcsw, ivcsw, minflt, majflt, core or container; default cpu),
+// `order` (asc or desc; default desc) and `limit`
+fn processes<'a>(snapshot: &'a SystemSnapshot, req: &Request) -> Result<Vec<&'a ProcessRecord>, String> {
+    let param = |name: &str| req.query.get(name).map(String::as_str);
+    let sort_by = match param("sort") {
+        None => SortBy::Cpu,
+        Some(name) => SortBy::from_name(name).ok_or_else(|| format!("unknown sort `{}`; expected pid, cpu, mem, name, fds, conn, net, swap, vcsw, ivcsw, minflt, majflt, core or container", name))?,
+    };
+    let descending = match param("order") {
+        None | Some("desc") => true,
+        Some("asc") => false,
+        Some(other) => return Err(format!("unknown order `{}`; expected asc or desc", other)),
+    };
+    let limit = match param("limit") {
+        None => usize::MAX,
+        Some(n) => n.parse().map_err(|_| format!("limit must be a number, got `{}`", n))?,
+    };
+    let query = param("filter").unwrap_or("").to_lowercase();
+    let mut procs: Vec<&ProcessRecord> = snapshot.processes.iter().filter(|p| p.matches(&query)).collect();
+    procs.sort_by(|a, b| compare(sort_by, descending, a, b));
+    procs.truncate(limit);
+    Ok(procs)
+}
+
+// The same order as the table: ties by ascending PID, NaN CPU below everything
+fn compare(sort_by: SortBy, descending: bool, a: &ProcessRecord, b: &ProcessRecord) -> Ordering {
+    let cpu_key = |c: f32| if c.is_nan() { f32::NEG_INFINITY } else { c };
+    let primary = match sort_by {
+        SortBy::Pid => a.pid.cmp(&b.pid),
+        SortBy::Cpu => cpu_key(a.cpu_percent).total_cmp(&cpu_key(b.cpu_percent)),
+        SortBy::Mem => a.mem_bytes.cmp(&b.mem_bytes),
+        SortBy::Name => a.name.cmp(&b.name),
+        SortBy::Fds => a.open_fds.cmp(&b.open_fds),
+        SortBy::Conn => a.connections.cmp(&b.connections),
+        SortBy::Net => a.net_queued_bytes.cmp(&b.net_queued_bytes),
+        SortBy::Swap => a.swap_bytes.cmp(&b.swap_bytes),
+        // A single snapshot only has totals
+        SortBy::Vcsw => a.voluntary_ctxt_switches.cmp(&b.voluntary_ctxt_switches),
+        SortBy::Ivcsw => a.nonvoluntary_ctxt_switches.cmp(&b.nonvoluntary_ctxt_switches),
+        SortBy::Minflt => a.minor_faults.cmp(&b.minor_faults),
+        SortBy::Majflt => a.major_faults.cmp(&b.major_faults),
+        SortBy::Core => a.processor.cmp(&b.processor),
+        SortBy::Container => a.container.cmp(&b.container),
+    };
+    let primary = if descending { primary.reverse() } else { primary };
+    primary.then_with(|| a.pid.cmp(&b.pid))
+}
+
+fn json(status: u16, value: &impl Serialize) -> Response {
+    match serde_json::to_string(value) {
+        Ok(body) => Response { status, content_type: CONTENT_TYPE, body },
+        Err(e) => Response::error(500, &e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{server::parse_query, snapshot::NetworkRecord};
+
+    fn process(pid: u32, name: &str, cpu: f32, mem: u64) -> ProcessRecord {
+        ProcessRecord { pid, parent_pid: None, name: name.into(), user: None, state: "S".into(), cpu_percent: cpu, mem_bytes: mem, kernel_thread: false, start_time: 0, open_fds: None, connections: None, net_queued_bytes: None, swap_bytes: None, voluntary_ctxt_switches: None, nonvoluntary_ctxt_switches: None, minor_faults: None, major_faults: None, processor: None, container: None, exe: None, cmdline: Vec::new() }
+    }
+
+    fn snapshot() -> SystemSnapshot {
+        SystemSnapshot {
+            timestamp: "2024-01-01T00:00:00Z".into(),
+            cpus: vec![10.0],
+            memory: MemoryRecord { total_bytes: 100, used_bytes: 50, available_bytes: 0, cached_bytes: None, buffers_bytes: None, dirty_bytes: None, anon_huge_pages_bytes: None, pressure: None, pressure_free_percent: None, huge_pages: Vec::new(), swap_total_bytes: 0, swap_used_bytes: 0, swapped_in_bytes: None, swapped_out_bytes: None },
+            load: LoadRecord { one: 1.0, five: 0.5, fifteen: 0.25 },
+            boot_time: 0,
+            cpu_time: None,
+            cpufreq: None,
+            pressure: Vec::new(),
+            networks: vec![NetworkRecord { name: "eth0".into(), received_bytes: 1, transmitted_bytes: 2, received_errors: 0, transmitted_errors: 0, received_dropped: None, transmitted_dropped: None, up: None }],
+            processes: vec![process(1, "init", 0.5, 300), process(20, "nginx", 3.0, 100), process(21, "nginx", 3.0, 200), process(30, "bash", 1.0, 50)],
+        }
+    }
+
+    fn get(target: &str) -> (u16, serde_json::Value) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let req = Request { method: "GET".into(), path: path.into(), query: parse_query(query) };
+        let response = respond(&snapshot(), &req);
+        assert_eq!(response.content_type, CONTENT_TYPE);
+        (response.status, serde_json::from_str(&response.body).unwrap())
+    }
+
+    fn pids(value: &serde_json::Value) -> Vec<u64> {
+        value.as_array().unwrap().iter().map(|p| p["pid"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn processes_are_filtered_sorted_and_limited() {
+        let (status, body) = get("/api/processes");
+        assert_eq!(status, 200);
+        assert_eq!(pids(&body), vec![20, 21, 30, 1]);
+        assert_eq!(pids(&get("/api/processes?sort=mem&order=asc").1), vec![30, 20, 21, 1]);
+        assert_eq!(pids(&get("/api/processes?filter=NGINX&sort=name&limit=1").1), vec![20]);
+        assert_eq!(body[0]["name"], "nginx");
+        assert_eq!(body[0]["cpu_percent"], 3.0);
+    }
+
+    #[test]
+    fn bad_parameters_are_rejected() {
+        for target in ["/api/processes?sort=size", "/api/processes?order=up", "/api/processes?limit=ten"] {
+            let (status, body) = get(target);
+            assert_eq!(status, 400, "{}", target);
+            assert!(body["error"].is_string());
+        }
+        assert_eq!(get("/api/nothing").0, 404);
+    }
+
+    #[test]
+    fn system_and_networks() {
+        let (status, body) = get("/api/system");
+        assert_eq!(status, 200);
+        assert_eq!(body["memory"]["used_bytes"], 50);
+        assert_eq!(body["load"]["fifteen"], 0.25);
+        assert_eq!(body["cpus"][0], 10.0);
+        assert_eq!(body["boot_time"], 0);
+        assert!(body.get("processes").is_none());
+        let (_, body) = get("/api/networks");
+        assert_eq!(body[0]["name"], "eth0");
+        assert_eq!(body[0]["transmitted_bytes"], 2);
+    }
+}
@@ -0,0 +1,3350 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, path::PathBuf, time::{Duration, Instant}};
+use crossterm::event::{KeyCode, KeyEvent};
+use sysinfo::{Pid, PidExt};
+use tui::widgets::TableState;
+
+use crate::{actions::{ActionError, ActionLog, ActionResult}, alerts::{Alert, Alerts}, columns::Column, config::{self, Config}, conns::{Connection, Listener}, editor::LineEditor, exec::{self, Run, RunState, Runner}, exits::ExitLog, export, fds, pacing::Pacer, handle::ProcessHandle, keys::{Action, Keymap}, logins::Login, perf::{self, Perf}, procfs::CpuTime, query::{self, Query}, remote::RemoteStatus, replay::{ReplayCommand, ReplayStatus}, sched::{self, Scheduling}, system::{Details, Extras, NetSample, SystemSource, SystemTotals}, theme::Theme, trails::Trails};
+
+// How long a bell alert inverts the table header
+pub const FLASH_TIME: Duration = Duration::from_millis(500);
+
+// Status messages kept for the message log
+pub const MESSAGE_LOG: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Pid,
+    Cpu,
+    Mem,
+    Name,
+    Fds,
+    Conn,
+    Net,
+    Swap,
+    Vcsw,
+    Ivcsw,
+    Minflt,
+    Majflt,
+    Core,
+    Container,
+}
+
+impl SortBy {
+    // In the order --sort and the default_sort error list them
+    pub const ALL: [SortBy; 14] = [
+        SortBy::Pid,
+        SortBy::Cpu,
+        SortBy::Mem,
+        SortBy::Name,
+        SortBy::Fds,
+        SortBy::Conn,
+        SortBy::Net,
+        SortBy::Swap,
+        SortBy::Vcsw,
+        SortBy::Ivcsw,
+        SortBy::Minflt,
+        SortBy::Majflt,
+        SortBy::Core,
+        SortBy::Container,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SortBy::ALL.into_iter().find(|s| s.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortBy::Pid => "pid",
+            SortBy::Cpu => "cpu",
+            SortBy::Mem => "mem",
+            SortBy::Name => "name",
+            SortBy::Fds => "fds",
+            SortBy::Conn => "conn",
+            SortBy::Net => "net",
+            SortBy::Swap => "swap",
+            SortBy::Vcsw => "vcsw",
+            SortBy::Ivcsw => "ivcsw",
+            SortBy::Minflt => "minflt",
+            SortBy::Majflt => "majflt",
+            SortBy::Core => "core",
+            SortBy::Container => "container",
+        }
+    }
+}
+
+// The run states 'S' steps through; None is every process
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StateFilter {
+    Running,
+    DiskSleep,
+    Zombie,
+    Stopped,
+}
+
+impl StateFilter {
+    // All, then each state in turn, then All again
+    pub fn next(filter: Option<Self>) -> Option<Self> {
+        match filter {
+            None => Some(StateFilter::Running),
+            Some(StateFilter::Running) => Some(StateFilter::DiskSleep),
+            Some(StateFilter::DiskSleep) => Some(StateFilter::Zombie),
+            Some(StateFilter::Zombie) => Some(StateFilter::Stopped),
+            Some(StateFilter::Stopped) => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StateFilter::Running => "running",
+            StateFilter::DiskSleep => "D-state",
+            StateFilter::Zombie => "zombie",
+            StateFilter::Stopped => "stopped",
+        }
+    }
+
+    // Stopped includes stopped by a debugger, 't'
+    pub fn matches(self, state: char) -> bool {
+        match self {
+            StateFilter::Running => state == 'R',
+            StateFilter::DiskSleep => state == 'D',
+            StateFilter::Zombie => state == 'Z',
+            StateFilter::Stopped => matches!(state, 'T' | 't'),
+        }
+    }
+}
+
+// What the Name column shows. The search matches all three whichever is shown.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NameDisplay {
+    Basename,
+    ExePath,
+    Cmdline,
+}
+
+impl NameDisplay {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "basename" => Some(NameDisplay::Basename),
+            "exe_path" => Some(NameDisplay::ExePath),
+            "cmdline" => Some(NameDisplay::Cmdline),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NameDisplay::Basename => "basename",
+            NameDisplay::ExePath => "exe_path",
+            NameDisplay::Cmdline => "cmdline",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            NameDisplay::Basename => NameDisplay::ExePath,
+            NameDisplay::ExePath => NameDisplay::Cmdline,
+            NameDisplay::Cmdline => NameDisplay::Basename,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StatusKind {
+    Info,
+    Warning,
+    Error,
+    // A fired alert rule that couldn't be sent as a desktop notification
+    Alert,
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub kind: StatusKind,
+    pub text: String,
+    pub time: chrono::DateTime<chrono::Local>,
+}
+
+// One stored sample of a process
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistorySample {
+    pub time_ms: i64,
+    pub cpu: f32,
+    pub mem: u64,
+}
+
+// What the connections popup shows: the TCP and UDP sockets of one process, re-read every
+// fds::INTERVAL while it is open, until the process exits
+pub struct ConnectionsView {
+    pub process: ProcessHandle,
+    pub name: String,
+    pub list: Vec<Connection>,
+    pub updated: Instant,
+}
+
+// What the listening sockets panel shows, re-read every fds::INTERVAL while it is open, and
+// the highlighted row
+pub struct ListenersView {
+    pub list: Vec<Listener>,
+    pub row: usize,
+    pub updated: Instant,
+}
+
+// What the logins panel shows, re-read every fds::INTERVAL while it is open, and the
+// highlighted row
+pub struct LoginsView {
+    pub list: Vec<Login>,
+    pub row: usize,
+    pub updated: Instant,
+}
+
+// What the details popup shows: the selected process's row as it was when the popup opened,
+// and the settings the source could read
+pub struct DetailsView {
+    pub process: ProcessHandle,
+    pub name: String,
+    pub parent: Option<Pid>,
+    pub user: Option<String>,
+    pub state: char,
+    pub fds: Option<u32>,
+    pub details: Details,
+}
+
+// The per-process settings that can be typed in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Setting {
+    Affinity,
+    IoPriority,
+}
+
+impl Setting {
+    // How messages name it
+    pub fn label(self) -> &'static str {
+        match self {
+            Setting::Affinity => "CPU affinity",
+            Setting::IoPriority => "I/O priority",
+        }
+    }
+}
+
+// A new value for one process's setting being typed, started from the current one
+pub struct SettingInput {
+    pub setting: Setting,
+    pub process: ProcessHandle,
+    pub name: String,
+    pub text: LineEditor,
+}
+
+// One interface in the header: its totals since it came up, whether it is up, and its errors
+// and drops since the previous refresh, each pair as (received, transmitted)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interface {
+    pub name: String,
+    pub received: u64,
+    pub transmitted: u64,
+    pub up: Option<bool>,
+    pub errors: (u64, u64),
+    // None where the source doesn't count drops
+    pub dropped: Option<(u64, u64)>,
+}
+
+// The running totals an interface reported last time, to take the next differences from
+#[derive(Clone, Copy)]
+struct Counters {
+    bytes: (u64, u64),
+    errors: (u64, u64),
+    dropped: Option<(u64, u64)>,
+}
+
+// The interfaces, and the traffic over every one but loopback: the rate over the last
+// refresh, the highest rate seen, and the bytes moved since rust-htop started or the counters
+// were reset, each as (received, transmitted). A bridge and the interfaces in it see the same
+// packets, so bridged traffic is counted once for each.
+#[derive(Default)]
+pub struct NetSession {
+    counters: HashMap<String, Counters>,
+    pub interfaces: Vec<Interface>,
+    pub rate: (f64, f64),
+    pub peak: (f64, f64),
+    pub total: (u64, u64),
+}
+
+// Growth of a pair of running totals. A total that went down was reset with its interface,
+// and adds nothing.
+fn grown(before: (u64, u64), after: (u64, u64)) -> (u64, u64) {
+    (after.0.saturating_sub(before.0), after.1.saturating_sub(before.1))
+}
+
+impl NetSession {
+    // An interface's first reading only sets its baselines
+    pub fn update(&mut self, networks: &[NetSample], seconds: f64) {
+        let mut moved = (0, 0);
+        let mut counters = HashMap::with_capacity(networks.len());
+        self.interfaces.clear();
+        for n in networks {
+            let now = Counters { bytes: (n.total_received, n.total_transmitted), errors: n.errors, dropped: n.dropped };
+            let before = self.counters.get(n.name).copied().unwrap_or(now);
+            let bytes = grown(before.bytes, now.bytes);
+            if n.name != "lo" {
+                moved = (moved.0 + bytes.0, moved.1 + bytes.1);
+            }
+            self.interfaces.push(Interface {
+                name: n.name.to_string(),
+                received: n.total_received,
+                transmitted: n.total_transmitted,
+                up: n.up,
+                errors: grown(before.errors, now.errors),
+                dropped: now.dropped.map(|dropped| grown(before.dropped.unwrap_or(dropped), dropped)),
+            });
+            counters.insert(n.name.to_string(), now);
+        }
+        self.counters = counters;
+        self.total = (self.total.0 + moved.0, self.total.1 + moved.1);
+        if seconds > 0.0 {
+            self.rate = (moved.0 as f64 / seconds, moved.1 as f64 / seconds);
+            self.peak = (self.peak.0.max(self.rate.0), self.peak.1.max(self.rate.1));
+        }
+    }
+
+    // The baselines stay, so the next refresh still has a rate
+    pub fn reset(&mut self) {
+        self.peak = self.rate;
+        self.total = (0, 0);
+    }
+}
+
+// How many refreshes in a row with pages going to or from swap before the header calls it
+// swapping rather than a blip
+pub const SWAP_BUSY_REFRESHES: u32 = 3;
+
+// Paging to and from swap over the last refresh, in bytes per second as (in, out), and how
+// many refreshes in a row have moved any. None until two readings are in, and where the
+// source has no /proc/vmstat.
+#[derive(Default)]
+pub struct SwapActivity {
+    last: Option<(u64, u64)>,
+    pub rate: Option<(f64, f64)>,
+    pub busy: u32,
+}
+
+impl SwapActivity {
+    pub fn update(&mut self, swapped: Option<(u64, u64)>, seconds: f64) {
+        let Some(now) = swapped else {
+            *self = SwapActivity::default();
+            return;
+        };
+        if let Some(before) = self.last.replace(now) && seconds > 0.0 {
+            let moved = grown(before, now);
+            self.rate = Some((moved.0 as f64 / seconds, moved.1 as f64 / seconds));
+            self.busy = if moved == (0, 0) { 0 } else { self.busy + 1 };
+        }
+    }
+
+    pub fn swapping(&self) -> bool {
+        self.busy >= SWAP_BUSY_REFRESHES
+    }
+}
+
+// The share of CPU time over the last refresh that the hypervisor gave to someone else (steal)
+// and that this machine spent running its own guests, in percent. Bare metal has neither, so
+// each is only shown once it has been seen above zero, and kept from then on so it doesn't
+// blink in and out.
+#[derive(Default)]
+pub struct Virtualization {
+    last: Option<CpuTime>,
+    pub steal: Option<f32>,
+    pub guest: Option<f32>,
+}
+
+impl Virtualization {
+    pub fn update(&mut self, cpu_time: Option<CpuTime>) {
+        let Some(now) = cpu_time else {
+            *self = Virtualization::default();
+            return;
+        };
+        let Some(before) = self.last.replace(now) else { return };
+        let total = now.total.saturating_sub(before.total);
+        if total == 0 {
+            return;
+        }
+        let share = |after: u64, before: u64| after.saturating_sub(before) as f32 * 100.0 / total as f32;
+        let (steal, guest) = (share(now.steal, before.steal), share(now.guest, before.guest));
+        if steal > 0.0 || self.steal.is_some() {
+            self.steal = Some(steal);
+        }
+        if guest > 0.0 || self.guest.is_some() {
+            self.guest = Some(guest);
+        }
+    }
+}
+
+// The sort menu after F6: every column the source can fill, and the highlighted row
+pub struct SortMenu {
+    pub columns: Vec<Column>,
+    pub row: usize,
+}
+
+// The users picker after 'u': everyone who owns a process, with how many, and the highlighted row
+pub struct UserPicker {
+    pub users: Vec<(String, usize)>,
+    pub row: usize,
+}
+
+// What the history popup shows: the samples of one process between two times
+pub struct HistoryView {
+    pub pid: Pid,
+    pub name: String,
+    pub since_ms: i64,
+    pub until_ms: i64,
+    pub samples: Vec<HistorySample>,
+}
+
+pub struct App {
+    pub source: Box<dyn SystemSource>,
+    pub last_updated: Instant,
+    pub refresh_rate: Duration,
+    // Waits longer than refresh_rate between refreshes that don't fit in it
+    pub pacer: Pacer,
+    pub search_query: LineEditor,
+    pub searching: bool,
+    // The search lists the processes it doesn't match; the user and state filters are not inverted
+    pub search_inverted: bool,
+    pub sort_by: SortBy,
+    pub descending: bool,
+    pub tree_view: bool,
+    // In tree view, siblings are ordered by their subtrees' CPU or memory, which parent rows show
+    pub tree_totals: bool,
+    // The header's memory breakdown line is shown
+    pub memory_detail: bool,
+    // What the footer below the table adds up; None while it is hidden
+    pub footer: Option<Aggregate>,
+    pub hide_kernel_threads: bool,
+    // Processes below the config's hide_below_* thresholds are left out; this many were
+    pub hide_idle: bool,
+    pub hidden_idle: usize,
+    // How many rows past row_limit were cut off the end of the list
+    pub beyond_limit: usize,
+    // Our own process is left out. It is found by the PID taken at startup, and only among
+    // this machine's processes.
+    pub hide_self: bool,
+    pub self_pid: Pid,
+    pub self_hidden: bool,
+    // Refreshing is stopped and the last snapshot stays on screen
+    pub paused: bool,
+    // The highlighted row follows its process through refreshes, sorting and filtering
+    pub table_state: TableState,
+    // Whether the search, user or state filter was on when the view was last built, and the
+    // process that was selected when the first of them went on; clearing them goes back to it
+    filtered: bool,
+    unfiltered_pid: Option<Pid>,
+    // The selected process while a filter leaves nothing to highlight, so it is found again
+    // when the list comes back
+    hidden_pid: Option<Pid>,
+    // Prepared snapshot the draw closure renders from
+    pub processes: Vec<ProcInfo>,
+    pub visible: Vec<usize>,
+    // Tree depth of each row in `visible`; empty outside tree view
+    pub depths: Vec<usize>,
+    pub net: NetSession,
+    pub swap: SwapActivity,
+    pub virtualization: Virtualization,
+    pub totals: SystemTotals,
+    // When the data on screen was sampled, in ms since the Unix epoch: the frame's time in a
+    // replay, otherwise when it came in
+    pub sampled_ms: i64,
+    // The agent being watched with --connect, and whether it is reachable
+    pub remote: Option<RemoteStatus>,
+    // Position and speed when playing back a recording
+    pub replay: Option<ReplayStatus>,
+    // Set whenever something visible changed; the main loop skips drawing while it's false.
+    // Widgets that need periodic redraws (clocks, timers) should set it from the tick handler.
+    pub dirty: bool,
+    pub quit: bool,
+    // The config file that was loaded, if any
+    pub config_path: Option<PathBuf>,
+    // The [profile.<name>] applied on top of it; saving writes into that table
+    pub profile: Option<String>,
+    // The loaded config, kept so saving can write back settings that have no runtime state
+    pub config: Config,
+    // The config as the file and profile have it, without the environment and command line
+    // layered over it, so that saving doesn't write one run's flags into the file
+    pub file_config: Config,
+    // Shown in place of the search bar until the next key press or status_seconds
+    pub status: Option<Status>,
+    pub status_until: Option<Instant>,
+    // The latest MESSAGE_LOG statuses, oldest first
+    pub messages: VecDeque<Status>,
+    // The message log popup is open, scrolled this many messages back from the newest
+    pub message_log: Option<usize>,
+    // Ctrl+Z was pressed; the main loop owns the terminal so it does the actual suspending
+    pub suspend: bool,
+    pub keymap: Keymap,
+    pub theme: Theme,
+    // Table columns in display order, from the `columns` setting
+    pub columns: Vec<Column>,
+    pub name_display: NameDisplay,
+    pub alerts: Alerts,
+    // Alerts fired by the latest refreshes, for the main loop to deliver
+    pub fired_alerts: Vec<Alert>,
+    // `on_fire` commands started by alerts, and their log
+    pub commands: Runner,
+    // The command log popup is open; the next key closes it
+    pub show_commands: bool,
+    // A bell alert is due; the main loop owns the terminal so it writes the BEL
+    pub bell: bool,
+    // The table header is drawn inverted until then, for bell alerts
+    pub flash_until: Option<Instant>,
+    // The help overlay is open; the next key closes it
+    pub show_help: bool,
+    // The alert rules popup is open; the next key closes it
+    pub show_alerts: bool,
+    // The real-time and negatively niced processes popup ('ctrl+r')
+    pub show_realtime: bool,
+    // Mark real-time processes in the table, which takes reading their scheduling policy
+    pub realtime_badge: bool,
+    // The history of the selected process was asked for; the main loop owns the database so
+    // it runs the query and fills in `history`
+    pub history_wanted: bool,
+    // The history popup is open; the next key closes it
+    pub history: Option<HistoryView>,
+    // The connections popup is open; the next key closes it
+    pub connections: Option<ConnectionsView>,
+    pub listeners: Option<ListenersView>,
+    pub logins: Option<LoginsView>,
+    // The details popup is open; the next key closes it
+    pub details: Option<DetailsView>,
+    // Recent CPU use of the selected process and the one in the details popup
+    pub trails: Trails,
+    // Frame timings for the overlay, measured only while it is shown
+    pub perf: Perf,
+    // A CPU list or I/O priority being typed after 'P' or 'I'
+    pub setting_input: Option<SettingInput>,
+    // Patterns whose processes are always highlighted, matched like the search box
+    pub watches: Vec<String>,
+    // Per entry of `processes`, whether a watch matches it; refreshed by update_view
+    pub watched: Vec<bool>,
+    // A watch pattern being typed after 'w'
+    pub watch_input: Option<LineEditor>,
+    // The watch list popup is open, with this row highlighted
+    pub watch_list: Option<usize>,
+    // What came of recent affinity and I/O priority changes
+    pub actions: ActionLog,
+    // The action log popup is open, scrolled this many results back from the newest
+    pub action_log: Option<usize>,
+    // The processes that have gone since earlier refreshes
+    pub exits: ExitLog,
+    // The exit log popup is open, scrolled this many exits back from the newest
+    pub exit_log: Option<usize>,
+    // Only this user's processes are listed; combines with the search
+    pub user_filter: Option<String>,
+    pub user_picker: Option<UserPicker>,
+    pub sort_menu: Option<SortMenu>,
+    // Only processes in this run state are listed, stepped through with 'S'
+    pub state_filter: Option<StateFilter>,
+}
+
+// Owned copy of process info to avoid borrow conflicts. The cell text is kept alongside the
+// values and only re-formatted when a value changes at display precision, so building table
+// rows borrows strings instead of allocating new ones every frame.
+pub struct ProcInfo {
+    pub pid: sysinfo::Pid,
+    pub name: String,
+    pub cpu: f32,
+    pub mem: u64,
+    pub parent: Option<Pid>,
+    pub kernel_thread: bool,
+    pub user: Option<String>,
+    // One-letter run state as shown by ps: R, S, D, Z, T, I, ...
+    pub state: char,
+    // Seconds since the Unix epoch; 0 when unknown
+    pub start_time: u64,
+    // Open file descriptors and TCP/UDP sockets; None when they weren't counted
+    pub fds: Option<u32>,
+    pub conns: Option<u32>,
+    // Bytes waiting in the send and receive queues of those sockets; see netio.rs
+    pub net: Option<u64>,
+    // Swapped-out bytes; None when it wasn't read
+    pub swap: Option<u64>,
+    // Context switches since the previous refresh, worked out from the running totals; None
+    // until two readings of the same process are in
+    pub vcsw: Option<u64>,
+    pub ivcsw: Option<u64>,
+    pub voluntary_total: Option<u64>,
+    pub involuntary_total: Option<u64>,
+    // Page faults per second since the previous refresh, from the growth of the totals
+    pub minflt: Option<f64>,
+    pub majflt: Option<f64>,
+    pub minor_total: Option<u64>,
+    pub major_total: Option<u64>,
+    // Growth of the totals in the latest refresh; set_fault_rates turns it into the rates
+    fault_growth: Option<(u64, u64)>,
+    // The logical CPU it last ran on; None where that can't be read
+    pub processor: Option<usize>,
+    // The CPU scheduling policy and the nice value; None where they weren't read
+    pub scheduling: Option<Scheduling>,
+    pub nice: Option<i32>,
+    // The container's name, or its short ID until the name is known; None outside containers
+    // or when it wasn't read
+    pub container: Option<String>,
+    // The executable's path and the arguments; None and empty when they can't be read
+    pub exe: Option<String>,
+    pub cmd: Vec<String>,
+    // The arguments joined with spaces, for the Name column
+    pub cmdline: String,
+    // CPU and memory together with its descendants in the tree; set by subtree_totals for
+    // parents in tree view, None otherwise
+    pub subtree: Option<(f32, u64)>,
+    // Name, path and command line lowercased, for the search
+    search_text: String,
+    pub pid_text: String,
+    pub cpu_text: String,
+    pub mem_text: String,
+    pub fds_text: String,
+    pub conns_text: String,
+    pub net_text: String,
+    pub swap_text: String,
+    pub vcsw_text: String,
+    pub ivcsw_text: String,
+    pub minflt_text: String,
+    pub majflt_text: String,
+    pub core_text: String,
+    pub container_text: String,
+}
+
+// What the footer shows for the rows listed: how many there are and the sum and maximum of
+// their CPU and memory, from which the averages follow
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aggregate {
+    pub rows: usize,
+    pub cpu_sum: f32,
+    pub cpu_max: f32,
+    pub mem_sum: u64,
+    pub mem_max: u64,
+}
+
+impl Aggregate {
+    pub fn of(procs: &[ProcInfo], rows: &[usize]) -> Self {
+        rows.iter().map(|&i| &procs[i]).fold(Aggregate { rows: rows.len(), ..Aggregate::default() }, |sum, p| Aggregate {
+            cpu_sum: sum.cpu_sum + p.cpu,
+            cpu_max: sum.cpu_max.max(p.cpu),
+            mem_sum: sum.mem_sum + p.mem,
+            mem_max: sum.mem_max.max(p.mem),
+            ..sum
+        })
+    }
+
+    pub fn cpu_average(&self) -> f32 {
+        if self.rows == 0 { 0.0 } else { self.cpu_sum / self.rows as f32 }
+    }
+
+    pub fn mem_average(&self) -> u64 {
+        self.mem_sum.checked_div(self.rows as u64).unwrap_or(0)
+    }
+}
+
+// One process as reported by the latest refresh
+pub struct ProcSample<'a> {
+    pub pid: Pid,
+    pub name: &'a str,
+    pub cpu: f32,
+    pub mem: u64,
+    pub parent: Option<Pid>,
+    pub kernel_thread: bool,
+    pub user: Option<&'a str>,
+    pub state: char,
+    pub start_time: u64,
+    pub fds: Option<u32>,
+    pub conns: Option<u32>,
+    pub net: Option<u64>,
+    pub swap: Option<u64>,
+    pub voluntary_switches: Option<u64>,
+    pub involuntary_switches: Option<u64>,
+    pub minor_faults: Option<u64>,
+    pub major_faults: Option<u64>,
+    pub processor: Option<usize>,
+    pub scheduling: Option<Scheduling>,
+    pub nice: Option<i32>,
+    pub container: Option<&'a str>,
+    pub exe: Option<&'a str>,
+    pub cmd: &'a [String],
+}
+
+// A sleeping process with nothing but its PID and name, for tests to fill in what they need
+#[cfg(test)]
+pub fn sample(pid: usize, name: &str) -> ProcSample<'_> {
+    ProcSample { pid: Pid::from(pid), name, cpu: 0.0, mem: 0, parent: None, kernel_thread: false, user: None, state: 'S', start_time: 0, fds: None, conns: None, net: None, swap: None, voluntary_switches: None, involuntary_switches: None, minor_faults: None, major_faults: None, processor: None, scheduling: None, nice: None, container: None, exe: None, cmd: &[] }
+}
+
+impl ProcInfo {
+    pub fn new(sample: &ProcSample) -> Self {
+        Self {
+            pid: sample.pid,
+            name: sample.name.to_string(),
+            cpu: sample.cpu,
+            mem: sample.mem,
+            parent: sample.parent,
+            kernel_thread: sample.kernel_thread,
+            user: sample.user.map(str::to_string),
+            state: sample.state,
+            start_time: sample.start_time,
+            fds: sample.fds,
+            conns: sample.conns,
+            net: sample.net,
+            swap: sample.swap,
+            vcsw: None,
+            ivcsw: None,
+            voluntary_total: sample.voluntary_switches,
+            involuntary_total: sample.involuntary_switches,
+            minflt: None,
+            majflt: None,
+            minor_total: sample.minor_faults,
+            major_total: sample.major_faults,
+            fault_growth: None,
+            processor: sample.processor,
+            scheduling: sample.scheduling,
+            nice: sample.nice,
+            container: sample.container.map(str::to_string),
+            exe: sample.exe.map(str::to_string),
+            cmd: sample.cmd.to_vec(),
+            cmdline: sample.cmd.join(" "),
+            subtree: None,
+            search_text: search_text(sample.name, sample.exe, sample.cmd),
+            pid_text: sample.pid.to_string(),
+            cpu_text: format_cpu(sample.cpu),
+            mem_text: format_mem(sample.mem),
+            fds_text: format_fds(sample.fds),
+            conns_text: format_fds(sample.conns),
+            net_text: format_swap(sample.net),
+            swap_text: format_swap(sample.swap),
+            vcsw_text: format_count(None),
+            ivcsw_text: format_count(None),
+            minflt_text: format_rate(None),
+            majflt_text: format_rate(None),
+            core_text: format_core(sample.processor),
+            container_text: sample.container.unwrap_or("-").to_string(),
+        }
+    }
+
+    pub fn update(&mut self, sample: &ProcSample) {
+        if self.name != sample.name || self.exe.as_deref() != sample.exe || self.cmd != sample.cmd {
+            self.name.clear();
+            self.name.push_str(sample.name);
+            self.exe = sample.exe.map(str::to_string);
+            // Some programs, like postgres, rewrite their arguments to show what they are doing
+            if self.cmd != sample.cmd {
+                self.cmd = sample.cmd.to_vec();
+                self.cmdline = sample.cmd.join(" ");
+            }
+            self.search_text = search_text(sample.name, sample.exe, sample.cmd);
+        }
+        if (self.cpu * 100.0).round() != (sample.cpu * 100.0).round() {
+            self.cpu_text = format_cpu(sample.cpu);
+        }
+        if self.mem != sample.mem {
+            self.mem_text = format_mem(sample.mem);
+        }
+        self.cpu = sample.cpu;
+        self.mem = sample.mem;
+        self.parent = sample.parent;
+        self.kernel_thread = sample.kernel_thread;
+        if self.user.as_deref() != sample.user {
+            self.user = sample.user.map(str::to_string);
+        }
+        self.state = sample.state;
+        if self.fds != sample.fds {
+            self.fds = sample.fds;
+            self.fds_text = format_fds(sample.fds);
+        }
+        if self.conns != sample.conns {
+            self.conns = sample.conns;
+            self.conns_text = format_fds(sample.conns);
+        }
+        if self.net != sample.net {
+            self.net = sample.net;
+            self.net_text = format_swap(sample.net);
+        }
+        if self.swap != sample.swap {
+            self.swap = sample.swap;
+            self.swap_text = format_swap(sample.swap);
+        }
+        if self.processor != sample.processor {
+            self.processor = sample.processor;
+            self.core_text = format_core(sample.processor);
+        }
+        self.scheduling = sample.scheduling;
+        self.nice = sample.nice;
+        if self.container.as_deref() != sample.container {
+            self.container = sample.container.map(str::to_string);
+            self.container_text = sample.container.unwrap_or("-").to_string();
+        }
+        // A reused PID is a different process whose totals started over
+        let same = self.start_time == sample.start_time;
+        let vcsw = delta(self.voluntary_total.filter(|_| same), sample.voluntary_switches);
+        let ivcsw = delta(self.involuntary_total.filter(|_| same), sample.involuntary_switches);
+        if self.vcsw != vcsw {
+            self.vcsw = vcsw;
+            self.vcsw_text = format_count(vcsw);
+        }
+        if self.ivcsw != ivcsw {
+            self.ivcsw = ivcsw;
+            self.ivcsw_text = format_count(ivcsw);
+        }
+        self.fault_growth = delta(self.minor_total.filter(|_| same), sample.minor_faults).zip(delta(self.major_total.filter(|_| same), sample.major_faults));
+        self.minor_total = sample.minor_faults;
+        self.major_total = sample.major_faults;
+        self.voluntary_total = sample.voluntary_switches;
+        self.involuntary_total = sample.involuntary_switches;
+        self.start_time = sample.start_time;
+    }
+
+    // The search box rule: a case-insensitive match on the name, executable path or command
+    // line, or part of the PID. `query` must already be lowercase.
+    pub fn matches(&self, query: &str) -> bool {
+        query.is_empty() || self.matches_name(query) || self.pid_text.contains(query)
+    }
+
+    // Under a real-time scheduling policy, so it runs ahead of every normal process
+    pub fn realtime(&self) -> bool {
+        self.scheduling.is_some_and(|s| s.policy.realtime())
+    }
+
+    // Can keep normal processes off the CPU: real-time, or niced below the default
+    pub fn boosted(&self) -> bool {
+        self.realtime() || self.nice.is_some_and(|nice| nice < 0)
+    }
+
+    // The name, path or command line contains `query`, which is lowercase
+    pub fn matches_name(&self, query: &str) -> bool {
+        self.search_text.contains(query)
+    }
+
+    // The Name column's text. A process whose path or arguments can't be read, like a kernel
+    // thread, shows its name.
+    pub fn display_name(&self, display: NameDisplay) -> &str {
+        match display {
+            NameDisplay::ExePath => self.exe.as_deref().unwrap_or(&self.name),
+            NameDisplay::Cmdline if !self.cmdline.is_empty() => &self.cmdline,
+            _ => &self.name,
+        }
+    }
+
+    // Called after `update` with the time between the two samples. A repeated sample (a
+    // paused replay) keeps the previous rates.
+    pub fn set_fault_rates(&mut self, seconds: f64) {
+        if seconds <= 0.0 {
+            return;
+        }
+        let (minflt, majflt) = match self.fault_growth {
+            Some((minor, major)) => (Some(minor as f64 / seconds), Some(major as f64 / seconds)),
+            None => (None, None),
+        };
+        if self.minflt != minflt {
+            self.minflt = minflt;
+            self.minflt_text = format_rate(minflt);
+        }
+        if self.majflt != majflt {
+            self.majflt = majflt;
+            self.majflt_text = format_rate(majflt);
+        }
+    }
+}
+
+impl App {
+    pub fn new(config: Config, source: Box<dyn SystemSource>) -> Self {
+        let sort_by = SortBy::from_name(&config.default_sort).unwrap_or(SortBy::Cpu);
+        let filter = config.default_filter.clone();
+        // Problems with [keys] and [theme] were already reported when the config was parsed
+        let (keymap, _) = Keymap::from_config(&config.keys);
+        let (theme, _) = Theme::resolve(&config.theme);
+        let columns = shown_columns(&config.columns, source.as_ref());
+        let (alerts, _) = Alerts::from_config(&config.alerts);
+        let mut app = Self {
+            source,
+            last_updated: Instant::now(),
+            refresh_rate: Duration::from_millis(config.refresh_rate),
+            pacer: Pacer::default(),
+            searching: !filter.is_empty(),
+            search_query: LineEditor::new(filter),
+            search_inverted: false,
+            sort_by,
+            descending: config.descending,
+            tree_view: config.tree_view,
+            tree_totals: config.tree_totals,
+            memory_detail: config.memory_detail,
+            footer: config.show_footer.then(Aggregate::default),
+            hide_kernel_threads: !config.show_kernel_threads,
+            hide_idle: config.hide_idle,
+            hidden_idle: 0,
+            beyond_limit: 0,
+            hide_self: config.hide_self,
+            self_pid: Pid::from_u32(std::process::id()),
+            self_hidden: false,
+            paused: config.start_paused,
+            table_state: TableState::default(),
+            filtered: false,
+            unfiltered_pid: None,
+            hidden_pid: None,
+            processes: Vec::new(),
+            visible: Vec::new(),
+            depths: Vec::new(),
+            net: NetSession::default(),
+            swap: SwapActivity::default(),
+            virtualization: Virtualization::default(),
+            totals: SystemTotals::default(),
+            sampled_ms: 0,
+            remote: None,
+            replay: None,
+            dirty: true,
+            quit: false,
+            suspend: false,
+            status: None,
+            status_until: None,
+            messages: VecDeque::new(),
+            message_log: None,
+            config_path: None,
+            profile: None,
+            file_config: config.clone(),
+            keymap,
+            theme,
+            columns,
+            name_display: NameDisplay::from_name(&config.name_display).unwrap_or(NameDisplay::Basename),
+            alerts,
+            fired_alerts: Vec::new(),
+            commands: Runner::default(),
+            show_commands: false,
+            bell: false,
+            flash_until: None,
+            show_help: false,
+            show_alerts: false,
+            show_realtime: false,
+            realtime_badge: config.realtime_badge,
+            history_wanted: false,
+            history: None,
+            connections: None,
+            details: None,
+            trails: Trails::default(),
+            perf: Perf::default(),
+            setting_input: None,
+            watches: config.watches.clone(),
+            watched: Vec::new(),
+            watch_input: None,
+            watch_list: None,
+            actions: ActionLog::default(),
+            action_log: None,
+            exits: ExitLog::new(config.exit_log_size),
+            exit_log: None,
+            user_filter: None,
+            user_picker: None,
+            sort_menu: None,
+            listeners: None,
+            logins: None,
+            state_filter: None,
+            config,
+        };
+        app.perf.set_enabled(app.config.show_perf);
+        app.update_snapshot();
+        app
+    }
+
+    // Whether the wait since the last refresh is over, stretched while refreshing lags
+    pub fn refresh_due(&self) -> bool {
+        self.last_updated.elapsed() >= self.pacer.interval(self.refresh_rate)
+    }
+
+    pub fn refresh(&mut self) {
+        let timing = self.perf.start();
+        let started = Instant::now();
+        self.source.want(self.extras());
+        self.source.refresh();
+        self.last_updated = Instant::now();
+        self.update_snapshot();
+        // Once its process has exited the popup keeps the last list rather than reading
+        // whatever has the PID now
+        if let Some(view) = &self.connections && view.updated.elapsed() >= fds::INTERVAL && !self.has_exited(&view.process) {
+            self.show_connections(view.process, view.name.clone());
+        }
+        if let Some(view) = &self.listeners && view.updated.elapsed() >= fds::INTERVAL {
+            self.show_listeners(view.row);
+        }
+        if let Some(view) = &self.logins && view.updated.elapsed() >= fds::INTERVAL {
+            self.show_logins(view.row);
+        }
+        self.perf.record(perf::Stage::Refresh, timing);
+        self.pacer.record(started.elapsed());
+        if let Some(took) = self.pacer.lagging(self.refresh_rate) {
+            log::warn!("refresh took {:?}, longer than the {:?} interval; waiting {:?}", took, self.refresh_rate, self.pacer.interval(self.refresh_rate));
+        }
+        log::debug!("refreshed in {:?} (sampling {:?}): {} processes, {} shown", started.elapsed(), self.last_updated - started, self.processes.len(), self.visible.len());
+        // A replay's clock isn't the wall clock, and its alerts went off long ago
+        if self.replay.is_none() {
+            let fired = self.alerts.evaluate(&self.processes, &self.totals, self.last_updated);
+            if self.config.allow_exec {
+                for alert in &fired {
+                    if let Some(command) = &alert.command {
+                        self.commands.start(&alert.title, command, chrono::Local::now());
+                    }
+                }
+            }
+            self.fired_alerts.extend(fired);
+        }
+        self.dirty = true;
+    }
+
+    // The processes the real-time popup lists: real-time ones first, by priority, then the
+    // rest by how far below the default they are niced
+    pub fn boosted(&self) -> Vec<&ProcInfo> {
+        let mut boosted: Vec<&ProcInfo> = self.processes.iter().filter(|p| p.boosted()).collect();
+        boosted.sort_by_key(|p| (!p.realtime(), std::cmp::Reverse(p.scheduling.map_or(0, |s| s.priority)), p.nice.unwrap_or(0), p.pid));
+        boosted
+    }
+
+    // What the columns on screen and the sort order need read beyond the basics, and the
+    // scheduling policy for the RT badge
+    fn extras(&self) -> Extras {
+        let used = |column, sort| self.columns.contains(&column) || self.sort_by == sort;
+        Extras {
+            fds: used(Column::Fds, SortBy::Fds),
+            sockets: used(Column::Conn, SortBy::Conn),
+            net: used(Column::Net, SortBy::Net),
+            status: used(Column::Swap, SortBy::Swap) || used(Column::Ivcsw, SortBy::Ivcsw) || used(Column::Vcsw, SortBy::Vcsw),
+            stat: used(Column::Minflt, SortBy::Minflt) || used(Column::Majflt, SortBy::Majflt) || used(Column::Core, SortBy::Core) || self.realtime_badge,
+            containers: used(Column::Container, SortBy::Container),
+        }
+    }
+
+    // Rebuild everything derived from the source; called after a data refresh
+    fn update_snapshot(&mut self) {
+        self.totals = self.source.totals();
+        self.remote = self.source.remote();
+        self.replay = self.source.replay();
+        // Rates are over the data's own clock, so a replay shows the rates it recorded
+        let sampled_ms = self.replay.as_ref().map_or_else(|| chrono::Utc::now().timestamp_millis(), |r| r.time_ms);
+        let selected = self.selected_pid();
+        let samples = self.source.processes();
+        // A seek in a replay isn't processes exiting
+        if self.replay.is_none() {
+            self.exits.record(&self.processes, &samples, sampled_ms);
+        }
+        merge_processes(&mut self.processes, samples);
+        let seconds = (sampled_ms - self.sampled_ms) as f64 / 1000.0;
+        for p in &mut self.processes {
+            p.set_fault_rates(seconds);
+        }
+        self.net.update(&self.source.networks(), seconds);
+        self.swap.update(self.totals.swapped, seconds);
+        self.virtualization.update(self.totals.cpu_time);
+        self.sampled_ms = sampled_ms;
+        self.rebuild_view(selected);
+        self.record_trails();
+    }
+
+    fn record_trails(&mut self) {
+        let looked_at: Vec<Pid> = self.selected_pid().into_iter().chain(self.details.as_ref().map(|d| d.process.pid)).collect();
+        self.trails.update(&self.processes, &looked_at, self.sampled_ms);
+    }
+
+    // Re-filter and re-sort without touching the data; called on sort/filter changes
+    pub fn update_view(&mut self) {
+        let selected = self.selected_pid();
+        self.rebuild_view(selected);
+    }
+
+    fn rebuild_view(&mut self, selected: Option<Pid>) {
+        filter_processes(&self.processes, self.search_query.as_str(), self.search_inverted, &mut self.visible);
+        if let Some(user) = &self.user_filter {
+            self.visible.retain(|&i| self.processes[i].user.as_ref() == Some(user));
+        }
+        if let Some(state) = self.state_filter {
+            self.visible.retain(|&i| state.matches(self.processes[i].state));
+        }
+        if self.hide_kernel_threads {
+            self.visible.retain(|&i| !self.processes[i].kernel_thread);
+        }
+        let before = self.visible.len();
+        if self.hide_self && self.remote.is_none() && self.replay.is_none() {
+            self.visible.retain(|&i| self.processes[i].pid != self.self_pid);
+        }
+        self.self_hidden = self.visible.len() < before;
+        let before = self.visible.len();
+        if self.hide_idle {
+            let (cpu, mem) = (self.config.hide_below_cpu, self.config.hide_below_mem_mb.saturating_mul(1024 * 1024));
+            self.visible.retain(|&i| self.processes[i].cpu >= cpu || self.processes[i].mem >= mem);
+        }
+        self.hidden_idle = before - self.visible.len();
+        sort_processes(self.sort_by, self.descending, &self.processes, &mut self.visible);
+        watch_processes(&self.processes, &self.watches, &mut self.watched);
+        if self.tree_view {
+            if self.tree_totals {
+                subtree_totals(&mut self.processes, &self.visible);
+                sort_by_subtree(self.sort_by, self.descending, &self.processes, &mut self.visible);
+            }
+            tree_order(&self.processes, &mut self.visible, &mut self.depths);
+        } else {
+            self.depths.clear();
+        }
+        // Last, so what is kept is the top of the list as sorted
+        let limit = self.config.row_limit;
+        self.beyond_limit = if limit > 0 { self.visible.len().saturating_sub(limit) } else { 0 };
+        if self.beyond_limit > 0 {
+            self.visible.truncate(limit);
+            self.depths.truncate(limit);
+        }
+        if self.footer.is_some() {
+            self.footer = Some(Aggregate::of(&self.processes, &self.visible));
+        }
+        self.reselect(selected);
+    }
+
+    // A flat, unfiltered list from the top: no search, user or state filter, the configured
+    // default_sort and direction, out of tree view and without the footer whatever the config
+    // starts with, and the first row selected. Watches, pausing and the kernel thread and idle
+    // toggles are left alone.
+    pub fn reset_view(&mut self) {
+        self.searching = false;
+        self.search_query.clear();
+        self.search_inverted = false;
+        self.user_filter = None;
+        self.state_filter = None;
+        self.sort_by = SortBy::from_name(&self.config.default_sort).unwrap_or(SortBy::Cpu);
+        self.descending = self.config.descending;
+        self.tree_view = false;
+        self.footer = None;
+        self.rebuild_view(None);
+        self.unfiltered_pid = None;
+        self.hidden_pid = None;
+        self.table_state.select((!self.visible.is_empty()).then_some(0));
+    }
+
+    // Puts the highlight back on `selected` if it is still listed, or on the process from
+    // before filtering once the filters are all cleared. Otherwise it keeps its row, moved up
+    // if the list got shorter than that. The table scrolls only as far as it needs to show it.
+    fn reselect(&mut self, selected: Option<Pid>) {
+        let filtered = !self.search_query.is_empty() || self.user_filter.is_some() || self.state_filter.is_some();
+        let wanted = match (self.filtered, filtered) {
+            (false, true) => {
+                self.unfiltered_pid = selected;
+                selected
+            },
+            (true, false) => self.unfiltered_pid.take().filter(|&pid| self.visible.iter().any(|&i| self.processes[i].pid == pid)).or(selected),
+            _ => selected,
+        };
+        self.filtered = filtered;
+        self.hidden_pid = None;
+        let Some(row) = self.table_state.selected() else { return };
+        let found = wanted.and_then(|pid| self.visible.iter().position(|&i| self.processes[i].pid == pid));
+        match found {
+            Some(found) => self.table_state.select(Some(found)),
+            None if self.visible.is_empty() => self.hidden_pid = wanted,
+            None => self.table_state.select(Some(row.min(self.visible.len() - 1))),
+        }
+    }
+
+    fn selected_pid(&self) -> Option<Pid> {
+        match self.selected_process() {
+            Some(p) => Some(p.pid),
+            None => self.hidden_pid,
+        }
+    }
+
+    // While searching, plain characters and the editing keys change the query and only other
+    // keys (Up and Down, other ctrl combinations, F keys) go through the keymap
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        log::trace!("key {:?} with {:?}", key.code, key.modifiers);
+        self.dirty = true;
+        self.status = None;
+        if self.modal_open() {
+            return self.modal_key(key);
+        }
+        match key.code {
+            KeyCode::Esc => {
+                self.searching = false;
+                self.search_query.clear();
+                self.search_inverted = false;
+                self.user_filter = None;
+                self.update_view();
+                return;
+            },
+            // Completes the word at the end, so only with the cursor there
+            KeyCode::Tab if self.searching => {
+                if self.search_query.split().1.is_empty() && let Some(completed) = query::complete(self.search_query.as_str()) {
+                    self.search_query = LineEditor::new(completed);
+                    self.update_view();
+                }
+                return;
+            },
+            _ if self.searching && self.search_query.handle_key(&key) => {
+                self.update_view();
+                return;
+            },
+            _ => {},
+        }
+        if self.replay.is_some() && let Some(command) = replay_command(key.code) {
+            self.control_replay(command);
+            return;
+        }
+        if let Some(action) = self.keymap.action(&key) {
+            self.perform(action);
+        }
+    }
+
+    // A popup or prompt is open and takes the keys instead of the table. Every one that
+    // handles keys in modal_key has to be here, or a held Up or Down would move the table
+    // underneath it.
+    fn modal_open(&self) -> bool {
+        self.show_help || self.show_alerts || self.show_realtime || self.show_commands || self.history.is_some() || self.connections.is_some() || self.details.is_some()
+            || self.setting_input.is_some() || self.watch_input.is_some() || self.watch_list.is_some() || self.action_log.is_some() || self.message_log.is_some() || self.exit_log.is_some()
+            || self.user_picker.is_some() || self.sort_menu.is_some() || self.listeners.is_some() || self.logins.is_some()
+    }
+
+    // A key for whichever of them is open. The popups that only show something close on any key.
+    fn modal_key(&mut self, key: KeyEvent) {
+        if self.show_help || self.show_alerts || self.show_realtime || self.show_commands || self.history.is_some() || self.connections.is_some() || self.details.is_some() {
+            self.show_help = false;
+            self.show_alerts = false;
+            self.show_realtime = false;
+            self.show_commands = false;
+            self.history = None;
+            self.connections = None;
+            self.details = None;
+            return;
+        }
+        if self.setting_input.is_some() {
+            self.type_setting(key);
+            return;
+        }
+        if self.watch_input.is_some() {
+            self.type_watch(key);
+            return;
+        }
+        if self.watch_list.is_some() {
+            self.edit_watch_list(key.code);
+            return;
+        }
+        if self.action_log.is_some() {
+            self.scroll_action_log(key.code);
+            return;
+        }
+        if let Some(back) = self.message_log {
+            self.message_log = scroll_log(back, self.messages.len(), key.code);
+            return;
+        }
+        if let Some(back) = self.exit_log {
+            self.exit_log = scroll_log(back, self.exits.len(), key.code);
+            return;
+        }
+        if self.user_picker.is_some() {
+            self.pick_user(key.code);
+            return;
+        }
+        if self.sort_menu.is_some() {
+            self.pick_sort(key.code);
+            return;
+        }
+        if self.listeners.is_some() {
+            self.pick_listener(key.code);
+            return;
+        }
+        if self.logins.is_some() {
+            self.pick_login(key.code);
+        }
+    }
+
+    // A held key comes in faster than frames are drawn, so the main loop hands over the
+    // repeats waiting since the last frame together. Up and Down on the table move by all of
+    // them at once; anything else, or the same keys in a popup or prompt, is handled a press
+    // at a time.
+    pub fn handle_repeated_key(&mut self, key: KeyEvent, times: usize) {
+        let on_table = !self.modal_open();
+        let rows = match self.keymap.action(&key) {
+            Some(Action::Up) => -(times as isize),
+            Some(Action::Down) => times as isize,
+            _ => 0,
+        };
+        if times > 1 && on_table && rows != 0 {
+            self.dirty = true;
+            self.status = None;
+            return self.move_selection(rows);
+        }
+        for _ in 0..times {
+            self.handle_key(key);
+        }
+    }
+
+    // Bracketed paste delivers the pasted text as one event, so it goes into the prompt being
+    // typed in as a whole and none of it reaches the keymap. Line breaks, tabs and other control
+    // characters are dropped; outside a prompt the paste is ignored.
+    pub fn paste(&mut self, text: &str) {
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        self.dirty = true;
+        if let Some(input) = &mut self.setting_input {
+            input.text.insert(&text);
+        } else if let Some(input) = &mut self.watch_input {
+            input.insert(&text);
+        } else if self.searching && !self.modal_open() {
+            self.search_query.insert(&text);
+            self.update_view();
+        }
+    }
+
+    pub fn perform(&mut self, action: Action) {
+        match action {
+            // Raw mode delivers Ctrl+C as a key rather than SIGINT, so it is bound to quit by default
+            Action::Quit => self.quit = true,
+            Action::Suspend => self.suspend = true,
+            Action::Help => self.show_help = true,
+            Action::Search => {
+                self.searching = true;
+                self.search_query.clear();
+                self.update_view();
+            },
+            Action::InvertSearch => {
+                self.search_inverted = !self.search_inverted;
+                self.update_view();
+            },
+            Action::FilterUser => self.open_user_picker(),
+            Action::FilterState => {
+                self.state_filter = StateFilter::next(self.state_filter);
+                self.update_view();
+            },
+            Action::SortCpu => {
+                self.sort_by = SortBy::Cpu;
+                self.update_view();
+            },
+            Action::SortMem => {
+                self.sort_by = SortBy::Mem;
+                self.update_view();
+            },
+            Action::SortName => {
+                self.sort_by = SortBy::Name;
+                self.update_view();
+            },
+            Action::SortFds => {
+                self.sort_by = SortBy::Fds;
+                self.update_view();
+            },
+            Action::SortSwap => {
+                self.sort_by = SortBy::Swap;
+                self.update_view();
+            },
+            Action::SortIvcsw => {
+                self.sort_by = SortBy::Ivcsw;
+                self.update_view();
+            },
+            Action::SortMajflt => {
+                self.sort_by = SortBy::Majflt;
+                self.update_view();
+            },
+            Action::ReverseSort => {
+                self.descending = !self.descending;
+                self.update_view();
+            },
+            Action::SortMenu => self.open_sort_menu(),
+            Action::CycleNameDisplay => self.name_display = self.name_display.next(),
+            Action::ToggleTree => {
+                self.tree_view = !self.tree_view;
+                self.update_view();
+            },
+            Action::ToggleTreeTotals => {
+                self.tree_totals = !self.tree_totals;
+                if !self.tree_view {
+                    self.set_status(StatusKind::Info, format!("Subtree totals {}; they show in tree view", if self.tree_totals { "on" } else { "off" }));
+                }
+                self.update_view();
+            },
+            Action::ToggleKernelThreads => {
+                self.hide_kernel_threads = !self.hide_kernel_threads;
+                self.update_view();
+            },
+            Action::ToggleIdle => {
+                self.hide_idle = !self.hide_idle;
+                self.update_view();
+            },
+            Action::ToggleSelf => {
+                self.hide_self = !self.hide_self;
+                self.update_view();
+            },
+            // A replay has its own play/pause, which also moves its clock
+            Action::Pause if self.replay.is_some() => self.control_replay(ReplayCommand::TogglePlay),
+            Action::Pause => self.paused = !self.paused,
+            Action::ResetNetwork => {
+                self.net.reset();
+                self.set_status(StatusKind::Info, "Network peaks and session totals reset");
+            },
+            Action::ToggleMemoryDetail => self.memory_detail = !self.memory_detail,
+            Action::ResetView => {
+                self.reset_view();
+                self.set_status(StatusKind::Info, format!("View reset: no filters, sorted by {}, tree view and footer off", self.sort_by.name()));
+            },
+            Action::ToggleFooter => {
+                self.footer = match self.footer {
+                    Some(_) => None,
+                    None => Some(Aggregate::default()),
+                };
+                self.update_view();
+            },
+            Action::SaveSettings => self.save_settings(),
+            Action::Export => self.export(),
+            Action::ToggleAlerts => {
+                self.alerts.enabled = !self.alerts.enabled;
+                let text = match (self.alerts.enabled, self.alerts.len()) {
+                    (_, 0) => "No alert rules are configured".to_string(),
+                    (true, n) => format!("Alerts on ({} rules)", n),
+                    (false, _) => "Alerts off".to_string(),
+                };
+                self.set_status(StatusKind::Info, text);
+            },
+            Action::Alerts => match self.alerts.len() {
+                0 => self.set_status(StatusKind::Info, "No alert rules are configured"),
+                _ => self.show_alerts = true,
+            },
+            Action::Realtime => match self.boosted().len() {
+                _ if !self.realtime_badge => self.set_status(StatusKind::Info, "Scheduling policies aren't read while realtime_badge is off"),
+                0 => self.set_status(StatusKind::Info, "No process is real-time or at a negative nice"),
+                _ => self.show_realtime = true,
+            },
+            Action::CommandLog => match self.commands.log.len() {
+                0 => self.set_status(StatusKind::Info, "No alert commands have run yet"),
+                _ => self.show_commands = true,
+            },
+            Action::Watch => self.watch_input = Some(LineEditor::default()),
+            Action::Watches => match self.watches.len() {
+                0 => self.set_status(StatusKind::Info, "No watches yet; 'w' adds one"),
+                _ => self.watch_list = Some(0),
+            },
+            Action::ActionLog => match self.actions.results().len() {
+                0 => self.set_status(StatusKind::Info, "No processes have been changed yet"),
+                _ => self.action_log = Some(0),
+            },
+            // Opens empty rather than saying so in a status that would then be in it
+            Action::Messages => self.message_log = Some(0),
+            Action::ExitLog => match self.exits.len() {
+                _ if self.config.exit_log_size == 0 => self.set_status(StatusKind::Info, "Exits aren't logged while exit_log_size is 0"),
+                0 => self.set_status(StatusKind::Info, "No processes have exited since rust-htop started"),
+                _ => self.exit_log = Some(0),
+            },
+            Action::History => match self.selected_process() {
+                Some(_) => self.history_wanted = true,
+                None => self.set_status(StatusKind::Info, "Select a process to see its history"),
+            },
+            Action::Connections => match self.selected_process() {
+                Some(p) => self.show_connections(ProcessHandle::of(p), p.name.clone()),
+                None => self.set_status(StatusKind::Info, "Select a process to see its connections"),
+            },
+            Action::Listeners => self.show_listeners(0),
+            Action::Logins => self.show_logins(0),
+            Action::Details => match self.selected_process() {
+                Some(p) => {
+                    let details = self.source.details(p.pid);
+                    self.details = Some(DetailsView { process: ProcessHandle::of(p), name: p.name.clone(), parent: p.parent, user: p.user.clone(), state: p.state, fds: p.fds, details });
+                    // A process selected since the last refresh has no trail yet
+                    self.record_trails();
+                },
+                None => self.set_status(StatusKind::Info, "Select a process to see its details"),
+            },
+            Action::SetAffinity => self.edit_setting(Setting::Affinity),
+            Action::SetIoPriority => self.edit_setting(Setting::IoPriority),
+            Action::Up => self.move_selection(-1),
+            Action::Down => self.move_selection(1),
+            Action::Top => self.table_state.select((!self.visible.is_empty()).then_some(0)),
+            Action::Bottom => self.table_state.select(self.visible.len().checked_sub(1)),
+            Action::TogglePerf => self.perf.set_enabled(!self.perf.enabled()),
+        }
+    }
+
+    // Whether the process a popup or prompt was opened for is gone from the latest refresh,
+    // having exited or left its PID to another
+    pub fn has_exited(&self, process: &ProcessHandle) -> bool {
+        process.resolve(&self.processes).is_none()
+    }
+
+    // A process that can't be read closes the popup with the reason
+    fn show_connections(&mut self, process: ProcessHandle, name: String) {
+        let pid = process.pid;
+        match self.source.connections(pid) {
+            Ok(list) => self.connections = Some(ConnectionsView { process, name, list, updated: Instant::now() }),
+            Err(e) => {
+                self.connections = None;
+                self.set_status(StatusKind::Warning, format!("Can't list the connections of {} ({}): {}", name, pid, e));
+            },
+        }
+    }
+
+    // The highlight stays on `row` across re-reads, as far down as the list still goes
+    fn show_listeners(&mut self, row: usize) {
+        match self.source.listeners() {
+            Ok(list) if list.is_empty() => {
+                self.listeners = None;
+                self.set_status(StatusKind::Info, "No sockets are listening");
+            },
+            Ok(list) => self.listeners = Some(ListenersView { row: row.min(list.len() - 1), list, updated: Instant::now() }),
+            Err(e) => {
+                self.listeners = None;
+                self.set_status(StatusKind::Warning, format!("Can't list the listening sockets: {}", e));
+            },
+        }
+    }
+
+    // Up and Down move, Enter selects the process holding the highlighted socket, and anything
+    // else closes the panel
+    fn pick_listener(&mut self, code: KeyCode) {
+        let Some(view) = &mut self.listeners else { return };
+        match code {
+            KeyCode::Up => view.row = view.row.saturating_sub(1),
+            KeyCode::Down => view.row = (view.row + 1).min(view.list.len() - 1),
+            KeyCode::Enter => {
+                let listener = self.listeners.take().map(|mut view| view.list.swap_remove(view.row));
+                match listener.and_then(|l| l.pid) {
+                    Some(pid) => self.select_pid(Pid::from_u32(pid)),
+                    None => self.set_status(StatusKind::Info, "The process holding that socket can't be seen; try running as root"),
+                }
+            },
+            _ => self.listeners = None,
+        }
+    }
+
+    fn show_logins(&mut self, row: usize) {
+        match self.source.logins() {
+            Ok(list) if list.is_empty() => {
+                self.logins = None;
+                self.set_status(StatusKind::Info, "Nobody is logged in");
+            },
+            Ok(list) => self.logins = Some(LoginsView { row: row.min(list.len() - 1), list, updated: Instant::now() }),
+            Err(e) => {
+                self.logins = None;
+                self.set_status(StatusKind::Warning, format!("Can't list who is logged in: {}", e));
+            },
+        }
+    }
+
+    // Up and Down move, Enter filters on the highlighted session's user, and anything else
+    // closes the panel
+    fn pick_login(&mut self, code: KeyCode) {
+        let Some(view) = &mut self.logins else { return };
+        match code {
+            KeyCode::Up => view.row = view.row.saturating_sub(1),
+            KeyCode::Down => view.row = (view.row + 1).min(view.list.len() - 1),
+            KeyCode::Enter => {
+                self.user_filter = self.logins.take().map(|mut view| view.list.swap_remove(view.row).user);
+                self.update_view();
+            },
+            _ => self.logins = None,
+        }
+    }
+
+    // "rust-htop — dbserver01 [postgres]"; `host` is export::host, worked out once
+    pub fn terminal_title(&self, host: &str) -> String {
+        let mut title = format!("rust-htop — {}", host);
+        let mut filters = Vec::new();
+        if !self.search_query.is_empty() {
+            filters.push(format!("{}{}", if self.search_inverted { "not " } else { "" }, self.search_query.as_str()));
+        }
+        if let Some(user) = &self.user_filter {
+            filters.push(format!("user {}", user));
+        }
+        if !filters.is_empty() {
+            title.push_str(&format!(" [{}]", filters.join(", ")));
+        }
+        title
+    }
+
+    // Moves the highlight to `pid`, clearing the search, user and state filters if they hide it
+    fn select_pid(&mut self, pid: Pid) {
+        let row = |app: &App| app.visible.iter().position(|&i| app.processes[i].pid == pid);
+        if row(self).is_none() && self.processes.iter().any(|p| p.pid == pid) {
+            self.searching = false;
+            self.search_query.clear();
+            self.search_inverted = false;
+            self.user_filter = None;
+            self.state_filter = None;
+            self.update_view();
+        }
+        match row(self) {
+            Some(row) => self.table_state.select(Some(row)),
+            None => self.set_status(StatusKind::Info, format!("Process {} isn't listed; it may have exited or be hidden", pid)),
+        }
+    }
+
+    // Shows the new position right away instead of at the next refresh
+    fn control_replay(&mut self, command: ReplayCommand) {
+        self.source.control(command);
+        self.update_snapshot();
+    }
+
+    // The line editor has the pattern; Enter adds it and Esc drops it
+    fn type_watch(&mut self, key: KeyEvent) {
+        let Some(input) = &mut self.watch_input else { return };
+        if input.handle_key(&key) {
+            return;
+        }
+        match key.code {
+            KeyCode::Tab => {
+                if input.split().1.is_empty() && let Some(completed) = query::complete(input.as_str()) {
+                    *input = LineEditor::new(completed);
+                }
+            },
+            KeyCode::Enter => {
+                let pattern = input.as_str().trim().to_string();
+                self.watch_input = None;
+                if pattern.is_empty() {
+                    return;
+                }
+                if self.watches.contains(&pattern) {
+                    self.set_status(StatusKind::Info, format!("Already watching {:?}", pattern));
+                    return;
+                }
+                self.watches.push(pattern);
+                self.update_view();
+                let count = self.watched.iter().filter(|&&w| w).count();
+                self.set_status(StatusKind::Info, format!("Watching {:?}: {} processes match now", self.watches.last().unwrap(), count));
+            },
+            KeyCode::Esc => self.watch_input = None,
+            _ => {},
+        }
+    }
+
+    // The prompt starts from the current value, or says why the setting can't be changed
+    fn edit_setting(&mut self, setting: Setting) {
+        let Some(p) = self.selected_process() else {
+            return self.set_status(StatusKind::Info, format!("Select a process to change its {}", setting.label()));
+        };
+        let (process, name) = (ProcessHandle::of(p), p.name.clone());
+        let pid = process.pid;
+        // Asking for a value for a process that has gone would only end in an error
+        if !self.source.exists(pid) {
+            return self.record_action(pid, name, setting, Err(ActionError::Gone));
+        }
+        let details = self.source.details(pid);
+        let current = match setting {
+            Setting::Affinity => details.affinity.map(|cpus| cpus.map(|cpus| sched::format_cpu_list(&cpus))),
+            Setting::IoPriority => details.io_priority.map(|io| io.map(|io| io.effective(details.nice.unwrap_or(0)).to_string())),
+        };
+        match current {
+            Some(Ok(text)) => self.setting_input = Some(SettingInput { setting, process, name, text: LineEditor::new(text) }),
+            Some(Err(e)) => self.record_action(pid, name, setting, Err(ActionError::Failed(format!("can't read the current one: {}", e)))),
+            None => self.record_action(pid, name, setting, Err(ActionError::Unsupported)),
+        }
+    }
+
+    fn record_action(&mut self, pid: Pid, name: String, setting: Setting, outcome: Result<String, ActionError>) {
+        self.actions.push(ActionResult { time: chrono::Local::now(), pid, name, what: setting.label(), outcome }, Instant::now());
+        self.dirty = true;
+    }
+
+    // Checked every tick, so a toast stays up for TOAST_TIME rounded up to the tick rate
+    pub fn expire_toasts(&mut self, now: Instant) {
+        if self.actions.expire(now) {
+            self.dirty = true;
+        }
+    }
+
+    // Enter applies the value to the process, Esc leaves it as it was
+    fn type_setting(&mut self, key: KeyEvent) {
+        let Some(input) = &mut self.setting_input else { return };
+        if input.text.handle_key(&key) {
+            return;
+        }
+        match key.code {
+            KeyCode::Enter => {
+                let Some(SettingInput { setting, process, name, text }) = self.setting_input.take() else { return };
+                let pid = process.pid;
+                // Exited while the value was typed, or exited and its PID went to another
+                // process, which mustn't get the new value instead
+                if self.has_exited(&process) || !self.source.exists(pid) {
+                    return self.record_action(pid, name, setting, Err(ActionError::Gone));
+                }
+                let outcome = match setting {
+                    Setting::Affinity => sched::parse_cpu_list(text.as_str(), self.totals.cpus.len()).map_err(ActionError::Invalid)
+                        .and_then(|cpus| self.source.set_affinity(pid, &cpus).map(|()| sched::format_cpu_list(&cpus))),
+                    Setting::IoPriority => sched::parse_io_priority(text.as_str()).map_err(ActionError::Invalid)
+                        .and_then(|io| self.source.set_io_priority(pid, io).map(|()| io.to_string())),
+                };
+                self.record_action(pid, name, setting, outcome);
+            },
+            KeyCode::Esc => self.setting_input = None,
+            _ => {},
+        }
+    }
+
+    fn scroll_action_log(&mut self, code: KeyCode) {
+        let Some(back) = self.action_log else { return };
+        self.action_log = scroll_log(back, self.actions.results().len(), code);
+    }
+
+    // Up and Down pick a watch, Delete or d removes it, and anything else closes the list
+    fn edit_watch_list(&mut self, code: KeyCode) {
+        let Some(row) = self.watch_list else { return };
+        match code {
+            KeyCode::Up => self.watch_list = Some(row.saturating_sub(1)),
+            KeyCode::Down => self.watch_list = Some((row + 1).min(self.watches.len().saturating_sub(1))),
+            KeyCode::Delete | KeyCode::Backspace | KeyCode::Char('d') => {
+                if row < self.watches.len() {
+                    self.watches.remove(row);
+                    self.update_view();
+                }
+                self.watch_list = match self.watches.len() {
+                    0 => None,
+                    n => Some(row.min(n - 1)),
+                };
+            },
+            _ => self.watch_list = None,
+        }
+    }
+
+    // Starts on the column being sorted by
+    fn open_sort_menu(&mut self) {
+        let columns: Vec<Column> = Column::ALL.into_iter().filter(|&c| self.source.has_column(c)).collect();
+        let row = columns.iter().position(|c| c.sort() == self.sort_by).unwrap_or(0);
+        self.sort_menu = Some(SortMenu { columns, row });
+    }
+
+    // Enter sorts by the highlighted column, or the other way round if it already is
+    fn pick_sort(&mut self, code: KeyCode) {
+        let Some(menu) = &mut self.sort_menu else { return };
+        match code {
+            KeyCode::Up => menu.row = menu.row.saturating_sub(1),
+            KeyCode::Down => menu.row = (menu.row + 1).min(menu.columns.len() - 1),
+            KeyCode::Enter => {
+                let sort = menu.columns[menu.row].sort();
+                self.sort_menu = None;
+                if sort == self.sort_by {
+                    self.descending = !self.descending;
+                } else {
+                    self.sort_by = sort;
+                }
+                self.update_view();
+            },
+            _ => self.sort_menu = None,
+        }
+    }
+
+    // Starts on the user being filtered on, if any
+    fn open_user_picker(&mut self) {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for user in self.processes.iter().filter_map(|p| p.user.as_deref()) {
+            *counts.entry(user).or_default() += 1;
+        }
+        let mut users: Vec<(String, usize)> = counts.into_iter().map(|(user, n)| (user.to_string(), n)).collect();
+        users.sort();
+        if users.is_empty() {
+            return self.set_status(StatusKind::Info, "No process has a known user");
+        }
+        let row = self.user_filter.as_ref().and_then(|filter| users.iter().position(|(user, _)| user == filter)).unwrap_or(0);
+        self.user_picker = Some(UserPicker { users, row });
+    }
+
+    // Up and Down move, Enter filters on the highlighted user, and anything else closes the list
+    fn pick_user(&mut self, code: KeyCode) {
+        let Some(picker) = &mut self.user_picker else { return };
+        match code {
+            KeyCode::Up => picker.row = picker.row.saturating_sub(1),
+            KeyCode::Down => picker.row = (picker.row + 1).min(picker.users.len() - 1),
+            KeyCode::Enter => {
+                self.user_filter = self.user_picker.take().map(|mut picker| picker.users.swap_remove(picker.row).0);
+                self.update_view();
+            },
+            _ => self.user_picker = None,
+        }
+    }
+
+    // Commands that finished since the last call. A failure stays on the status line until
+    // dismissed; the rest are in the log popup.
+    pub fn poll_commands(&mut self) -> Vec<Run> {
+        let finished = self.commands.poll();
+        for run in &finished {
+            if run.state != RunState::Exited(Some(0)) {
+                self.set_status(StatusKind::Warning, format!("Command for {:?}: {}", run.rule, exec::describe(&run.state)));
+            }
+        }
+        if !finished.is_empty() {
+            self.dirty = true;
+        }
+        finished
+    }
+
+    // A bell alert: the bell, unless `quiet` is set, and a short flash of the table header
+    pub fn ring(&mut self, now: Instant) {
+        self.bell = !self.config.quiet;
+        self.flash_until = Some(now + FLASH_TIME);
+        self.dirty = true;
+    }
+
+    // Checked every tick, so the flash lasts FLASH_TIME rounded up to the tick rate
+    pub fn expire_flash(&mut self, now: Instant) {
+        if self.flash_until.is_some_and(|until| now >= until) {
+            self.flash_until = None;
+            self.dirty = true;
+        }
+    }
+
+    // Every status goes into the message log too, so one that was replaced or timed out
+    // before it was read can still be looked up
+    pub fn set_status(&mut self, kind: StatusKind, text: impl Into<String>) {
+        let status = Status { kind, text: text.into(), time: chrono::Local::now() };
+        self.messages.push_back(status.clone());
+        if self.messages.len() > MESSAGE_LOG {
+            self.messages.pop_front();
+        }
+        self.status = Some(status);
+        self.status_until = (self.config.status_seconds > 0).then(|| Instant::now() + Duration::from_secs(self.config.status_seconds));
+        self.dirty = true;
+    }
+
+    // Checked every tick, so a status lasts status_seconds rounded up to the tick rate
+    pub fn expire_status(&mut self, now: Instant) {
+        if self.status_until.is_some_and(|until| now >= until) {
+            self.status = None;
+            self.status_until = None;
+            self.dirty = true;
+        }
+    }
+
+    // The loaded config with the interactively changed settings applied
+    pub fn current_config(&self) -> Config {
+        let mut config = self.config.clone();
+        config.refresh_rate = self.refresh_rate.as_millis() as u64;
+        config.default_sort = self.sort_by.name().to_string();
+        config.name_display = self.name_display.name().to_string();
+        config.descending = self.descending;
+        config.tree_view = self.tree_view;
+        config.tree_totals = self.tree_totals;
+        config.memory_detail = self.memory_detail;
+        config.show_footer = self.footer.is_some();
+        config.show_kernel_threads = !self.hide_kernel_threads;
+        config.hide_idle = self.hide_idle;
+        config.hide_self = self.hide_self;
+        config.watches = self.watches.clone();
+        config
+    }
+
+    // What W writes: the file's settings, with those changed in the interface since startup or
+    // the last save or reload. A flag or RUST_HTOP_* variable given for one run (--paused,
+    // --filter, a theme) is only in `config` and so isn't saved. Comparing against `config`
+    // keeps a view setting a flag picked, such as --tree, out too, unless it was changed since.
+    pub fn saved_config(&self) -> Config {
+        let (started, now) = (&self.config, self.current_config());
+        let mut saved = self.file_config.clone();
+        if now.refresh_rate != started.refresh_rate {
+            saved.refresh_rate = now.refresh_rate;
+        }
+        if now.default_sort != started.default_sort {
+            saved.default_sort = now.default_sort.clone();
+        }
+        if now.descending != started.descending {
+            saved.descending = now.descending;
+        }
+        if now.name_display != started.name_display {
+            saved.name_display = now.name_display.clone();
+        }
+        if now.tree_view != started.tree_view {
+            saved.tree_view = now.tree_view;
+        }
+        if now.tree_totals != started.tree_totals {
+            saved.tree_totals = now.tree_totals;
+        }
+        if now.memory_detail != started.memory_detail {
+            saved.memory_detail = now.memory_detail;
+        }
+        if now.show_footer != started.show_footer {
+            saved.show_footer = now.show_footer;
+        }
+        if now.show_kernel_threads != started.show_kernel_threads {
+            saved.show_kernel_threads = now.show_kernel_threads;
+        }
+        if now.hide_idle != started.hide_idle {
+            saved.hide_idle = now.hide_idle;
+        }
+        if now.hide_self != started.hide_self {
+            saved.hide_self = now.hide_self;
+        }
+        if now.watches != started.watches {
+            saved.watches = now.watches.clone();
+        }
+        saved
+    }
+
+    // Apply a reloaded config without touching transient state like the search query and
+    // selection. Sort settings only follow the file when the file's value changed, so an
+    // unrelated edit doesn't undo a sort picked interactively.
+    pub fn apply_config(&mut self, new: Config) {
+        self.refresh_rate = Duration::from_millis(new.refresh_rate);
+        self.keymap = Keymap::from_config(&new.keys).0;
+        self.theme = Theme::resolve(&new.theme).0;
+        self.columns = shown_columns(&new.columns, self.source.as_ref());
+        // Changed rules start from scratch; unchanged ones keep what they have seen
+        if new.alerts != self.config.alerts {
+            let enabled = self.alerts.enabled;
+            self.alerts = Alerts::from_config(&new.alerts).0;
+            self.alerts.enabled = enabled;
+        }
+        if new.watches != self.config.watches {
+            self.watches = new.watches.clone();
+        }
+        self.exits.set_limit(new.exit_log_size);
+        if new.default_sort != self.config.default_sort && let Some(sort_by) = SortBy::from_name(&new.default_sort) {
+            self.sort_by = sort_by;
+        }
+        if new.name_display != self.config.name_display && let Some(display) = NameDisplay::from_name(&new.name_display) {
+            self.name_display = display;
+        }
+        if new.descending != self.config.descending {
+            self.descending = new.descending;
+        }
+        if new.tree_view != self.config.tree_view {
+            self.tree_view = new.tree_view;
+        }
+        if new.tree_totals != self.config.tree_totals {
+            self.tree_totals = new.tree_totals;
+        }
+        if new.memory_detail != self.config.memory_detail {
+            self.memory_detail = new.memory_detail;
+        }
+        if new.show_footer != self.config.show_footer {
+            self.footer = new.show_footer.then(Aggregate::default);
+        }
+        if new.show_kernel_threads != self.config.show_kernel_threads {
+            self.hide_kernel_threads = !new.show_kernel_threads;
+        }
+        if new.hide_idle != self.config.hide_idle {
+            self.hide_idle = new.hide_idle;
+        }
+        if new.hide_self != self.config.hide_self {
+            self.hide_self = new.hide_self;
+        }
+        self.config = new;
+        self.update_view();
+        self.dirty = true;
+    }
+
+    fn save_settings(&mut self) {
+        let path = self.config_path.clone().unwrap_or_else(config::default_path);
+        let saved = self.saved_config();
+        match config::save(&saved, &path, self.profile.as_deref()) {
+            Ok(()) => {
+                self.file_config = saved;
+                self.config = self.current_config();
+                self.set_status(StatusKind::Info, format!("Settings saved to {}", path.display()));
+                self.config_path = Some(path);
+            },
+            Err(e) => self.set_status(StatusKind::Error, format!("Could not save settings to {}: {}", path.display(), e)),
+        }
+    }
+
+    fn export(&mut self) {
+        let dir = if self.config.export_dir.is_empty() { PathBuf::from(".") } else { PathBuf::from(&self.config.export_dir) };
+        match export::write(self, &dir, chrono::Local::now()) {
+            Ok(path) => self.set_status(StatusKind::Info, format!("Exported {} processes to {}", self.visible.len(), path.display())),
+            Err(e) => self.set_status(StatusKind::Error, format!("Could not export to {}: {}", dir.display(), e)),
+        }
+    }
+
+    // The process under the highlighted row
+    pub fn selected_process(&self) -> Option<&ProcInfo> {
+        let row = self.table_state.selected()?;
+        self.visible.get(row).map(|&i| &self.processes[i])
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        self.table_state.select(step_selection(self.table_state.selected(), delta, self.visible.len(), self.config.wrap_navigation));
+    }
+}
+
+// The configured columns the source can fill. Reading them from /proc is Linux only, and a
+// table of dashes elsewhere says nothing, so those are left out; a list with nothing left
+// shows the default columns.
+fn shown_columns(names: &[String], source: &dyn SystemSource) -> Vec<Column> {
+    let (columns, _) = Column::resolve(names);
+    let shown: Vec<Column> = columns.into_iter().filter(|&c| source.has_column(c)).collect();
+    match shown.is_empty() {
+        true => Column::resolve(&[]).0,
+        false => shown,
+    }
+}
+
+// Scrolling a log popup `back` entries from the newest of `len`: Up and Down go back and
+// forth one at a time, PageUp and PageDown by ten, Home and End to the newest and the oldest,
+// and anything else closes it
+fn scroll_log(back: usize, len: usize, code: KeyCode) -> Option<usize> {
+    let oldest = len.saturating_sub(1);
+    match code {
+        KeyCode::Down => Some((back + 1).min(oldest)),
+        KeyCode::Up => Some(back.saturating_sub(1)),
+        KeyCode::PageDown => Some((back + 10).min(oldest)),
+        KeyCode::PageUp => Some(back.saturating_sub(10)),
+        KeyCode::Home => Some(0),
+        KeyCode::End => Some(oldest),
+        _ => None,
+    }
+}
+
+// The row `delta` rows on from `selected` among `rows`, stopping at the ends or, with `wrap`,
+// going round to the other one. Nothing is selected in an empty list.
+pub fn step_selection(selected: Option<usize>, delta: isize, rows: usize, wrap: bool) -> Option<usize> {
+    if rows == 0 {
+        return None;
+    }
+    let i = selected.unwrap_or(0).min(rows - 1) as isize + delta;
+    Some(if wrap { i.rem_euclid(rows as isize) } else { i.clamp(0, rows as isize - 1) } as usize)
+}
+
+// The fixed playback keys; they only apply while replaying
+fn replay_command(code: KeyCode) -> Option<ReplayCommand> {
+    match code {
+        KeyCode::Char(' ') => Some(ReplayCommand::TogglePlay),
+        KeyCode::Left => Some(ReplayCommand::Step(-1)),
+        KeyCode::Right => Some(ReplayCommand::Step(1)),
+        KeyCode::Char('<') => Some(ReplayCommand::Slower),
+        KeyCode::Char('>') => Some(ReplayCommand::Faster),
+        _ => None,
+    }
+}
+
+// One line per part: the search can't type a newline, so a query never spans two of them
+fn search_text(name: &str, exe: Option<&str>, cmd: &[String]) -> String {
+    let mut text = name.to_lowercase();
+    for part in exe.into_iter().chain([cmd.join(" ").as_str()]) {
+        text.push('\n');
+        text.push_str(&part.to_lowercase());
+    }
+    text
+}
+
+pub fn format_cpu(cpu: f32) -> String {
+    format!("{:.2}%", cpu)
+}
+
+// sysinfo reports process memory in bytes
+pub fn format_mem(mem: u64) -> String {
+    format!("{:.2} MB", mem as f64 / (1024.0 * 1024.0))
+}
+
+// "-" when it wasn't read: not Linux, or no permission
+pub fn format_swap(swap: Option<u64>) -> String {
+    swap.map_or_else(|| "-".to_string(), format_mem)
+}
+
+// "850/s", "12.4k/s", "1.2M/s"; "-" before the second reading
+pub fn format_rate(rate: Option<f64>) -> String {
+    match rate {
+        None => "-".to_string(),
+        Some(r) if r < 1000.0 => format!("{:.0}/s", r),
+        Some(r) if r < 1_000_000.0 => format!("{:.1}k/s", r / 1000.0),
+        Some(r) => format!("{:.1}M/s", r / 1_000_000.0),
+    }
+}
+
+// Context switches since the previous refresh; "-" before the second reading
+pub fn format_count(count: Option<u64>) -> String {
+    count.map_or_else(|| "-".to_string(), |n| n.to_string())
+}
+
+// The growth of a running total. A total that went down belongs to a new process.
+fn delta(before: Option<u64>, after: Option<u64>) -> Option<u64> {
+    after?.checked_sub(before?)
+}
+
+// How long the system or a process has been up: "47 min", "6 h 12 m", "2 days 3 h", and from
+// a week on only the days, "13 days"
+pub fn format_duration(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60);
+    let plural = if days == 1 { "" } else { "s" };
+    match secs {
+        0..3600 => format!("{} min", mins),
+        3600..86_400 => format!("{} h {} m", hours, mins),
+        86_400..604_800 => format!("{} day{} {} h", days, plural, hours),
+        _ => format!("{} day{}", days, plural),
+    }
+}
+
+// Bytes scaled by 1024 to one decimal, with `suffix` ("/s" for a rate) after the unit, in a
+// fixed width so the header doesn't shift as the figures change: "   0.0 B/s ", "  12.3 MB/s"
+pub fn format_bytes(bytes: f64, suffix: &str) -> String {
+    let mut value = bytes.max(0.0);
+    let mut unit = 0;
+    while value >= 1023.95 && unit < BYTE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:>6.1} {:<width$}", value, format!("{}{}", BYTE_UNITS[unit], suffix), width = 2 + suffix.len())
+}
+
+const BYTE_UNITS: [&str; 7] = ["B", "KB", "MB", "GB", "TB", "PB", "EB"];
+
+// "-" when the descriptors weren't counted: no permission, not Linux, or not counted yet. Also
+// used for the socket counts.
+pub fn format_fds(fds: Option<u32>) -> String {
+    fds.map_or_else(|| "-".to_string(), |n| n.to_string())
+}
+
+pub fn format_core(processor: Option<usize>) -> String {
+    processor.map_or_else(|| "-".to_string(), |n| n.to_string())
+}
+
+// Update `procs` in place from the latest samples: known PIDs keep their allocations,
+// new PIDs are appended and exited ones are dropped.
+pub fn merge_processes<'a>(procs: &mut Vec<ProcInfo>, samples: impl IntoIterator<Item = ProcSample<'a>>) {
+    let known: HashMap<Pid, usize> = procs.iter().enumerate().map(|(i, p)| (p.pid, i)).collect();
+    let mut seen = vec![false; procs.len()];
+    for sample in samples {
+        match known.get(&sample.pid) {
+            Some(&i) => {
+                procs[i].update(&sample);
+                seen[i] = true;
+            },
+            None => {
+                procs.push(ProcInfo::new(&sample));
+                seen.push(true);
+            },
+        }
+    }
+    let mut seen = seen.into_iter();
+    procs.retain(|_| seen.next().unwrap_or(false));
+}
+
+// Fill `order` with the indices of processes matching the search query, or with `inverted` the
+// ones it doesn't match. An empty query keeps everything either way.
+pub fn filter_processes(procs: &[ProcInfo], query: &str, inverted: bool, order: &mut Vec<usize>) {
+    order.clear();
+    if query.is_empty() {
+        order.extend(0..procs.len());
+        return;
+    }
+    let query = Query::parse(query);
+    order.extend(procs.iter().enumerate().filter(|(_, p)| query.matches(p) != inverted).map(|(i, _)| i));
+}
+
+// Marks the processes any watch pattern matches. Patterns are parsed once here rather than for
+// every process.
+pub fn watch_processes(procs: &[ProcInfo], watches: &[String], watched: &mut Vec<bool>) {
+    watched.clear();
+    if watches.is_empty() {
+        return;
+    }
+    let queries: Vec<Query> = watches.iter().map(|w| Query::parse(w)).collect();
+    watched.extend(procs.iter().map(|p| queries.iter().any(|q| q.matches(p))));
+}
+
+// Processes without a rate sort below every one with a rate
+fn rate_key(rate: Option<f64>) -> f64 {
+    rate.unwrap_or(f64::NEG_INFINITY)
+}
+
+// Ties are broken by ascending PID regardless of direction so equal rows don't swap places
+// between refreshes. A NaN CPU value sorts below every real value.
+pub fn sort_processes(sort_by: SortBy, descending: bool, procs: &[ProcInfo], order: &mut [usize]) {
+    let cpu_key = |c: f32| if c.is_nan() { f32::NEG_INFINITY } else { c };
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&procs[a], &procs[b]);
+        let primary = match sort_by {
+            SortBy::Pid => a.pid.cmp(&b.pid),
+            SortBy::Cpu => cpu_key(a.cpu).total_cmp(&cpu_key(b.cpu)),
+            SortBy::Mem => a.mem.cmp(&b.mem),
+            SortBy::Name => a.name.cmp(&b.name),
+            // Processes without a count sort below every counted one
+            SortBy::Fds => a.fds.cmp(&b.fds),
+            SortBy::Conn => a.conns.cmp(&b.conns),
+            SortBy::Net => a.net.cmp(&b.net),
+            SortBy::Swap => a.swap.cmp(&b.swap),
+            SortBy::Vcsw => a.vcsw.cmp(&b.vcsw),
+            SortBy::Ivcsw => a.ivcsw.cmp(&b.ivcsw),
+            SortBy::Minflt => rate_key(a.minflt).total_cmp(&rate_key(b.minflt)),
+            SortBy::Majflt => rate_key(a.majflt).total_cmp(&rate_key(b.majflt)),
+            SortBy::Core => a.processor.cmp(&b.processor),
+            // Outside a container sorts below every container
+            SortBy::Container => a.container.cmp(&b.container),
+        };
+        let primary = if descending { primary.reverse() } else { primary };
+        primary.then_with(|| a.pid.cmp(&b.pid))
+    });
+}
+
+// Adds every listed process's CPU and memory to each of its listed ancestors, so a parent's
+// `subtree` covers itself and everything below it; processes without listed children get None
+pub fn subtree_totals(procs: &mut [ProcInfo], order: &[usize]) {
+    let rows: HashMap<Pid, usize> = order.iter().map(|&i| (procs[i].pid, i)).collect();
+    for p in procs.iter_mut() {
+        p.subtree = None;
+    }
+    for &i in order {
+        let (cpu, mem) = (procs[i].cpu, procs[i].mem);
+        let mut at = i;
+        // A parent loop would go round forever; no real chain is longer than the list
+        for _ in 0..order.len() {
+            let Some(&parent) = procs[at].parent.and_then(|parent| rows.get(&parent)) else { break };
+            if parent == i {
+                break;
+            }
+            let own = (procs[parent].cpu, procs[parent].mem);
+            let total = procs[parent].subtree.get_or_insert(own);
+            *total = (total.0 + cpu, total.1 + mem);
+            at = parent;
+        }
+    }
+}
+
+// Orders the rows by subtree CPU or memory rather than each process's own, so tree_order puts
+// the heaviest subtree first among its siblings. Other sort keys don't add up and stay as
+// they are.
+pub fn sort_by_subtree(sort_by: SortBy, descending: bool, procs: &[ProcInfo], order: &mut [usize]) {
+    if !matches!(sort_by, SortBy::Cpu | SortBy::Mem) {
+        return;
+    }
+    let total = |i: usize| procs[i].subtree.unwrap_or((procs[i].cpu, procs[i].mem));
+    let cpu_key = |c: f32| if c.is_nan() { f32::NEG_INFINITY } else { c };
+    order.sort_by(|&a, &b| {
+        let primary = match sort_by {
+            SortBy::Mem => total(a).1.cmp(&total(b).1),
+            _ => cpu_key(total(a).0).total_cmp(&cpu_key(total(b).0)),
+        };
+        let primary = if descending { primary.reverse() } else { primary };
+        primary.then_with(|| procs[a].pid.cmp(&procs[b].pid))
+    });
+}
+
+// Reorder the sorted rows depth-first so every process follows its parent. Siblings keep
+// their sorted order, and a process whose parent isn't among the rows becomes a root.
+pub fn tree_order(procs: &[ProcInfo], order: &mut Vec<usize>, depths: &mut Vec<usize>) {
+    let rows: HashMap<Pid, usize> = order.iter().map(|&i| (procs[i].pid, i)).collect();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    for &i in order.iter() {
+        match procs[i].parent.and_then(|parent| rows.get(&parent)) {
+            Some(&parent) if parent != i => children.entry(parent).or_default().push(i),
+            _ => roots.push(i),
+        }
+    }
+
+    let mut tree = Vec::with_capacity(order.len());
+    depths.clear();
+    let mut placed = HashSet::new();
+    let mut stack: Vec<(usize, usize)> = roots.into_iter().rev().map(|i| (i, 0)).collect();
+    while let Some((i, depth)) = stack.pop() {
+        if !placed.insert(i) {
+            continue;
+        }
+        tree.push(i);
+        depths.push(depth);
+        if let Some(kids) = children.get(&i) {
+            stack.extend(kids.iter().rev().map(|&k| (k, depth + 1)));
+        }
+    }
+    // Parent loops have no root to start from; keep those rows rather than losing them
+    for &i in order.iter() {
+        if placed.insert(i) {
+            tree.push(i);
+            depths.push(0);
+        }
+    }
+    *order = tree;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn procs(data: &[(usize, &str, f32, u64)]) -> Vec<ProcInfo> {
+        data.iter().map(|&(pid, name, cpu, mem)| ProcInfo::new(&ProcSample { cpu, mem, ..sample(pid, name) })).collect()
+    }
+
+    fn sorted_pids(procs: &[ProcInfo], sort_by: SortBy, descending: bool) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..procs.len()).collect();
+        sort_processes(sort_by, descending, procs, &mut order);
+        order.iter().map(|&i| usize::from(procs[i].pid)).collect()
+    }
+
+    fn filtered_pids(procs: &[ProcInfo], query: &str) -> Vec<usize> {
+        let mut order = Vec::new();
+        filter_processes(procs, query, false, &mut order);
+        order.iter().map(|&i| usize::from(procs[i].pid)).collect()
+    }
+
+    #[test]
+    fn sort_by_each_key_and_direction() {
+        let list = procs(&[(1, "bash", 5.0, 300), (2, "Xorg", 50.0, 100), (3, "cron", 0.5, 200)]);
+        let cases = [
+            (SortBy::Cpu, false, vec![3, 1, 2]),
+            (SortBy::Cpu, true, vec![2, 1, 3]),
+            (SortBy::Mem, false, vec![2, 3, 1]),
+            (SortBy::Mem, true, vec![1, 3, 2]),
+            (SortBy::Name, false, vec![2, 1, 3]),
+            (SortBy::Name, true, vec![3, 1, 2]),
+            (SortBy::Pid, true, vec![3, 2, 1]),
+        ];
+        for (sort_by, descending, expected) in cases {
+            assert_eq!(sorted_pids(&list, sort_by, descending), expected);
+        }
+    }
+
+    #[test]
+    fn the_sort_menu_starts_on_the_current_column_and_reverses_it() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::with_processes(&[(1, "a", 5.0, 0), (2, "b", 1.0, 0)])));
+        app.perform(Action::SortMenu);
+        let menu = app.sort_menu.as_ref().unwrap();
+        assert_eq!(menu.columns, Column::ALL);
+        assert_eq!(menu.columns[menu.row], Column::Cpu);
+        // Past mem and fds to conn, which has to be counted to sort by
+        for code in [KeyCode::Down, KeyCode::Down, KeyCode::Down, KeyCode::Enter] {
+            app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+        assert!(app.sort_menu.is_none());
+        assert!(app.sort_by == SortBy::Conn && app.descending);
+        assert!(app.extras().sockets);
+
+        app.perform(Action::SortMenu);
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(app.sort_by == SortBy::Conn && !app.descending);
+        app.perform(Action::SortMenu);
+        app.handle_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+        assert!(app.sort_menu.is_none() && !app.descending);
+    }
+
+    #[test]
+    fn uncounted_descriptors_sort_below_counted_ones() {
+        let mut list = procs(&[(1, "a", 0.0, 0), (2, "b", 0.0, 0), (3, "c", 0.0, 0)]);
+        for (p, fds) in list.iter_mut().zip([Some(12), None, Some(900)]) {
+            p.update(&ProcSample { pid: p.pid, fds, ..sample(0, "x") });
+        }
+        assert_eq!(sorted_pids(&list, SortBy::Fds, true), vec![3, 1, 2]);
+        assert_eq!(sorted_pids(&list, SortBy::Fds, false), vec![2, 1, 3]);
+        assert_eq!((list[0].fds_text.as_str(), list[1].fds_text.as_str()), ("12", "-"));
+    }
+
+    #[test]
+    fn processes_sort_by_the_core_they_last_ran_on() {
+        let mut list = procs(&[(1, "a", 0.0, 0), (2, "b", 0.0, 0), (3, "c", 0.0, 0), (4, "d", 0.0, 0)]);
+        for (p, processor) in list.iter_mut().zip([Some(3), None, Some(0), Some(3)]) {
+            p.update(&ProcSample { pid: p.pid, processor, ..sample(0, "x") });
+        }
+        assert_eq!(sorted_pids(&list, SortBy::Core, false), vec![2, 3, 1, 4]);
+        assert_eq!(sorted_pids(&list, SortBy::Core, true), vec![1, 4, 3, 2]);
+        assert_eq!((list[0].core_text.as_str(), list[1].core_text.as_str()), ("3", "-"));
+    }
+
+    #[test]
+    fn context_switches_are_counted_per_refresh() {
+        let sample = |start_time, voluntary, involuntary| ProcSample { start_time, voluntary_switches: Some(voluntary), involuntary_switches: Some(involuntary), ..sample(7, "db") };
+        let mut p = ProcInfo::new(&sample(100, 1000, 50));
+        assert_eq!((p.vcsw, p.vcsw_text.as_str()), (None, "-"));
+        p.update(&sample(100, 1400, 52));
+        assert_eq!((p.vcsw, p.ivcsw), (Some(400), Some(2)));
+        assert_eq!(p.ivcsw_text, "2");
+        // The PID was reused by a process that started later
+        p.update(&sample(200, 30, 1));
+        assert_eq!((p.vcsw, p.ivcsw), (None, None));
+        p.update(&sample(200, 35, 9));
+        assert_eq!((p.vcsw, p.ivcsw), (Some(5), Some(8)));
+    }
+
+    #[test]
+    fn page_faults_become_rates_over_the_sample_interval() {
+        let sample = |minor, major| ProcSample { start_time: 100, minor_faults: Some(minor), major_faults: Some(major), ..sample(7, "db") };
+        let mut p = ProcInfo::new(&sample(1000, 10));
+        p.set_fault_rates(2.0);
+        assert_eq!((p.minflt, p.majflt_text.as_str()), (None, "-"));
+        p.update(&sample(25_000, 30));
+        p.set_fault_rates(2.0);
+        assert_eq!((p.minflt, p.majflt), (Some(12_000.0), Some(10.0)));
+        assert_eq!((p.minflt_text.as_str(), p.majflt_text.as_str()), ("12.0k/s", "10/s"));
+        // Showing the same replay frame again keeps the rates
+        p.update(&sample(25_000, 30));
+        p.set_fault_rates(0.0);
+        assert_eq!(p.majflt, Some(10.0));
+        assert_eq!(format_rate(Some(2_500_000.0)), "2.5M/s");
+    }
+
+    #[test]
+    fn ties_are_ordered_by_pid_in_both_directions() {
+        let list = procs(&[(30, "a", 1.0, 10), (10, "b", 1.0, 10), (20, "c", 1.0, 10)]);
+        for sort_by in [SortBy::Cpu, SortBy::Mem] {
+            assert_eq!(sorted_pids(&list, sort_by, false), vec![10, 20, 30]);
+            assert_eq!(sorted_pids(&list, sort_by, true), vec![10, 20, 30]);
+        }
+    }
+
+    #[test]
+    fn nan_cpu_sorts_lowest_without_panicking() {
+        let list = procs(&[(1, "a", f32::NAN, 0), (2, "b", 0.0, 0), (3, "c", 10.0, 0)]);
+        assert_eq!(sorted_pids(&list, SortBy::Cpu, true), vec![3, 2, 1]);
+        assert_eq!(sorted_pids(&list, SortBy::Cpu, false), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn filter_cases() {
+        let list = procs(&[(1, "systemd", 0.0, 0), (42, "Firefox", 0.0, 0), (420, "bash", 0.0, 0)]);
+        let cases: [(&str, Vec<usize>); 6] = [
+            ("", vec![1, 42, 420]),
+            ("firefox", vec![42]),
+            ("FIRE", vec![42]),
+            ("42", vec![42, 420]),
+            ("s", vec![1, 420]),
+            ("nomatch", vec![]),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(filtered_pids(&list, query), expected, "query {:?}", query);
+        }
+    }
+
+    #[test]
+    fn an_inverted_search_keeps_what_it_does_not_match() {
+        let list = procs(&[(1, "systemd", 0.0, 0), (42, "chrome", 0.0, 0), (43, "Chrome", 0.0, 0), (420, "bash", 0.0, 0)]);
+        let mut order = Vec::new();
+        filter_processes(&list, "chrome", true, &mut order);
+        assert_eq!(order, vec![0, 3]);
+        filter_processes(&list, "", true, &mut order);
+        assert_eq!(order, vec![0, 1, 2, 3]);
+
+        let mut source = crate::system::FakeSource::with_processes(&[(1, "systemd", 0.0, 0), (42, "chrome", 0.0, 0), (420, "bash", 0.0, 0)]);
+        source.processes[0].state = 'R';
+        source.processes[1].state = 'R';
+        let mut app = App::new(Config::default(), Box::new(source));
+        app.perform(Action::Search);
+        for c in "chrome".chars() {
+            app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        app.handle_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL));
+        assert!(app.search_inverted);
+        assert_eq!(app.search_query.as_str(), "chrome");
+        assert_eq!(app.visible.len(), 2);
+        // The state filter still narrows the rest rather than being inverted too
+        app.perform(Action::FilterState);
+        assert_eq!(app.visible.iter().map(|&i| usize::from(app.processes[i].pid)).collect::<Vec<_>>(), vec![1]);
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(!app.search_inverted);
+    }
+
+    #[test]
+    fn the_selection_stops_or_wraps_at_the_ends() {
+        assert_eq!(step_selection(Some(2), 1, 3, false), Some(2));
+        assert_eq!(step_selection(Some(2), 1, 3, true), Some(0));
+        assert_eq!(step_selection(Some(0), -1, 3, false), Some(0));
+        assert_eq!(step_selection(Some(0), -1, 3, true), Some(2));
+        assert_eq!(step_selection(Some(1), 1, 3, true), Some(2));
+        assert_eq!(step_selection(None, 1, 3, false), Some(1));
+        // A selection left past the end by a shrinking list counts from the last row
+        assert_eq!(step_selection(Some(9), -1, 3, false), Some(1));
+        for wrap in [false, true] {
+            assert_eq!(step_selection(Some(0), 1, 1, wrap), Some(0));
+            assert_eq!(step_selection(Some(0), -1, 1, wrap), Some(0));
+            assert_eq!(step_selection(Some(0), 1, 0, wrap), None);
+            assert_eq!(step_selection(None, -1, 0, wrap), None);
+        }
+
+        let source = crate::system::FakeSource::with_processes(&[(1, "init", 0.0, 0), (2, "kthreadd", 0.0, 0), (3, "bash", 0.0, 0)]);
+        let mut app = App::new(Config { wrap_navigation: true, ..Config::default() }, Box::new(source));
+        app.table_state.select(Some(0));
+        app.perform(Action::Up);
+        assert_eq!(app.table_state.selected(), Some(2));
+        app.perform(Action::Down);
+        assert_eq!(app.table_state.selected(), Some(0));
+        // Home and End go to the ends without wrapping past them
+        app.perform(Action::Bottom);
+        app.perform(Action::Bottom);
+        assert_eq!(app.table_state.selected(), Some(2));
+        app.perform(Action::Top);
+        assert_eq!(app.table_state.selected(), Some(0));
+    }
+
+    #[test]
+    fn the_selected_process_stays_selected_through_filtering() {
+        let mut source = crate::system::FakeSource::with_processes(&[(1, "systemd", 5.0, 0), (42, "postgres", 4.0, 0), (43, "postfix", 3.0, 0), (44, "bash", 2.0, 0), (45, "sshd", 1.0, 0)]);
+        for p in &mut source.processes {
+            p.state = 'R';
+        }
+        let mut app = App::new(Config::default(), Box::new(source));
+        let selected = |app: &App| app.selected_process().map(|p| usize::from(p.pid));
+        app.table_state.select(Some(2));
+        assert_eq!(selected(&app), Some(43));
+        app.perform(Action::Search);
+        app.paste("post");
+        assert_eq!(selected(&app), Some(43));
+        assert_eq!(app.table_state.selected(), Some(1));
+        // Sorting moves the row, not the highlight off its process
+        app.perform(Action::SortName);
+        assert_eq!(selected(&app), Some(43));
+
+        // Filtered out, the highlight keeps its row, clamped to the shorter list
+        app.search_query.clear();
+        app.paste("bash");
+        assert_eq!(selected(&app), Some(44));
+        // Nothing left; the selection comes back when the list does
+        app.paste("x");
+        assert_eq!(selected(&app), None);
+        app.handle_key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+        assert_eq!(selected(&app), Some(44));
+
+        // Clearing every filter goes back to the process from before the first one
+        app.perform(Action::FilterState);
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(app.state_filter.is_some());
+        assert_eq!(selected(&app), Some(44));
+        app.perform(Action::FilterState);
+        app.perform(Action::FilterState);
+        app.perform(Action::FilterState);
+        app.perform(Action::FilterState);
+        assert!(app.state_filter.is_none());
+        assert_eq!(selected(&app), Some(43));
+    }
+
+    #[test]
+    fn a_paste_goes_into_the_open_prompt_in_one_piece() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "systemd", 0.0, 0), (42, "postgres", 0.0, 0)]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        // Outside a prompt a paste does nothing, not even the quit its 'q' would be
+        app.paste("quit");
+        assert!(!app.quit);
+        assert_eq!(app.search_query.as_str(), "");
+
+        app.perform(Action::Search);
+        app.paste("post\tgres\n");
+        assert_eq!(app.search_query.as_str(), "postgres");
+        assert_eq!(app.visible.len(), 1);
+        assert!(!app.quit);
+
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
+        app.paste("sys\r\ntemd");
+        assert_eq!(app.watch_input.as_ref().map(LineEditor::as_str), Some("systemd"));
+    }
+
+    #[test]
+    fn every_prompt_edits_at_the_cursor() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "systemd", 0.0, 0), (42, "postgres", 0.0, 0), (43, "postfix", 0.0, 0)]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        let key = |app: &mut App, code, modifiers| app.handle_key(KeyEvent::new(code, modifiers));
+        app.perform(Action::Search);
+        app.paste("postgres");
+        assert_eq!(app.visible.len(), 1);
+        for _ in 0..5 {
+            key(&mut app, KeyCode::Left, KeyModifiers::NONE);
+        }
+        key(&mut app, KeyCode::Char('u'), KeyModifiers::CONTROL);
+        assert_eq!(app.search_query.split(), ("", "tgres"));
+        key(&mut app, KeyCode::Delete, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Delete, KeyModifiers::NONE);
+        assert_eq!(app.search_query.as_str(), "res");
+        assert_eq!(app.visible.len(), 1);
+        key(&mut app, KeyCode::Char('w'), KeyModifiers::CONTROL);
+        key(&mut app, KeyCode::End, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('w'), KeyModifiers::CONTROL);
+        key(&mut app, KeyCode::Char('p'), KeyModifiers::NONE);
+        assert_eq!(app.visible.len(), 2);
+        // Up and Down still move through the list while typing
+        key(&mut app, KeyCode::Down, KeyModifiers::NONE);
+        assert_eq!(app.table_state.selected(), Some(1));
+
+        key(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('w'), KeyModifiers::NONE);
+        app.paste("sytemd");
+        key(&mut app, KeyCode::Home, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Right, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Right, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('s'), KeyModifiers::NONE);
+        key(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(app.watches, vec!["systemd"]);
+    }
+
+    #[test]
+    fn our_own_process_is_hidden_by_its_pid() {
+        let me = std::process::id();
+        let source = crate::system::FakeSource::with_processes(&[(1, "init", 0.0, 0), (me, "renamed", 0.0, 0), (me + 1, "rust-htop", 0.0, 0)]);
+        let mut app = App::new(Config { hide_self: true, ..Config::default() }, Box::new(source));
+        let pids = |app: &App| app.visible.iter().map(|&i| usize::from(app.processes[i].pid)).collect::<Vec<_>>();
+        assert_eq!(pids(&app), vec![1, me as usize + 1]);
+        assert!(app.self_hidden);
+        app.handle_key(KeyEvent::new(KeyCode::Char('O'), KeyModifiers::SHIFT));
+        assert_eq!((pids(&app).len(), app.self_hidden), (3, false));
+        assert!(!app.current_config().hide_self);
+    }
+
+    #[test]
+    fn names_show_three_ways_and_the_search_matches_all_of_them() {
+        let mut source = crate::system::FakeSource::with_processes(&[(10, "postgres", 0.0, 0), (2, "kthreadd", 0.0, 0)]);
+        source.processes[0].exe = Some("/usr/lib/postgresql/16/bin/postgres".into());
+        source.processes[0].cmd = vec!["postgres:".into(), "checkpointer".into()];
+        let mut app = App::new(Config { name_display: "exe_path".into(), ..Config::default() }, Box::new(source));
+        let names = |app: &App| app.processes.iter().map(|p| p.display_name(app.name_display).to_string()).collect::<Vec<_>>();
+        assert_eq!(names(&app), vec!["/usr/lib/postgresql/16/bin/postgres", "kthreadd"]);
+        app.handle_key(KeyEvent::new(KeyCode::Char('N'), KeyModifiers::SHIFT));
+        assert_eq!(names(&app), vec!["postgres: checkpointer", "kthreadd"]);
+        app.perform(Action::CycleNameDisplay);
+        assert_eq!(app.name_display, NameDisplay::Basename);
+        assert_eq!(app.current_config().name_display, "basename");
+
+        for query in ["postgresql/16", "CHECKPOINTER", "postgres"] {
+            assert_eq!(filtered_pids(&app.processes, query), vec![10], "{:?}", query);
+        }
+        assert_eq!(filtered_pids(&app.processes, "postgres/usr"), Vec::<usize>::new());
+
+        // A rewritten command line is picked up on the next refresh
+        let renamed = ProcSample { cmd: &["postgres: walwriter".to_string()], ..sample(10, "postgres") };
+        app.processes[0].update(&renamed);
+        assert_eq!(app.processes[0].display_name(NameDisplay::Cmdline), "postgres: walwriter");
+        assert_eq!(app.processes[0].display_name(NameDisplay::ExePath), "postgres");
+        assert_eq!(filtered_pids(&app.processes, "walwriter"), vec![10]);
+    }
+
+    #[test]
+    fn formatting_helpers() {
+        assert_eq!(format_cpu(0.0), "0.00%");
+        assert_eq!(format_cpu(12.345), "12.35%");
+        assert_eq!(format_cpu(250.0), "250.00%");
+        assert_eq!(format_mem(0), "0.00 MB");
+        assert_eq!(format_mem(1024 * 1024), "1.00 MB");
+        assert_eq!(format_mem(1536 * 1024), "1.50 MB");
+    }
+
+    #[test]
+    fn byte_figures_keep_their_width() {
+        assert_eq!(format_bytes(0.0, "/s"), "   0.0 B/s ");
+        assert_eq!(format_bytes(1536.0, "/s"), "   1.5 KB/s");
+        assert_eq!(format_bytes(1023.9 * 1024.0 * 1024.0, ""), "1023.9 MB");
+        assert_eq!(format_bytes(1023.99 * 1024.0 * 1024.0, ""), "   1.0 GB");
+        // A u64 counter can't go past exabytes
+        assert_eq!(format_bytes(u64::MAX as f64, ""), "  16.0 EB");
+    }
+
+    #[test]
+    fn network_traffic_adds_up_every_interface_but_loopback() {
+        let sample = |name, total_received, total_transmitted| NetSample { name, total_received, total_transmitted, errors: (0, 0), dropped: None, up: None };
+        let mut net = NetSession::default();
+        net.update(&[sample("eth0", 5000, 100), sample("lo", 1 << 30, 1 << 30)], 1.0);
+        assert_eq!((net.rate, net.total), ((0.0, 0.0), (0, 0)));
+        net.update(&[sample("eth0", 7000, 300), sample("wlan0", 10, 10), sample("lo", 1 << 31, 1 << 31)], 2.0);
+        assert_eq!((net.rate, net.total), ((1000.0, 100.0), (2000, 200)));
+        // eth0 was recreated and starts again from zero; wlan0 sends its first difference
+        net.update(&[sample("eth0", 50, 0), sample("wlan0", 410, 10)], 1.0);
+        assert_eq!((net.rate, net.peak, net.total), ((400.0, 0.0), (1000.0, 100.0), (2400, 200)));
+        net.reset();
+        assert_eq!((net.peak, net.total), ((400.0, 0.0), (0, 0)));
+        net.update(&[sample("eth0", 150, 0), sample("wlan0", 410, 10)], 1.0);
+        assert_eq!((net.rate, net.total), ((100.0, 0.0), (100, 0)));
+
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::default()));
+        app.net.total = (5, 5);
+        app.handle_key(KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT));
+        assert_eq!(app.net.total, (0, 0));
+    }
+
+    #[test]
+    fn interface_errors_and_drops_are_counted_since_the_previous_refresh() {
+        let sample = |errors, dropped| NetSample { name: "eth0", total_received: 0, total_transmitted: 0, errors, dropped, up: Some(true) };
+        let mut net = NetSession::default();
+        net.update(&[sample((7, 1), Some((40, 0))), NetSample { name: "wlan0", up: None, ..sample((0, 0), None) }], 1.0);
+        assert_eq!(net.interfaces.iter().map(|n| (n.errors, n.dropped)).collect::<Vec<_>>(), [((0, 0), Some((0, 0))), ((0, 0), None)]);
+        net.update(&[sample((9, 1), Some((41, 3)))], 1.0);
+        assert_eq!(net.interfaces.len(), 1);
+        assert_eq!((net.interfaces[0].errors, net.interfaces[0].dropped), ((2, 0), Some((1, 3))));
+        // The counters went back to zero with the link
+        net.update(&[sample((0, 0), Some((0, 0)))], 1.0);
+        assert_eq!((net.interfaces[0].errors, net.interfaces[0].dropped), ((0, 0), Some((0, 0))));
+    }
+
+    #[test]
+    fn swapping_counts_as_busy_after_several_refreshes_in_a_row() {
+        let mut swap = SwapActivity::default();
+        swap.update(Some((4096, 0)), 1.0);
+        assert_eq!(swap.rate, None);
+        swap.update(Some((4096, 8192)), 2.0);
+        assert_eq!((swap.rate, swap.busy), (Some((0.0, 4096.0)), 1));
+        swap.update(Some((8192, 8192)), 1.0);
+        swap.update(Some((8192, 12288)), 1.0);
+        assert!(swap.swapping());
+        // A quiet refresh starts the count over
+        swap.update(Some((8192, 12288)), 1.0);
+        assert_eq!((swap.rate, swap.busy, swap.swapping()), (Some((0.0, 0.0)), 0, false));
+        swap.update(None, 1.0);
+        assert_eq!(swap.rate, None);
+    }
+
+    #[test]
+    fn steal_and_guest_time_appear_once_there_is_some() {
+        let mut virt = Virtualization::default();
+        let time = |total, steal, guest| Some(CpuTime { total, steal, guest });
+        virt.update(time(1000, 0, 0));
+        virt.update(time(2000, 0, 0));
+        assert_eq!((virt.steal, virt.guest), (None, None));
+        virt.update(time(3000, 70, 0));
+        assert_eq!((virt.steal, virt.guest), (Some(7.0), None));
+        // Still shown when the hypervisor leaves it alone for a while
+        virt.update(time(4000, 70, 250));
+        assert_eq!((virt.steal, virt.guest), (Some(0.0), Some(25.0)));
+        virt.update(None);
+        assert_eq!(virt.steal, None);
+    }
+
+    #[test]
+    fn the_terminal_title_names_the_host_and_the_filters() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::default()));
+        assert_eq!(app.terminal_title("dbserver01"), "rust-htop — dbserver01");
+        app.search_query = LineEditor::new("postgres");
+        assert_eq!(app.terminal_title("dbserver01"), "rust-htop — dbserver01 [postgres]");
+        app.search_inverted = true;
+        app.user_filter = Some("alice".into());
+        assert_eq!(app.terminal_title("dbserver01"), "rust-htop — dbserver01 [not postgres, user alice]");
+    }
+
+    #[test]
+    fn durations_coarsen_at_each_unit() {
+        let cases = [
+            (0, "0 min"),
+            (59, "0 min"),
+            (60, "1 min"),
+            (47 * 60 + 30, "47 min"),
+            (3599, "59 min"),
+            (3600, "1 h 0 m"),
+            (6 * 3600 + 12 * 60 + 59, "6 h 12 m"),
+            (86_399, "23 h 59 m"),
+            (86_400, "1 day 0 h"),
+            (2 * 86_400 + 3 * 3600, "2 days 3 h"),
+            (604_799, "6 days 23 h"),
+            (604_800, "7 days"),
+            (13 * 86_400 + 5 * 3600, "13 days"),
+            (u64::MAX, "213503982334601 days"),
+        ];
+        for (secs, text) in cases {
+            assert_eq!(format_duration(secs), text, "{} seconds", secs);
+        }
+    }
+
+    #[test]
+    fn extra_readings_follow_the_columns_and_the_sort() {
+        // The RT badge needs the scheduling policy from /proc/<pid>/stat
+        assert!(App::new(Config::default(), Box::new(crate::system::FakeSource::default())).extras().stat);
+        let config = Config { columns: vec!["name".into(), "conn".into()], realtime_badge: false, ..Config::default() };
+        let mut app = App::new(config, Box::new(crate::system::FakeSource::default()));
+        assert_eq!(app.extras(), Extras { sockets: true, ..Extras::default() });
+        app.perform(Action::SortSwap);
+        assert_eq!(app.extras(), Extras { sockets: true, status: true, ..Extras::default() });
+        app.sort_by = SortBy::Core;
+        assert_eq!(app.extras(), Extras { sockets: true, stat: true, ..Extras::default() });
+        assert_eq!((format_swap(Some(20 << 20)), format_swap(None)), ("20.00 MB".to_string(), "-".to_string()));
+    }
+
+    #[test]
+    fn columns_the_source_cant_fill_are_left_out() {
+        let source = || crate::system::FakeSource { missing_columns: vec![Column::Fds, Column::Swap], ..Default::default() };
+        let config = Config { columns: ["pid", "fds", "name", "swap", "cpu"].map(String::from).to_vec(), ..Config::default() };
+        let mut app = App::new(config.clone(), Box::new(source()));
+        assert_eq!(app.columns, [Column::Pid, Column::Name, Column::Cpu]);
+        app.apply_config(Config { columns: vec!["mem".into(), "fds".into()], ..config.clone() });
+        assert_eq!(app.columns, [Column::Mem]);
+        // With nothing left the table still has its default columns
+        let app = App::new(Config { columns: vec!["swap".into()], ..config }, Box::new(source()));
+        assert_eq!(app.columns, Column::ALL[..4]);
+    }
+
+    #[test]
+    fn ctrl_c_quits_even_while_searching() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::default()));
+        app.handle_key(KeyEvent::new(KeyCode::Char('/'), KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE));
+        assert!(!app.quit);
+        assert_eq!(app.search_query.as_str(), "c");
+        app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn ctrl_z_requests_suspend_without_editing_search() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::default()));
+        app.handle_key(KeyEvent::new(KeyCode::Char('/'), KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert!(app.suspend);
+        assert_eq!(app.search_query.as_str(), "");
+    }
+
+    #[test]
+    fn printable_keys_are_typed_while_searching() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::default()));
+        app.handle_key(KeyEvent::new(KeyCode::Char('/'), KeyModifiers::NONE));
+        for c in "sql".chars() {
+            app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        assert!(!app.quit);
+        assert_eq!(app.search_query.as_str(), "sql");
+    }
+
+    #[test]
+    fn tab_completes_a_column_prefix_in_the_search() {
+        let source = crate::system::FakeSource::with_processes(&[(101, "bash", 0.0, 0), (7, "chrome-101", 0.0, 0)]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        // Not with the cursor in the middle of the text
+        for code in [KeyCode::Char('/'), KeyCode::Char('p'), KeyCode::Left, KeyCode::Tab] {
+            app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+        assert_eq!(app.search_query.as_str(), "p");
+        for code in [KeyCode::Right, KeyCode::Tab, KeyCode::Char('1'), KeyCode::Char('0'), KeyCode::Char('1')] {
+            app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+        assert_eq!(app.search_query.as_str(), "pid:101");
+        assert_eq!(app.visible.iter().map(|&i| app.processes[i].name.as_str()).collect::<Vec<_>>(), ["bash"]);
+    }
+
+    #[test]
+    fn configured_keys_drive_actions() {
+        let mut config = Config::default();
+        config.keys.insert("toggle_tree".into(), crate::keys::KeySpec::One("F5".into()));
+        let mut app = App::new(config, Box::new(crate::system::FakeSource::default()));
+        app.handle_key(KeyEvent::new(KeyCode::Char('t'), KeyModifiers::NONE));
+        assert!(!app.tree_view);
+        app.handle_key(KeyEvent::new(KeyCode::F(5), KeyModifiers::NONE));
+        assert!(app.tree_view);
+        app.handle_key(KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE));
+        assert!(app.show_help);
+        app.handle_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(!app.show_help);
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn startup_view_comes_from_config() {
+        let config = Config { default_filter: "myapp".into(), start_paused: true, show_kernel_threads: false, ..Config::default() };
+        let mut app = App::new(config, Box::new(crate::system::FakeSource::default()));
+        assert!(app.searching && app.search_query.as_str() == "myapp");
+        assert!(app.paused && app.hide_kernel_threads);
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::NONE));
+        assert!(!app.paused);
+    }
+
+    #[test]
+    fn saving_writes_interface_changes_but_not_one_run_flags() {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join(format!("rust-htop-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "refresh_rate = 2000\n").unwrap();
+        let (file, _) = config::load(&path, None).unwrap();
+        let args = crate::cli::Cli::try_parse_from(["rust-htop", "--filter", "nginx", "--paused", "--limit", "5", "--tree", "--refresh-ms", "500"]).unwrap();
+        let mut app = App::new(crate::cli::merge(file.clone(), &args), Box::new(crate::system::FakeSource::default()));
+        app.config_path = Some(path.clone());
+        app.file_config = file;
+        app.sort_by = SortBy::Mem;
+        app.hide_idle = true;
+        app.perform(Action::SaveSettings);
+        let (saved, _) = config::load(&path, None).unwrap();
+        assert_eq!((saved.default_filter.as_str(), saved.start_paused, saved.row_limit), ("", false, 0));
+        assert_eq!((saved.tree_view, saved.refresh_rate), (false, 2000));
+        assert_eq!((saved.default_sort.as_str(), saved.hide_idle), ("mem", true));
+        // Undoing a flag's setting in the interface is a change too
+        app.tree_view = false;
+        app.perform(Action::SaveSettings);
+        assert!(!config::load(&path, None).unwrap().0.tree_view);
+        app.tree_view = true;
+        app.perform(Action::SaveSettings);
+        assert!(config::load(&path, None).unwrap().0.tree_view);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn apply_config_keeps_search_and_selection() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "a", 1.0, 0), (2, "ab", 2.0, 0), (3, "b", 3.0, 0)]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        app.handle_key(KeyEvent::new(KeyCode::Char('/'), KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+        app.table_state.select(Some(1));
+        app.sort_by = SortBy::Name;
+
+        app.apply_config(Config { refresh_rate: 250, ..Config::default() });
+        assert_eq!(app.refresh_rate, Duration::from_millis(250));
+        assert_eq!(app.search_query.as_str(), "a");
+        assert_eq!(app.visible.len(), 2);
+        assert_eq!(app.table_state.selected(), Some(1));
+        assert!(matches!(app.sort_by, SortBy::Name));
+
+        app.apply_config(Config { refresh_rate: 250, default_sort: "mem".into(), ..Config::default() });
+        assert!(matches!(app.sort_by, SortBy::Mem));
+    }
+
+    #[test]
+    fn refreshes_queue_alerts_until_they_are_toggled_off() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "idle", 1.0, 0), (2, "runaway", 99.0, 0)]);
+        let alerts = vec![crate::alerts::AlertConfig { when: "process cpu > 90".into(), cooldown: "0s".into(), ..Default::default() }];
+        let mut app = App::new(Config { alerts, ..Config::default() }, Box::new(source));
+        app.fired_alerts.clear();
+        app.refresh();
+        assert_eq!(app.fired_alerts.len(), 1);
+        assert!(app.fired_alerts[0].message.starts_with("runaway (PID 2)"), "{:?}", app.fired_alerts[0].message);
+
+        app.fired_alerts.clear();
+        app.handle_key(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::NONE));
+        assert_eq!(app.status.as_ref().unwrap().text, "Alerts off");
+        app.refresh();
+        assert!(app.fired_alerts.is_empty());
+        app.handle_key(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::NONE));
+        assert_eq!(app.status.as_ref().unwrap().text, "Alerts on (1 rules)");
+    }
+
+    #[test]
+    fn a_bell_flashes_and_quiet_keeps_only_the_flash() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::default()));
+        let now = Instant::now();
+        app.ring(now);
+        assert!(app.bell);
+        app.expire_flash(now + FLASH_TIME / 2);
+        assert!(app.flash_until.is_some());
+        app.expire_flash(now + FLASH_TIME);
+        assert!(app.flash_until.is_none());
+
+        let mut app = App::new(Config { quiet: true, ..Config::default() }, Box::new(crate::system::FakeSource::default()));
+        app.ring(now);
+        assert!(!app.bell);
+        assert!(app.flash_until.is_some());
+    }
+
+    #[test]
+    fn watches_come_from_config_and_are_saved_back() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "init", 0.0, 0), (42, "Postgres", 0.0, 0), (420, "bash", 0.0, 0)]);
+        let mut app = App::new(Config { watches: vec!["postgres".into()], ..Config::default() }, Box::new(source));
+        assert_eq!(app.watched, vec![false, true, false]);
+        app.handle_key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
+        for c in " 4x".chars() {
+            app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        app.handle_key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.watches, vec!["postgres", "4"]);
+        assert_eq!(app.watched, vec![false, true, true]);
+        assert_eq!(app.status.as_ref().unwrap().text, "Watching \"4\": 2 processes match now");
+        // Watching doesn't filter, and the search doesn't unwatch
+        assert_eq!(app.visible.len(), 3);
+        assert_eq!(app.current_config().watches, vec!["postgres", "4"]);
+
+        app.handle_key(KeyEvent::new(KeyCode::Char('L'), KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+        assert_eq!(app.watches, vec!["postgres"]);
+        assert_eq!(app.watch_list, Some(0));
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(app.watch_list.is_none());
+        assert_eq!(app.watched, vec![false, true, false]);
+    }
+
+    #[test]
+    fn the_user_picker_filters_together_with_the_search() {
+        let mut source = crate::system::FakeSource::with_processes(&[(1, "systemd", 0.0, 0), (20, "bash", 0.0, 0), (30, "bash", 0.0, 0), (40, "vim", 0.0, 0)]);
+        for (p, user) in source.processes.iter_mut().zip(["root", "alice", "bob", "alice"]) {
+            p.user = Some(user.into());
+        }
+        let mut app = App::new(Config::default(), Box::new(source));
+        app.handle_key(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::NONE));
+        let users = &app.user_picker.as_ref().unwrap().users;
+        assert_eq!(users, &vec![("alice".to_string(), 2), ("bob".to_string(), 1), ("root".to_string(), 1)]);
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.user_filter.as_deref(), Some("alice"));
+        let pids = |app: &App| app.visible.iter().map(|&i| usize::from(app.processes[i].pid)).collect::<Vec<_>>();
+        assert_eq!(pids(&app), vec![20, 40]);
+
+        // Both have to match
+        for c in "/bash".chars() {
+            app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        assert_eq!(pids(&app), vec![20]);
+
+        // Reopening starts on the current user; a key other than Enter changes nothing
+        app.perform(Action::FilterUser);
+        assert_eq!(app.user_picker.as_ref().unwrap().row, 0);
+        app.handle_key(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+        assert!(app.user_picker.is_none());
+        assert_eq!(app.user_filter.as_deref(), Some("alice"));
+
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(app.user_filter.is_none());
+        assert_eq!(app.visible.len(), 4);
+    }
+
+    #[test]
+    fn the_state_filter_cycles_and_combines_with_the_search() {
+        let mut source = crate::system::FakeSource::with_processes(&[(1, "dd", 0.0, 0), (2, "dd", 0.0, 0), (3, "rsync", 0.0, 0), (4, "gdb", 0.0, 0), (5, "defunct", 0.0, 0)]);
+        for (p, state) in source.processes.iter_mut().zip(['D', 'R', 'D', 't', 'Z']) {
+            p.state = state;
+        }
+        let mut app = App::new(Config::default(), Box::new(source));
+        let pids = |app: &App| app.visible.iter().map(|&i| usize::from(app.processes[i].pid)).collect::<Vec<_>>();
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            app.handle_key(KeyEvent::new(KeyCode::Char('S'), KeyModifiers::SHIFT));
+            seen.push((app.state_filter.map(StateFilter::label), pids(&app)));
+        }
+        assert_eq!(seen, vec![
+            (Some("running"), vec![2]),
+            (Some("D-state"), vec![1, 3]),
+            (Some("zombie"), vec![5]),
+            (Some("stopped"), vec![4]),
+            (None, vec![1, 2, 3, 4, 5]),
+        ]);
+        app.perform(Action::FilterState);
+        app.perform(Action::FilterState);
+        app.perform(Action::Search);
+        app.handle_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE));
+        assert_eq!(pids(&app), vec![3]);
+    }
+
+    #[test]
+    fn a_row_limit_keeps_the_top_of_the_sorted_list() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "a", 1.0, 0), (2, "b", 5.0, 0), (3, "c", 3.0, 0), (4, "d", 4.0, 0), (5, "e", 2.0, 0)]);
+        let mut app = App::new(Config { row_limit: 3, ..Config::default() }, Box::new(source));
+        let pids = |app: &App| app.visible.iter().map(|&i| usize::from(app.processes[i].pid)).collect::<Vec<_>>();
+        assert_eq!((pids(&app), app.beyond_limit), (vec![2, 4, 3], 2));
+        // After the filters, not before
+        app.perform(Action::Search);
+        app.handle_key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE));
+        assert_eq!((pids(&app), app.beyond_limit), (vec![5], 0));
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        // The selection stops at the last row kept
+        app.perform(Action::Bottom);
+        app.perform(Action::Down);
+        assert_eq!(app.table_state.selected(), Some(2));
+        app.apply_config(Config::default());
+        assert_eq!((app.visible.len(), app.beyond_limit), (5, 0));
+    }
+
+    #[test]
+    fn idle_processes_are_hidden_below_both_thresholds() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "sleeper", 0.0, 1 << 20), (2, "busy", 3.0, 1 << 20), (3, "big", 0.1, 200 << 20), (4, "tiny", 0.4, 9 << 20)]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        app.handle_key(KeyEvent::new(KeyCode::Char('h'), KeyModifiers::NONE));
+        let mut pids: Vec<usize> = app.visible.iter().map(|&i| usize::from(app.processes[i].pid)).collect();
+        pids.sort();
+        assert_eq!((pids, app.hidden_idle), (vec![2, 3], 2));
+        assert!(app.current_config().hide_idle);
+
+        app.apply_config(Config { hide_idle: true, hide_below_cpu: 0.3, hide_below_mem_mb: 500, ..Config::default() });
+        assert_eq!((app.visible.len(), app.hidden_idle), (2, 2));
+        app.perform(Action::ToggleIdle);
+        assert_eq!((app.visible.len(), app.hidden_idle), (4, 0));
+        // A threshold too large to count in bytes hides everything below the CPU one
+        app.apply_config(Config { hide_idle: true, hide_below_mem_mb: u64::MAX, ..Config::default() });
+        app.perform(Action::ToggleIdle);
+        assert_eq!((app.visible.len(), app.hidden_idle), (1, 3));
+    }
+
+    #[test]
+    fn affinity_is_edited_from_the_current_list() {
+        let mut source = crate::system::FakeSource::with_processes(&[(42, "postgres", 9.0, 0), (7, "sshd", 1.0, 0)]);
+        source.totals.cpus = vec![0.0; 8];
+        source.affinity.insert(42, vec![0, 1, 2, 3]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        app.table_state.select(Some(0));
+        app.perform(Action::SetAffinity);
+        assert_eq!(app.setting_input.as_ref().unwrap().text.as_str(), "0-3");
+        for code in [KeyCode::Backspace, KeyCode::Char('2'), KeyCode::Char(','), KeyCode::Char('7'), KeyCode::Enter] {
+            app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+        assert_eq!(app.actions.results()[0].text(), "postgres (42) now has CPU affinity 0-2,7");
+        assert_eq!(app.source.details(Pid::from(42)).affinity, Some(Ok(vec![0, 1, 2, 7])));
+
+        // A bad list changes nothing
+        app.perform(Action::SetAffinity);
+        app.handle_key(KeyEvent::new(KeyCode::Char('8'), KeyModifiers::NONE));
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        let result = &app.actions.results()[1];
+        assert_eq!(result.outcome, Err(ActionError::Invalid("there is no CPU 78; this machine has 0-7".into())));
+        assert_eq!(result.text(), "Could not set the CPU affinity of postgres (42): there is no CPU 78; this machine has 0-7");
+        assert_eq!(app.source.details(Pid::from(42)).affinity, Some(Ok(vec![0, 1, 2, 7])));
+
+        app.table_state.select(Some(1));
+        app.perform(Action::SetAffinity);
+        assert!(app.setting_input.is_none());
+        assert_eq!(app.actions.results()[2].outcome, Err(ActionError::Unsupported));
+        assert_eq!(app.actions.toasts().count(), 3);
+    }
+
+    #[test]
+    fn a_process_that_has_gone_is_reported_without_asking() {
+        let mut source = crate::system::FakeSource::with_processes(&[(42, "postgres", 9.0, 0)]);
+        source.affinity.insert(42, vec![0]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        app.table_state.select(Some(0));
+        // It exits between the last refresh and the key press
+        app.source = Box::new(crate::system::FakeSource::default());
+        app.perform(Action::SetAffinity);
+        assert!(app.setting_input.is_none());
+        assert_eq!(app.actions.results()[0].text(), "postgres (42) no longer exists");
+
+        app.perform(Action::ActionLog);
+        assert_eq!(app.action_log, Some(0));
+        app.handle_key(KeyEvent::new(KeyCode::End, KeyModifiers::NONE));
+        assert_eq!(app.action_log, Some(0));
+        app.handle_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+        assert_eq!(app.action_log, None);
+    }
+
+    #[test]
+    fn a_lagging_refresh_pushes_the_next_one_back() {
+        let mut app = App::new(Config { refresh_rate: 1000, ..Config::default() }, Box::new(crate::system::FakeSource::default()));
+        app.last_updated = Instant::now() - Duration::from_millis(1500);
+        assert!(app.refresh_due());
+        app.pacer.record(Duration::from_millis(1200));
+        assert!(!app.refresh_due());
+        app.last_updated = Instant::now() - Duration::from_millis(2500);
+        assert!(app.refresh_due());
+    }
+
+    #[test]
+    fn the_footer_adds_up_the_listed_rows() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "nginx", 10.0, 100), (2, "nginx", 30.0, 300), (3, "postgres", 50.0, 1000), (4, "nginx", 20.0, 200)]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        assert_eq!(app.footer, None);
+        app.perform(Action::ToggleFooter);
+        assert_eq!(app.footer, Some(Aggregate { rows: 4, cpu_sum: 110.0, cpu_max: 50.0, mem_sum: 1600, mem_max: 1000 }));
+        app.search_query = LineEditor::new("nginx");
+        app.update_view();
+        let footer = app.footer.unwrap();
+        assert_eq!(footer, Aggregate { rows: 3, cpu_sum: 60.0, cpu_max: 30.0, mem_sum: 600, mem_max: 300 });
+        assert_eq!((footer.cpu_average(), footer.mem_average()), (20.0, 200));
+        app.search_query = LineEditor::new("nothing");
+        app.update_view();
+        assert_eq!(app.footer.map(|f| (f.rows, f.cpu_average(), f.mem_average())), Some((0, 0.0, 0)));
+        assert!(app.current_config().show_footer);
+        app.perform(Action::ToggleFooter);
+        assert_eq!(app.footer, None);
+    }
+
+    #[test]
+    fn resetting_the_view_keeps_watches_and_pausing() {
+        let source = crate::system::FakeSource::with_processes(&[(1, "nginx", 10.0, 100), (2, "nginx", 30.0, 300), (3, "postgres", 50.0, 1000)]);
+        let mut app = App::new(Config { default_sort: "mem".into(), descending: false, ..Config::default() }, Box::new(source));
+        app.sort_by = SortBy::Pid;
+        app.descending = true;
+        app.search_query = LineEditor::new("nginx");
+        app.search_inverted = true;
+        app.user_filter = Some("www".into());
+        app.state_filter = Some(StateFilter::Zombie);
+        app.tree_view = true;
+        app.perform(Action::ToggleFooter);
+        app.watches = vec!["postgres".into()];
+        app.paused = true;
+        app.perform(Action::ResetView);
+        assert!(app.search_query.is_empty() && !app.search_inverted);
+        assert_eq!((app.user_filter.as_deref(), app.state_filter), (None, None));
+        assert!(app.sort_by == SortBy::Mem && !app.descending);
+        assert_eq!((app.tree_view, app.footer), (false, None));
+        assert_eq!(app.visible.len(), 3);
+        assert_eq!(app.table_state.selected(), Some(0));
+        assert_eq!((app.watches.as_slice(), app.paused), (["postgres".to_string()].as_slice(), true));
+    }
+
+    #[test]
+    fn real_time_processes_are_listed_ahead_of_negative_nice() {
+        use crate::sched::Policy;
+        let mut source = crate::system::FakeSource::with_processes(&[(1, "init", 0.0, 0), (20, "pipewire", 0.0, 0), (30, "migration/0", 0.0, 0), (40, "Xorg", 0.0, 0), (50, "backup", 0.0, 0)]);
+        for p in &mut source.processes {
+            p.scheduling = Some(Scheduling { policy: Policy::Normal, priority: 0 });
+            p.nice = Some(0);
+        }
+        source.processes[1].scheduling = Some(Scheduling { policy: Policy::RoundRobin, priority: 20 });
+        source.processes[2].scheduling = Some(Scheduling { policy: Policy::Fifo, priority: 99 });
+        source.processes[3].nice = Some(-5);
+        source.processes[4].nice = Some(19);
+        let mut app = App::new(Config::default(), Box::new(source));
+        let names: Vec<&str> = app.boosted().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["migration/0", "pipewire", "Xorg"]);
+        app.perform(Action::Realtime);
+        assert!(app.show_realtime);
+        app.handle_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+        assert!(!app.show_realtime);
+
+        app.realtime_badge = false;
+        app.perform(Action::Realtime);
+        assert!(!app.show_realtime);
+        assert!(app.status.as_ref().is_some_and(|s| s.text.contains("realtime_badge is off")));
+    }
+
+    #[test]
+    fn statuses_time_out_and_stay_in_the_message_log() {
+        let mut app = App::new(Config { status_seconds: 5, ..Config::default() }, Box::new(crate::system::FakeSource::default()));
+        for i in 0..MESSAGE_LOG + 3 {
+            app.set_status(StatusKind::Info, format!("message {}", i));
+        }
+        assert_eq!(app.messages.len(), MESSAGE_LOG);
+        assert_eq!(app.messages[0].text, "message 3");
+        let shown = Instant::now();
+        app.expire_status(shown);
+        assert!(app.status.is_some());
+        app.expire_status(shown + Duration::from_secs(5));
+        assert!(app.status.is_none());
+
+        // 0 keeps it until a key is pressed
+        app.config.status_seconds = 0;
+        app.set_status(StatusKind::Warning, "disk full");
+        app.expire_status(shown + Duration::from_secs(3600));
+        assert_eq!(app.status.as_ref().map(|s| s.text.as_str()), Some("disk full"));
+
+        app.perform(Action::Messages);
+        app.handle_key(KeyEvent::new(KeyCode::End, KeyModifiers::NONE));
+        assert_eq!(app.message_log, Some(MESSAGE_LOG - 1));
+        app.handle_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+        assert_eq!(app.message_log, None);
+    }
+
+    #[test]
+    fn held_arrow_keys_move_by_every_repeat_at_once() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::with_processes(&[(1, "a", 5.0, 0), (2, "b", 4.0, 0), (3, "c", 3.0, 0), (4, "d", 2.0, 0), (5, "e", 1.0, 0)])));
+        app.table_state.select(Some(0));
+        let down = KeyEvent::new(KeyCode::Down, KeyModifiers::NONE);
+        app.handle_repeated_key(down, 3);
+        assert_eq!(app.table_state.selected(), Some(3));
+        // Stops at the end as single presses do
+        app.handle_repeated_key(down, 10);
+        assert_eq!(app.table_state.selected(), Some(4));
+        app.handle_repeated_key(KeyEvent::new(KeyCode::Up, KeyModifiers::NONE), 2);
+        assert_eq!(app.table_state.selected(), Some(2));
+
+        // In the search the typed characters are all kept
+        app.perform(Action::Search);
+        app.handle_repeated_key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE), 2);
+        assert_eq!(app.search_query.as_str(), "ee");
+    }
+
+    #[test]
+    fn processes_gone_at_a_refresh_go_in_the_exit_log() {
+        let mut app = App::new(Config::default(), Box::new(crate::system::FakeSource::with_processes(&[(1, "init", 0.0, 0), (4242, "cc1", 80.0, 512)])));
+        app.perform(Action::ExitLog);
+        assert!(app.exit_log.is_none());
+        assert!(app.status.as_ref().is_some_and(|s| s.text.starts_with("No processes have exited")));
+        app.source = Box::new(crate::system::FakeSource::with_processes(&[(1, "init", 0.0, 0)]));
+        app.refresh();
+        assert_eq!(app.exits.exits().iter().map(|e| (e.pid, e.name.as_str(), e.cpu)).collect::<Vec<_>>(), [(Pid::from(4242), "cc1", 80.0)]);
+        app.perform(Action::ExitLog);
+        assert_eq!(app.exit_log, Some(0));
+        app.handle_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(app.exit_log.is_none() && !app.quit);
+        // Lowering the size on a reload drops the oldest
+        app.apply_config(Config { exit_log_size: 0, ..Config::default() });
+        assert!(app.exits.is_empty());
+    }
+
+    #[test]
+    fn a_reused_pid_does_not_get_the_value_typed_for_the_process_before() {
+        let source = |start_time| {
+            let mut source = crate::system::FakeSource::with_processes(&[(42, "postgres", 9.0, 0)]);
+            source.processes[0].start_time = start_time;
+            source.affinity.insert(42, vec![0]);
+            source
+        };
+        let mut app = App::new(Config::default(), Box::new(source(100)));
+        app.table_state.select(Some(0));
+        app.perform(Action::SetAffinity);
+        app.setting_input.as_mut().unwrap().text = "1".into();
+        // postgres exits and a new process is given its PID while the value is typed
+        app.source = Box::new(source(200));
+        app.refresh();
+        assert!(app.has_exited(&app.setting_input.as_ref().unwrap().process));
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.actions.results()[0].text(), "postgres (42) no longer exists");
+        assert_eq!(app.source.details(Pid::from(42)).affinity, Some(Ok(vec![0])));
+    }
+
+    #[test]
+    fn io_priority_is_lowered_to_idle() {
+        use crate::sched::{IoClass, IoPriority};
+        let mut source = crate::system::FakeSource::with_processes(&[(9, "backup", 9.0, 0)]);
+        source.io_priority.insert(9, IoPriority { class: IoClass::None, level: 0 });
+        let mut app = App::new(Config::default(), Box::new(source));
+        app.table_state.select(Some(0));
+        app.perform(Action::SetIoPriority);
+        // Unset shows what the process actually gets
+        assert_eq!(app.setting_input.as_ref().unwrap().text.as_str(), "best-effort 4");
+        app.setting_input.as_mut().unwrap().text = "idle".into();
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(app.actions.results()[0].text(), "backup (9) now has I/O priority idle");
+        assert_eq!(app.source.details(Pid::from(9)).io_priority, Some(Ok(IoPriority { class: IoClass::Idle, level: 0 })));
+
+        app.perform(Action::SetIoPriority);
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(app.setting_input.is_none());
+        assert_eq!(app.source.details(Pid::from(9)).io_priority.unwrap().unwrap().class, IoClass::Idle);
+    }
+
+    #[test]
+    fn merge_updates_in_place_and_drops_exited() {
+        let mut list = procs(&[(1, "a", 1.0, 0), (2, "b", 2.0, 0)]);
+        merge_processes(&mut list, vec![
+            ProcSample { cpu: 3.0, ..sample(2, "b") },
+            sample(3, "c"),
+        ]);
+        let pids: Vec<usize> = list.iter().map(|p| usize::from(p.pid)).collect();
+        assert_eq!(pids, vec![2, 3]);
+        assert_eq!(list[0].cpu_text, "3.00%");
+    }
+
+    #[test]
+    fn tree_order_nests_children_in_sorted_order() {
+        // 1 ─┬─ 10 ── 100
+        //    └─ 20
+        // 50 (parent not listed)
+        let mut list = procs(&[(1, "init", 0.0, 0), (10, "a", 5.0, 0), (20, "b", 9.0, 0), (100, "c", 1.0, 0), (50, "d", 3.0, 0)]);
+        list[1].parent = Some(Pid::from(1));
+        list[2].parent = Some(Pid::from(1));
+        list[3].parent = Some(Pid::from(10));
+        list[4].parent = Some(Pid::from(999));
+        let mut order: Vec<usize> = (0..list.len()).collect();
+        sort_processes(SortBy::Cpu, true, &list, &mut order);
+        let mut depths = Vec::new();
+        tree_order(&list, &mut order, &mut depths);
+        let pids: Vec<usize> = order.iter().map(|&i| usize::from(list[i].pid)).collect();
+        assert_eq!(pids, vec![50, 1, 20, 10, 100]);
+        assert_eq!(depths, vec![0, 0, 1, 1, 2]);
+    }
+
+    // 1 ─┬─ 10 ─┬─ 100
+    //    │       └─ 101
+    //    └─ 20
+    // 2 ── 30
+    fn forest() -> Vec<ProcInfo> {
+        let mut list = procs(&[(1, "init", 1.0, 10), (10, "make", 2.0, 20), (100, "cc", 30.0, 300), (101, "cc", 25.0, 200), (20, "sshd", 40.0, 1000), (2, "kthreadd", 0.0, 0), (30, "kworker", 3.0, 0)]);
+        for (i, parent) in [(1, 1), (2, 10), (3, 10), (4, 1), (6, 2)] {
+            list[i].parent = Some(Pid::from(parent));
+        }
+        list
+    }
+
+    fn tree_pids(list: &mut [ProcInfo], sort_by: SortBy, by_subtree: bool) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..list.len()).collect();
+        sort_processes(sort_by, true, list, &mut order);
+        if by_subtree {
+            subtree_totals(list, &order);
+            sort_by_subtree(sort_by, true, list, &mut order);
+        }
+        tree_order(list, &mut order, &mut Vec::new());
+        order.iter().map(|&i| usize::from(list[i].pid)).collect()
+    }
+
+    #[test]
+    fn subtrees_add_up_every_listed_descendant() {
+        let mut list = forest();
+        let order: Vec<usize> = (0..list.len()).collect();
+        subtree_totals(&mut list, &order);
+        let totals: Vec<Option<(f32, u64)>> = list.iter().map(|p| p.subtree).collect();
+        assert_eq!(totals, [Some((98.0, 1530)), Some((57.0, 520)), None, None, None, Some((3.0, 0)), None]);
+        // A filtered-out child takes its subtree with it, as its children are shown as roots
+        let order: Vec<usize> = (0..list.len()).filter(|&i| i != 1).collect();
+        subtree_totals(&mut list, &order);
+        assert_eq!((list[0].subtree, list[1].subtree), (Some((41.0, 1010)), None));
+    }
+
+    #[test]
+    fn siblings_sort_by_their_own_key_or_by_their_subtree() {
+        let mut list = forest();
+        // On their own, sshd beats make
+        assert_eq!(tree_pids(&mut list, SortBy::Cpu, false), [1, 20, 10, 100, 101, 2, 30]);
+        // make's compilers put its subtree above sshd
+        assert_eq!(tree_pids(&mut list, SortBy::Cpu, true), [1, 10, 100, 101, 20, 2, 30]);
+        assert_eq!(tree_pids(&mut list, SortBy::Mem, true), [1, 20, 10, 100, 101, 2, 30]);
+        // Names don't add up, so the tree is sorted as usual
+        assert_eq!(tree_pids(&mut list, SortBy::Name, true), tree_pids(&mut list, SortBy::Name, false));
+    }
+
+    #[test]
+    fn tree_order_keeps_rows_caught_in_parent_loops() {
+        let mut list = procs(&[(1, "a", 0.0, 0), (2, "b", 0.0, 0)]);
+        list[0].parent = Some(Pid::from(2));
+        list[1].parent = Some(Pid::from(1));
+        let mut order = vec![0, 1];
+        let mut depths = Vec::new();
+        tree_order(&list, &mut order, &mut depths);
+        assert_eq!(order.len(), 2);
+        assert_eq!(depths, vec![0, 0]);
+    }
+}
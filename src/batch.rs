@@ -0,0 +1,260 @@
+use std::{io::{self, Write}, sync::atomic::{AtomicBool, Ordering}, thread, time::Duration};
+
+use crate::{app::{self, App, ProcInfo}, snapshot::SystemSnapshot};
+
+// Column widths of the plain-text table. Names are cut to fit so every line has the same
+// layout no matter what is running.
+const PID_WIDTH: usize = 7;
+const NAME_WIDTH: usize = 25;
+const CPU_WIDTH: usize = 9;
+const MEM_WIDTH: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Format {
+    // Fixed-width table, one per snapshot
+    #[default]
+    Text,
+    // One SystemSnapshot object per line
+    Json,
+    // Plain sentences for screen readers: the totals, then one process per line, with no table
+    // layout, symbols or colour
+    Lines,
+}
+
+// How many processes --accessible lists without --limit; hearing every one read out each
+// refresh helps nobody
+pub const LINES_LIMIT: usize = 10;
+
+pub struct BatchOptions {
+    // None runs until stopped
+    pub iterations: Option<u64>,
+    pub delay: Duration,
+    pub format: Format,
+    // Only the first N rows of each snapshot
+    pub limit: Option<usize>,
+}
+
+// Sample, print, repeat. Each iteration waits `delay` first so CPU usage covers a full
+// interval, like the TUI's first refresh. Stops early once `stop` is set or the reader goes
+// away (`rust-htop --stream | head -1`); Rust ignores SIGPIPE, so that shows up as EPIPE.
+pub fn run(app: &mut App, options: &BatchOptions, out: &mut impl Write, stop: &AtomicBool) -> io::Result<()> {
+    match run_until_error(app, options, out, stop) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    }
+}
+
+fn run_until_error(app: &mut App, options: &BatchOptions, out: &mut impl Write, stop: &AtomicBool) -> io::Result<()> {
+    let mut done = 0;
+    while options.iterations.is_none_or(|n| done < n) && !stop.load(Ordering::Relaxed) {
+        thread::sleep(options.delay);
+        app.refresh();
+        match options.format {
+            Format::Text => {
+                let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                write_table(app, &now, options.limit, out)?;
+            },
+            Format::Json => write_json(app, options.limit, out)?,
+            Format::Lines => {
+                let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                write_lines(app, &now, options.limit.or(Some(LINES_LIMIT)), out)?;
+            },
+        }
+        // Flushed per iteration so a pipe sees each snapshot as soon as it is taken
+        out.flush()?;
+        done += 1;
+    }
+    Ok(())
+}
+
+// The visible rows of `app` in display order: the same filter and sort as the TUI. The count
+// includes the rows row_limit cut off.
+pub fn write_table(app: &App, time: &str, limit: Option<usize>, out: &mut impl Write) -> io::Result<()> {
+    let count = app.visible.len() + app.beyond_limit;
+    if time.is_empty() {
+        writeln!(out, "rust-htop: {} processes, sorted by {}", count, app.sort_by.name())?;
+    } else {
+        writeln!(out, "rust-htop {}: {} processes, sorted by {}", time, count, app.sort_by.name())?;
+    }
+    writeln!(out, "{:>pw$} {:<nw$} {:>cw$} {:>mw$}", "PID", "NAME", "CPU%", "MEM", pw = PID_WIDTH, nw = NAME_WIDTH, cw = CPU_WIDTH, mw = MEM_WIDTH)?;
+    for &i in app.visible.iter().take(limit.unwrap_or(usize::MAX)) {
+        write_row(&app.processes[i], out)?;
+    }
+    writeln!(out)
+}
+
+// A whole line is built before writing so a reader never sees half an object
+pub fn write_json(app: &App, limit: Option<usize>, out: &mut impl Write) -> io::Result<()> {
+    let mut line = serde_json::to_string(&SystemSnapshot::capture(app, limit))?;
+    line.push('\n');
+    out.write_all(line.as_bytes())
+}
+
+// The same figures the interface shows, worded to be read out: a heading with the sort and
+// filter, the system totals, then the visible rows in display order, and a blank line
+pub fn write_lines(app: &App, time: &str, limit: Option<usize>, out: &mut impl Write) -> io::Result<()> {
+    let bytes = |n: f64, suffix: &str| app::format_bytes(n, suffix).trim().to_string();
+    let order = if app.descending { "highest first" } else { "lowest first" };
+    let count = app.visible.len() + app.beyond_limit;
+    let mut heading = format!("rust-htop at {}: {} processes, sorted by {}, {}", time, count, app.sort_by.name(), order);
+    if !app.search_query.is_empty() {
+        heading.push_str(&format!(", matching {}", app.search_query.as_str()));
+    }
+    let listed = limit.unwrap_or(usize::MAX).min(app.visible.len());
+    if listed < count {
+        heading.push_str(&format!(", the first {} listed", listed));
+    }
+    writeln!(out, "{}.", heading)?;
+    let totals = &app.totals;
+    let load = format!("{:.2}, {:.2}, {:.2}", totals.load[0], totals.load[1], totals.load[2]);
+    if totals.boot_time > 0 {
+        let up = (app.sampled_ms / 1000).saturating_sub(totals.boot_time as i64).max(0) as u64;
+        writeln!(out, "Up {}, load average {}.", app::format_duration(up), load)?;
+    } else {
+        writeln!(out, "Load average {}.", load)?;
+    }
+    let mut memory = format!("Memory {} used of {}", bytes(totals.mem_used as f64, ""), bytes(totals.mem_total as f64, ""));
+    if totals.mem_available > 0 {
+        memory.push_str(&format!(", {} available", bytes(totals.mem_available as f64, "")));
+    }
+    if let Some(pressure) = totals.mem_pressure {
+        memory.push_str(&format!(", pressure {}", pressure.level.name()));
+    }
+    if totals.swap_total > 0 {
+        memory.push_str(&format!(". Swap {} used of {}", bytes(totals.swap_used as f64, ""), bytes(totals.swap_total as f64, "")));
+    }
+    writeln!(out, "{}.", memory)?;
+    writeln!(out, "Network receiving {}, sending {}.", bytes(app.net.rate.0, "/s"), bytes(app.net.rate.1, "/s"))?;
+    for (n, &i) in app.visible.iter().take(limit.unwrap_or(usize::MAX)).enumerate() {
+        let p = &app.processes[i];
+        let mut line = format!("{}. {}, PID {}, CPU {}, memory {}", n + 1, p.name, p.pid_text, p.cpu_text.trim(), p.mem_text.trim());
+        if let Some(user) = &p.user {
+            line.push_str(&format!(", user {}", user));
+        }
+        writeln!(out, "{}.", line)?;
+    }
+    writeln!(out)
+}
+
+fn write_row(p: &ProcInfo, out: &mut impl Write) -> io::Result<()> {
+    let name: String = p.name.chars().take(NAME_WIDTH).collect();
+    writeln!(out, "{:>pw$} {:<nw$} {:>cw$} {:>mw$}", p.pid_text, name, p.cpu_text, p.mem_text, pw = PID_WIDTH, nw = NAME_WIDTH, cw = CPU_WIDTH, mw = MEM_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, system::FakeSource};
+
+    #[test]
+    fn table_has_fixed_columns_in_view_order() {
+        let source = FakeSource::with_processes(&[
+            (1, "systemd", 0.5, 12 * 1024 * 1024),
+            (200, "postgres: checkpointer process", 42.0, 512 * 1024 * 1024),
+            (3000, "bash", 1.25, 4 * 1024 * 1024),
+        ]);
+        let config = Config { default_filter: "s".into(), ..Config::default() };
+        let app = App::new(config, Box::new(source));
+        let mut out = Vec::new();
+        write_table(&app, "", None, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines, vec![
+            "rust-htop: 3 processes, sorted by cpu",
+            "    PID NAME                           CPU%          MEM",
+            "    200 postgres: checkpointer pr    42.00%    512.00 MB",
+            "   3000 bash                          1.25%      4.00 MB",
+            "      1 systemd                       0.50%     12.00 MB",
+            "",
+        ]);
+        assert!(lines[1..5].iter().all(|l| l.len() == lines[1].len()));
+    }
+
+    #[test]
+    fn lines_read_as_sentences_in_view_order() {
+        let mut source = FakeSource::with_processes(&[
+            (1, "systemd", 0.5, 12 * 1024 * 1024),
+            (200, "postgres", 42.0, 512 * 1024 * 1024),
+            (3000, "bash", 1.25, 4 * 1024 * 1024),
+        ]);
+        source.processes[1].user = Some("postgres".into());
+        source.totals.mem_total = 8 << 30;
+        source.totals.mem_used = 3 << 30;
+        source.totals.mem_available = 5 << 30;
+        source.totals.load = [1.5, 0.75, 0.25];
+        let config = Config { default_filter: "s".into(), ..Config::default() };
+        let app = App::new(config, Box::new(source));
+        let mut out = Vec::new();
+        write_lines(&app, "12:00:00", Some(2), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().collect::<Vec<_>>(), vec![
+            "rust-htop at 12:00:00: 3 processes, sorted by cpu, highest first, matching s, the first 2 listed.",
+            "Load average 1.50, 0.75, 0.25.",
+            "Memory 3.0 GB used of 8.0 GB, 5.0 GB available.",
+            "Network receiving 0.0 B/s, sending 0.0 B/s.",
+            "1. postgres, PID 200, CPU 42.00%, memory 512.00 MB, user postgres.",
+            "2. bash, PID 3000, CPU 1.25%, memory 4.00 MB.",
+            "",
+        ]);
+        // Nothing a screen reader would spell out symbol by symbol
+        assert!(text.chars().all(|c| c.is_ascii_alphanumeric() || " .,:%/-\n".contains(c)), "{}", text);
+    }
+
+    #[test]
+    fn stops_after_the_requested_iterations() {
+        let mut app = App::new(Config::default(), Box::new(FakeSource::with_processes(&[(1, "init", 0.0, 0)])));
+        let mut out = Vec::new();
+        let options = BatchOptions { iterations: Some(2), delay: Duration::ZERO, format: Format::Text, limit: None };
+        run(&mut app, &options, &mut out, &AtomicBool::new(false)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().matches("PID NAME").count(), 2);
+        let mut out = Vec::new();
+        run(&mut app, &BatchOptions { iterations: None, ..options }, &mut out, &AtomicBool::new(true)).unwrap();
+        assert!(out.is_empty());
+    }
+
+    // Accepts `lines` complete lines, then fails like a closed pipe
+    struct ClosingPipe {
+        written: Vec<u8>,
+        lines: usize,
+    }
+
+    impl Write for ClosingPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written.iter().filter(|&&b| b == b'\n').count() >= self.lines {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_closed_reader_ends_the_stream_cleanly() {
+        let mut app = App::new(Config::default(), Box::new(FakeSource::with_processes(&[(1, "init", 0.0, 0)])));
+        let mut pipe = ClosingPipe { written: Vec::new(), lines: 1 };
+        let options = BatchOptions { iterations: None, delay: Duration::ZERO, format: Format::Json, limit: None };
+        run(&mut app, &options, &mut pipe, &AtomicBool::new(false)).unwrap();
+        let text = String::from_utf8(pipe.written).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        assert!(serde_json::from_str::<SystemSnapshot>(text.trim_end()).is_ok());
+    }
+
+    #[test]
+    fn json_output_is_one_parsable_snapshot_per_line() {
+        let source = FakeSource::with_processes(&[(1, "init", 0.0, 0), (2, "kthreadd", 3.0, 0), (3, "bash", 1.0, 0)]);
+        let mut app = App::new(Config::default(), Box::new(source));
+        let mut out = Vec::new();
+        let options = BatchOptions { iterations: Some(2), delay: Duration::ZERO, format: Format::Json, limit: Some(2) };
+        run(&mut app, &options, &mut out, &AtomicBool::new(false)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with('\n'));
+        let snapshots: Vec<SystemSnapshot> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(snapshots.len(), 2);
+        let pids: Vec<u32> = snapshots[0].processes.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, vec![2, 3]);
+    }
+}
@@ -0,0 +1,468 @@
+use std::{path::PathBuf, time::Duration};
+use clap::{builder::PossibleValuesParser, ArgGroup, Parser, Subcommand};
+
+use crate::{app::SortBy, batch::Format, config::{self, Config}};
+
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("RUST_HTOP_GIT_HASH"), ")");
+
+// --help and --version are answered by clap while parsing, which happens before the
+// terminal is put into raw mode
+#[derive(Parser, Debug, Default)]
+#[command(name = "rust-htop", about = "A terminal process monitor", version = VERSION)]
+#[command(group(ArgGroup::new("batch_mode").args(["batch", "once", "stream", "accessible"]).multiple(true)))]
+#[command(group(ArgGroup::new("outputs").args(["log_csv", "prometheus_listen", "http_listen", "serve", "record", "history_db"]).multiple(true)))]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Load this config file instead of searching the default locations
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the [profile.<name>] overrides from the config file
+    #[arg(long, value_name = "NAME", env = "RUST_HTOP_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Print the config file that would be loaded and exit
+    #[arg(long)]
+    pub print_config_path: bool,
+
+    /// Write a debug log to FILE: refresh timings, snapshot sizes, config loading and errors
+    #[arg(long, value_name = "FILE", env = "RUST_HTOP_LOG")]
+    pub debug_log: Option<PathBuf>,
+
+    /// How much --debug-log records; trace adds every key press
+    #[arg(long, value_name = "LEVEL", requires = "debug_log", default_value = "debug", value_parser = ["error", "warn", "info", "debug", "trace"])]
+    pub debug_level: String,
+
+    /// Refresh interval in milliseconds, from 100 to 60000
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(config::MIN_REFRESH_MS..=config::MAX_REFRESH_MS))]
+    pub refresh_ms: Option<u64>,
+
+    /// Initial sort column
+    #[arg(long, value_parser = PossibleValuesParser::new(SortBy::ALL.map(SortBy::name)))]
+    pub sort: Option<String>,
+
+    /// Sort in descending order
+    #[arg(long, conflicts_with = "ascending")]
+    pub descending: bool,
+
+    /// Sort in ascending order
+    #[arg(long)]
+    pub ascending: bool,
+
+    /// Start with this search query applied
+    #[arg(long, value_name = "QUERY")]
+    pub filter: Option<String>,
+
+    /// Start in tree view
+    #[arg(long, conflicts_with = "no_tree")]
+    pub tree: bool,
+
+    /// Start in the flat list even if the config enables tree view
+    #[arg(long)]
+    pub no_tree: bool,
+
+    /// Hide kernel threads
+    #[arg(long, conflicts_with = "show_kernel_threads")]
+    pub hide_kernel_threads: bool,
+
+    /// Show kernel threads even if the config hides them
+    #[arg(long)]
+    pub show_kernel_threads: bool,
+
+    /// Start with refreshing paused
+    #[arg(long)]
+    pub paused: bool,
+
+    /// Show rust-htop's own refresh, row and draw times in a corner
+    #[arg(long)]
+    pub show_perf: bool,
+
+    /// Print snapshots to stdout instead of starting the interface
+    #[arg(short = 'b', long)]
+    pub batch: bool,
+
+    /// Print a single snapshot and exit; the same as --batch --iterations 1
+    #[arg(long, conflicts_with = "iterations")]
+    pub once: bool,
+
+    /// Print one JSON snapshot per line until stopped; the same as --batch --format json
+    #[arg(long, conflicts_with_all = ["once", "iterations"])]
+    pub stream: bool,
+
+    /// Print the totals and the top processes as plain sentences each refresh, for screen
+    /// readers; the same as --batch --format lines, and the default when stdout isn't a terminal
+    #[arg(long, conflicts_with_all = ["stream", "format"])]
+    pub accessible: bool,
+
+    /// Number of snapshots to print in batch mode [default: until stopped]
+    #[arg(short = 'n', long, value_name = "N", requires = "batch_mode", value_parser = clap::value_parser!(u64).range(1..))]
+    pub iterations: Option<u64>,
+
+    /// Seconds between batch snapshots [default: the refresh rate]
+    #[arg(short = 'd', long, visible_alias = "interval", value_name = "SECS", requires = "batch_mode", value_parser = parse_delay)]
+    pub delay: Option<Duration>,
+
+    /// Snapshot format in batch mode
+    #[arg(long, value_enum, default_value_t, requires = "batch_mode")]
+    pub format: Format,
+
+    /// Only list the first N processes after filtering and sorting, in the table and in every
+    /// snapshot [default: 10 with --accessible]
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub limit: Option<usize>,
+
+    /// Append one CSV row per process to FILE every --log-interval
+    #[arg(long, value_name = "FILE", conflicts_with = "batch_mode")]
+    pub log_csv: Option<PathBuf>,
+
+    /// Seconds between CSV log entries
+    #[arg(long, value_name = "SECS", requires = "log_csv", value_parser = parse_delay, default_value = "5")]
+    pub log_interval: Duration,
+
+    /// Record every refresh to a session file for --replay
+    #[arg(long, value_name = "FILE", conflicts_with = "batch_mode")]
+    pub record: Option<PathBuf>,
+
+    /// Start a new session file once the recording reaches this size; the old one is kept as FILE.1
+    #[arg(long, value_name = "MB", requires = "record", default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    pub record_max_mb: u64,
+
+    /// Keep per-refresh system totals and busy processes in this SQLite database (needs the history feature)
+    #[arg(long, value_name = "FILE", conflicts_with = "batch_mode")]
+    pub history_db: Option<PathBuf>,
+
+    /// Delete history older than this many days when starting
+    #[arg(long, value_name = "DAYS", requires = "history_db", default_value_t = 7, value_parser = clap::value_parser!(u64).range(1..))]
+    pub history_retention_days: u64,
+
+    /// Keep the history of processes using at least this much CPU, in percent
+    #[arg(long, value_name = "PERCENT", requires = "history_db", default_value_t = 1.0, value_parser = parse_threshold)]
+    pub history_min_cpu: f64,
+
+    /// Also keep the history of processes using at least this much memory
+    #[arg(long, value_name = "MB", requires = "history_db", default_value_t = 100)]
+    pub history_min_mem_mb: u64,
+
+    /// Play back a --record session instead of watching this machine
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["batch_mode", "connect", "serve", "headless", "record", "history_db"])]
+    pub replay: Option<PathBuf>,
+
+    /// Serve Prometheus metrics at http://ADDR/metrics (needs the prometheus feature)
+    #[arg(long, value_name = "ADDR", conflicts_with = "batch_mode")]
+    pub prometheus_listen: Option<String>,
+
+    /// Also export per-process metrics, summed by name, for processes matching QUERY
+    #[arg(long, value_name = "QUERY", requires = "prometheus_listen")]
+    pub prometheus_processes: Option<String>,
+
+    /// Serve the JSON API at http://ADDR/api/ (needs the http-api feature)
+    #[arg(long, value_name = "ADDR", conflicts_with = "batch_mode")]
+    pub http_listen: Option<String>,
+
+    /// Run headless and stream snapshots to `--connect` viewers on ADDR
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["batch_mode", "connect"])]
+    pub serve: Option<String>,
+
+    /// Show the processes of a `--serve` agent instead of this machine
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "batch_mode")]
+    pub connect: Option<String>,
+
+    /// Run without the interface, only feeding the outputs such as --log-csv
+    #[arg(long, requires = "outputs", conflicts_with = "batch_mode")]
+    pub headless: bool,
+}
+
+fn parse_delay(text: &str) -> Result<Duration, String> {
+    match text.parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        Ok(_) => Err("must be a positive number of seconds".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Write a commented config.toml with every setting and its default
+    InitConfig {
+        /// Where to write it, or - for stdout [default: the user config directory]
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Compare two `--once --format json` snapshots: new, gone, restarted and changed processes
+    Diff {
+        #[arg(value_name = "BEFORE")]
+        before: PathBuf,
+
+        #[arg(value_name = "AFTER")]
+        after: PathBuf,
+
+        /// Smallest change to report: CPU percentage points, or percent of memory
+        #[arg(long, value_name = "N", default_value_t = 10.0, value_parser = parse_threshold)]
+        threshold: f64,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+}
+
+fn parse_threshold(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(n),
+        Ok(_) => Err("must be zero or a positive number".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl Cli {
+    // Whether the figures are written out as sentences instead of drawing the interface: when
+    // asked for, or when stdout isn't a terminal and nothing else was asked for. Outputs such
+    // as --log-csv only run alongside the interface or headless, so asking for one keeps the
+    // interface rather than silently dropping the output.
+    pub fn accessible_output(&self, terminal: bool) -> bool {
+        let batch = self.batch || self.once || self.stream;
+        let outputs = self.log_csv.is_some() || self.record.is_some() || self.prometheus_listen.is_some() || self.http_listen.is_some() || self.history_db.is_some();
+        self.accessible || (!batch && !terminal && !outputs && !self.headless && self.serve.is_none() && self.connect.is_none() && self.replay.is_none())
+    }
+}
+
+// Precedence is CLI flags, then RUST_HTOP_* variables, then config.toml, then the built-in
+// defaults. `config` already holds the variables and file values layered over the defaults, so
+// only flags that were given override it.
+pub fn merge(mut config: Config, cli: &Cli) -> Config {
+    if let Some(ms) = cli.refresh_ms {
+        config.refresh_rate = ms;
+    }
+    if let Some(sort) = &cli.sort {
+        config.default_sort = sort.clone();
+    }
+    if cli.descending {
+        config.descending = true;
+    }
+    if cli.ascending {
+        config.descending = false;
+    }
+    if let Some(filter) = &cli.filter {
+        config.default_filter = filter.clone();
+    }
+    if cli.tree {
+        config.tree_view = true;
+    }
+    if cli.no_tree {
+        config.tree_view = false;
+    }
+    if cli.hide_kernel_threads {
+        config.show_kernel_threads = false;
+    }
+    if cli.show_kernel_threads {
+        config.show_kernel_threads = true;
+    }
+    if cli.paused {
+        config.start_paused = true;
+    }
+    if cli.show_perf {
+        config.show_perf = true;
+    }
+    if let Some(limit) = cli.limit {
+        config.row_limit = limit;
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("rust-htop").chain(args.iter().copied())).unwrap()
+    }
+
+    fn file(text: &str) -> Config {
+        config::parse(text, "config.toml".as_ref()).unwrap().0
+    }
+
+    #[test]
+    fn defaults_without_file_or_flags() {
+        let merged = merge(Config::default(), &cli(&[]));
+        assert_eq!(merged.refresh_rate, 1000);
+        assert_eq!(merged.default_sort, "cpu");
+        assert!(merged.descending);
+        assert!(merged.default_filter.is_empty());
+        assert!(!merged.tree_view);
+        assert!(merged.show_kernel_threads);
+        assert!(!merged.start_paused);
+    }
+
+    #[test]
+    fn file_values_survive_when_no_flag_is_given() {
+        let merged = merge(file("refresh_rate = 250\ndefault_sort = \"mem\"\ndescending = false\n"), &cli(&[]));
+        assert_eq!(merged.refresh_rate, 250);
+        assert_eq!(merged.default_sort, "mem");
+        assert!(!merged.descending);
+    }
+
+    #[test]
+    fn flags_override_file_values() {
+        let base = file("refresh_rate = 250\ndefault_sort = \"mem\"\ndescending = false\n");
+        let merged = merge(base, &cli(&["--refresh-ms", "500", "--sort", "name", "--descending", "--filter", "nginx", "--tree", "--hide-kernel-threads"]));
+        assert_eq!(merged.refresh_rate, 500);
+        assert_eq!(merged.default_sort, "name");
+        assert!(merged.descending);
+        assert_eq!(merged.default_filter, "nginx");
+        assert!(merged.tree_view);
+        assert!(!merged.show_kernel_threads);
+        assert!(!merge(Config::default(), &cli(&["--ascending"])).descending);
+    }
+
+    #[test]
+    fn flags_override_environment_variables() {
+        let vars = [("RUST_HTOP_REFRESH_MS", "500"), ("RUST_HTOP_SORT", "name")].map(|(name, value)| (name.to_string(), value.to_string()));
+        let (base, _) = config::apply_env(file("refresh_rate = 250\ndefault_sort = \"mem\"\n"), vars);
+        let merged = merge(base.clone(), &cli(&["--refresh-ms", "100"]));
+        assert_eq!((merged.refresh_rate, merged.default_sort.as_str()), (100, "name"));
+        assert_eq!(merge(base, &cli(&[])).refresh_rate, 500);
+    }
+
+    #[test]
+    fn startup_view_options_come_from_the_file_unless_flagged() {
+        let base = file("default_filter = \"myapp\"\ntree_view = true\nshow_kernel_threads = false\nstart_paused = true\n");
+        let merged = merge(base.clone(), &cli(&[]));
+        assert_eq!(merged.default_filter, "myapp");
+        assert!(merged.tree_view && !merged.show_kernel_threads && merged.start_paused);
+        let merged = merge(base, &cli(&["--filter", "", "--no-tree", "--show-kernel-threads"]));
+        assert!(merged.default_filter.is_empty());
+        assert!(!merged.tree_view && merged.show_kernel_threads);
+    }
+
+    #[test]
+    fn version_includes_crate_version_and_commit() {
+        let err = Cli::try_parse_from(["rust-htop", "--version"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+        let text = err.to_string();
+        assert!(text.starts_with(&format!("rust-htop {} (", env!("CARGO_PKG_VERSION"))), "{}", text);
+        assert!(!text.contains("()"), "{}", text);
+    }
+
+    #[test]
+    fn batch_options() {
+        let args = cli(&["-b", "-n", "3", "-d", "0.5"]);
+        assert!(args.batch);
+        assert_eq!(args.iterations, Some(3));
+        assert_eq!(args.delay, Some(Duration::from_millis(500)));
+        assert_eq!(args.format, Format::Text);
+        let args = cli(&["--once", "--format", "json", "--limit", "10"]);
+        assert!(args.once && args.format == Format::Json && args.limit == Some(10));
+        let args = cli(&["--stream", "--interval", "2", "--limit", "5", "--filter", "nginx"]);
+        assert!(args.stream && args.delay == Some(Duration::from_secs(2)));
+        let args = cli(&["--accessible", "-n", "2", "--limit", "3", "--sort", "mem"]);
+        assert!(args.accessible && args.iterations == Some(2) && args.limit == Some(3));
+        // The interface takes it too
+        assert_eq!(merge(Config::default(), &cli(&["--limit", "20"])).row_limit, 20);
+    }
+
+    #[test]
+    fn every_sort_key_is_a_sort_flag() {
+        for sort in SortBy::ALL {
+            assert_eq!(cli(&["--sort", sort.name()]).sort.as_deref(), Some(sort.name()));
+        }
+    }
+
+    #[test]
+    fn redirected_output_is_written_as_sentences_unless_an_output_was_asked_for() {
+        assert!(cli(&[]).accessible_output(false));
+        assert!(!cli(&[]).accessible_output(true));
+        assert!(cli(&["--accessible"]).accessible_output(true));
+        assert!(!cli(&["--once"]).accessible_output(false));
+        for args in [&["--log-csv", "x.csv"][..], &["--record", "s.rhr"], &["--prometheus-listen", ":9100"], &["--http-listen", ":8080"], &["--history-db", "h.sqlite"], &["--replay", "s.rhr"], &["--connect", "db1:7070"]] {
+            assert!(!cli(args).accessible_output(false), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn csv_log_options() {
+        let args = cli(&["--log-csv", "out.csv", "--headless"]);
+        assert_eq!(args.log_csv.as_deref(), Some("out.csv".as_ref()));
+        assert_eq!(args.log_interval, Duration::from_secs(5));
+        assert!(args.headless);
+        assert_eq!(cli(&["--log-csv", "out.csv", "--log-interval", "30"]).log_interval, Duration::from_secs(30));
+        let args = cli(&["--headless", "--prometheus-listen", "0.0.0.0:9898", "--prometheus-processes", "nginx"]);
+        assert!(args.headless && args.log_csv.is_none());
+        assert_eq!(args.prometheus_listen.as_deref(), Some("0.0.0.0:9898"));
+        assert_eq!(args.prometheus_processes.as_deref(), Some("nginx"));
+        assert_eq!(cli(&["--headless", "--http-listen", "127.0.0.1:8080"]).http_listen.as_deref(), Some("127.0.0.1:8080"));
+    }
+
+    #[test]
+    fn record_options() {
+        let args = cli(&["--record", "session.rhr"]);
+        assert_eq!(args.record.as_deref(), Some("session.rhr".as_ref()));
+        assert_eq!(args.record_max_mb, 100);
+        assert!(cli(&["--record", "session.rhr", "--record-max-mb", "5", "--headless"]).record_max_mb == 5);
+    }
+
+    #[test]
+    fn history_options() {
+        let args = cli(&["--history-db", "history.sqlite"]);
+        assert_eq!(args.history_db.as_deref(), Some("history.sqlite".as_ref()));
+        assert_eq!((args.history_retention_days, args.history_min_cpu, args.history_min_mem_mb), (7, 1.0, 100));
+        let args = cli(&["--headless", "--history-db", "h.sqlite", "--history-retention-days", "30", "--history-min-cpu", "5", "--history-min-mem-mb", "0"]);
+        assert_eq!((args.history_retention_days, args.history_min_cpu, args.history_min_mem_mb), (30, 5.0, 0));
+        assert!(Cli::try_parse_from(["rust-htop", "--replay", "s.rhr", "--history-db", "h.sqlite"]).is_err());
+    }
+
+    #[test]
+    fn replay_options() {
+        assert_eq!(cli(&["--replay", "session.rhr", "--sort", "mem"]).replay.as_deref(), Some("session.rhr".as_ref()));
+        for args in [&["--replay", "s.rhr", "--record", "t.rhr"][..], &["--replay", "s.rhr", "--headless", "--log-csv", "x.csv"], &["--replay", "s.rhr", "--once"], &["--replay", "s.rhr", "--connect", "db1:7070"]] {
+            assert!(Cli::try_parse_from(std::iter::once("rust-htop").chain(args.iter().copied())).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn remote_options() {
+        assert_eq!(cli(&["--serve", "0.0.0.0:7070"]).serve.as_deref(), Some("0.0.0.0:7070"));
+        let args = cli(&["--connect", "db1:7070", "--sort", "mem"]);
+        assert_eq!(args.connect.as_deref(), Some("db1:7070"));
+        // A viewer can feed the other outputs from the remote data
+        assert!(cli(&["--connect", "db1:7070", "--headless", "--log-csv", "db1.csv"]).headless);
+    }
+
+    #[test]
+    fn diff_options() {
+        let Some(Command::Diff { before, after, threshold, format }) = cli(&["diff", "a.json", "b.json"]).command else { panic!("not a diff") };
+        assert_eq!((before, after), ("a.json".into(), "b.json".into()));
+        assert_eq!((threshold, format), (10.0, Format::Text));
+        let Some(Command::Diff { threshold, format, .. }) = cli(&["diff", "a.json", "b.json", "--threshold", "2.5", "--format", "json"]).command else { panic!("not a diff") };
+        assert_eq!((threshold, format), (2.5, Format::Json));
+        for args in [&["diff", "a.json"][..], &["diff", "a.json", "b.json", "--threshold", "-1"]] {
+            assert!(Cli::try_parse_from(std::iter::once("rust-htop").chain(args.iter().copied())).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn debug_log_options() {
+        let args = cli(&["--debug-log", "/tmp/rust-htop.log"]);
+        assert_eq!(args.debug_log.as_deref(), Some("/tmp/rust-htop.log".as_ref()));
+        assert_eq!(args.debug_level, "debug");
+        assert_eq!(cli(&["--debug-log", "x.log", "--debug-level", "trace"]).debug_level, "trace");
+        for args in [&["--debug-level", "trace"][..], &["--debug-log", "x.log", "--debug-level", "loud"]] {
+            assert!(Cli::try_parse_from(std::iter::once("rust-htop").chain(args.iter().copied())).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_rejected_by_clap() {
+        for args in [&["--sort", "size"][..], &["--refresh-ms", "0"], &["--refresh-ms", "99"], &["--refresh-ms", "60001"], &["--refresh-ms", "fast"], &["--ascending", "--descending"], &["--tree", "--no-tree"], &["--hide-kernel-threads", "--show-kernel-threads"], &["-n", "1"], &["-b", "-d", "0"], &["-b", "-d", "soon"], &["--format", "json"], &["--once", "-n", "2"], &["--once", "--format", "xml"], &["--headless"], &["--log-interval", "5"], &["--log-csv", "out.csv", "--headless", "--once"], &["--stream", "--once"], &["--stream", "-n", "3"], &["--prometheus-processes", "nginx"], &["--serve", "0.0.0.0:7070", "--connect", "db1:7070"], &["--serve", ":7070", "--once"], &["--connect", "db1:7070", "-b"], &["--record-max-mb", "5"], &["--record", "s.rhr", "--record-max-mb", "0"], &["--history-retention-days", "3"], &["--history-db", "h.sqlite", "--history-retention-days", "0"], &["--history-db", "h.sqlite", "--once"], &["--accessible", "--format", "json"], &["--limit", "0"], &["--accessible", "--stream"], &["--log-csv", "out.csv", "--once"], &["--record", "s.rhr", "-b"], &["--prometheus-listen", ":9100", "--stream"], &["--http-listen", ":8080", "--once"], &["--log-csv", "out.csv", "--accessible"]] {
+            let parsed = Cli::try_parse_from(std::iter::once("rust-htop").chain(args.iter().copied()));
+            assert!(parsed.is_err(), "{:?} should be rejected", args);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use serde_json::Value;
+use sysinfo::PidExt;
+
+use crate::app::{ProcInfo, SortBy};
+
+// Every column the process table can show. The table and the export both go through this
+// list, so a column added here shows up in both with the same name and order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Column {
+    Pid,
+    Name,
+    Cpu,
+    Mem,
+    // Open file descriptors, counted in the background; see fds.rs
+    Fds,
+    // Open TCP and UDP sockets, counted along with the descriptors
+    Conn,
+    // Bytes queued in those sockets, with the netio feature; see netio.rs
+    Net,
+    // Swapped-out memory, read from /proc/<pid>/status on each refresh
+    Swap,
+    // Context switches since the previous refresh, from the same status file
+    Vcsw,
+    Ivcsw,
+    // Page faults per second since the previous refresh, from /proc/<pid>/stat
+    Minflt,
+    Majflt,
+    // The logical CPU the process last ran on, from the same stat file
+    Core,
+    // The container's name from Docker or Podman, or its short ID; see containers.rs
+    Container,
+}
+
+pub const DEFAULT_COLUMNS: &[&str] = &["pid", "name", "cpu", "mem"];
+
+impl Column {
+    pub const ALL: [Column; 14] = [Column::Pid, Column::Name, Column::Cpu, Column::Mem, Column::Fds, Column::Conn, Column::Net, Column::Swap, Column::Vcsw, Column::Ivcsw, Column::Minflt, Column::Majflt, Column::Core, Column::Container];
+
+    // The name used in the `columns` setting
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Pid => "pid",
+            Column::Name => "name",
+            Column::Cpu => "cpu",
+            Column::Mem => "mem",
+            Column::Fds => "fds",
+            Column::Conn => "conn",
+            Column::Net => "net",
+            Column::Swap => "swap",
+            Column::Vcsw => "vcsw",
+            Column::Ivcsw => "ivcsw",
+            Column::Minflt => "minflt",
+            Column::Majflt => "majflt",
+            Column::Core => "core",
+            Column::Container => "container",
+        }
+    }
+
+    // Every column sorts the table; the sort menu lists them from here
+    pub fn sort(self) -> SortBy {
+        match self {
+            Column::Pid => SortBy::Pid,
+            Column::Name => SortBy::Name,
+            Column::Cpu => SortBy::Cpu,
+            Column::Mem => SortBy::Mem,
+            Column::Fds => SortBy::Fds,
+            Column::Conn => SortBy::Conn,
+            Column::Net => SortBy::Net,
+            Column::Swap => SortBy::Swap,
+            Column::Vcsw => SortBy::Vcsw,
+            Column::Ivcsw => SortBy::Ivcsw,
+            Column::Minflt => SortBy::Minflt,
+            Column::Majflt => SortBy::Majflt,
+            Column::Core => SortBy::Core,
+            Column::Container => SortBy::Container,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Pid => "PID",
+            Column::Name => "Name",
+            Column::Cpu => "CPU %",
+            Column::Mem => "Memory MB",
+            Column::Fds => "FDs",
+            Column::Conn => "Conns",
+            Column::Net => "Net queue MB",
+            Column::Swap => "Swap MB",
+            Column::Vcsw => "VCSW",
+            Column::Ivcsw => "IVCSW",
+            Column::Minflt => "MINFLT/s",
+            Column::Majflt => "MAJFLT/s",
+            Column::Core => "Core",
+            Column::Container => "Container",
+        }
+    }
+
+    pub fn width(self) -> u16 {
+        match self {
+            Column::Pid => 10,
+            Column::Name => 25,
+            Column::Cpu => 10,
+            Column::Mem => 15,
+            Column::Fds => 7,
+            Column::Conn => 7,
+            Column::Net | Column::Swap => 15,
+            Column::Vcsw | Column::Ivcsw => 9,
+            Column::Minflt | Column::Majflt => 10,
+            Column::Core => 6,
+            Column::Container => 20,
+        }
+    }
+
+    // The field name in exports; these follow the JSON snapshot where it has the same field
+    pub fn field(self) -> &'static str {
+        match self {
+            Column::Pid => "pid",
+            Column::Name => "name",
+            Column::Cpu => "cpu_percent",
+            Column::Mem => "mem_bytes",
+            Column::Fds => "open_fds",
+            Column::Conn => "connections",
+            Column::Net => "net_queued_bytes",
+            Column::Swap => "swap_bytes",
+            Column::Vcsw => "voluntary_switches",
+            Column::Ivcsw => "involuntary_switches",
+            Column::Minflt => "minor_faults_per_second",
+            Column::Majflt => "major_faults_per_second",
+            Column::Core => "processor",
+            Column::Container => "container",
+        }
+    }
+
+    // The cached cell text; see ProcInfo
+    pub fn text(self, p: &ProcInfo) -> &str {
+        match self {
+            Column::Pid => &p.pid_text,
+            Column::Name => &p.name,
+            Column::Cpu => &p.cpu_text,
+            Column::Mem => &p.mem_text,
+            Column::Fds => &p.fds_text,
+            Column::Conn => &p.conns_text,
+            Column::Net => &p.net_text,
+            Column::Swap => &p.swap_text,
+            Column::Vcsw => &p.vcsw_text,
+            Column::Ivcsw => &p.ivcsw_text,
+            Column::Minflt => &p.minflt_text,
+            Column::Majflt => &p.majflt_text,
+            Column::Core => &p.core_text,
+            Column::Container => &p.container_text,
+        }
+    }
+
+    // The exported value. CPU is rounded to the two decimals on screen; memory is exact. An
+    // unread FDS, CONN, SWAP, context switch count, fault rate or core is null; a fault rate is rounded to two decimals.
+    // Processes outside containers have a null container.
+    pub fn value(self, p: &ProcInfo) -> Value {
+        match self {
+            Column::Pid => Value::from(p.pid.as_u32()),
+            Column::Name => Value::from(p.name.as_str()),
+            Column::Cpu => Value::from(((p.cpu as f64) * 100.0).round() / 100.0),
+            Column::Mem => Value::from(p.mem),
+            Column::Fds => p.fds.map_or(Value::Null, Value::from),
+            Column::Conn => p.conns.map_or(Value::Null, Value::from),
+            Column::Net => p.net.map_or(Value::Null, Value::from),
+            Column::Swap => p.swap.map_or(Value::Null, Value::from),
+            Column::Vcsw => p.vcsw.map_or(Value::Null, Value::from),
+            Column::Ivcsw => p.ivcsw.map_or(Value::Null, Value::from),
+            Column::Minflt => p.minflt.map_or(Value::Null, |rate| Value::from((rate * 100.0).round() / 100.0)),
+            Column::Majflt => p.majflt.map_or(Value::Null, |rate| Value::from((rate * 100.0).round() / 100.0)),
+            Column::Core => p.processor.map_or(Value::Null, Value::from),
+            Column::Container => p.container.as_deref().map_or(Value::Null, Value::from),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Column> {
+        Column::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    // Unknown and repeated names are skipped with a warning. A list left with no columns
+    // falls back to the default one.
+    pub fn resolve(names: &[String]) -> (Vec<Column>, Vec<String>) {
+        let mut columns = Vec::new();
+        let mut warnings = Vec::new();
+        for name in names {
+            match Column::from_name(name) {
+                Some(column) if columns.contains(&column) => warnings.push(format!("column `{}` is listed twice", name)),
+                Some(column) => columns.push(column),
+                None => {
+                    let known: Vec<&str> = Column::ALL.iter().map(|c| c.name()).collect();
+                    warnings.push(format!("unknown column `{}` ignored (expected one of {})", name, known.join(", ")));
+                },
+            }
+        }
+        if columns.is_empty() {
+            warnings.push("no columns to show; using the default columns".into());
+            columns = DEFAULT_COLUMNS.iter().filter_map(|name| Column::from_name(name)).collect();
+        }
+        (columns, warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn defaults_name_every_column_in_order() {
+        let (columns, warnings) = Column::resolve(&names(DEFAULT_COLUMNS));
+        assert_eq!(columns, Column::ALL[..4].to_vec());
+        assert!(warnings.is_empty());
+        let (columns, _) = Column::resolve(&names(&Column::ALL.map(Column::name)));
+        assert_eq!(columns, Column::ALL.to_vec());
+    }
+
+    #[test]
+    fn bad_names_are_warnings() {
+        let (columns, warnings) = Column::resolve(&names(&["mem", "size", "pid", "mem"]));
+        assert_eq!(columns, vec![Column::Mem, Column::Pid]);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("unknown column `size`"), "{:?}", warnings);
+        let (columns, warnings) = Column::resolve(&[]);
+        assert_eq!(columns, Column::ALL[..4].to_vec());
+        assert_eq!(warnings.len(), 1);
+    }
+}
@@ -0,0 +1,988 @@
+use std::{collections::BTreeMap, env, fmt, fs, io, path::{Path, PathBuf}, time::SystemTime};
+use chrono::format::{Item, StrftimeItems};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::{alerts::{AlertConfig, Alerts}, app::{NameDisplay, SortBy}, columns::{self, Column}, keys::{KeySpec, Keymap}, theme::{Theme, ThemeConfig}};
+
+// Every key is optional so a partial config.toml only overrides what it mentions
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(deserialize_with = "milliseconds")]
+    pub refresh_rate: u64,
+    pub default_sort: String,
+    pub descending: bool,
+    pub default_filter: String,
+    pub tree_view: bool,
+    // In tree view, order siblings by the CPU or memory of their whole subtree
+    pub tree_totals: bool,
+    // Show the memory breakdown under the network line
+    pub memory_detail: bool,
+    // The sum, average and maximum of the listed rows below the table
+    pub show_footer: bool,
+    pub show_kernel_threads: bool,
+    // Hide processes below both thresholds
+    pub hide_idle: bool,
+    // Leave rust-htop's own process out of the table
+    pub hide_self: bool,
+    pub hide_below_cpu: f32,
+    pub hide_below_mem_mb: u64,
+    // List only the first this many rows; 0 lists them all
+    pub row_limit: usize,
+    // Up on the first row goes to the last and Down on the last to the first
+    pub wrap_navigation: bool,
+    pub start_paused: bool,
+    pub full_refresh: bool,
+    // Start with the frame timings overlay shown
+    pub show_perf: bool,
+    // How long a status message stays up; 0 keeps it until a key is pressed
+    pub status_seconds: u64,
+    // Never ring the terminal bell; bell alerts only flash
+    pub quiet: bool,
+    // Let alert rules run their `on_fire` commands
+    pub allow_exec: bool,
+    // Column names in display order
+    pub columns: Vec<String>,
+    // What the Name column shows: "basename", "exe_path" or "cmdline"
+    pub name_display: String,
+    // "HP 12/64 × 2MiB" after the uptime for each huge page pool that is set aside
+    pub show_huge_pages: bool,
+    // Each interface's errors and drops since the previous refresh, after its totals
+    pub show_net_errors: bool,
+    // "RT" before the name of processes under a real-time scheduling policy
+    pub realtime_badge: bool,
+    // How many exited processes the exit log keeps; 0 keeps none
+    pub exit_log_size: usize,
+    // "rust-htop — <host> [<filter>]" as the terminal window or tab title
+    pub set_terminal_title: bool,
+    // strftime-style format of the clock and snapshot time in the header
+    pub time_format: String,
+    pub export_format: String,
+    // Where 'E' writes; empty for the working directory
+    pub export_dir: String,
+    // How exports and the CSV log write times ("rfc3339" or strftime), decimals and fields
+    pub export_timestamp_format: String,
+    pub export_decimal_separator: String,
+    pub export_delimiter: String,
+    // Watch patterns, matched like the search box
+    pub watches: Vec<String>,
+    pub theme: ThemeConfig,
+    // Action name to key(s); actions left out keep their default keys
+    pub keys: BTreeMap<String, KeySpec>,
+    // [[alert]] rules, in the order they are checked
+    #[serde(rename = "alert")]
+    pub alerts: Vec<AlertConfig>,
+    // [profile.<name>] tables of overrides. They are merged into the top level while loading,
+    // so this is only kept to list the available names.
+    #[serde(rename = "profile", skip_serializing)]
+    pub profiles: BTreeMap<String, toml::Table>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh_rate: 1000,
+            default_sort: "cpu".into(),
+            descending: true,
+            default_filter: String::new(),
+            tree_view: false,
+            tree_totals: false,
+            memory_detail: false,
+            show_footer: false,
+            show_kernel_threads: true,
+            hide_idle: false,
+            hide_self: false,
+            hide_below_cpu: 0.5,
+            hide_below_mem_mb: 10,
+            row_limit: 0,
+            wrap_navigation: false,
+            start_paused: false,
+            full_refresh: false,
+            show_perf: false,
+            status_seconds: 10,
+            quiet: false,
+            allow_exec: false,
+            columns: columns::DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect(),
+            name_display: "basename".into(),
+            show_huge_pages: true,
+            show_net_errors: true,
+            realtime_badge: true,
+            exit_log_size: 200,
+            set_terminal_title: true,
+            time_format: "%H:%M:%S".into(),
+            export_format: "csv".into(),
+            export_dir: String::new(),
+            export_timestamp_format: "rfc3339".into(),
+            export_decimal_separator: ".".into(),
+            export_delimiter: ",".into(),
+            watches: Vec::new(),
+            theme: ThemeConfig::default(),
+            keys: BTreeMap::new(),
+            alerts: Vec::new(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+// One-line description of every key, written above it in the generated template. A test
+// checks this stays in step with the serialized Config.
+const KEY_DOCS: &[(&str, &str)] = &[
+    ("refresh_rate", "Refresh interval in milliseconds (--refresh-ms)"),
+    ("default_sort", "Initial sort column: \"pid\", \"cpu\", \"mem\", \"name\", \"fds\", \"conn\", \"net\", \"swap\", \"vcsw\", \"ivcsw\", \"minflt\", \"majflt\", \"core\" or \"container\" (--sort)"),
+    ("descending", "Sort in descending order at startup (--descending / --ascending)"),
+    ("default_filter", "Search query applied at startup; empty for none (--filter)"),
+    ("tree_view", "Start in tree view (--tree / --no-tree)"),
+    ("tree_totals", "In tree view, order siblings by their whole subtree's CPU or memory and show the totals on parent rows ('T' toggles)"),
+    ("memory_detail", "Start with the header's memory breakdown shown: total, used, available, cached, buffers and dirty ('M' toggles)"),
+    ("show_footer", "Start with a line below the table that adds up the listed rows: the sum, average and maximum of their CPU % and memory ('=' toggles)"),
+    ("show_kernel_threads", "List kernel threads (--show-kernel-threads / --hide-kernel-threads)"),
+    ("hide_idle", "Start with idle processes hidden ('h' toggles)"),
+    ("hide_self", "Leave rust-htop's own process out of the table ('O' toggles)"),
+    ("hide_below_cpu", "While idle processes are hidden, a process still shows if its CPU % is at least this..."),
+    ("hide_below_mem_mb", "...or if it uses at least this many MB of memory"),
+    ("row_limit", "List only the first this many processes after filtering and sorting, with a count of the rest; 0 lists them all (--limit)"),
+    ("wrap_navigation", "Up on the first row selects the last and Down on the last selects the first; Home and End always go to the ends"),
+    ("start_paused", "Start with refreshing paused (--paused)"),
+    ("full_refresh", "Refresh every sysinfo subsystem each cycle instead of only what is displayed"),
+    ("show_perf", "Start with rust-htop's own refresh, row and draw times shown in a corner (--show-perf; ctrl+p toggles)"),
+    ("status_seconds", "Seconds a status message stays in place of the search bar, unless a key is pressed first; 0 keeps it until then. ctrl+l shows the last 100"),
+    ("quiet", "Never ring the terminal bell; alerts with action = \"bell\" only flash the table header"),
+    ("allow_exec", "Let alert rules run their `on_fire` shell commands; off, the commands are ignored"),
+    ("columns", "Process table columns in order, from \"pid\", \"name\", \"cpu\", \"mem\", \"fds\", \"conn\", \"net\", \"swap\", \"vcsw\", \"ivcsw\", \"minflt\", \"majflt\", \"core\" and \"container\" (the ones after mem cost extra /proc reads, so they are off by default)"),
+    ("name_display", "What the Name column shows: \"basename\", \"exe_path\" or \"cmdline\" ('N' cycles); the search matches all three"),
+    ("show_huge_pages", "Show each huge page pool's pages in use, of those set aside, after the uptime, like \"HP 12/64 × 2MiB\"; nothing is shown where no huge pages are configured"),
+    ("show_net_errors", "Show each interface's receive/transmit errors and drops since the previous refresh in the header; an interface with new errors turns red"),
+    ("realtime_badge", "Mark processes running under SCHED_FIFO, SCHED_RR or SCHED_DEADLINE with \"RT\" before their name (Linux; costs a /proc/<pid>/stat read per process each refresh). ctrl+r lists them with the processes at a negative nice"),
+    ("exit_log_size", "How many of the processes that exited between refreshes 'e' lists, newest first; 0 keeps none"),
+    ("set_terminal_title", "Title the terminal window or tab \"rust-htop — <host>\", with the search in brackets while there is one; the old title comes back on exit. Turn off where a multiplexer manages titles"),
+    ("time_format", "How the header shows the time now and of the snapshot on screen, strftime-style like \"%H:%M:%S\" or \"%a %d %b %H:%M\""),
+    ("export_format", "What 'E' writes the visible table as: \"csv\" or \"json\""),
+    ("export_dir", "Directory 'E' writes exports to; empty for the working directory"),
+    ("export_timestamp_format", "How exports and --csv-log write times: \"rfc3339\", like 2024-05-01T03:14:05+02:00, or strftime-style like \"%d.%m.%Y %H:%M:%S\". The interface keeps time_format"),
+    ("export_decimal_separator", "The decimal point of numbers in CSV exports and --csv-log: \".\" or \",\". JSON numbers always use \".\""),
+    ("export_delimiter", "What separates CSV fields: \",\", \";\", \"|\" or \"\\t\"; it can't be the decimal separator too"),
+    ("watches", "Processes matching any of these, like the search box, are always highlighted ('w' adds, 'L' lists)"),
+    ("theme", "Colour theme: \"dark\", \"light\" or \"mono\". To change single elements (text, header, border,
+# selected_row, watch, cpu_warn, cpu_crit, gauge_fill, warning, error) replace this line with a [theme] table
+# holding `name` and a [theme.colors] table of names, \"#rrggbb\" or 0-255, e.g. header = \"cyan\""),
+    ("keys", "Key bindings, e.g. quit = [\"q\", \"ctrl+c\"] or toggle_tree = \"F5\". Press ? to see every action"),
+    ("alert", "Alert rules, each an [[alert]] table. The condition is either `when`, like \"process cpu > 90% for 30s\"
+# or \"memory > 95%\", or `metric` (process_cpu, process_mem, system_cpu, system_mem, system_swap or load)
+# with `threshold` (percent; MB for process_mem), an optional `match` for process metrics and
+# `for_seconds`. Optional `name`, `cooldown` (default \"5m\"), `action` (\"notify\", \"banner\", \"bell\")
+# and `on_fire`, a shell command run when the rule fires if allow_exec = true, e.g.
+#   [[alert]]
+#   metric = \"process_cpu\"
+#   match = \"postgres\"
+#   threshold = 80
+#   for_seconds = 30
+#   action = \"banner\""),
+];
+
+const TEMPLATE_HEADER: &str = "\
+# rust-htop configuration, generated by `rust-htop init-config`.
+# Every key is optional; anything left out keeps the default shown here.
+# Precedence, highest first: command-line flags (named next to each key), RUST_HTOP_<KEY>
+# environment variables, the selected profile, the values in this file, then the built-in
+# defaults.
+#
+# Profiles are [profile.<name>] tables that can override any key below, e.g.
+#   [profile.db]
+#   refresh_rate = 500
+#   default_filter = \"postgres\"
+# and are picked with --profile <name> or the RUST_HTOP_PROFILE environment variable.
+";
+
+fn key_doc(key: &str) -> Option<&'static str> {
+    KEY_DOCS.iter().find(|(k, _)| *k == key).map(|(_, doc)| *doc)
+}
+
+// The defaults serialized as toml with each key's description above it
+pub fn template() -> String {
+    let body = toml::to_string(&Config::default()).expect("default config serializes");
+    let mut out = String::from(TEMPLATE_HEADER);
+    let mut table = String::new();
+    // Blank lines are dropped since every key and table gets one above its description
+    for line in body.lines().filter(|l| !l.is_empty()) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            table = format!("{}.", name);
+            out.push('\n');
+            if let Some(doc) = key_doc(name) {
+                out.push_str(&format!("# {}\n", doc));
+            }
+        } else if let Some((key, _)) = line.split_once(" = ") {
+            out.push('\n');
+            if let Some(doc) = key_doc(&format!("{}{}", table, key)) {
+                out.push_str(&format!("# {}\n", doc));
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+// Where `init-config` writes by default: the XDG / platform location rather than the cwd
+pub fn default_path() -> PathBuf {
+    search_paths().into_iter().next().unwrap_or_else(|| PathBuf::from(FILE_NAME))
+}
+
+// Refuses to replace an existing file unless `force` is set
+pub fn write_template(path: &Path, force: bool) -> io::Result<()> {
+    if path.exists() && !force {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists (use --force to overwrite)", path.display())));
+    }
+    if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, template())
+}
+
+// Write `config` to `path`, keeping comments and keys the user added by editing the existing
+// document in place. The new contents go to a temporary file that is renamed over the
+// original, so an interrupted save never leaves a truncated config behind. With a profile
+// selected the settings go into its [profile.<name>] table instead of the top level.
+pub fn save(config: &Config, path: &Path, profile: Option<&str>) -> io::Result<()> {
+    let existing = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let text = merge_into_document(&existing, config, profile).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("toml.tmp");
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+fn merge_into_document(existing: &str, config: &Config, profile: Option<&str>) -> Result<String, String> {
+    let mut doc: toml_edit::DocumentMut = existing.parse().map_err(|e: toml_edit::TomlError| e.to_string())?;
+    let Some(name) = profile else {
+        let new: toml_edit::DocumentMut = toml::to_string(config).map_err(|e| e.to_string())?.parse().map_err(|e: toml_edit::TomlError| e.to_string())?;
+        merge_tables(doc.as_table_mut(), new.as_table());
+        return Ok(doc.to_string());
+    };
+
+    // A profile only records what differs from the top level, plus the keys it already had, so
+    // the rest keeps following top-level edits
+    let base = toml::Table::try_from(parse(existing, Path::new("")).map_err(|e| e.to_string())?.0).map_err(|e| e.to_string())?;
+    let current = toml::Table::try_from(config).map_err(|e| e.to_string())?;
+    let profiles = doc.entry("profile").or_insert_with(|| {
+        let mut table = toml_edit::Table::new();
+        table.set_implicit(true);
+        toml_edit::Item::Table(table)
+    });
+    let section = profiles.as_table_mut().ok_or("`profile` is not a table")?.entry(name).or_insert_with(toml_edit::table);
+    let section = section.as_table_mut().ok_or_else(|| format!("`profile.{}` is not a table", name))?;
+    let changes = profile_changes(current, &base, section);
+    let new: toml_edit::DocumentMut = toml::to_string(&changes).map_err(|e| e.to_string())?.parse().map_err(|e: toml_edit::TomlError| e.to_string())?;
+    merge_tables(section, new.as_table());
+    Ok(doc.to_string())
+}
+
+// The keys of `current` that differ from `base` or are already set in the profile `section`,
+// comparing nested tables key by key
+fn profile_changes(current: toml::Table, base: &toml::Table, section: &toml_edit::Table) -> toml::Table {
+    let mut changes = toml::Table::new();
+    for (key, value) in current {
+        let existing = section.get(&key);
+        match (value, base.get(&key)) {
+            (toml::Value::Table(current), Some(toml::Value::Table(base))) => {
+                let empty = toml_edit::Table::new();
+                let nested = profile_changes(current, base, existing.and_then(toml_edit::Item::as_table).unwrap_or(&empty));
+                if !nested.is_empty() {
+                    changes.insert(key, toml::Value::Table(nested));
+                }
+            },
+            (value, base) => {
+                if base != Some(&value) || existing.is_some() {
+                    changes.insert(key, value);
+                }
+            },
+        }
+    }
+    changes
+}
+
+fn merge_tables(dst: &mut toml_edit::Table, src: &toml_edit::Table) {
+    for (key, item) in src.iter() {
+        match (dst.get_mut(key), item) {
+            (Some(toml_edit::Item::Table(dst)), toml_edit::Item::Table(src)) => merge_tables(dst, src),
+            (Some(toml_edit::Item::Value(old)), toml_edit::Item::Value(new)) => {
+                // Keep any trailing comment on the line
+                let decor = old.decor().clone();
+                *old = new.clone();
+                *old.decor_mut() = decor;
+            },
+            _ => {
+                dst.insert(key, item.clone());
+            },
+        }
+    }
+}
+
+// Polls a config file's modification time; cheap enough to call on every tick
+pub struct ConfigWatcher {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    // True once per change. A file that disappears is not a change, so deleting the
+    // config keeps the running settings.
+    pub fn changed(&mut self) -> bool {
+        match modified(&self.path) {
+            Some(time) if Some(time) != self.modified => {
+                self.modified = Some(time);
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    // toml's message already carries the line and column
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Invalid(path, msg) => write!(f, "{}: {}", path.display(), msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// The refresh_rate range. Below it every pass of the loop refreshes and pins a core; above it
+// the screen looks frozen.
+pub const MIN_REFRESH_MS: u64 = 100;
+pub const MAX_REFRESH_MS: u64 = 60_000;
+
+// A negative refresh_rate would otherwise fail with serde's "expected u64"
+fn milliseconds<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let ms = i64::deserialize(deserializer)?;
+    u64::try_from(ms).map_err(|_| serde::de::Error::custom(format!("refresh_rate must be a positive number of milliseconds, not {}", ms)))
+}
+
+impl Config {
+    // Settings that can't be used are an error. A refresh_rate out of range is brought into
+    // it instead, with a warning saying so.
+    pub fn validate(&mut self) -> Result<Vec<String>, String> {
+        let mut warnings = Vec::new();
+        let clamped = self.refresh_rate.clamp(MIN_REFRESH_MS, MAX_REFRESH_MS);
+        if clamped != self.refresh_rate {
+            warnings.push(format!("refresh_rate = {} is outside {}-{} milliseconds; using {}", self.refresh_rate, MIN_REFRESH_MS, MAX_REFRESH_MS, clamped));
+            self.refresh_rate = clamped;
+        }
+        if SortBy::from_name(&self.default_sort).is_none() {
+            let names = SortBy::ALL.map(|s| format!("{:?}", s.name()));
+            let (last, rest) = names.split_last().expect("there are sort keys");
+            return Err(format!("unknown default_sort {:?} (expected {} or {})", self.default_sort, rest.join(", "), last));
+        }
+        if self.hide_below_cpu.is_nan() || self.hide_below_cpu < 0.0 {
+            return Err(format!("hide_below_cpu must be 0 or more, not {}", self.hide_below_cpu));
+        }
+        if NameDisplay::from_name(&self.name_display).is_none() {
+            return Err(format!("unknown name_display {:?} (expected \"basename\", \"exe_path\" or \"cmdline\")", self.name_display));
+        }
+        if StrftimeItems::new(&self.time_format).any(|item| matches!(item, Item::Error)) {
+            return Err(format!("time_format {:?} is not a valid strftime format", self.time_format));
+        }
+        if !matches!(self.export_format.as_str(), "csv" | "json") {
+            return Err(format!("unknown export_format {:?} (expected \"csv\" or \"json\")", self.export_format));
+        }
+        if self.export_timestamp_format != "rfc3339" && StrftimeItems::new(&self.export_timestamp_format).any(|item| matches!(item, Item::Error)) {
+            return Err(format!("export_timestamp_format {:?} is not \"rfc3339\" or a valid strftime format", self.export_timestamp_format));
+        }
+        if !matches!(self.export_decimal_separator.as_str(), "." | ",") {
+            return Err(format!("unknown export_decimal_separator {:?} (expected \".\" or \",\")", self.export_decimal_separator));
+        }
+        if !matches!(self.export_delimiter.as_str(), "," | ";" | "|" | "\t") {
+            return Err(format!("unknown export_delimiter {:?} (expected \",\", \";\", \"|\" or \"\\t\")", self.export_delimiter));
+        }
+        // "1,50,2048" could be 1.50 and 2048 or 1 and 50.2048
+        if self.export_delimiter == self.export_decimal_separator {
+            return Err(format!("export_delimiter and export_decimal_separator are both {:?}, which would make numbers and fields run together; use export_delimiter = \";\"", self.export_delimiter));
+        }
+        Ok(warnings)
+    }
+}
+
+const FILE_NAME: &str = "config.toml";
+
+// Lookup order: $XDG_CONFIG_HOME/rust-htop, the platform config directory (which is the same
+// place on Linux), then the working directory for configs predating the lookup
+pub fn search_paths() -> Vec<PathBuf> {
+    let xdg = env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()).map(|dir| PathBuf::from(dir).join("rust-htop"));
+    let platform = ProjectDirs::from("", "", "rust-htop").map(|dirs| dirs.config_dir().to_path_buf());
+    candidates(xdg, platform)
+}
+
+fn candidates(xdg: Option<PathBuf>, platform: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for dir in [xdg, platform].into_iter().flatten() {
+        let path = dir.join(FILE_NAME);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths.push(PathBuf::from(FILE_NAME));
+    paths
+}
+
+// The first search path that exists, if any
+pub fn resolve_path() -> Option<PathBuf> {
+    search_paths().into_iter().find(|p| p.is_file())
+}
+
+pub struct Loaded {
+    pub path: Option<PathBuf>,
+    pub config: Config,
+    // What the file and profile alone set, without the RUST_HTOP_* variables; what saving
+    // starts from
+    pub file: Config,
+    pub warnings: Vec<String>,
+}
+
+// An explicitly given path overrides the search order and has to exist; without one the
+// first existing search path is loaded, or the defaults when there is none
+pub fn load_config(explicit: Option<PathBuf>, profile: Option<&str>) -> Result<Loaded, ConfigError> {
+    let path = match explicit {
+        Some(path) if !path.is_file() => {
+            return Err(ConfigError::Read(path, io::Error::new(io::ErrorKind::NotFound, "config file not found")));
+        },
+        Some(path) => Some(path),
+        None => resolve_path(),
+    };
+    let (config, warnings) = match (&path, profile) {
+        (Some(path), _) => load(path, profile)?,
+        (None, Some(name)) => {
+            let searched = search_paths().iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ");
+            return Err(ConfigError::Invalid(PathBuf::from(FILE_NAME), format!("profile `{}` was selected but no config file was found (searched {})", name, searched)));
+        },
+        (None, None) => (Config::default(), Vec::new()),
+    };
+    let (merged, env_warnings) = apply_env(config.clone(), env::vars());
+    Ok(Loaded { path, config: merged, file: config, warnings: warnings.into_iter().chain(env_warnings).collect() })
+}
+
+const ENV_PREFIX: &str = "RUST_HTOP_";
+
+// The command-line flags' names for the settings that have one, as variables
+const ENV_ALIASES: &[(&str, &str)] = &[("REFRESH_MS", "refresh_rate"), ("SORT", "default_sort"), ("FILTER", "default_filter")];
+
+// Variables the command line parser reads itself
+const ENV_FLAGS: &[&str] = &["PROFILE", "LOG"];
+
+// Settings made of tables, which a single variable can't hold
+const FILE_ONLY: &[&str] = &["keys", "alert"];
+
+// Layers RUST_HTOP_<KEY> variables (RUST_HTOP_REFRESH_RATE, RUST_HTOP_THEME, ...) over the file's
+// settings; the command-line flags go over them in turn. The keys are those of the serialized
+// Config, so a new setting can be set this way without being listed here. Switches take
+// true/false, 1/0, yes/no or on/off and lists are comma-separated. A variable that doesn't
+// parse, an unknown setting or a value the file would be rejected or warned about for is left
+// out with a warning, and the rest still apply.
+pub fn apply_env(mut config: Config, vars: impl IntoIterator<Item = (String, String)>) -> (Config, Vec<String>) {
+    let defaults = toml::Table::try_from(Config::default()).expect("the defaults serialize");
+    let mut vars: Vec<(String, String)> = vars.into_iter().filter(|(name, _)| name.starts_with(ENV_PREFIX)).collect();
+    vars.sort();
+    let mut warnings = Vec::new();
+    for (name, text) in vars {
+        let suffix = &name[ENV_PREFIX.len()..];
+        if ENV_FLAGS.contains(&suffix) {
+            continue;
+        }
+        let key = ENV_ALIASES.iter().find(|(alias, _)| *alias == suffix).map_or_else(|| suffix.to_ascii_lowercase(), |(_, key)| key.to_string());
+        let applied = match defaults.get(&key) {
+            None => Err("no such setting".to_string()),
+            Some(toml::Value::Table(_)) => Err("only config.toml can set this".to_string()),
+            Some(_) if FILE_ONLY.contains(&key.as_str()) => Err("only config.toml can set this".to_string()),
+            Some(default) => env_value(default, &text).and_then(|value| with_value(&config, &key, value)),
+        };
+        match applied {
+            Ok(updated) => config = updated,
+            Err(msg) => warnings.push(format!("{}={:?}: {}; ignored", name, text, msg)),
+        }
+    }
+    (config, warnings)
+}
+
+// The text as a value of the same type as the setting's default
+fn env_value(default: &toml::Value, text: &str) -> Result<toml::Value, String> {
+    let text = text.trim();
+    Ok(match default {
+        toml::Value::Boolean(_) => match text.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => toml::Value::Boolean(true),
+            "false" | "0" | "no" | "off" => toml::Value::Boolean(false),
+            _ => return Err("expected true or false".into()),
+        },
+        toml::Value::Integer(_) => toml::Value::Integer(text.parse().map_err(|_| "expected a whole number")?),
+        toml::Value::Float(_) => toml::Value::Float(text.parse().map_err(|_| "expected a number")?),
+        toml::Value::Array(_) => toml::Value::Array(text.split(',').map(str::trim).filter(|item| !item.is_empty()).map(|item| toml::Value::String(item.into())).collect()),
+        _ => toml::Value::String(text.into()),
+    })
+}
+
+fn with_value(config: &Config, key: &str, value: toml::Value) -> Result<Config, String> {
+    let mut table = toml::Table::try_from(config).map_err(|e| e.to_string())?;
+    // A theme with its own colours is a table; the variable picks its name and keeps the colours
+    match table.get_mut(key) {
+        Some(toml::Value::Table(current)) if value.is_str() => current.insert("name".into(), value),
+        _ => table.insert(key.into(), value),
+    };
+    let mut updated: Config = table.try_into().map_err(|e: toml::de::Error| e.message().to_string())?;
+    // A value the file would be clamped for is as likely a typo as one it is rejected for
+    if let Some(warning) = updated.validate()?.into_iter().next() {
+        return Err(warning);
+    }
+    let before = setting_warnings(config);
+    if let Some(warning) = setting_warnings(&updated).into_iter().find(|w| !before.contains(w)) {
+        return Err(warning);
+    }
+    updated.profiles = config.profiles.clone();
+    Ok(updated)
+}
+
+// A missing file is not an error and yields the defaults. Unknown keys are returned as
+// warnings rather than rejected, since they are most likely typos but may also be settings
+// from a newer version.
+pub fn load(path: &Path, profile: Option<&str>) -> Result<(Config, Vec<String>), ConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(ConfigError::Read(path.to_path_buf(), e)),
+    };
+    parse_profile(&text, path, profile)
+}
+
+pub fn parse(text: &str, path: &Path) -> Result<(Config, Vec<String>), ConfigError> {
+    parse_profile(text, path, None)
+}
+
+// With a profile, its table is layered over the top-level keys before deserializing. Without
+// one the text is deserialized directly, which keeps line numbers in type errors.
+pub fn parse_profile(text: &str, path: &Path, profile: Option<&str>) -> Result<(Config, Vec<String>), ConfigError> {
+    let mut unknown = Vec::new();
+    let on_unknown = |key: serde_ignored::Path| unknown.push(key.to_string());
+    let mut config: Config = match profile {
+        None => serde_ignored::deserialize(toml::Deserializer::new(text), on_unknown),
+        Some(name) => {
+            let mut table: toml::Table = toml::from_str(text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+            let overrides = select_profile(&table, name).map_err(|msg| ConfigError::Invalid(path.to_path_buf(), msg))?;
+            merge_values(&mut table, overrides);
+            serde_ignored::deserialize(toml::Value::Table(table), on_unknown)
+        },
+    }.map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+    let clamped = config.validate().map_err(|msg| ConfigError::Invalid(path.to_path_buf(), msg))?;
+    let mut warnings: Vec<String> = unknown.into_iter().map(|key| format!("{}: unknown key `{}` ignored", path.display(), key)).collect();
+    warnings.extend(clamped.into_iter().map(|w| format!("{}: {}", path.display(), w)));
+    warnings.extend(setting_warnings(&config).into_iter().map(|w| format!("{}: {}", path.display(), w)));
+    Ok((config, warnings))
+}
+
+// What is wrong with settings that still load: unknown key names, theme colours, columns and
+// alert rules, which are skipped
+fn setting_warnings(config: &Config) -> Vec<String> {
+    let (_, key_warnings) = Keymap::from_config(&config.keys);
+    let (_, theme_warnings) = Theme::resolve(&config.theme);
+    let (_, column_warnings) = Column::resolve(&config.columns);
+    let (_, mut alert_warnings) = Alerts::from_config(&config.alerts);
+    if !config.allow_exec && config.alerts.iter().any(|a| !a.on_fire.is_empty()) {
+        alert_warnings.push("on_fire commands are ignored unless allow_exec = true".into());
+    }
+    key_warnings.into_iter().chain(theme_warnings).chain(column_warnings).chain(alert_warnings).collect()
+}
+
+fn select_profile(table: &toml::Table, name: &str) -> Result<toml::Table, String> {
+    let profiles = table.get("profile").and_then(toml::Value::as_table);
+    match profiles.and_then(|p| p.get(name)) {
+        Some(toml::Value::Table(overrides)) => Ok(overrides.clone()),
+        Some(_) => Err(format!("`profile.{}` must be a table", name)),
+        None => match profiles.map(|p| p.keys().map(String::as_str).collect::<Vec<_>>()).filter(|names| !names.is_empty()) {
+            Some(names) => Err(format!("unknown profile `{}`; available profiles: {}", name, names.join(", "))),
+            None => Err(format!("unknown profile `{}`; the file defines no [profile.<name>] sections", name)),
+        },
+    }
+}
+
+// Nested tables like [theme.colors] merge key by key; anything else is replaced
+fn merge_values(dst: &mut toml::Table, src: toml::Table) {
+    for (key, value) in src {
+        match (dst.get_mut(&key), value) {
+            (Some(toml::Value::Table(dst)), toml::Value::Table(src)) => merge_values(dst, src),
+            (_, value) => {
+                dst.insert(key, value);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(text: &str) -> Result<(Config, Vec<String>), ConfigError> {
+        parse(text, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn search_order_prefers_xdg_then_platform_then_cwd() {
+        let paths = candidates(Some("/xdg/rust-htop".into()), Some("/home/u/.config/rust-htop".into()));
+        assert_eq!(paths, vec![
+            PathBuf::from("/xdg/rust-htop/config.toml"),
+            PathBuf::from("/home/u/.config/rust-htop/config.toml"),
+            PathBuf::from("config.toml"),
+        ]);
+        let paths = candidates(Some("/same/rust-htop".into()), Some("/same/rust-htop".into()));
+        assert_eq!(paths, vec![PathBuf::from("/same/rust-htop/config.toml"), PathBuf::from("config.toml")]);
+        assert_eq!(candidates(None, None), vec![PathBuf::from("config.toml")]);
+    }
+
+    #[test]
+    fn template_documents_every_key_and_parses_back() {
+        let text = template();
+        let value: toml::Table = toml::from_str(&text).unwrap();
+        let mut keys = Vec::new();
+        for (key, v) in &value {
+            match v.as_table() {
+                Some(table) => keys.extend(table.keys().map(|k| format!("{}.{}", key, k))),
+                None => keys.push(key.clone()),
+            }
+        }
+        for key in &keys {
+            assert!(key_doc(key).is_some(), "no description for `{}`", key);
+        }
+        for (key, _) in KEY_DOCS {
+            assert!(keys.iter().any(|k| k == key) || value.contains_key(*key), "documented key `{}` is not in Config", key);
+        }
+        let (config, warnings) = parse_str(&text).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.refresh_rate, Config::default().refresh_rate);
+        assert!(text.contains("# Refresh interval in milliseconds (--refresh-ms)\nrefresh_rate = 1000\n"));
+    }
+
+    #[test]
+    fn write_template_refuses_to_overwrite() {
+        let path = env::temp_dir().join(format!("rust-htop-template-{}", std::process::id())).join("config.toml");
+        write_template(&path, false).unwrap();
+        let err = write_template(&path, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        write_template(&path, true).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(written, template());
+    }
+
+    #[test]
+    fn save_keeps_comments_and_unknown_keys() {
+        let existing = "# my monitor settings\nrefresh_rate = 2000 # slow box\nfuture_option = \"keep me\"\n";
+        let config = Config { refresh_rate: 500, default_sort: "mem".into(), ..Config::default() };
+        let text = merge_into_document(existing, &config, None).unwrap();
+        assert!(text.starts_with("# my monitor settings\nrefresh_rate = 500 # slow box\n"), "{}", text);
+        assert!(text.contains("future_option = \"keep me\""), "{}", text);
+        assert!(text.contains("default_sort = \"mem\""), "{}", text);
+        let (saved, warnings) = parse_str(&text).unwrap();
+        assert_eq!(saved.refresh_rate, 500);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    const PROFILES: &str = "refresh_rate = 2000\ndefault_sort = \"mem\"\n\n[theme.colors]\nheader = \"red\"\n\n[profile.db]\nrefresh_rate = 500\ndefault_filter = \"postgres\"\n\n[profile.db.theme.colors]\nborder = \"blue\"\n\n[profile.laptop]\nrefresh_rate = 5000\n";
+
+    #[test]
+    fn profile_overrides_top_level_which_overrides_defaults() {
+        let (config, warnings) = parse_profile(PROFILES, Path::new("config.toml"), Some("db")).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(config.refresh_rate, 500);
+        assert_eq!(config.default_filter, "postgres");
+        assert_eq!(config.default_sort, "mem");
+        assert!(config.descending);
+        assert_eq!(config.theme.colors.get("header").map(String::as_str), Some("red"));
+        assert_eq!(config.theme.colors.get("border").map(String::as_str), Some("blue"));
+        let (config, _) = parse_str(PROFILES).unwrap();
+        assert_eq!(config.refresh_rate, 2000);
+        assert!(config.default_filter.is_empty());
+        assert_eq!(config.profiles.keys().collect::<Vec<_>>(), vec!["db", "laptop"]);
+    }
+
+    #[test]
+    fn unknown_profile_lists_the_available_ones() {
+        let err = parse_profile(PROFILES, Path::new("config.toml"), Some("web")).unwrap_err();
+        assert_eq!(err.to_string(), "config.toml: unknown profile `web`; available profiles: db, laptop");
+        let err = parse_profile("refresh_rate = 1\n", Path::new("config.toml"), Some("web")).unwrap_err();
+        assert!(err.to_string().contains("defines no [profile.<name>] sections"), "{}", err);
+        let err = parse_profile("[profile.db]\nhide_below_cpu = -1.0\n", Path::new("config.toml"), Some("db")).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(..)));
+    }
+
+    #[test]
+    fn saving_with_a_profile_only_writes_what_differs() {
+        let config = Config { descending: false, ..parse_profile(PROFILES, Path::new("config.toml"), Some("db")).unwrap().0 };
+        let text = merge_into_document(PROFILES, &config, Some("db")).unwrap();
+        let doc: toml::Table = toml::from_str(&text).unwrap();
+        assert!(!doc.contains_key("descending"));
+        assert_eq!(doc["refresh_rate"].as_integer(), Some(2000));
+        let db = doc["profile"]["db"].as_table().unwrap();
+        assert_eq!(db["descending"].as_bool(), Some(false));
+        assert_eq!(db["refresh_rate"].as_integer(), Some(500));
+        assert!(!db.contains_key("default_sort"));
+        assert!(!db["theme"]["colors"].as_table().unwrap().contains_key("header"));
+        let (reloaded, _) = parse_profile(&text, Path::new("config.toml"), Some("db")).unwrap();
+        assert_eq!(Config { profiles: BTreeMap::new(), ..reloaded }, Config { profiles: BTreeMap::new(), ..config });
+        // A new profile gets its own table
+        let text = merge_into_document("", &Config { refresh_rate: 250, ..Config::default() }, Some("web")).unwrap();
+        assert_eq!(text, "[profile.web]\nrefresh_rate = 250\n");
+    }
+
+    #[test]
+    fn save_writes_atomically() {
+        let dir = env::temp_dir().join(format!("rust-htop-save-{}", std::process::id()));
+        let path = dir.join("config.toml");
+        save(&Config::default(), &path, None).unwrap();
+        save(&Config { descending: false, ..Config::default() }, &path, None).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let leftovers = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(text.contains("descending = false"));
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn watcher_reports_each_change_once() {
+        let path = env::temp_dir().join(format!("rust-htop-watch-{}.toml", std::process::id()));
+        fs::write(&path, "refresh_rate = 1000\n").unwrap();
+        let mut watcher = ConfigWatcher::new(path.clone());
+        assert!(!watcher.changed());
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(5)).unwrap();
+        let first = watcher.changed();
+        let second = watcher.changed();
+        fs::remove_file(&path).unwrap();
+        assert!(first);
+        assert!(!second);
+        assert!(!watcher.changed());
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn environment_variables_go_over_the_file() {
+        let (file, _) = parse_profile(PROFILES, Path::new("config.toml"), Some("db")).unwrap();
+        let (config, warnings) = apply_env(file.clone(), env(&[
+            ("RUST_HTOP_REFRESH_MS", "250"),
+            ("RUST_HTOP_TREE_VIEW", "Yes"),
+            ("RUST_HTOP_HIDE_BELOW_CPU", "2"),
+            ("RUST_HTOP_COLUMNS", "pid, name,cpu,"),
+            ("RUST_HTOP_THEME", "light"),
+            ("RUST_HTOP_FILTER", ""),
+            // The command line's own, and not for this crate at all
+            ("RUST_HTOP_PROFILE", "laptop"),
+            ("HOME", "/root"),
+        ]));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!((config.refresh_rate, config.tree_view, config.hide_below_cpu), (250, true, 2.0));
+        assert_eq!(config.columns, ["pid", "name", "cpu"]);
+        assert_eq!(config.theme.name, "light");
+        assert_eq!(config.theme.colors, file.theme.colors);
+        assert_eq!(config.default_filter, "");
+        // What no variable mentions stays as the file and profile had it
+        assert_eq!((config.default_sort.as_str(), config.descending), (file.default_sort.as_str(), file.descending));
+        assert_eq!(config.profiles.keys().collect::<Vec<_>>(), file.profiles.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bad_environment_variables_are_warned_about_and_skipped() {
+        let (config, warnings) = apply_env(Config::default(), env(&[
+            ("RUST_HTOP_REFRESH_MS", "fast"),
+            ("RUST_HTOP_REFRESH_RATE", "0"),
+            ("RUST_HTOP_SORT", "bogus"),
+            ("RUST_HTOP_HIDE_IDLE", "maybe"),
+            ("RUST_HTOP_HIDE_BELOW_MEM_MB", "-5"),
+            ("RUST_HTOP_THEME", "neon"),
+            ("RUST_HTOP_KEYS", "quit=x"),
+            ("RUST_HTOP_TREEVIEW", "1"),
+            ("RUST_HTOP_QUIET", "on"),
+        ]));
+        assert_eq!(config, Config { quiet: true, ..Config::default() });
+        assert_eq!(warnings.len(), 8, "{:?}", warnings);
+        for (warning, name) in warnings.iter().zip(["HIDE_BELOW_MEM_MB", "HIDE_IDLE", "KEYS", "REFRESH_MS", "REFRESH_RATE", "SORT", "THEME", "TREEVIEW"]) {
+            assert!(warning.starts_with(&format!("RUST_HTOP_{}=", name)) && warning.ends_with("; ignored"), "{}", warning);
+        }
+        assert!(warnings[1].contains("expected true or false"));
+        assert!(warnings[6].contains("unknown theme"));
+        assert!(warnings[7].contains("no such setting"));
+    }
+
+    #[test]
+    fn missing_file_uses_defaults() {
+        let (config, warnings) = load(Path::new("/nonexistent/rust-htop/config.toml"), None).unwrap();
+        assert_eq!(config.refresh_rate, 1000);
+        assert_eq!(config.default_sort, "cpu");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn explicit_path_must_exist() {
+        let err = load_config(Some("/nonexistent/db-server.toml".into()), None).err().unwrap();
+        assert!(matches!(err, ConfigError::Read(..)));
+        assert!(err.to_string().starts_with("/nonexistent/db-server.toml: config file not found"), "{}", err);
+    }
+
+    #[test]
+    fn explicit_path_is_loaded() {
+        let path = env::temp_dir().join(format!("rust-htop-explicit-{}.toml", std::process::id()));
+        fs::write(&path, "refresh_rate = 250\n").unwrap();
+        let loaded = load_config(Some(path.clone()), None);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.path.as_deref(), Some(path.as_path()));
+        assert_eq!(loaded.config.refresh_rate, 250);
+    }
+
+    #[test]
+    fn partial_file_keeps_other_defaults() {
+        let (config, warnings) = parse_str("default_sort = \"mem\"\n").unwrap();
+        assert_eq!(config.default_sort, "mem");
+        assert_eq!(config.refresh_rate, 1000);
+        assert!(!config.full_refresh);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn bad_type_reports_location() {
+        let err = parse_str("default_sort = \"cpu\"\nrefresh_rate = \"fast\"\n").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(..)));
+        let msg = err.to_string();
+        assert!(msg.starts_with("config.toml: "), "{}", msg);
+        assert!(msg.contains("line 2"), "{}", msg);
+        assert!(msg.contains("refresh_rate"), "{}", msg);
+    }
+
+    #[test]
+    fn refresh_rate_is_clamped_into_range() {
+        for (given, used) in [(0, 100), (99, 100), (100, 100), (1000, 1000), (60_000, 60_000), (60_001, 60_000), (u64::MAX, 60_000)] {
+            let mut config = Config { refresh_rate: given, ..Config::default() };
+            let warnings = config.validate().unwrap();
+            assert_eq!(config.refresh_rate, used, "{}", given);
+            assert_eq!(warnings.len(), (given != used) as usize, "{}", given);
+        }
+        let (config, warnings) = parse_str("refresh_rate = 0\n").unwrap();
+        assert_eq!(config.refresh_rate, MIN_REFRESH_MS);
+        assert_eq!(warnings, ["config.toml: refresh_rate = 0 is outside 100-60000 milliseconds; using 100"]);
+        let (config, warnings) = parse_str("refresh_rate = 3600000\n").unwrap();
+        assert_eq!(config.refresh_rate, MAX_REFRESH_MS);
+        assert!(warnings[0].ends_with("using 60000"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn negative_or_overflowing_refresh_rates_are_rejected() {
+        let err = parse_str("refresh_rate = -5\n").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(..)));
+        assert!(err.to_string().contains("refresh_rate must be a positive number of milliseconds, not -5"), "{}", err);
+        assert!(err.to_string().contains("line 1"), "{}", err);
+        assert!(matches!(parse_str("refresh_rate = 99999999999999999999\n"), Err(ConfigError::Parse(..))));
+    }
+
+    #[test]
+    fn syntax_error_is_reported() {
+        assert!(matches!(parse_str("refresh_rate = \n"), Err(ConfigError::Parse(..))));
+    }
+
+    #[test]
+    fn unknown_keys_are_warnings() {
+        let (config, warnings) = parse_str("refesh_rate = 500\n").unwrap();
+        assert_eq!(config.refresh_rate, 1000);
+        assert_eq!(warnings, vec!["config.toml: unknown key `refesh_rate` ignored".to_string()]);
+    }
+
+    #[test]
+    fn key_binding_problems_are_warnings() {
+        let (config, warnings) = parse_str("[keys]\nquit = \"F10\"\nkill = \"F9\"\n").unwrap();
+        assert_eq!(config.keys.len(), 2);
+        assert_eq!(warnings, vec!["config.toml: unknown action `kill` in [keys]".to_string()]);
+    }
+
+    #[test]
+    fn theme_accepts_a_name_or_a_table() {
+        let (config, warnings) = parse_str("theme = \"light\"\n").unwrap();
+        assert_eq!(config.theme.name, "light");
+        assert!(config.theme.colors.is_empty() && warnings.is_empty());
+        let (config, warnings) = parse_str("[theme]\nname = \"mono\"\n[theme.colors]\nheader = \"red\"\ncpu_crit = \"nope\"\n").unwrap();
+        assert_eq!(config.theme.name, "mono");
+        assert_eq!(config.theme.colors.get("header").map(String::as_str), Some("red"));
+        assert_eq!(warnings, vec!["config.toml: [theme.colors] cpu_crit: unknown colour \"nope\"".to_string()]);
+        // Overrides alone keep the default theme
+        let (config, _) = parse_str("[theme.colors]\nheader = \"red\"\n").unwrap();
+        assert_eq!(config.theme.name, "dark");
+        let (saved, _) = parse_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(saved.theme, config.theme);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let err = parse_str("default_sort = \"size\"\n").unwrap_err();
+        assert!(err.to_string().contains("unknown default_sort \"size\" (expected \"pid\", \"cpu\", "), "{}", err);
+        assert!(err.to_string().ends_with(", \"core\" or \"container\")"), "{}", err);
+        let err = parse_str("time_format = \"%H:%Q\"\n").unwrap_err();
+        assert!(err.to_string().contains("time_format \"%H:%Q\" is not a valid strftime format"), "{}", err);
+        let err = parse_str("export_decimal_separator = \",\"\n").unwrap_err();
+        assert!(err.to_string().contains("export_delimiter and export_decimal_separator are both \",\""), "{}", err);
+        assert!(parse_str("export_decimal_separator = \",\"\nexport_delimiter = \"\\t\"\n").is_ok());
+        let err = parse_str("export_delimiter = \".\"\n").unwrap_err();
+        assert!(err.to_string().contains("unknown export_delimiter \".\""), "{}", err);
+        let err = parse_str("export_timestamp_format = \"%Q\"\n").unwrap_err();
+        assert!(err.to_string().contains("export_timestamp_format \"%Q\" is not"), "{}", err);
+        let err = parse_str("export_format = \"xlsx\"\n").unwrap_err();
+        assert!(err.to_string().contains("unknown export_format \"xlsx\""), "{}", err);
+    }
+
+    #[test]
+    fn alerts_are_tables_and_bad_rules_are_warnings() {
+        let (config, warnings) = parse_str("[[alert]]\nwhen = \"memory > 95%\"\n\n[[alert]]\nname = \"Runaway\"\nwhen = \"process cpu > 90 for 30s\"\ncooldown = \"10m\"\n\n[[alert]]\nwhen = \"disk > 90\"\n\n[[alert]]\nmetric = \"process_mem\"\nmatch = \"java\"\nthreshold = 2048\nfor_seconds = 60\naction = \"banner\"\n").unwrap();
+        assert_eq!(config.alerts.len(), 4);
+        assert_eq!(config.alerts[3].pattern, "java");
+        assert_eq!(config.alerts[0].cooldown, "5m");
+        assert_eq!(config.alerts[1].name, "Runaway");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("config.toml: alert `disk > 90` ignored"), "{:?}", warnings);
+        let (_, warnings) = parse_str("[[alert]]\nwhen = \"load > 8\"\non_fire = \"uptime >> /tmp/load.log\"\n").unwrap();
+        assert_eq!(warnings, vec!["config.toml: on_fire commands are ignored unless allow_exec = true"]);
+        assert!(parse_str("allow_exec = true\n\n[[alert]]\nwhen = \"load > 8\"\non_fire = \"uptime\"\n").unwrap().1.is_empty());
+        let (saved, _) = parse_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(saved.alerts, config.alerts);
+        let merged = merge_into_document("refresh_rate = 500\n\n[[alert]]\nwhen = \"cpu > 1\"\n", &config, None).unwrap();
+        assert_eq!(parse_str(&merged).unwrap().0.alerts, config.alerts);
+    }
+
+    #[test]
+    fn column_problems_are_warnings() {
+        let (config, warnings) = parse_str("columns = [\"name\", \"size\"]\n").unwrap();
+        assert_eq!(config.columns, vec!["name".to_string(), "size".to_string()]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("config.toml: unknown column `size`"), "{:?}", warnings);
+    }
+}
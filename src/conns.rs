@@ -0,0 +1,209 @@
+use std::{collections::{HashMap, HashSet}, fs, net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}, path::Path};
+
+// The socket tables, with the protocol name each one's rows are shown with
+const TABLES: [(&str, &str); 4] = [("tcp", "tcp"), ("tcp6", "tcp"), ("udp", "udp"), ("udp6", "udp")];
+
+// One row of /proc/net/{tcp,tcp6,udp,udp6}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Connection {
+    // "tcp" or "udp"
+    pub protocol: &'static str,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    // As ss prints it: LISTEN, ESTABLISHED, TIME_WAIT, ..., UNCONN for an unconnected UDP socket
+    pub state: &'static str,
+    pub inode: u64,
+}
+
+// A socket waiting for connections or datagrams, and the process holding it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listener {
+    pub protocol: &'static str,
+    pub local: SocketAddr,
+    // None when no process that can be read has it open: another user's, without root
+    pub pid: Option<u32>,
+}
+
+// Every TCP and UDP socket on the machine. A table that can't be read (no IPv6, or not
+// Linux) contributes nothing.
+pub fn read_all(proc: &Path) -> Vec<Connection> {
+    TABLES.iter().flat_map(|&(file, protocol)| {
+        let text = fs::read_to_string(proc.join("net").join(file)).unwrap_or_default();
+        parse_table(&text, protocol)
+    }).collect()
+}
+
+// The header line and rows that don't parse are skipped
+pub fn parse_table(text: &str, protocol: &'static str) -> Vec<Connection> {
+    text.lines().skip(1).filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let state = u8::from_str_radix(fields.get(3)?, 16).ok()?;
+        Some(Connection {
+            protocol,
+            local: parse_addr(fields.get(1)?)?,
+            remote: parse_addr(fields.get(2)?)?,
+            state: state_name(protocol, state),
+            inode: fields.get(9)?.parse().ok()?,
+        })
+    }).collect()
+}
+
+// "0100007F:1F90" is 127.0.0.1:8080. The kernel prints the address as 32-bit words in host
+// byte order and the port as a plain number.
+fn parse_addr(text: &str) -> Option<SocketAddr> {
+    let (addr, port) = text.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut bytes = Vec::with_capacity(16);
+    for word in addr.as_bytes().chunks(8) {
+        let word = u32::from_str_radix(std::str::from_utf8(word).ok()?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+        16 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+// The TCP states from include/net/tcp_states.h. UDP reuses them: 1 once connect() was called,
+// 7 otherwise.
+fn state_name(protocol: &str, state: u8) -> &'static str {
+    match (protocol, state) {
+        ("udp", 1) => "ESTABLISHED",
+        ("udp", _) => "UNCONN",
+        (_, 1) => "ESTABLISHED",
+        (_, 2) => "SYN_SENT",
+        (_, 3) => "SYN_RECV",
+        (_, 4) => "FIN_WAIT1",
+        (_, 5) => "FIN_WAIT2",
+        (_, 6) => "TIME_WAIT",
+        (_, 7) => "CLOSE",
+        (_, 8) => "CLOSE_WAIT",
+        (_, 9) => "LAST_ACK",
+        (_, 10) => "LISTEN",
+        (_, 11) => "CLOSING",
+        _ => "UNKNOWN",
+    }
+}
+
+// The inodes of the sockets a process has open, from its fd symlinks ("socket:[12345]").
+// None when the fd directory can't be read.
+pub fn socket_inodes(process_dir: &Path) -> Option<HashSet<u64>> {
+    let entries = fs::read_dir(process_dir.join("fd")).ok()?;
+    Some(entries.filter_map(|entry| socket_inode(&fs::read_link(entry.ok()?.path()).ok()?)).collect())
+}
+
+pub fn socket_inode(link: &Path) -> Option<u64> {
+    link.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+}
+
+// The TCP and UDP sockets of one process; other sockets, like Unix ones, are left out
+pub fn for_process(proc: &Path, pid: u32) -> Option<Vec<Connection>> {
+    let inodes = socket_inodes(&proc.join(pid.to_string()))?;
+    Some(read_all(proc).into_iter().filter(|c| inodes.contains(&c.inode)).collect())
+}
+
+// Which process holds each listening socket. Finding out means reading the fd links of every
+// process, so the owners are kept between calls and the walk is only repeated when a socket
+// turns up that the last one didn't see, or its owner has exited.
+#[derive(Default)]
+pub struct SocketOwners {
+    owners: HashMap<u64, u32>,
+    // Sockets the last walk found no readable owner for
+    unowned: HashSet<u64>,
+}
+
+impl SocketOwners {
+    // Listening TCP sockets and unconnected UDP ones, by port
+    pub fn listeners(&mut self, proc: &Path) -> Vec<Listener> {
+        let sockets: Vec<Connection> = read_all(proc).into_iter().filter(|c| c.state == "LISTEN" || c.state == "UNCONN").collect();
+        let stale = sockets.iter().any(|c| match self.owners.get(&c.inode) {
+            Some(pid) => !proc.join(pid.to_string()).exists(),
+            None => !self.unowned.contains(&c.inode),
+        });
+        if stale {
+            self.owners = owners(proc);
+            self.unowned = sockets.iter().map(|c| c.inode).filter(|inode| !self.owners.contains_key(inode)).collect();
+        }
+        let mut list: Vec<Listener> = sockets.iter().map(|c| Listener { protocol: c.protocol, local: c.local, pid: self.owners.get(&c.inode).copied() }).collect();
+        list.sort_by_key(|l| (l.local.port(), l.protocol, l.local, l.pid));
+        list
+    }
+}
+
+// By socket inode, the lowest PID that has it open: the parent of forked workers that share a
+// listener rather than one of the workers
+fn owners(proc: &Path) -> HashMap<u64, u32> {
+    let mut owners = HashMap::new();
+    let Ok(entries) = fs::read_dir(proc) else { return owners };
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+        for inode in socket_inodes(&entry.path()).unwrap_or_default() {
+            owners.entry(inode).and_modify(|owner: &mut u32| *owner = (*owner).min(pid)).or_insert(pid);
+        }
+    }
+    owners
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 662 1 0000000000000000 100 0 0 10 0\n   1: 0100007F:9330 0100007F:1538 01 00000000:00000000 02:000000B6 00000000     0        0 59418 2 0000000000000000 20 4 0 14 8\n   2: garbage\n";
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn tcp_rows_are_parsed() {
+        let rows = parse_table(TCP, "tcp");
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].local, "0.0.0.0:8080".parse().unwrap());
+        assert_eq!((rows[0].state, rows[0].inode), ("LISTEN", 662));
+        assert_eq!(rows[1].local, "127.0.0.1:37680".parse().unwrap());
+        assert_eq!(rows[1].remote, "127.0.0.1:5432".parse().unwrap());
+        assert_eq!(rows[1].state, "ESTABLISHED");
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn ipv6_and_udp_rows_are_parsed() {
+        let text = "header\n   0: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 4242 2 0000000000000000 0\n";
+        let rows = parse_table(text, "udp");
+        assert_eq!(rows[0].local, "[::1]:53".parse().unwrap());
+        assert_eq!((rows[0].protocol, rows[0].state), ("udp", "UNCONN"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn a_listening_socket_shows_up_for_this_process() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mine = for_process(Path::new("/proc"), std::process::id()).unwrap();
+        assert!(mine.iter().any(|c| c.local == addr && c.state == "LISTEN"), "{:?}", mine);
+        let listeners = SocketOwners::default().listeners(Path::new("/proc"));
+        assert!(listeners.contains(&Listener { protocol: "tcp", local: addr, pid: Some(std::process::id()) }), "{:?}", listeners);
+    }
+
+    #[cfg(all(target_os = "linux", target_endian = "little"))]
+    #[test]
+    fn listeners_go_to_the_lowest_pid_holding_them() {
+        use std::os::unix::fs::symlink;
+        let proc = std::env::temp_dir().join(format!("rust-htop-listeners-{}", std::process::id()));
+        fs::create_dir_all(proc.join("net")).unwrap();
+        fs::write(proc.join("net/tcp"), TCP).unwrap();
+        // A master and its worker share the socket
+        for pid in ["812", "90"] {
+            fs::create_dir_all(proc.join(pid).join("fd")).unwrap();
+            symlink("socket:[662]", proc.join(pid).join("fd/3")).unwrap();
+        }
+        let mut owners = SocketOwners::default();
+        let listeners = owners.listeners(&proc);
+        assert_eq!(listeners, [Listener { protocol: "tcp", local: "0.0.0.0:8080".parse().unwrap(), pid: Some(90) }]);
+        // The worker is left when the master exits
+        fs::remove_dir_all(proc.join("90")).unwrap();
+        assert_eq!(owners.listeners(&proc)[0].pid, Some(812));
+        fs::remove_dir_all(proc.join("812")).unwrap();
+        assert_eq!(owners.listeners(&proc)[0].pid, None);
+        fs::remove_dir_all(&proc).unwrap();
+    }
+}
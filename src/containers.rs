@@ -0,0 +1,235 @@
+use std::{collections::HashMap, fs, path::Path, time::{Duration, Instant}};
+#[cfg(all(unix, feature = "containers"))]
+use std::{io::{Read, Write}, os::unix::net::UnixStream, path::PathBuf, sync::mpsc, thread};
+use sysinfo::{Pid, PidExt};
+
+// How many characters of an ID are shown, like `docker ps`
+pub const SHORT_ID: usize = 12;
+
+// A lookup that failed (daemon down, ID unknown to it) is tried again after this long
+pub const RETRY: Duration = Duration::from_secs(60);
+
+// The container a process runs in, from the 64-hex-digit ID in its cgroup paths. The runtimes
+// name the cgroups differently, so any path segment that is such an ID once the common
+// prefixes and suffixes are off counts:
+//
+//   0::/system.slice/docker-3f2a9c...e1.scope        (Docker, systemd cgroup driver)
+//   12:memory:/docker/3f2a9c...e1                    (Docker, cgroupfs driver)
+//   0::/machine.slice/libpod-3f2a9c...e1.scope       (Podman)
+//   0::/kubepods/burstable/pod1234/cri-containerd-3f2a9c...e1.scope
+pub fn parse_container_id(cgroup: &str) -> Option<String> {
+    cgroup.lines().filter_map(|line| line.splitn(3, ':').nth(2)).flat_map(|path| path.split('/')).find_map(|segment| {
+        let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+        let id = ["docker-", "libpod-", "cri-containerd-", "crio-"].iter().find_map(|p| segment.strip_prefix(p)).unwrap_or(segment);
+        (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then(|| id.to_string())
+    })
+}
+
+pub fn read_container_id(proc: &Path, pid: Pid) -> Option<String> {
+    parse_container_id(&fs::read_to_string(proc.join(pid.as_u32().to_string()).join("cgroup")).ok()?)
+}
+
+pub fn short_id(id: &str) -> &str {
+    &id[..SHORT_ID.min(id.len())]
+}
+
+#[cfg_attr(not(all(unix, feature = "containers")), allow(dead_code))]
+enum Lookup {
+    Asked,
+    Found(String),
+    Failed(Instant),
+}
+
+// Container IDs to names. A name never changes for an ID, so every answer is kept for the
+// session. Lookups go to the Docker and Podman sockets on a worker thread and the answers are
+// picked up by `poll`, so a slow daemon never holds up a refresh; until an answer is in, and
+// without the `containers` feature, the short ID stands in.
+#[derive(Default)]
+pub struct Resolver {
+    lookups: HashMap<String, Lookup>,
+    #[cfg(all(unix, feature = "containers"))]
+    worker: Option<Worker>,
+}
+
+#[cfg(all(unix, feature = "containers"))]
+struct Worker {
+    requests: mpsc::Sender<String>,
+    answers: mpsc::Receiver<(String, Option<String>)>,
+}
+
+impl Resolver {
+    // The name for `id` if it is known, otherwise the short ID, asking for the name if
+    // nobody has yet
+    pub fn name(&mut self, id: &str, now: Instant) -> String {
+        match self.lookups.get(id) {
+            Some(Lookup::Found(name)) => return name.clone(),
+            Some(Lookup::Asked) => {},
+            Some(Lookup::Failed(at)) if now.duration_since(*at) < RETRY => {},
+            _ => self.ask(id),
+        }
+        short_id(id).to_string()
+    }
+
+    #[cfg(all(unix, feature = "containers"))]
+    fn ask(&mut self, id: &str) {
+        let worker = self.worker.get_or_insert_with(|| spawn(vec![PathBuf::from("/var/run/docker.sock"), PathBuf::from("/run/podman/podman.sock")]));
+        if worker.requests.send(id.to_string()).is_ok() {
+            self.lookups.insert(id.to_string(), Lookup::Asked);
+        }
+    }
+
+    #[cfg(not(all(unix, feature = "containers")))]
+    fn ask(&mut self, _id: &str) {}
+
+    // Takes in the answers that came back since the last call; true if there were any
+    #[cfg(all(unix, feature = "containers"))]
+    pub fn poll(&mut self, now: Instant) -> bool {
+        let Some(worker) = &self.worker else { return false };
+        let answers: Vec<_> = worker.answers.try_iter().collect();
+        for (id, name) in &answers {
+            let lookup = match name {
+                Some(name) => Lookup::Found(name.clone()),
+                None => Lookup::Failed(now),
+            };
+            self.lookups.insert(id.clone(), lookup);
+        }
+        !answers.is_empty()
+    }
+
+    #[cfg(not(all(unix, feature = "containers")))]
+    pub fn poll(&mut self, _now: Instant) -> bool {
+        false
+    }
+}
+
+// One lookup at a time, each socket tried in turn
+#[cfg(all(unix, feature = "containers"))]
+fn spawn(sockets: Vec<PathBuf>) -> Worker {
+    let (requests, inbox) = mpsc::channel::<String>();
+    let (outbox, answers) = mpsc::channel();
+    thread::spawn(move || {
+        for id in inbox {
+            let name = sockets.iter().find_map(|socket| match inspect(socket, &id) {
+                Ok(name) => name,
+                Err(e) => {
+                    log::debug!("container lookup of {} on {}: {}", short_id(&id), socket.display(), e);
+                    None
+                },
+            });
+            if outbox.send((id, name)).is_err() {
+                return;
+            }
+        }
+    });
+    Worker { requests, answers }
+}
+
+// GET /containers/<id>/json, which Docker and Podman's compatible API both answer. HTTP/1.0
+// keeps the body unchunked and has the daemon close the connection when it is done.
+#[cfg(all(unix, feature = "containers"))]
+fn inspect(socket: &Path, id: &str) -> std::io::Result<Option<String>> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(format!("GET /containers/{}/json HTTP/1.0\r\nHost: localhost\r\n\r\n", id).as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(parse_inspect_response(&response))
+}
+
+// The container's name from an inspect response; None for anything but a 200 with a name.
+// Docker puts a slash in front of it.
+pub fn parse_inspect_response(response: &str) -> Option<String> {
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    if head.lines().next()?.split_whitespace().nth(1)? != "200" {
+        return None;
+    }
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let name = value.get("Name")?.as_str()?.trim_start_matches('/');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f2a9c1d5e7b8a90c1d2e3f4a5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e1f2a3b4";
+
+    #[test]
+    fn container_ids_are_found_in_each_runtimes_cgroup_paths() {
+        for cgroup in [
+            format!("0::/system.slice/docker-{}.scope\n", ID),
+            format!("12:memory:/docker/{}\n11:cpu:/docker/{}\n", ID, ID),
+            format!("0::/machine.slice/libpod-{}.scope/container\n", ID),
+            format!("0::/kubepods.slice/kubepods-burstable.slice/kubepods-pod12.slice/cri-containerd-{}.scope\n", ID),
+        ] {
+            assert_eq!(parse_container_id(&cgroup).as_deref(), Some(ID), "{}", cgroup);
+        }
+        assert_eq!(parse_container_id("0::/user.slice/user-1000.slice/session-2.scope\n"), None);
+        assert_eq!(parse_container_id(&format!("0::/docker-{}.scope\n", &ID[..40])), None);
+        assert_eq!(short_id(ID), "3f2a9c1d5e7b");
+    }
+
+    #[test]
+    fn names_come_from_a_successful_inspect() {
+        let ok = "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"Id\":\"3f2a\",\"Name\":\"/nginx-prod\",\"State\":{}}";
+        assert_eq!(parse_inspect_response(ok).as_deref(), Some("nginx-prod"));
+        assert_eq!(parse_inspect_response("HTTP/1.0 404 Not Found\r\n\r\n{\"message\":\"No such container\"}"), None);
+        assert_eq!(parse_inspect_response("HTTP/1.0 200 OK\r\n\r\nnot json"), None);
+        assert_eq!(parse_inspect_response(""), None);
+    }
+
+    #[test]
+    fn the_short_id_stands_in_until_a_name_is_known() {
+        let mut resolver = Resolver::default();
+        let now = Instant::now();
+        assert_eq!(resolver.name(ID, now), "3f2a9c1d5e7b");
+        resolver.lookups.insert(ID.to_string(), Lookup::Found("nginx-prod".into()));
+        assert_eq!(resolver.name(ID, now), "nginx-prod");
+    }
+
+    #[cfg(all(unix, feature = "containers"))]
+    #[test]
+    fn the_worker_asks_the_socket_and_failures_are_retried_later() {
+        use std::os::unix::net::UnixListener;
+        let path = std::env::temp_dir().join(format!("rust-htop-docker-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // The request can arrive in pieces
+            let mut request = Vec::new();
+            let mut buf = [0; 512];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                assert!(n > 0, "connection closed mid-request");
+                request.extend_from_slice(&buf[..n]);
+            }
+            assert!(String::from_utf8_lossy(&request).starts_with(&format!("GET /containers/{}/json ", ID)));
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n{\"Name\":\"/nginx-prod\"}").unwrap();
+        });
+        let mut resolver = Resolver { worker: Some(spawn(vec![path.join("missing"), path.clone()])), ..Resolver::default() };
+        let now = Instant::now();
+        assert_eq!(resolver.name(ID, now), "3f2a9c1d5e7b");
+        server.join().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !resolver.poll(now) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(resolver.name(ID, now), "nginx-prod");
+
+        // Nothing listens any more: the short ID, and no new lookup until RETRY has passed
+        let other = ID.replace('3', "4");
+        resolver.name(&other, now);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !resolver.poll(now) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(resolver.lookups[&other], Lookup::Failed(_)));
+        assert_eq!(resolver.name(&other, now + Duration::from_secs(1)), short_id(&other));
+        assert!(matches!(resolver.lookups[&other], Lookup::Failed(_)));
+        resolver.name(&other, now + RETRY);
+        assert!(matches!(resolver.lookups[&other], Lookup::Asked));
+    }
+}
@@ -0,0 +1,163 @@
+use std::{borrow::Cow, fs::{self, File}, io::{self, BufWriter, Write}, path::PathBuf, time::{Duration, Instant}};
+use sysinfo::PidExt;
+
+use crate::{app::App, export::Locale};
+
+const HEADER: &str = "timestamp,pid,name,cpu_percent,mem_bytes,user";
+
+// Appends one row per process every `interval` for graphing later. The file is opened on the
+// first write and reopened after an error, so a full disk or a removed directory costs the
+// rows of that interval rather than the whole log.
+pub struct CsvLog {
+    pub path: PathBuf,
+    pub interval: Duration,
+    last: Option<Instant>,
+    out: Option<BufWriter<File>>,
+}
+
+impl CsvLog {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self { path, interval, last: None, out: None }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.is_none_or(|t| t.elapsed() >= self.interval)
+    }
+
+    // Every process of the current snapshot, not just the filtered view. Rows are buffered and
+    // flushed once per call.
+    pub fn write(&mut self, app: &App) -> io::Result<()> {
+        self.last = Some(Instant::now());
+        let result = self.write_rows(app);
+        if result.is_err() {
+            self.out = None;
+        }
+        result
+    }
+
+    fn write_rows(&mut self, app: &App) -> io::Result<()> {
+        let out = match &mut self.out {
+            Some(out) => out,
+            None => self.out.insert(open(&self.path, &Locale::from_config(&app.config))?),
+        };
+        let locale = Locale::from_config(&app.config);
+        let timestamp = locale.field(&locale.timestamp(&chrono::Utc::now())).into_owned();
+        // With row_limit the log keeps to the rows the table lists, in its order
+        let order = if app.config.row_limit > 0 {
+            app.visible.clone()
+        } else {
+            let mut order: Vec<usize> = (0..app.processes.len()).collect();
+            order.sort_by_key(|&i| app.processes[i].pid);
+            order
+        };
+        for i in order {
+            let p = &app.processes[i];
+            let fields = [timestamp.clone(), p.pid.as_u32().to_string(), locale.field(&p.name).into_owned(), locale.decimal(p.cpu.into()), p.mem.to_string(), locale.field(p.user.as_deref().unwrap_or("")).into_owned()];
+            writeln!(out, "{}", locale.join(fields))?;
+        }
+        out.flush()
+    }
+}
+
+// Appends to an existing log; a new or empty file gets the header first
+fn open(path: &PathBuf, locale: &Locale) -> io::Result<BufWriter<File>> {
+    let fresh = fs::metadata(path).map(|m| m.len() == 0).unwrap_or(true);
+    let mut out = BufWriter::new(File::options().create(true).append(true).open(path)?);
+    if fresh {
+        writeln!(out, "{}", locale.join(HEADER.split(',')))?;
+    }
+    Ok(out)
+}
+
+// RFC 4180: fields with commas, quotes or line breaks are quoted and quotes are doubled
+pub fn escape(field: &str) -> Cow<'_, str> {
+    escape_with(field, ',')
+}
+
+// The same for fields separated by `delimiter` rather than commas
+pub fn escape_with(field: &str, delimiter: char) -> Cow<'_, str> {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, system::FakeSource};
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(escape("nginx"), "nginx");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("two\nlines"), "\"two\nlines\"");
+        assert_eq!(escape(""), "");
+    }
+
+    #[test]
+    fn header_is_written_once_and_rows_append() {
+        let path = std::env::temp_dir().join(format!("rust-htop-log-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut source = FakeSource::with_processes(&[(20, "web, worker", 1.5, 2048), (3, "init", 0.0, 0)]);
+        source.processes[0].user = Some("www".into());
+        let app = App::new(Config::default(), Box::new(source));
+
+        let mut log = CsvLog::new(path.clone(), Duration::from_secs(60));
+        assert!(log.due());
+        log.write(&app).unwrap();
+        assert!(!log.due());
+        // A second logger on the same file, like a restarted monitor, appends without a header
+        CsvLog::new(path.clone(), Duration::ZERO).write(&app).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], HEADER);
+        assert!(lines[1].ends_with(",3,init,0.00,0,"), "{}", lines[1]);
+        assert!(lines[2].ends_with(",20,\"web, worker\",1.50,2048,www"), "{}", lines[2]);
+        assert_eq!(text.matches(HEADER).count(), 1);
+    }
+
+    #[test]
+    fn a_row_limit_keeps_the_log_to_the_top_rows() {
+        let path = std::env::temp_dir().join(format!("rust-htop-log-limit-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let source = FakeSource::with_processes(&[(1, "init", 0.0, 0), (2, "web", 9.0, 0), (3, "db", 4.0, 0)]);
+        let app = App::new(Config { row_limit: 2, ..Config::default() }, Box::new(source));
+        CsvLog::new(path.clone(), Duration::ZERO).write(&app).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let names: Vec<&str> = text.lines().skip(1).map(|l| l.split(',').nth(2).unwrap()).collect();
+        assert_eq!(names, ["web", "db"]);
+    }
+
+    #[test]
+    fn the_log_follows_the_export_settings() {
+        let path = std::env::temp_dir().join(format!("rust-htop-log-locale-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let source = FakeSource::with_processes(&[(20, "web; worker", 1.5, 2048)]);
+        let config = Config { export_timestamp_format: "%s".into(), export_decimal_separator: ",".into(), export_delimiter: ";".into(), ..Config::default() };
+        let app = App::new(config, Box::new(source));
+        CsvLog::new(path.clone(), Duration::ZERO).write(&app).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "timestamp;pid;name;cpu_percent;mem_bytes;user");
+        let (time, rest) = lines[1].split_once(';').unwrap();
+        assert!(time.parse::<i64>().unwrap().abs_diff(chrono::Utc::now().timestamp()) < 60, "{}", lines[1]);
+        assert_eq!(rest, "20;\"web; worker\";1,50;2048;");
+    }
+
+    #[test]
+    fn errors_are_returned_and_retried() {
+        let app = App::new(Config::default(), Box::new(FakeSource::default()));
+        let mut log = CsvLog::new("/nonexistent/rust-htop/log.csv".into(), Duration::ZERO);
+        assert!(log.write(&app).is_err());
+        assert!(log.due());
+        assert!(log.write(&app).is_err());
+    }
+}
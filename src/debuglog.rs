@@ -0,0 +1,77 @@
+use std::{fs::{File, OpenOptions}, io::{self, Write}, path::Path, sync::Mutex};
+use chrono::{Local, SecondsFormat};
+use log::{LevelFilter, Log, Metadata, Record};
+
+// `--debug-log`: every record goes to one file, one line each, flushed as it is written so
+// the last lines before a freeze are on disk. Nothing is ever written to the terminal, which
+// belongs to the interface. Without the flag no logger is installed and the `log` macros stop
+// at the max level check.
+struct FileLogger {
+    file: Mutex<File>,
+    level: LevelFilter,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format_line(&Local::now().to_rfc3339_opts(SecondsFormat::Millis, false), record);
+        if let Ok(mut file) = self.file.lock() {
+            // A full disk loses log lines, not the session
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+// "2024-05-01T03:14:05.123+02:00 DEBUG htop::app: refreshed in 12ms"
+fn format_line(timestamp: &str, record: &Record) -> String {
+    format!("{} {:<5} {}: {}\n", timestamp, record.level(), record.target(), record.args())
+}
+
+// Appends to `path`, so a session that is restarted to reproduce a problem keeps the first
+// one's lines
+pub fn init(path: &Path, level: LevelFilter) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let logger = FileLogger { file: Mutex::new(file), level };
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| io::Error::other(e.to_string()))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_carry_the_time_level_and_target() {
+        let line = format_line("2024-05-01T03:14:05.123+02:00", &Record::builder()
+            .args(format_args!("refreshed in {}ms", 12))
+            .level(log::Level::Debug)
+            .target("htop::app")
+            .build());
+        assert_eq!(line, "2024-05-01T03:14:05.123+02:00 DEBUG htop::app: refreshed in 12ms\n");
+    }
+
+    #[test]
+    fn records_are_filtered_by_level() {
+        let path = std::env::temp_dir().join(format!("rust-htop-debuglog-{}", std::process::id()));
+        let logger = FileLogger { file: Mutex::new(File::create(&path).unwrap()), level: LevelFilter::Debug };
+        for (level, text) in [(log::Level::Debug, "kept"), (log::Level::Trace, "dropped")] {
+            logger.log(&Record::builder().args(format_args!("{}", text)).level(level).target("t").build());
+        }
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.contains("kept") && !written.contains("dropped"), "{}", written);
+    }
+}
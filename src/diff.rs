@@ -0,0 +1,301 @@
+use std::{collections::{BTreeMap, HashMap}, fs, io::{self, Write}, path::Path};
+use serde::Serialize;
+
+use crate::{app::{format_cpu, format_mem}, batch::Format, snapshot::{ProcessRecord, SystemSnapshot}};
+
+// Memory changes below this never count, however large in percent: a helper going from 8 KB
+// to 64 KB isn't what anyone diffs snapshots for
+const MIN_MEM_CHANGE: u64 = 1 << 20;
+
+#[derive(Debug, Serialize)]
+pub struct ProcessChange<'a> {
+    pub before: &'a ProcessRecord,
+    pub after: &'a ProcessRecord,
+}
+
+impl ProcessChange<'_> {
+    fn cpu_delta(&self) -> f32 {
+        self.after.cpu_percent - self.before.cpu_percent
+    }
+
+    fn mem_delta(&self) -> i128 {
+        self.after.mem_bytes as i128 - self.before.mem_bytes as i128
+    }
+}
+
+// What happened between two snapshots. Lists are ordered by how much they matter: new and
+// gone processes by their CPU then memory, changes by the size of the CPU change.
+#[derive(Debug, Default, Serialize)]
+pub struct SnapshotDiff<'a> {
+    pub appeared: Vec<&'a ProcessRecord>,
+    pub disappeared: Vec<&'a ProcessRecord>,
+    // Same name, different process: a service that was restarted
+    pub restarted: Vec<ProcessChange<'a>>,
+    // The same process with a CPU or memory change past the threshold
+    pub changed: Vec<ProcessChange<'a>>,
+}
+
+// Processes are paired by PID and start time, which together name one process even when the
+// PID is reused later. Snapshots without start times pair by PID and name instead. What is
+// left is paired by name, oldest PID first, so a restarted nginx shows up as a restart rather
+// than as one nginx leaving and another arriving.
+//
+// `threshold` applies to both resources: a change of at least that many percentage points of
+// CPU, or of that percent of the larger memory figure (and at least 1 MB).
+pub fn diff<'a>(before: &'a SystemSnapshot, after: &'a SystemSnapshot, threshold: f64) -> SnapshotDiff<'a> {
+    let key = |p: &ProcessRecord| if p.start_time == 0 { (p.pid, Err(p.name.clone())) } else { (p.pid, Ok(p.start_time)) };
+    let mut by_key: HashMap<_, &ProcessRecord> = before.processes.iter().map(|p| (key(p), p)).collect();
+    let mut result = SnapshotDiff::default();
+    let mut unmatched_after = Vec::new();
+    for p in &after.processes {
+        match by_key.remove(&key(p)) {
+            Some(old) => {
+                let change = ProcessChange { before: old, after: p };
+                if significant(&change, threshold) {
+                    result.changed.push(change);
+                }
+            },
+            None => unmatched_after.push(p),
+        }
+    }
+
+    let mut gone_by_name: BTreeMap<&str, Vec<&ProcessRecord>> = BTreeMap::new();
+    for p in by_key.into_values() {
+        gone_by_name.entry(&p.name).or_default().push(p);
+    }
+    for list in gone_by_name.values_mut() {
+        list.sort_by_key(|p| std::cmp::Reverse(p.pid));
+    }
+    unmatched_after.sort_by_key(|p| p.pid);
+    for p in unmatched_after {
+        match gone_by_name.get_mut(p.name.as_str()).and_then(Vec::pop) {
+            Some(old) => result.restarted.push(ProcessChange { before: old, after: p }),
+            None => result.appeared.push(p),
+        }
+    }
+    result.disappeared = gone_by_name.into_values().flatten().collect();
+
+    let heaviest = |a: &&ProcessRecord, b: &&ProcessRecord| {
+        b.cpu_percent.total_cmp(&a.cpu_percent).then(b.mem_bytes.cmp(&a.mem_bytes)).then(a.pid.cmp(&b.pid))
+    };
+    result.appeared.sort_by(heaviest);
+    result.disappeared.sort_by(heaviest);
+    result.restarted.sort_by(|a, b| a.after.name.cmp(&b.after.name).then(a.after.pid.cmp(&b.after.pid)));
+    result.changed.sort_by(|a, b| {
+        b.cpu_delta().abs().total_cmp(&a.cpu_delta().abs())
+            .then(b.mem_delta().abs().cmp(&a.mem_delta().abs()))
+            .then(a.after.pid.cmp(&b.after.pid))
+    });
+    result
+}
+
+fn significant(change: &ProcessChange, threshold: f64) -> bool {
+    let cpu = change.cpu_delta().abs() as f64 >= threshold;
+    let mem_delta = change.mem_delta().unsigned_abs();
+    let larger = change.before.mem_bytes.max(change.after.mem_bytes) as f64;
+    let mem = mem_delta >= MIN_MEM_CHANGE as u128 && mem_delta as f64 >= larger * threshold / 100.0;
+    cpu || mem
+}
+
+// Reads a `--once --format json` file. A `--stream` capture works too: its first snapshot
+// is used.
+pub fn load(path: &Path) -> Result<SystemSnapshot, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    serde_json::from_str(text.trim()).or_else(|_| serde_json::from_str(first))
+        .map_err(|e| format!("{} is not a rust-htop JSON snapshot: {}", path.display(), e))
+}
+
+pub fn write(diff: &SnapshotDiff, format: Format, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, diff)?;
+            writeln!(out)
+        },
+        Format::Text | Format::Lines => write_text(diff, out),
+    }
+}
+
+fn write_text(diff: &SnapshotDiff, out: &mut impl Write) -> io::Result<()> {
+    if diff.appeared.is_empty() && diff.disappeared.is_empty() && diff.restarted.is_empty() && diff.changed.is_empty() {
+        return writeln!(out, "No significant differences");
+    }
+    for (title, list) in [("Appeared", &diff.appeared), ("Disappeared", &diff.disappeared)] {
+        if list.is_empty() {
+            continue;
+        }
+        writeln!(out, "{} ({})", title, list.len())?;
+        writeln!(out, "{:>7} {:<25} {:>9} {:>12}", "PID", "NAME", "CPU%", "MEM")?;
+        for p in list {
+            writeln!(out, "{:>7} {:<25} {:>9} {:>12}", p.pid, cut(&p.name), format_cpu(p.cpu_percent), format_mem(p.mem_bytes))?;
+        }
+        writeln!(out)?;
+    }
+    for (title, list) in [("Restarted", &diff.restarted), ("Changed", &diff.changed)] {
+        if list.is_empty() {
+            continue;
+        }
+        writeln!(out, "{} ({})", title, list.len())?;
+        writeln!(out, "{:>7} {:>7} {:<25} {:>9} {:>9} {:>12} {:>12}", "OLD PID", "PID", "NAME", "CPU%", "NOW", "MEM", "NOW")?;
+        for c in list {
+            writeln!(
+                out, "{:>7} {:>7} {:<25} {:>9} {:>9} {:>12} {:>12}",
+                c.before.pid, c.after.pid, cut(&c.after.name),
+                format_cpu(c.before.cpu_percent), format_cpu(c.after.cpu_percent),
+                format_mem(c.before.mem_bytes), format_mem(c.after.mem_bytes),
+            )?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn cut(name: &str) -> String {
+    name.chars().take(25).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{LoadRecord, MemoryRecord};
+
+    const MB: u64 = 1 << 20;
+
+    fn p(pid: u32, name: &str, start_time: u64, cpu: f32, mem: u64) -> ProcessRecord {
+        ProcessRecord { pid, parent_pid: None, name: name.into(), user: None, state: "S".into(), cpu_percent: cpu, mem_bytes: mem, kernel_thread: false, start_time, open_fds: None, connections: None, net_queued_bytes: None, swap_bytes: None, voluntary_ctxt_switches: None, nonvoluntary_ctxt_switches: None, minor_faults: None, major_faults: None, processor: None, container: None, exe: None, cmdline: Vec::new() }
+    }
+
+    fn snapshot(processes: Vec<ProcessRecord>) -> SystemSnapshot {
+        SystemSnapshot {
+            timestamp: "2024-01-01T00:00:00Z".into(),
+            cpus: Vec::new(),
+            memory: MemoryRecord { total_bytes: 0, used_bytes: 0, available_bytes: 0, cached_bytes: None, buffers_bytes: None, dirty_bytes: None, anon_huge_pages_bytes: None, pressure: None, pressure_free_percent: None, huge_pages: Vec::new(), swap_total_bytes: 0, swap_used_bytes: 0, swapped_in_bytes: None, swapped_out_bytes: None },
+            load: LoadRecord { one: 0.0, five: 0.0, fifteen: 0.0 },
+            boot_time: 0,
+            cpu_time: None,
+            cpufreq: None,
+            pressure: Vec::new(),
+            networks: Vec::new(),
+            processes,
+        }
+    }
+
+    fn pids(list: &[&ProcessRecord]) -> Vec<u32> {
+        list.iter().map(|p| p.pid).collect()
+    }
+
+    fn pairs(list: &[ProcessChange]) -> Vec<(u32, u32)> {
+        list.iter().map(|c| (c.before.pid, c.after.pid)).collect()
+    }
+
+    #[test]
+    fn identical_snapshots_have_no_differences() {
+        let a = snapshot(vec![p(1, "init", 100, 0.5, 10 * MB), p(2, "sshd", 100, 0.0, MB)]);
+        let d = diff(&a, &a, 10.0);
+        assert!(d.appeared.is_empty() && d.disappeared.is_empty() && d.restarted.is_empty() && d.changed.is_empty());
+        let mut out = Vec::new();
+        write(&d, Format::Text, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No significant differences\n");
+    }
+
+    #[test]
+    fn new_and_gone_processes() {
+        let before = snapshot(vec![p(1, "init", 100, 0.0, MB), p(50, "cron", 100, 0.0, MB), p(51, "backup", 100, 5.0, MB)]);
+        let after = snapshot(vec![p(1, "init", 100, 0.0, MB), p(60, "light", 200, 1.0, MB), p(61, "heavy", 200, 50.0, MB)]);
+        let d = diff(&before, &after, 10.0);
+        assert_eq!(pids(&d.appeared), vec![61, 60]);
+        assert_eq!(pids(&d.disappeared), vec![51, 50]);
+        assert!(d.restarted.is_empty() && d.changed.is_empty());
+    }
+
+    #[test]
+    fn a_reused_pid_is_a_different_process() {
+        let before = snapshot(vec![p(300, "worker", 100, 1.0, MB)]);
+        let after = snapshot(vec![p(300, "compiler", 500, 1.0, MB)]);
+        let d = diff(&before, &after, 10.0);
+        assert_eq!(pids(&d.appeared), vec![300]);
+        assert_eq!(pids(&d.disappeared), vec![300]);
+        assert_eq!(d.appeared[0].name, "compiler");
+    }
+
+    #[test]
+    fn restarted_services_match_by_name() {
+        let before = snapshot(vec![p(10, "nginx", 100, 1.0, MB), p(11, "nginx", 100, 1.0, MB), p(20, "postgres", 100, 2.0, MB)]);
+        let after = snapshot(vec![p(11, "nginx", 100, 1.0, MB), p(40, "nginx", 900, 1.0, MB), p(41, "postgres", 900, 2.0, MB), p(42, "nginx", 900, 1.0, MB)]);
+        let d = diff(&before, &after, 10.0);
+        assert_eq!(pairs(&d.restarted), vec![(10, 40), (20, 41)]);
+        assert_eq!(pids(&d.appeared), vec![42]);
+        assert!(d.disappeared.is_empty());
+    }
+
+    #[test]
+    fn changes_below_the_threshold_are_noise() {
+        let before = snapshot(vec![
+            p(1, "steady", 100, 10.0, 100 * MB),
+            p(2, "busier", 100, 10.0, 100 * MB),
+            p(3, "leaky", 100, 1.0, 100 * MB),
+            p(4, "tiny", 100, 0.0, 64 * 1024),
+            p(5, "calmer", 100, 80.0, 100 * MB),
+        ]);
+        let after = snapshot(vec![
+            p(1, "steady", 100, 19.0, 105 * MB),
+            p(2, "busier", 100, 25.0, 100 * MB),
+            p(3, "leaky", 100, 1.0, 200 * MB),
+            p(4, "tiny", 100, 0.0, 512 * 1024),
+            p(5, "calmer", 100, 20.0, 100 * MB),
+        ]);
+        let d = diff(&before, &after, 10.0);
+        assert_eq!(pairs(&d.changed), vec![(5, 5), (2, 2), (3, 3)]);
+        assert_eq!(pairs(&diff(&before, &after, 5.0).changed), vec![(5, 5), (2, 2), (1, 1), (3, 3)]);
+        assert!(diff(&before, &after, 100.0).changed.is_empty());
+    }
+
+    #[test]
+    fn snapshots_without_start_times_pair_by_pid_and_name() {
+        let before = snapshot(vec![p(7, "old", 0, 0.0, MB), p(8, "same", 0, 0.0, MB)]);
+        let after = snapshot(vec![p(7, "new", 0, 0.0, MB), p(8, "same", 0, 40.0, MB)]);
+        let d = diff(&before, &after, 10.0);
+        assert_eq!(pids(&d.appeared), vec![7]);
+        assert_eq!(pids(&d.disappeared), vec![7]);
+        assert_eq!(pairs(&d.changed), vec![(8, 8)]);
+    }
+
+    #[test]
+    fn text_and_json_output() {
+        let before = snapshot(vec![p(10, "nginx", 100, 1.0, MB), p(20, "app", 100, 5.0, 10 * MB)]);
+        let after = snapshot(vec![p(40, "nginx", 900, 1.0, MB), p(20, "app", 100, 45.0, 10 * MB), p(30, "cron", 900, 0.0, MB)]);
+        let d = diff(&before, &after, 10.0);
+        let mut out = Vec::new();
+        write(&d, Format::Text, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Appeared (1)");
+        assert_eq!(lines[2], "     30 cron                          0.00%      1.00 MB");
+        assert!(text.contains("Restarted (1)\nOLD PID     PID NAME"), "{}", text);
+        assert!(text.contains("     10      40 nginx"));
+        assert!(text.contains("     20      20 app                           5.00%    45.00%     10.00 MB     10.00 MB"), "{}", text);
+
+        let mut out = Vec::new();
+        write(&d, Format::Json, &mut out).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value["appeared"][0]["pid"], 30);
+        assert_eq!(value["restarted"][0]["before"]["pid"], 10);
+        assert_eq!(value["changed"][0]["after"]["cpu_percent"], 45.0);
+        assert_eq!(value["disappeared"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn loads_once_and_stream_files() {
+        let a = snapshot(vec![p(1, "init", 0, 0.0, MB)]);
+        let b = snapshot(vec![p(2, "sshd", 0, 0.0, MB)]);
+        let path = std::env::temp_dir().join(format!("rust-htop-diff-{}.json", std::process::id()));
+        fs::write(&path, format!("{}\n", serde_json::to_string(&a).unwrap())).unwrap();
+        assert_eq!(load(&path).unwrap(), a);
+        fs::write(&path, format!("{}\n{}\n", serde_json::to_string(&a).unwrap(), serde_json::to_string(&b).unwrap())).unwrap();
+        assert_eq!(load(&path).unwrap(), a);
+        fs::write(&path, "top - 10:00:00 up 1 day").unwrap();
+        assert!(load(&path).unwrap_err().contains("not a rust-htop JSON snapshot"));
+        fs::remove_file(&path).unwrap();
+        assert!(load(&path).unwrap_err().starts_with("could not read"));
+    }
+}
@@ -0,0 +1,198 @@
+// The line being typed in a prompt (the search, a watch pattern, a CPU list or I/O priority)
+// with a cursor in it. The cursor moves and deletes by grapheme cluster, so an accented letter
+// or a joined emoji is one step however many code points it is made of.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LineEditor {
+    text: String,
+    // Byte offset into `text`, always on a cluster boundary
+    cursor: usize,
+}
+
+impl LineEditor {
+    // `text` with the cursor after it
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        LineEditor { cursor: text.len(), text }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    // The text before and after the cursor
+    pub fn split(&self) -> (&str, &str) {
+        self.text.split_at(self.cursor)
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    pub fn backspace(&mut self) {
+        let start = self.previous_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.previous_boundary();
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    // Back over any spaces, then over the word in front of them, like Ctrl+W in a shell
+    pub fn delete_word(&mut self) {
+        let before = &self.text[..self.cursor];
+        let mut clusters = before.grapheme_indices(true).rev().peekable();
+        let mut start = self.cursor;
+        while let Some(&(i, g)) = clusters.peek() && g.trim().is_empty() {
+            start = i;
+            clusters.next();
+        }
+        while let Some(&(i, g)) = clusters.peek() && !g.trim().is_empty() {
+            start = i;
+            clusters.next();
+        }
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    pub fn delete_to_start(&mut self) {
+        self.text.replace_range(..self.cursor, "");
+        self.cursor = 0;
+    }
+
+    // Carries out an editing key; false for any other key, which the prompt handles itself
+    pub fn handle_key(&mut self, key: &KeyEvent) -> bool {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('w') if control => self.delete_word(),
+            KeyCode::Char('u') if control => self.delete_to_start(),
+            KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => self.insert(c.encode_utf8(&mut [0; 4])),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left => self.left(),
+            KeyCode::Right => self.right(),
+            KeyCode::Home => self.home(),
+            KeyCode::End => self.end(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn previous_boundary(&self) -> usize {
+        self.text[..self.cursor].grapheme_indices(true).next_back().map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..].graphemes(true).next().map_or(self.cursor, |g| self.cursor + g.len())
+    }
+}
+
+impl From<&str> for LineEditor {
+    fn from(text: &str) -> Self {
+        LineEditor::new(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn characters_go_in_at_the_cursor() {
+        let mut line = LineEditor::new("postgres");
+        line.home();
+        line.insert("pg:");
+        assert_eq!(line.split(), ("pg:", "postgres"));
+        line.end();
+        line.left();
+        line.left();
+        assert!(line.handle_key(&key(KeyCode::Char('X'), KeyModifiers::SHIFT)));
+        assert_eq!(line.as_str(), "pg:postgrXes");
+        // Ctrl and Alt combinations are the prompt's to handle
+        assert!(!line.handle_key(&key(KeyCode::Char('x'), KeyModifiers::CONTROL)));
+        assert!(!line.handle_key(&key(KeyCode::Enter, KeyModifiers::NONE)));
+        assert_eq!(line.as_str(), "pg:postgrXes");
+    }
+
+    #[test]
+    fn the_cursor_steps_over_whole_clusters() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let mut line = LineEditor::new(format!("微cafe\u{301}{}", family));
+        line.left();
+        assert_eq!(line.split().1, family);
+        line.left();
+        assert_eq!(line.split().1, format!("e\u{301}{}", family));
+        line.backspace();
+        assert_eq!(line.as_str(), format!("微cae\u{301}{}", family));
+        line.delete();
+        assert_eq!(line.as_str(), format!("微ca{}", family));
+        line.home();
+        line.right();
+        assert_eq!(line.split(), ("微", "ca👨\u{200d}👩\u{200d}👧"));
+        line.delete();
+        line.delete();
+        line.delete();
+        assert_eq!(line.as_str(), "微");
+        // Nothing to delete or move past at either end
+        line.delete();
+        line.right();
+        line.home();
+        line.backspace();
+        line.left();
+        assert_eq!(line.split(), ("", "微"));
+    }
+
+    #[test]
+    fn words_and_the_start_of_the_line_are_deleted_back_from_the_cursor() {
+        let mut line = LineEditor::new("best-effort  4");
+        line.handle_key(&key(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(line.as_str(), "best-effort  ");
+        line.handle_key(&key(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(line.as_str(), "");
+
+        let mut line = LineEditor::new("ps aux ünïcødé");
+        line.left();
+        line.left();
+        line.handle_key(&key(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(line.split(), ("ps aux ", "dé"));
+        line.handle_key(&key(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(line.split(), ("", "dé"));
+        line.handle_key(&key(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(line.as_str(), "dé");
+    }
+}
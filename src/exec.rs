@@ -0,0 +1,185 @@
+use std::{collections::{HashSet, VecDeque}, process::{Command, Stdio}, sync::mpsc, thread};
+use chrono::{DateTime, Local};
+
+// Entries kept for the log popup; older ones are dropped
+const LOG_LEN: usize = 50;
+
+// Output kept per run. Anything longer is cut with a marker.
+pub const OUTPUT_LIMIT: usize = 2000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunState {
+    Running,
+    // The exit code, or None when the command was killed by a signal
+    Exited(Option<i32>),
+    // The shell itself couldn't be started
+    Failed(String),
+    // The rule's previous run was still going
+    Skipped,
+}
+
+#[derive(Clone, Debug)]
+pub struct Run {
+    pub rule: String,
+    pub command: String,
+    pub started: DateTime<Local>,
+    pub state: RunState,
+    // Standard output then standard error, cut to OUTPUT_LIMIT
+    pub output: String,
+}
+
+// `on_fire` commands. Each runs through the shell on its own thread, so a slow command never
+// holds up a refresh, and the results come back through `poll`. A rule never has two runs at
+// once: firing while the last one is still going is logged as skipped.
+pub struct Runner {
+    pub log: VecDeque<Run>,
+    running: HashSet<String>,
+    // Finished runs; the rule name and start time find their log entry
+    results: mpsc::Receiver<Run>,
+    sender: mpsc::Sender<Run>,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        let (sender, results) = mpsc::channel();
+        Self { log: VecDeque::new(), running: HashSet::new(), results, sender }
+    }
+}
+
+impl Runner {
+    pub fn start(&mut self, rule: &str, command: &str, now: DateTime<Local>) {
+        let mut run = Run { rule: rule.to_string(), command: command.to_string(), started: now, state: RunState::Running, output: String::new() };
+        if !self.running.insert(rule.to_string()) {
+            run.state = RunState::Skipped;
+            self.push(run);
+            return;
+        }
+        let sender = self.sender.clone();
+        let mut done = run.clone();
+        self.push(run);
+        thread::spawn(move || {
+            match shell(&done.command).stdin(Stdio::null()).output() {
+                Ok(out) => {
+                    let mut text = String::from_utf8_lossy(&out.stdout).into_owned();
+                    text.push_str(&String::from_utf8_lossy(&out.stderr));
+                    done.state = RunState::Exited(out.status.code());
+                    done.output = truncate(text);
+                },
+                Err(e) => done.state = RunState::Failed(e.to_string()),
+            }
+            let _ = sender.send(done);
+        });
+    }
+
+    // Runs that finished since the last call, now also updated in the log
+    pub fn poll(&mut self) -> Vec<Run> {
+        let mut finished = Vec::new();
+        while let Ok(done) = self.results.try_recv() {
+            self.running.remove(&done.rule);
+            // Gone from the log if fifty newer runs pushed it out
+            if let Some(entry) = self.log.iter_mut().find(|r| r.rule == done.rule && r.started == done.started && r.state == RunState::Running) {
+                *entry = done.clone();
+            }
+            finished.push(done);
+        }
+        finished
+    }
+
+    fn push(&mut self, run: Run) {
+        if self.log.len() == LOG_LEN {
+            self.log.pop_front();
+        }
+        self.log.push_back(run);
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+fn truncate(mut text: String) -> String {
+    if text.len() > OUTPUT_LIMIT {
+        let mut end = OUTPUT_LIMIT;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("… (cut)");
+    }
+    text
+}
+
+// "exit 0", "killed by a signal", "could not start: ..."
+pub fn describe(state: &RunState) -> String {
+    match state {
+        RunState::Running => "running".to_string(),
+        RunState::Exited(Some(code)) => format!("exit {}", code),
+        RunState::Exited(None) => "killed by a signal".to_string(),
+        RunState::Failed(e) => format!("could not start: {}", e),
+        RunState::Skipped => "skipped, the previous run is still going".to_string(),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait(runner: &mut Runner) -> Vec<Run> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let finished = runner.poll();
+            if !finished.is_empty() || Instant::now() > deadline {
+                return finished;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn commands_run_in_the_background_and_report_back() {
+        let mut runner = Runner::default();
+        runner.start("disk", "echo out; echo err >&2; exit 3", Local::now());
+        assert_eq!(runner.log[0].state, RunState::Running);
+        let finished = wait(&mut runner);
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].state, RunState::Exited(Some(3)));
+        assert_eq!(finished[0].output, "out\nerr\n");
+        assert_eq!(runner.log[0].state, RunState::Exited(Some(3)));
+    }
+
+    #[test]
+    fn a_rule_runs_one_command_at_a_time() {
+        let mut runner = Runner::default();
+        let now = Local::now();
+        runner.start("slow", "sleep 0.2", now);
+        runner.start("slow", "sleep 0.2", now);
+        runner.start("other", "true", now);
+        assert_eq!(runner.log[1].state, RunState::Skipped);
+        let mut done = Vec::new();
+        while done.len() < 2 {
+            done.extend(wait(&mut runner));
+        }
+        assert!(done.iter().all(|r| r.state == RunState::Exited(Some(0))));
+        runner.start("slow", "true", Local::now());
+        assert_eq!(runner.log[3].state, RunState::Running);
+    }
+
+    #[test]
+    fn long_output_is_cut() {
+        let text = truncate("é".repeat(OUTPUT_LIMIT));
+        assert!(text.len() < OUTPUT_LIMIT + 20);
+        assert!(text.ends_with("… (cut)"));
+        assert_eq!(describe(&RunState::Exited(None)), "killed by a signal");
+    }
+}
@@ -0,0 +1,145 @@
+// The processes that were listed at one refresh and gone at the next, for the exit log popup.
+// Nothing traces them: an exit is noticed by comparing the processes before a refresh with the
+// samples it brought, so a process that started and ended between two refreshes is never seen
+// at all, and what is logged is how it looked at the last refresh it was alive for. A PID is
+// compared together with its start time, so when the kernel hands a PID to a new process the
+// old one is logged as exited and the new one isn't.
+
+use std::collections::{HashSet, VecDeque};
+use sysinfo::Pid;
+
+use crate::app::{ProcInfo, ProcSample};
+
+pub struct Exit {
+    // When the refresh that missed it was taken, in ms
+    pub time_ms: i64,
+    pub pid: Pid,
+    pub name: String,
+    pub user: Option<String>,
+    pub cpu: f32,
+    pub mem: u64,
+    // From its start until the last refresh it was seen at, when the source knows start times
+    pub lifetime: Option<u64>,
+}
+
+pub struct ExitLog {
+    // Newest last
+    exits: VecDeque<Exit>,
+    limit: usize,
+    // When the previous refresh was taken, which is the last time the exited processes were seen
+    seen_ms: i64,
+}
+
+impl ExitLog {
+    // Keeps the last `limit` exits; 0 keeps none
+    pub fn new(limit: usize) -> Self {
+        ExitLog { exits: VecDeque::new(), limit, seen_ms: 0 }
+    }
+
+    pub fn exits(&self) -> &VecDeque<Exit> {
+        &self.exits
+    }
+
+    pub fn len(&self) -> usize {
+        self.exits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exits.is_empty()
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    // `before` is what was listed up to now and `samples` what the refresh taken at `now_ms`
+    // found. The first refresh has nothing before it to have exited.
+    pub fn record(&mut self, before: &[ProcInfo], samples: &[ProcSample], now_ms: i64) {
+        if self.limit > 0 && !before.is_empty() {
+            let alive: HashSet<(Pid, u64)> = samples.iter().map(|s| (s.pid, s.start_time)).collect();
+            let seen_secs = (self.seen_ms / 1000).max(0) as u64;
+            self.exits.extend(before.iter().filter(|p| !alive.contains(&(p.pid, p.start_time))).map(|p| Exit {
+                time_ms: now_ms,
+                pid: p.pid,
+                name: p.name.clone(),
+                user: p.user.clone(),
+                cpu: p.cpu,
+                mem: p.mem,
+                lifetime: (p.start_time > 0 && seen_secs >= p.start_time).then(|| seen_secs - p.start_time),
+            }));
+            self.trim();
+        }
+        self.seen_ms = now_ms;
+    }
+
+    fn trim(&mut self) {
+        while self.exits.len() > self.limit {
+            self.exits.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{FakeSource, SystemSource};
+
+    // PIDs, names and start times
+    fn source(list: &[(u32, &str, u64)]) -> FakeSource {
+        let mut source = FakeSource::with_processes(&list.iter().map(|&(pid, name, _)| (pid, name, 1.5, 4096)).collect::<Vec<_>>());
+        for (p, &(_, _, start_time)) in source.processes.iter_mut().zip(list) {
+            p.start_time = start_time;
+        }
+        source
+    }
+
+    fn listed(source: &FakeSource) -> Vec<ProcInfo> {
+        source.processes().iter().map(ProcInfo::new).collect()
+    }
+
+    #[test]
+    fn processes_missing_from_the_next_refresh_are_logged() {
+        let mut log = ExitLog::new(10);
+        let first = source(&[(1, "init", 100), (4242, "cc1", 1000), (4243, "ld", 1020)]);
+        log.record(&[], &first.processes(), 1_030_000);
+        assert!(log.is_empty());
+        let second = source(&[(1, "init", 100)]);
+        log.record(&listed(&first), &second.processes(), 1_031_000);
+        let names: Vec<&str> = log.exits().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["cc1", "ld"]);
+        let cc1 = &log.exits()[0];
+        assert_eq!((cc1.pid, cc1.time_ms, cc1.cpu, cc1.mem), (Pid::from(4242), 1_031_000, 1.5, 4096));
+        // Last seen at the first refresh
+        assert_eq!(cc1.lifetime, Some(30));
+        log.record(&listed(&second), &second.processes(), 1_032_000);
+        assert_eq!(log.len(), 2);
+    }
+
+    #[test]
+    fn a_reused_pid_logs_the_old_process_only() {
+        let mut log = ExitLog::new(10);
+        let before = source(&[(7, "cron", 500)]);
+        let after = source(&[(7, "backup.sh", 900)]);
+        log.record(&listed(&before), &after.processes(), 1_000_000);
+        assert_eq!(log.exits().iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["cron"]);
+        // Without start times a PID that is still there is taken to be the same process
+        let mut log = ExitLog::new(10);
+        log.record(&listed(&source(&[(7, "cron", 0)])), &source(&[(7, "backup.sh", 0)]).processes(), 1_000_000);
+        assert!(log.is_empty());
+        log.record(&listed(&source(&[(7, "cron", 0)])), &[], 1_001_000);
+        assert_eq!(log.exits()[0].lifetime, None);
+    }
+
+    #[test]
+    fn only_the_newest_exits_are_kept() {
+        let mut log = ExitLog::new(2);
+        log.record(&listed(&source(&[(1, "a", 0), (2, "b", 0), (3, "c", 0)])), &[], 1000);
+        assert_eq!(log.exits().iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["b", "c"]);
+        log.set_limit(1);
+        assert_eq!(log.exits()[0].name, "c");
+        log.set_limit(0);
+        log.record(&listed(&source(&[(4, "d", 0)])), &[], 2000);
+        assert!(log.is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+use std::{borrow::Cow, fmt, fs::File, io::{self, Write}, path::{Path, PathBuf}};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone};
+use serde_json::{json, Map, Value};
+use sysinfo::{System, SystemExt};
+
+use crate::{app::{App, ProcInfo}, config::Config, csvlog};
+
+// How the exports written for other programs and spreadsheets spell times, decimals and CSV
+// fields, from the export_* settings. The interface keeps its own formatting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    // A strftime format, or "rfc3339"
+    pub timestamp_format: String,
+    pub decimal_separator: char,
+    pub delimiter: char,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale { timestamp_format: "rfc3339".into(), decimal_separator: '.', delimiter: ',' }
+    }
+}
+
+impl Locale {
+    // The config was validated, so each separator is one character
+    pub fn from_config(config: &Config) -> Self {
+        let default = Locale::default();
+        Locale {
+            timestamp_format: config.export_timestamp_format.clone(),
+            decimal_separator: config.export_decimal_separator.chars().next().unwrap_or(default.decimal_separator),
+            delimiter: config.export_delimiter.chars().next().unwrap_or(default.delimiter),
+        }
+    }
+
+    pub fn timestamp<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String where Tz::Offset: fmt::Display {
+        match self.timestamp_format.as_str() {
+            "rfc3339" => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            format => time.format(format).to_string(),
+        }
+    }
+
+    // With two decimal places, like the table
+    pub fn decimal(&self, value: f64) -> String {
+        let text = format!("{:.2}", value);
+        match self.decimal_separator {
+            '.' => text,
+            separator => text.replace('.', &separator.to_string()),
+        }
+    }
+
+    pub fn field<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        csvlog::escape_with(text, self.delimiter)
+    }
+
+    // Fields that need it must already have gone through `field`
+    pub fn join<S: AsRef<str>>(&self, fields: impl IntoIterator<Item = S>) -> String {
+        let mut line = String::new();
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                line.push(self.delimiter);
+            }
+            line.push_str(field.as_ref());
+        }
+        line
+    }
+}
+
+// 'E': the table as it is on screen, with the current filter, sort order and columns, written
+// to a new file named after the time, like rust-htop-20240501-031405.csv. In tree view each
+// row also gets its depth so the nesting survives. Returns the path written.
+pub fn write(app: &App, dir: &Path, now: DateTime<Local>) -> io::Result<PathBuf> {
+    let extension = if app.config.export_format == "json" { "json" } else { "csv" };
+    let text = render(app, &host(app), now);
+    let stem = format!("rust-htop-{}", now.format("%Y%m%d-%H%M%S"));
+    // Two exports within a second get numbered rather than overwriting each other
+    for n in 1.. {
+        let name = if n == 1 { format!("{}.{}", stem, extension) } else { format!("{}-{}.{}", stem, n, extension) };
+        let path = dir.join(name);
+        match File::create_new(&path) {
+            Ok(mut file) => {
+                file.write_all(text.as_bytes())?;
+                return Ok(path);
+            },
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+pub fn render(app: &App, host: &str, now: DateTime<Local>) -> String {
+    let locale = Locale::from_config(&app.config);
+    let timestamp = locale.timestamp(&now);
+    if app.config.export_format == "json" {
+        json(app, host, &timestamp)
+    } else {
+        csv(app, host, &timestamp, &locale)
+    }
+}
+
+// Whose processes these are: the agent being watched, or this machine
+pub fn host(app: &App) -> String {
+    match (&app.remote, &app.replay) {
+        (Some(remote), _) => remote.addr.clone(),
+        (None, Some(_)) => "a replayed recording".to_string(),
+        (None, None) => System::new().host_name().unwrap_or_else(|| "unknown host".to_string()),
+    }
+}
+
+// The rows on screen, with their tree depth in tree view
+fn rows(app: &App) -> impl Iterator<Item = (Option<usize>, &ProcInfo)> {
+    app.visible.iter().enumerate().map(|(row, &i)| (app.tree_view.then(|| app.depths.get(row).copied().unwrap_or(0)), &app.processes[i]))
+}
+
+fn view(app: &App) -> String {
+    let mut parts = Vec::new();
+    if !app.search_query.is_empty() {
+        parts.push(format!("filter {}{:?}", if app.search_inverted { "not " } else { "" }, app.search_query.as_str()));
+    }
+    parts.push(format!("sorted by {} {}", app.sort_by.name(), if app.descending { "descending" } else { "ascending" }));
+    if app.tree_view {
+        parts.push("tree view".to_string());
+    }
+    if app.hide_kernel_threads {
+        parts.push("kernel threads hidden".to_string());
+    }
+    parts.join(", ")
+}
+
+fn csv(app: &App, host: &str, timestamp: &str, locale: &Locale) -> String {
+    let mut out = format!("# rust-htop export of {} at {}\n# {}\n", host, timestamp, view(app));
+    let mut header: Vec<&str> = app.columns.iter().map(|c| c.field()).collect();
+    if app.tree_view {
+        header.insert(0, "depth");
+    }
+    out.push_str(&locale.join(header));
+    out.push('\n');
+    for (depth, p) in rows(app) {
+        let mut fields: Vec<String> = app.columns.iter().map(|c| match c.value(p) {
+            Value::String(text) => locale.field(&text).into_owned(),
+            Value::Number(n) if n.is_f64() => locale.decimal(n.as_f64().unwrap_or(0.0)),
+            other => other.to_string(),
+        }).collect();
+        if let Some(depth) = depth {
+            fields.insert(0, depth.to_string());
+        }
+        out.push_str(&locale.join(fields));
+        out.push('\n');
+    }
+    out
+}
+
+fn json(app: &App, host: &str, timestamp: &str) -> String {
+    let processes: Vec<Value> = rows(app).map(|(depth, p)| {
+        let mut row: Map<String, Value> = app.columns.iter().map(|c| (c.field().to_string(), c.value(p))).collect();
+        if let Some(depth) = depth {
+            row.insert("depth".into(), depth.into());
+        }
+        Value::Object(row)
+    }).collect();
+    let document = json!({
+        "host": host,
+        "timestamp": timestamp,
+        "filter": app.search_query.as_str(),
+        "filter_inverted": app.search_inverted,
+        "sort": app.sort_by.name(),
+        "descending": app.descending,
+        "tree_view": app.tree_view,
+        "hide_kernel_threads": app.hide_kernel_threads,
+        "columns": app.columns.iter().map(|c| c.field()).collect::<Vec<_>>(),
+        "processes": processes,
+    });
+    let mut text = serde_json::to_string_pretty(&document).unwrap_or_else(|e| {
+        log::error!("could not encode the export: {}", e);
+        String::new()
+    });
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use crate::{config::Config, keys::Action, system::FakeSource};
+
+    fn app(config: Config) -> App {
+        let mut source = FakeSource::with_processes(&[(1, "init", 0.1, 1 << 20), (20, "web, worker", 12.345, 3 << 20), (21, "web", 50.0, 2 << 20)]);
+        source.processes[1].parent = Some(1);
+        App::new(config, Box::new(source))
+    }
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 1, 3, 14, 5).unwrap()
+    }
+
+    #[test]
+    fn csv_follows_the_view() {
+        let mut app = app(Config { columns: vec!["name".into(), "cpu".into(), "pid".into()], ..Config::default() });
+        app.search_query = "web".into();
+        app.update_view();
+        let text = render(&app, "db1", now());
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].starts_with("# rust-htop export of db1 at 2024-05-01T03:14:05"), "{}", lines[0]);
+        assert_eq!(lines[1], "# filter \"web\", sorted by cpu descending");
+        assert_eq!(&lines[2..], ["name,cpu_percent,pid", "web,50.00,21", "\"web, worker\",12.35,20"]);
+    }
+
+    // Splits a CSV line on `delimiter` outside quotes and unquotes the fields, the way a
+    // spreadsheet reads it
+    fn parse_row(line: &str, delimiter: char) -> Vec<String> {
+        let (mut fields, mut field, mut quoted) = (Vec::new(), String::new(), false);
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                },
+                '"' => quoted = !quoted,
+                c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+        fields.push(field);
+        fields
+    }
+
+    #[test]
+    fn a_decimal_comma_and_semicolons_read_back_as_the_same_numbers() {
+        let config = Config {
+            columns: vec!["name".into(), "cpu".into(), "pid".into()],
+            export_timestamp_format: "%d.%m.%Y %H:%M:%S".into(),
+            export_decimal_separator: ",".into(),
+            export_delimiter: ";".into(),
+            ..Config::default()
+        };
+        let mut app = app(config);
+        app.update_view();
+        let text = render(&app, "db1", now());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "# rust-htop export of db1 at 01.05.2024 03:14:05");
+        assert_eq!(parse_row(lines[2], ';'), ["name", "cpu_percent", "pid"]);
+        let rows: Vec<(String, f64, u32)> = lines[3..].iter().map(|line| {
+            let fields = parse_row(line, ';');
+            (fields[0].clone(), fields[1].replace(',', ".").parse().unwrap(), fields[2].parse().unwrap())
+        }).collect();
+        assert_eq!(rows, [("web".to_string(), 50.0, 21), ("web, worker".to_string(), 12.35, 20), ("init".to_string(), 0.1, 1)]);
+        // The comma in the name needs no quotes between semicolons
+        assert_eq!(lines[4], "web, worker;12,35;20");
+    }
+
+    #[test]
+    fn fields_with_the_delimiter_are_quoted() {
+        let locale = Locale { delimiter: '|', ..Locale::default() };
+        let line = locale.join([locale.field("a|b").into_owned(), locale.decimal(1.5), locale.field("say \"hi\"").into_owned()]);
+        assert_eq!(line, "\"a|b\"|1.50|\"say \"\"hi\"\"\"");
+        assert_eq!(parse_row(&line, '|'), ["a|b", "1.50", "say \"hi\""]);
+        let tab = Locale { delimiter: '\t', ..Locale::default() };
+        assert_eq!(parse_row(&tab.join(["web, worker", "2"]), '\t'), ["web, worker", "2"]);
+        assert_eq!(Locale::default().timestamp(&now().with_timezone(&chrono::Utc)), now().with_timezone(&chrono::Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string());
+    }
+
+    #[test]
+    fn json_and_tree_view() {
+        let mut app = app(Config { export_format: "json".into(), ..Config::default() });
+        app.perform(Action::ToggleTree);
+        let value: Value = serde_json::from_str(&render(&app, "db1", now())).unwrap();
+        assert_eq!(value["host"], "db1");
+        assert_eq!(value["timestamp"].as_str().map(|t| DateTime::parse_from_rfc3339(t).unwrap().timestamp()), Some(now().timestamp()));
+        assert_eq!(value["tree_view"], true);
+        assert_eq!(value["columns"], json!(["pid", "name", "cpu_percent", "mem_bytes"]));
+        let pids: Vec<u64> = value["processes"].as_array().unwrap().iter().map(|p| p["pid"].as_u64().unwrap()).collect();
+        assert_eq!(pids, vec![21, 1, 20]);
+        assert_eq!(value["processes"][2]["depth"], 1);
+        assert_eq!(value["processes"][2]["cpu_percent"], 12.35);
+        assert_eq!(value["processes"][2]["mem_bytes"], 3 << 20);
+    }
+
+    #[test]
+    fn files_are_named_after_the_time_and_never_replaced() {
+        let dir = std::env::temp_dir().join(format!("rust-htop-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let app = app(Config::default());
+        let first = write(&app, &dir, now()).unwrap();
+        let second = write(&app, &dir, now()).unwrap();
+        assert_eq!(first.file_name().unwrap(), "rust-htop-20240501-031405.csv");
+        assert_eq!(second.file_name().unwrap(), "rust-htop-20240501-031405-2.csv");
+        assert!(std::fs::read_to_string(&first).unwrap().contains("pid,name,cpu_percent,mem_bytes\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(write(&app, &dir, now()).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashMap, fs, path::Path, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc}, thread, time::Duration};
+use sysinfo::{Pid, PidExt};
+
+use crate::{conns, netio::{self, Queues}};
+
+// How often a pass over every process starts
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+// Open descriptor counts for the FDS column, open sockets for the CONN column and what is
+// queued in them for the NET column. Reading /proc/<pid>/fd for every process takes too long
+// to do on each refresh, so while any of the FDS, CONN or NET columns is shown or sorted on, a
+// worker thread counts them all every INTERVAL and `poll` picks up the latest pass. A process
+// the pass couldn't read (another user's without root, or one started since) has no count.
+// Outside Linux there is no worker and nothing is counted.
+#[derive(Default)]
+pub struct FdCounter {
+    pub counts: HashMap<Pid, u32>,
+    // TCP and UDP sockets; only counted while asked for, since it means a readlink per fd
+    pub sockets: HashMap<Pid, u32>,
+    // The bytes queued in those sockets, also only while asked for
+    pub queues: HashMap<Pid, Queues>,
+    worker: Option<Worker>,
+}
+
+#[derive(Default)]
+struct Pass {
+    counts: HashMap<Pid, u32>,
+    sockets: HashMap<Pid, u32>,
+    queues: HashMap<Pid, Queues>,
+}
+
+struct Worker {
+    results: mpsc::Receiver<Pass>,
+    stop: Arc<AtomicBool>,
+    sockets: Arc<AtomicBool>,
+    queues: Arc<AtomicBool>,
+}
+
+// The thread finishes the pass it is on, then sees the flag and exits
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl FdCounter {
+    pub fn set_active(&mut self, fds: bool, sockets: bool, queues: bool) {
+        match (fds || sockets || queues, &self.worker) {
+            (true, None) if cfg!(target_os = "linux") => self.worker = Some(spawn(sockets, queues)),
+            (true, Some(worker)) => {
+                worker.sockets.store(sockets, Ordering::Relaxed);
+                worker.queues.store(queues, Ordering::Relaxed);
+            },
+            (false, Some(_)) => self.worker = None,
+            _ => {},
+        }
+        if self.worker.is_none() {
+            self.counts.clear();
+        }
+        if !sockets {
+            self.sockets.clear();
+        }
+        if !queues {
+            self.queues.clear();
+        }
+    }
+
+    // Takes the newest finished pass, if one came in since the last call
+    pub fn poll(&mut self) {
+        let Some(worker) = &self.worker else { return };
+        while let Ok(pass) = worker.results.try_recv() {
+            self.counts = pass.counts;
+            // A pass that was under way when sockets were turned off still counted them
+            if worker.sockets.load(Ordering::Relaxed) {
+                self.sockets = pass.sockets;
+            }
+            if worker.queues.load(Ordering::Relaxed) {
+                self.queues = pass.queues;
+            }
+        }
+    }
+}
+
+fn spawn(sockets: bool, queues: bool) -> Worker {
+    let (sender, results) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let sockets = Arc::new(AtomicBool::new(sockets));
+    let queues = Arc::new(AtomicBool::new(queues));
+    let (thread_stop, thread_sockets, thread_queues) = (Arc::clone(&stop), Arc::clone(&sockets), Arc::clone(&queues));
+    thread::spawn(move || {
+        while !thread_stop.load(Ordering::Relaxed) {
+            let started = std::time::Instant::now();
+            let pass = count_all(Path::new("/proc"), thread_sockets.load(Ordering::Relaxed), thread_queues.load(Ordering::Relaxed));
+            log::debug!("counted the descriptors of {} processes in {:?}", pass.counts.len(), started.elapsed());
+            if sender.send(pass).is_err() {
+                return;
+            }
+            thread::sleep(INTERVAL);
+        }
+    });
+    Worker { results, stop, sockets, queues }
+}
+
+// Every numbered directory under `proc` whose fd directory could be listed
+fn count_all(proc: &Path, sockets: bool, queues: bool) -> Pass {
+    let mut pass = Pass::default();
+    let Ok(entries) = fs::read_dir(proc) else { return pass };
+    // Sockets of other kinds, like Unix ones, aren't in the tables and aren't counted
+    let tables: Option<HashMap<u64, Queues>> = (sockets || queues).then(|| netio::read_all(proc));
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse().ok()) else { continue };
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else { continue };
+        let pid = Pid::from_u32(pid);
+        match &tables {
+            None => {
+                pass.counts.insert(pid, fds.count() as u32);
+            },
+            Some(tables) => {
+                let (mut count, mut open, mut queued) = (0, 0, Queues::default());
+                for fd in fds.flatten() {
+                    count += 1;
+                    if let Some(socket) = fs::read_link(fd.path()).ok().and_then(|target| conns::socket_inode(&target)).and_then(|inode| tables.get(&inode)) {
+                        open += 1;
+                        queued.tx += socket.tx;
+                        queued.rx += socket.rx;
+                    }
+                }
+                pass.counts.insert(pid, count);
+                if sockets {
+                    pass.sockets.insert(pid, open);
+                }
+                if queues {
+                    pass.queues.insert(pid, queued);
+                }
+            },
+        }
+    }
+    pass
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_and_non_process_entries_get_no_count() {
+        let dir = std::env::temp_dir().join(format!("rust-htop-fds-{}", std::process::id()));
+        for fd in ["0", "1", "2"] {
+            fs::create_dir_all(dir.join("10/fd").join(fd)).unwrap();
+        }
+        fs::create_dir_all(dir.join("11")).unwrap();
+        fs::create_dir_all(dir.join("self/fd/0")).unwrap();
+        let pass = count_all(&dir, false, false);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(pass.counts, HashMap::from([(Pid::from_u32(10), 3)]));
+        assert!(pass.sockets.is_empty());
+    }
+
+    // Two web workers sharing a listener, one with a download queued up, and a DNS server
+    #[cfg(unix)]
+    #[test]
+    fn queued_bytes_are_added_up_per_process() {
+        use std::os::unix::fs::symlink;
+        let dir = std::env::temp_dir().join(format!("rust-htop-queues-{}", std::process::id()));
+        fs::create_dir_all(dir.join("net")).unwrap();
+        fs::write(dir.join("net/tcp"), include_str!("../tests/data/net-tcp")).unwrap();
+        fs::write(dir.join("net/udp"), include_str!("../tests/data/net-udp")).unwrap();
+        let fds = [(20, &["socket:[30211]", "socket:[41102]", "/dev/null"][..]), (21, &["socket:[30211]", "socket:[41117]", "socket:[99999]"]), (22, &["socket:[18923]"])];
+        for (pid, targets) in fds {
+            fs::create_dir_all(dir.join(format!("{}/fd", pid))).unwrap();
+            for (fd, target) in targets.iter().enumerate() {
+                symlink(target, dir.join(format!("{}/fd/{}", pid, fd))).unwrap();
+            }
+        }
+        let pass = count_all(&dir, true, true);
+        fs::remove_dir_all(&dir).unwrap();
+        let queued = |pid| pass.queues[&Pid::from_u32(pid)];
+        assert_eq!(queued(20), Queues { tx: 128000, rx: 0 });
+        assert_eq!(queued(21), Queues { tx: 8192, rx: 2048 });
+        assert_eq!(queued(22), Queues { tx: 0, rx: 76800 });
+        // A Unix socket, here one missing from the tables, isn't counted
+        assert_eq!(pass.sockets[&Pid::from_u32(21)], 2);
+        assert_eq!(pass.counts[&Pid::from_u32(21)], 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn the_worker_counts_this_process() {
+        let mut counter = FdCounter::default();
+        let _listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        counter.set_active(false, true, false);
+        let me = Pid::from_u32(std::process::id());
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !counter.counts.contains_key(&me) && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            counter.poll();
+        }
+        assert!(counter.counts[&me] > 0);
+        assert!(counter.sockets[&me] >= 1);
+        counter.set_active(true, false, false);
+        assert!(counter.sockets.is_empty() && !counter.counts.is_empty());
+        counter.set_active(false, false, false);
+        assert!(counter.counts.is_empty());
+    }
+}
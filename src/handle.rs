@@ -0,0 +1,57 @@
+// A process as it was picked: its PID and when it started. Popups and prompts that stay open
+// across refreshes hold one of these rather than a bare PID, so that one whose process exits,
+// or whose PID the kernel hands to a new process, is shown as exited instead of going on to
+// read or change whatever has the number now.
+
+use sysinfo::Pid;
+use crate::app::ProcInfo;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ProcessHandle {
+    pub pid: Pid,
+    pub start_time: u64,
+}
+
+impl ProcessHandle {
+    pub fn of(p: &ProcInfo) -> Self {
+        ProcessHandle { pid: p.pid, start_time: p.start_time }
+    }
+
+    // The process, if it is still the one that was picked. A source that can't read start
+    // times gives 0 for every process, which still compares equal.
+    pub fn resolve<'a>(&self, procs: &'a [ProcInfo]) -> Option<&'a ProcInfo> {
+        procs.iter().find(|p| p.pid == self.pid && p.start_time == self.start_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{FakeSource, SystemSource};
+
+    // PIDs and start times
+    fn procs(list: &[(u32, u64)]) -> Vec<ProcInfo> {
+        let mut source = FakeSource::with_processes(&list.iter().map(|&(pid, _)| (pid, "postgres", 0.0, 0)).collect::<Vec<_>>());
+        for (p, &(_, start_time)) in source.processes.iter_mut().zip(list) {
+            p.start_time = start_time;
+        }
+        source.processes().iter().map(ProcInfo::new).collect()
+    }
+
+    #[test]
+    fn a_process_that_exits_no_longer_resolves() {
+        let before = procs(&[(7, 100), (42, 500)]);
+        let handle = ProcessHandle::of(&before[1]);
+        assert_eq!(handle.resolve(&before).map(|p| p.pid), Some(Pid::from(42)));
+        assert!(handle.resolve(&procs(&[(7, 100)])).is_none());
+    }
+
+    #[test]
+    fn a_reused_pid_is_not_the_same_process() {
+        let handle = ProcessHandle::of(&procs(&[(42, 500)])[0]);
+        assert!(handle.resolve(&procs(&[(42, 900)])).is_none());
+        // Without start times the PID is all there is to go on
+        let handle = ProcessHandle::of(&procs(&[(42, 0)])[0]);
+        assert!(handle.resolve(&procs(&[(42, 0)])).is_some());
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fs, path::Path, time::Duration};
+use rusqlite::{params, Connection};
+use sysinfo::PidExt;
+
+use crate::app::{App, HistorySample, ProcInfo};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS system (
+        time_ms INTEGER NOT NULL PRIMARY KEY,
+        cpu_percent REAL NOT NULL,
+        mem_used_bytes INTEGER NOT NULL,
+        mem_total_bytes INTEGER NOT NULL,
+        net_received_bytes INTEGER NOT NULL,
+        net_transmitted_bytes INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS process (
+        time_ms INTEGER NOT NULL,
+        pid INTEGER NOT NULL,
+        start_time INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        cpu_percent REAL NOT NULL,
+        mem_bytes INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS process_by_pid ON process (pid, start_time, time_ms);
+    CREATE INDEX IF NOT EXISTS process_by_time ON process (time_ms);
+";
+
+// Which processes get a row each refresh. Everything else is idle enough that a trend of it
+// isn't worth the disk space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    pub min_cpu: f32,
+    pub min_mem: u64,
+}
+
+impl Thresholds {
+    fn keeps(&self, p: &ProcInfo) -> bool {
+        p.cpu >= self.min_cpu || p.mem >= self.min_mem
+    }
+}
+
+// `--history-db`: a row of system totals per refresh, and one per process above the
+// thresholds, all written in a single transaction. Rows older than the retention period are
+// deleted when the database is opened.
+pub struct History {
+    conn: Connection,
+    thresholds: Thresholds,
+}
+
+impl History {
+    pub fn open(path: &Path, retention: Duration, thresholds: Thresholds, now_ms: i64) -> rusqlite::Result<Self> {
+        if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() {
+            // A missing directory is reported by the open below
+            let _ = fs::create_dir_all(dir);
+        }
+        Self::new(Connection::open(path)?, retention, thresholds, now_ms)
+    }
+
+    fn new(conn: Connection, retention: Duration, thresholds: Thresholds, now_ms: i64) -> rusqlite::Result<Self> {
+        // The interface also writes while the popup reads; a second instance on the same file
+        // waits briefly instead of failing
+        conn.busy_timeout(Duration::from_secs(1))?;
+        conn.execute_batch(SCHEMA)?;
+        let cutoff = now_ms - retention.as_millis() as i64;
+        conn.execute("DELETE FROM system WHERE time_ms < ?1", [cutoff])?;
+        conn.execute("DELETE FROM process WHERE time_ms < ?1", [cutoff])?;
+        Ok(Self { conn, thresholds })
+    }
+
+    pub fn write(&mut self, app: &App, time_ms: i64) -> rusqlite::Result<()> {
+        let totals = &app.totals;
+        let cpu = if totals.cpus.is_empty() { 0.0 } else { totals.cpus.iter().sum::<f32>() / totals.cpus.len() as f32 };
+        let networks = app.source.networks();
+        let received: u64 = networks.iter().map(|n| n.total_received).sum();
+        let transmitted: u64 = networks.iter().map(|n| n.total_transmitted).sum();
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO system VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![time_ms, cpu, totals.mem_used as i64, totals.mem_total as i64, received as i64, transmitted as i64],
+        )?;
+        {
+            let mut insert = tx.prepare_cached("INSERT INTO process VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            for p in app.processes.iter().filter(|p| self.thresholds.keeps(p)) {
+                insert.execute(params![time_ms, p.pid.as_u32(), p.start_time as i64, p.name, p.cpu, p.mem as i64])?;
+            }
+        }
+        tx.commit()
+    }
+
+    // The samples of one process since `since_ms`, oldest first. A start time of 0 (unknown)
+    // matches on the PID alone.
+    pub fn process(&self, p: &ProcInfo, since_ms: i64) -> rusqlite::Result<Vec<HistorySample>> {
+        let mut query = self.conn.prepare_cached(
+            "SELECT time_ms, cpu_percent, mem_bytes FROM process
+             WHERE pid = ?1 AND (?2 = 0 OR start_time = ?2) AND time_ms >= ?3 ORDER BY time_ms",
+        )?;
+        let rows = query.query_map(params![p.pid.as_u32(), p.start_time as i64, since_ms], |row| {
+            Ok(HistorySample { time_ms: row.get(0)?, cpu: row.get(1)?, mem: row.get::<_, i64>(2)? as u64 })
+        })?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, system::FakeSource};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+    const THRESHOLDS: Thresholds = Thresholds { min_cpu: 1.0, min_mem: 100 << 20 };
+
+    fn app(rows: &[(u32, &str, f32, u64)]) -> App {
+        App::new(Config::default(), Box::new(FakeSource::with_processes(rows)))
+    }
+
+    fn count(history: &History, table: &str) -> i64 {
+        history.conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn only_busy_or_large_processes_are_kept() {
+        let mut history = History::new(Connection::open_in_memory().unwrap(), DAY, THRESHOLDS, 0).unwrap();
+        let app = app(&[(1, "idle", 0.1, 1 << 20), (2, "busy", 40.0, 1 << 20), (3, "large", 0.0, 500 << 20)]);
+        history.write(&app, 1_000).unwrap();
+        history.write(&app, 2_000).unwrap();
+        assert_eq!(count(&history, "system"), 2);
+        assert_eq!(count(&history, "process"), 4);
+        let busy = app.processes.iter().find(|p| p.name == "busy").unwrap();
+        let samples = history.process(busy, 0).unwrap();
+        assert_eq!(samples, vec![
+            HistorySample { time_ms: 1_000, cpu: 40.0, mem: 1 << 20 },
+            HistorySample { time_ms: 2_000, cpu: 40.0, mem: 1 << 20 },
+        ]);
+        assert_eq!(history.process(busy, 1_500).unwrap().len(), 1);
+        let idle = app.processes.iter().find(|p| p.name == "idle").unwrap();
+        assert!(history.process(idle, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_reused_pid_has_its_own_history() {
+        let mut history = History::new(Connection::open_in_memory().unwrap(), DAY, THRESHOLDS, 0).unwrap();
+        let mut app = app(&[(7, "old", 50.0, 0)]);
+        app.processes[0].start_time = 100;
+        history.write(&app, 1_000).unwrap();
+        app.processes[0].start_time = 200;
+        app.processes[0].name = "new".into();
+        history.write(&app, 2_000).unwrap();
+        let samples = history.process(&app.processes[0], 0).unwrap();
+        assert_eq!(samples.iter().map(|s| s.time_ms).collect::<Vec<_>>(), vec![2_000]);
+    }
+
+    #[test]
+    fn old_rows_are_pruned_on_open() {
+        let path = std::env::temp_dir().join(format!("rust-htop-history-{}", std::process::id())).join("history.sqlite");
+        let _ = fs::remove_file(&path);
+        let app = app(&[(1, "busy", 50.0, 0)]);
+        let day_ms = DAY.as_millis() as i64;
+        {
+            let mut history = History::open(&path, DAY * 7, THRESHOLDS, 0).unwrap();
+            for day in 0..10 {
+                history.write(&app, day * day_ms).unwrap();
+            }
+        }
+        let history = History::open(&path, DAY * 7, THRESHOLDS, 10 * day_ms).unwrap();
+        assert_eq!(count(&history, "system"), 7);
+        assert_eq!(count(&history, "process"), 7);
+        drop(history);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn failures_are_errors_not_panics() {
+        assert!(History::open("/dev/null/history.sqlite".as_ref(), DAY, THRESHOLDS, 0).is_err());
+    }
+}
@@ -0,0 +1,573 @@
+use std::collections::{BTreeMap, HashMap};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Quit,
+    Suspend,
+    Help,
+    Search,
+    InvertSearch,
+    FilterUser,
+    FilterState,
+    SortCpu,
+    SortMem,
+    SortName,
+    SortFds,
+    SortSwap,
+    SortIvcsw,
+    SortMajflt,
+    ReverseSort,
+    SortMenu,
+    CycleNameDisplay,
+    ToggleTree,
+    ToggleTreeTotals,
+    ToggleKernelThreads,
+    ToggleIdle,
+    ToggleSelf,
+    Pause,
+    ResetNetwork,
+    ToggleMemoryDetail,
+    SaveSettings,
+    Export,
+    History,
+    Connections,
+    Listeners,
+    Logins,
+    Details,
+    SetAffinity,
+    SetIoPriority,
+    ToggleAlerts,
+    Alerts,
+    CommandLog,
+    Watch,
+    Watches,
+    ActionLog,
+    Messages,
+    ExitLog,
+    Realtime,
+    ToggleFooter,
+    ResetView,
+    Up,
+    Down,
+    Top,
+    Bottom,
+    TogglePerf,
+}
+
+impl Action {
+    pub const ALL: [Action; 50] = [
+        Action::Quit,
+        Action::Suspend,
+        Action::Help,
+        Action::Search,
+        Action::InvertSearch,
+        Action::FilterUser,
+        Action::FilterState,
+        Action::SortCpu,
+        Action::SortMem,
+        Action::SortName,
+        Action::SortFds,
+        Action::SortSwap,
+        Action::SortIvcsw,
+        Action::SortMajflt,
+        Action::ReverseSort,
+        Action::SortMenu,
+        Action::CycleNameDisplay,
+        Action::ToggleTree,
+        Action::ToggleTreeTotals,
+        Action::ToggleKernelThreads,
+        Action::ToggleIdle,
+        Action::ToggleSelf,
+        Action::Pause,
+        Action::ResetNetwork,
+        Action::ToggleMemoryDetail,
+        Action::SaveSettings,
+        Action::Export,
+        Action::History,
+        Action::Connections,
+        Action::Listeners,
+        Action::Logins,
+        Action::Details,
+        Action::SetAffinity,
+        Action::SetIoPriority,
+        Action::ToggleAlerts,
+        Action::Alerts,
+        Action::CommandLog,
+        Action::Watch,
+        Action::Watches,
+        Action::ActionLog,
+        Action::Messages,
+        Action::ExitLog,
+        Action::Realtime,
+        Action::ToggleFooter,
+        Action::ResetView,
+        Action::Up,
+        Action::Down,
+        Action::Top,
+        Action::Bottom,
+        Action::TogglePerf,
+    ];
+
+    // The name used in the [keys] table
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Suspend => "suspend",
+            Action::Help => "help",
+            Action::Search => "search",
+            Action::InvertSearch => "invert_search",
+            Action::FilterUser => "filter_user",
+            Action::FilterState => "filter_state",
+            Action::SortCpu => "sort_cpu",
+            Action::SortMem => "sort_mem",
+            Action::SortName => "sort_name",
+            Action::SortFds => "sort_fds",
+            Action::SortSwap => "sort_swap",
+            Action::SortIvcsw => "sort_ivcsw",
+            Action::SortMajflt => "sort_majflt",
+            Action::ReverseSort => "reverse_sort",
+            Action::SortMenu => "sort_menu",
+            Action::CycleNameDisplay => "cycle_name_display",
+            Action::ToggleTree => "toggle_tree",
+            Action::ToggleTreeTotals => "toggle_tree_totals",
+            Action::ToggleKernelThreads => "toggle_kernel_threads",
+            Action::ToggleIdle => "toggle_idle",
+            Action::ToggleSelf => "toggle_self",
+            Action::Pause => "pause",
+            Action::ResetNetwork => "reset_network",
+            Action::ToggleMemoryDetail => "toggle_memory_detail",
+            Action::SaveSettings => "save_settings",
+            Action::Export => "export",
+            Action::History => "history",
+            Action::Connections => "connections",
+            Action::Listeners => "listeners",
+            Action::Logins => "logins",
+            Action::Details => "details",
+            Action::SetAffinity => "set_affinity",
+            Action::SetIoPriority => "set_io_priority",
+            Action::ToggleAlerts => "toggle_alerts",
+            Action::Alerts => "alerts",
+            Action::CommandLog => "command_log",
+            Action::Watch => "watch",
+            Action::Watches => "watches",
+            Action::ActionLog => "action_log",
+            Action::Messages => "messages",
+            Action::ExitLog => "exit_log",
+            Action::Realtime => "realtime",
+            Action::ToggleFooter => "toggle_footer",
+            Action::ResetView => "reset_view",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::TogglePerf => "toggle_perf",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Suspend => "Suspend to the shell",
+            Action::Help => "Show this help",
+            Action::Search => "Search by name or PID",
+            Action::InvertSearch => "List what the search doesn't match (user and state filters still apply)",
+            Action::FilterUser => "Show only the processes of one user",
+            Action::FilterState => "Show only running, D-state, zombie or stopped processes",
+            Action::SortCpu => "Sort by CPU usage",
+            Action::SortMem => "Sort by memory usage",
+            Action::SortName => "Sort by name",
+            Action::SortFds => "Sort by open file descriptors (counted every 5 seconds)",
+            Action::SortSwap => "Sort by swapped-out memory",
+            Action::SortIvcsw => "Sort by involuntary context switches",
+            Action::SortMajflt => "Sort by major page faults",
+            Action::ReverseSort => "Reverse the sort order",
+            Action::SortMenu => "Pick the sort column from all of them",
+            Action::CycleNameDisplay => "Show names as the basename, executable path or command line",
+            Action::ToggleTree => "Toggle tree view",
+            Action::ToggleTreeTotals => "Order the tree by each subtree's total CPU or memory, shown on parent rows",
+            Action::ToggleKernelThreads => "Toggle kernel threads",
+            Action::ToggleIdle => "Hide or show processes below the idle thresholds",
+            Action::ToggleSelf => "Hide or show rust-htop itself",
+            Action::Pause => "Pause or resume refreshing",
+            Action::ResetNetwork => "Reset the network peaks and session totals",
+            Action::ToggleMemoryDetail => "Show or hide the memory breakdown",
+            Action::SaveSettings => "Save settings to the config file",
+            Action::Export => "Export the table as shown to a file",
+            Action::History => "Show the selected process's history",
+            Action::Connections => "Show the selected process's network connections",
+            Action::Listeners => "List the sockets listening on this machine",
+            Action::Logins => "List who is logged in to this machine",
+            Action::Details => "Show the selected process's details",
+            Action::SetAffinity => "Choose the CPUs the selected process may run on",
+            Action::SetIoPriority => "Change the selected process's I/O priority",
+            Action::ToggleAlerts => "Turn alerts off or on",
+            Action::Alerts => "Show the alert rules and which are firing",
+            Action::CommandLog => "Show the commands run by alert rules",
+            Action::Watch => "Always highlight processes matching a pattern",
+            Action::Watches => "List and remove watches",
+            Action::ActionLog => "Show what came of recent affinity and I/O priority changes",
+            Action::Messages => "Show the recent status messages",
+            Action::ExitLog => "Show the processes that exited recently",
+            Action::Realtime => "List real-time processes and those at a negative nice",
+            Action::ToggleFooter => "Add up the CPU and memory of the listed rows below the table",
+            Action::ResetView => "Clear the filters, sort as configured, leave tree view and go to the top",
+            Action::Up => "Select previous row",
+            Action::Down => "Select next row",
+            Action::Top => "Select the first row",
+            Action::Bottom => "Select the last row",
+            Action::TogglePerf => "Show or hide rust-htop's own timings",
+        }
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q", "ctrl+c"],
+            Action::Suspend => &["ctrl+z"],
+            Action::Help => &["?", "F1"],
+            Action::Search => &["/"],
+            Action::InvertSearch => &["ctrl+x"],
+            Action::FilterUser => &["u"],
+            Action::FilterState => &["S"],
+            Action::SortCpu => &["c"],
+            Action::SortMem => &["m"],
+            Action::SortName => &["n"],
+            Action::SortFds => &["f"],
+            Action::SortSwap => &["s"],
+            Action::SortIvcsw => &["i"],
+            Action::SortMajflt => &["F"],
+            Action::ReverseSort => &["r"],
+            Action::SortMenu => &["F6"],
+            Action::CycleNameDisplay => &["N"],
+            Action::ToggleTree => &["t"],
+            Action::ToggleTreeTotals => &["T"],
+            Action::ToggleKernelThreads => &["K"],
+            Action::ToggleIdle => &["h"],
+            Action::ToggleSelf => &["O"],
+            Action::Pause => &["p"],
+            Action::ResetNetwork => &["B"],
+            Action::ToggleMemoryDetail => &["M"],
+            Action::SaveSettings => &["W"],
+            Action::Export => &["E"],
+            Action::History => &["H"],
+            Action::Connections => &["C"],
+            Action::Listeners => &["l"],
+            Action::Logins => &["U"],
+            Action::Details => &["enter"],
+            Action::SetAffinity => &["P"],
+            Action::SetIoPriority => &["I"],
+            Action::ToggleAlerts => &["A"],
+            Action::Alerts => &["a"],
+            Action::CommandLog => &["X"],
+            Action::Watch => &["w"],
+            Action::Watches => &["L"],
+            Action::ActionLog => &["R"],
+            Action::Messages => &["ctrl+l"],
+            Action::ExitLog => &["e"],
+            Action::Realtime => &["ctrl+r"],
+            Action::ToggleFooter => &["="],
+            Action::ResetView => &["`", "F12"],
+            Action::Up => &["up"],
+            Action::Down => &["down"],
+            Action::Top => &["home"],
+            Action::Bottom => &["end"],
+            Action::TogglePerf => &["ctrl+p"],
+        }
+    }
+
+    // Actions the system can't carry out are left out of the keymap and the help
+    pub fn available(self) -> bool {
+        match self {
+            Action::SetAffinity | Action::SetIoPriority | Action::ActionLog | Action::Realtime => cfg!(target_os = "linux"),
+            _ => true,
+        }
+    }
+
+    // A debugging aid is bound like any other action but left out of the help
+    pub fn listed(self) -> bool {
+        self != Action::TogglePerf
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+// A value in the [keys] table: one key or a list of keys
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum KeySpec {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeySpec {
+    fn keys(&self) -> Vec<&str> {
+        match self {
+            KeySpec::One(key) => vec![key.as_str()],
+            KeySpec::Many(keys) => keys.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    // Shift is folded into the character itself ("shift+m" and "M" are the same key), which
+    // also matches how terminals report shifted letters
+    fn normalized(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Key {
+                code: KeyCode::Char(c.to_ascii_uppercase()),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Key { code, modifiers },
+        }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        Key::normalized(event.code, event.modifiers)
+    }
+}
+
+// Parses descriptions like "q", "ctrl+k", "F9", "shift+m", "alt+enter", "pagedown"
+pub fn parse_key(text: &str) -> Result<Key, String> {
+    let mut parts: Vec<&str> = text.split('+').collect();
+    // "+" and "ctrl++" name the plus key itself
+    if text.ends_with("++") || text == "+" {
+        parts.retain(|p| !p.is_empty());
+        parts.push("+");
+    }
+    let (name, mods) = parts.split_last().ok_or_else(|| format!("empty key {:?}", text))?;
+    let mut modifiers = KeyModifiers::NONE;
+    for m in mods {
+        modifiers |= match m.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return Err(format!("unknown modifier {:?} in {:?}", m, text)),
+        };
+    }
+    let mut chars = name.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match name.to_ascii_lowercase().as_str() {
+            "space" => KeyCode::Char(' '),
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            lower => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=24) => KeyCode::F(n),
+                _ => return Err(format!("unknown key {:?}", text)),
+            },
+        },
+    };
+    Ok(Key::normalized(code, modifiers))
+}
+
+pub fn format_key(key: &Key) -> String {
+    let mut out = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        out.push_str("ctrl+");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        out.push_str("alt+");
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) {
+        out.push_str("shift+");
+    }
+    match key.code {
+        KeyCode::Char(' ') => out.push_str("space"),
+        KeyCode::Char(c) => out.push(c),
+        KeyCode::F(n) => out.push_str(&format!("F{}", n)),
+        KeyCode::Up => out.push('↑'),
+        KeyCode::Down => out.push('↓'),
+        KeyCode::Left => out.push('←'),
+        KeyCode::Right => out.push('→'),
+        KeyCode::PageUp => out.push_str("PgUp"),
+        KeyCode::PageDown => out.push_str("PgDn"),
+        other => out.push_str(&format!("{:?}", other)),
+    }
+    out
+}
+
+pub struct Keymap {
+    actions: HashMap<Key, Action>,
+    // Effective keys per action in Action::ALL order, for the help overlay
+    keys: Vec<(Action, Vec<Key>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::from_config(&BTreeMap::new()).0
+    }
+}
+
+impl Keymap {
+    // An action listed in the config replaces all of its default keys. Problems are returned
+    // as warnings and the offending entry is skipped. When a key ends up on two actions the
+    // configured action wins over a default, and otherwise the first one listed wins.
+    pub fn from_config(config: &BTreeMap<String, KeySpec>) -> (Keymap, Vec<String>) {
+        let mut warnings = Vec::new();
+        let mut configured: HashMap<Action, Vec<Key>> = HashMap::new();
+        for (name, spec) in config {
+            let Some(action) = Action::from_name(name) else {
+                warnings.push(format!("unknown action `{}` in [keys]", name));
+                continue;
+            };
+            // A config shared with other systems may bind them anyway
+            if !action.available() {
+                continue;
+            }
+            let mut keys = Vec::new();
+            for text in spec.keys() {
+                match parse_key(text) {
+                    Ok(key) => keys.push(key),
+                    Err(e) => warnings.push(format!("[keys] {}: {}", name, e)),
+                }
+            }
+            configured.insert(action, keys);
+        }
+
+        let mut actions: HashMap<Key, Action> = HashMap::new();
+        let mut order: Vec<(Action, Key)> = Vec::new();
+        // Configured actions claim their keys first so defaults can't take them away
+        for pass_configured in [true, false] {
+            for action in Action::ALL.into_iter().filter(|a| a.available()) {
+                let keys = match configured.get(&action) {
+                    Some(keys) if pass_configured => keys.clone(),
+                    None if !pass_configured => action.default_keys().iter().map(|k| parse_key(k).expect("valid default key")).collect(),
+                    _ => continue,
+                };
+                for key in keys {
+                    match actions.get(&key) {
+                        Some(&other) if other != action => {
+                            warnings.push(format!("`{}` is bound to both {} and {}; using {}", format_key(&key), other.name(), action.name(), other.name()));
+                        },
+                        Some(_) => {},
+                        None => {
+                            actions.insert(key, action);
+                            order.push((action, key));
+                        },
+                    }
+                }
+            }
+        }
+
+        let keys = Action::ALL.into_iter()
+            .filter(|a| a.available())
+            .map(|action| (action, order.iter().filter(|(a, _)| *a == action).map(|(_, k)| *k).collect()))
+            .collect();
+        (Keymap { actions, keys }, warnings)
+    }
+
+    pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+        self.actions.get(&Key::from_event(event)).copied()
+    }
+
+    pub fn bindings(&self) -> &[(Action, Vec<Key>)] {
+        &self.keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        Key { code, modifiers }
+    }
+
+    fn keys_config(entries: &[(&str, &[&str])]) -> BTreeMap<String, KeySpec> {
+        entries.iter().map(|(name, keys)| (name.to_string(), KeySpec::Many(keys.iter().map(|k| k.to_string()).collect()))).collect()
+    }
+
+    #[test]
+    fn parses_key_descriptions() {
+        let cases = [
+            ("q", key(KeyCode::Char('q'), KeyModifiers::NONE)),
+            ("ctrl+k", key(KeyCode::Char('k'), KeyModifiers::CONTROL)),
+            ("Ctrl+K", key(KeyCode::Char('K'), KeyModifiers::CONTROL)),
+            ("F9", key(KeyCode::F(9), KeyModifiers::NONE)),
+            ("f12", key(KeyCode::F(12), KeyModifiers::NONE)),
+            ("shift+m", key(KeyCode::Char('M'), KeyModifiers::NONE)),
+            ("M", key(KeyCode::Char('M'), KeyModifiers::NONE)),
+            ("alt+enter", key(KeyCode::Enter, KeyModifiers::ALT)),
+            ("space", key(KeyCode::Char(' '), KeyModifiers::NONE)),
+            ("pagedown", key(KeyCode::PageDown, KeyModifiers::NONE)),
+            ("+", key(KeyCode::Char('+'), KeyModifiers::NONE)),
+            ("ctrl++", key(KeyCode::Char('+'), KeyModifiers::CONTROL)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_key(text), Ok(expected), "{:?}", text);
+        }
+        for bad in ["", "hyper+k", "F0", "F99", "enterr", "ctrl+"] {
+            assert!(parse_key(bad).is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn shifted_events_match_uppercase_bindings() {
+        let (keymap, _) = Keymap::from_config(&BTreeMap::new());
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('K'), KeyModifiers::SHIFT)), Some(Action::ToggleKernelThreads));
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('K'), KeyModifiers::NONE)), Some(Action::ToggleKernelThreads));
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)), Some(Action::Quit));
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn configured_keys_replace_defaults() {
+        let (keymap, warnings) = Keymap::from_config(&keys_config(&[("quit", &["F10"]), ("toggle_tree", &["F5", "ctrl+t"])]));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::F(10), KeyModifiers::NONE)), Some(Action::Quit));
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE)), None);
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL)), Some(Action::ToggleTree));
+        let tree = keymap.bindings().iter().find(|(a, _)| *a == Action::ToggleTree).unwrap();
+        assert_eq!(tree.1.iter().map(format_key).collect::<Vec<_>>(), vec!["F5", "ctrl+t"]);
+    }
+
+    #[test]
+    fn configured_key_takes_over_a_default_with_a_warning() {
+        let (keymap, warnings) = Keymap::from_config(&keys_config(&[("search", &["n"])]));
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE)), Some(Action::Search));
+        assert_eq!(warnings, vec!["`n` is bound to both search and sort_name; using search".to_string()]);
+        let sort_name = keymap.bindings().iter().find(|(a, _)| *a == Action::SortName).unwrap();
+        assert!(sort_name.1.is_empty());
+    }
+
+    #[test]
+    fn conflicts_between_configured_actions_are_reported() {
+        let (keymap, warnings) = Keymap::from_config(&keys_config(&[("quit", &["x"]), ("help", &["x"])]));
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE)), Some(Action::Quit));
+        assert_eq!(warnings, vec!["`x` is bound to both quit and help; using quit".to_string()]);
+    }
+
+    #[test]
+    fn bad_entries_are_warnings() {
+        let mut config = keys_config(&[("kill", &["F9"]), ("quit", &["ctrl+q", "hyper+q"])]);
+        config.insert("help".into(), KeySpec::One("F2".into()));
+        let (keymap, warnings) = Keymap::from_config(&config);
+        assert_eq!(warnings, vec![
+            "unknown action `kill` in [keys]".to_string(),
+            "[keys] quit: unknown modifier \"hyper\" in \"hyper+q\"".to_string(),
+        ]);
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)), Some(Action::Quit));
+        assert_eq!(keymap.action(&KeyEvent::new(KeyCode::F(2), KeyModifiers::NONE)), Some(Action::Help));
+    }
+}
@@ -0,0 +1,46 @@
+pub mod actions;
+pub mod alerts;
+#[cfg(feature = "http-api")]
+pub mod api;
+pub mod app;
+pub mod batch;
+pub mod cli;
+pub mod columns;
+pub mod config;
+pub mod conns;
+pub mod containers;
+pub mod csvlog;
+pub mod debuglog;
+pub mod diff;
+pub mod editor;
+pub mod exec;
+pub mod exits;
+pub mod export;
+pub mod fds;
+pub mod handle;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod keys;
+pub mod logins;
+#[cfg(target_os = "macos")]
+pub mod macos;
+pub mod netio;
+pub mod pacing;
+pub mod perf;
+pub mod procfs;
+pub mod query;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod record;
+pub mod remote;
+pub mod replay;
+pub mod sched;
+#[cfg(any(feature = "prometheus", feature = "http-api"))]
+pub mod server;
+pub mod snapshot;
+pub mod system;
+pub mod terminal;
+pub mod theme;
+pub mod trails;
+pub mod ui;
+pub mod width;
@@ -0,0 +1,113 @@
+// Who is logged in, from the utmpx database login, sshd and terminal emulators add a record to
+// for each session. It is read through the C library's getutxent, which knows where the
+// system keeps it and how its records are laid out.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Login {
+    pub user: String,
+    // "pts/0", "tty1", or ":0" for a graphical session
+    pub terminal: String,
+    // Where the session came from; None for a local one
+    pub host: Option<String>,
+    // Seconds since the epoch
+    pub since: u64,
+    // The session's login shell or sshd
+    pub pid: u32,
+}
+
+// Whether this platform's C library has getutxent
+pub const SUPPORTED: bool = cfg!(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "illumos", target_os = "solaris"));
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "illumos", target_os = "solaris"))]
+pub use utmpx::read;
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "illumos", target_os = "solaris")))]
+pub fn read() -> std::io::Result<Vec<Login>> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "there is no utmpx database here"))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "illumos", target_os = "solaris"))]
+mod utmpx {
+    use std::{ffi::c_char, io, sync::Mutex};
+
+    use super::Login;
+
+    // getutxent walks the database with state of its own, so only one walk at a time
+    static WALK: Mutex<()> = Mutex::new(());
+
+    // The sessions in progress, oldest first
+    pub fn read() -> io::Result<Vec<Login>> {
+        let _walk = WALK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut logins = Vec::new();
+        // SAFETY: the walk is serialized by WALK, and each record is copied out before the
+        // next getutxent call reuses its buffer
+        unsafe {
+            libc::setutxent();
+            loop {
+                let entry = libc::getutxent();
+                if entry.is_null() {
+                    break;
+                }
+                let entry = &*entry;
+                logins.extend(login(entry.ut_type, &entry.ut_user, &entry.ut_line, &entry.ut_host, entry.ut_tv.tv_sec as u64, entry.ut_pid as u32));
+            }
+            libc::endutxent();
+        }
+        logins.sort_by_key(|l| l.since);
+        Ok(logins)
+    }
+
+    // A record, if it is for a session in progress rather than a boot, a run level change or
+    // an ended session. Some systems keep the user of an ended session's record and only
+    // change its type; others clear the user too.
+    fn login(kind: libc::c_short, user: &[c_char], line: &[c_char], host: &[c_char], since: u64, pid: u32) -> Option<Login> {
+        let user = text(user);
+        if kind != libc::USER_PROCESS || user.is_empty() {
+            return None;
+        }
+        let host = text(host);
+        Some(Login { user, terminal: text(line), host: (!host.is_empty()).then_some(host), since, pid })
+    }
+
+    // A field padded with NULs, or filling its whole width without one
+    fn text(field: &[c_char]) -> String {
+        let bytes: Vec<u8> = field.iter().map(|&c| c as u8).take_while(|&b| b != 0).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn field<const N: usize>(value: &str) -> [c_char; N] {
+            let mut field = [0; N];
+            for (c, b) in field.iter_mut().zip(value.bytes()) {
+                *c = b as c_char;
+            }
+            field
+        }
+
+        #[test]
+        fn only_sessions_in_progress_are_logins() {
+            let login = |kind, user: &str, host: &str| login(kind, &field::<32>(user), &field::<32>("pts/3"), &field::<256>(host), 1_700_000_900, 4242);
+            assert_eq!(login(libc::USER_PROCESS, "bob", "10.0.0.7"), Some(Login { user: "bob".into(), terminal: "pts/3".into(), host: Some("10.0.0.7".into()), since: 1_700_000_900, pid: 4242 }));
+            assert_eq!(login(libc::USER_PROCESS, "alice", "").and_then(|l| l.host), None);
+            assert_eq!(login(libc::BOOT_TIME, "reboot", "6.8.0"), None);
+            assert_eq!(login(libc::DEAD_PROCESS, "bob", ""), None);
+            assert_eq!(login(libc::USER_PROCESS, "", ""), None);
+        }
+
+        #[test]
+        fn a_user_name_may_fill_its_whole_field() {
+            let name = "a".repeat(32);
+            assert_eq!(text(&field::<32>(&name)), name);
+            assert_eq!(text(&field::<32>("bob")), "bob");
+        }
+
+        #[test]
+        fn the_database_can_be_walked() {
+            // A container often has no sessions at all, so only that it reads
+            assert!(read().is_ok());
+        }
+    }
+}
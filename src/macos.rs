@@ -0,0 +1,39 @@
+// The macOS figures sysinfo either lacks or reports differently from Activity Monitor, which
+// is what people there compare against.
+
+use std::{ffi::CStr, mem, ptr};
+
+use crate::system::{MemPressure, PressureLevel};
+
+// The kernel's own verdict, from the memorystatus sysctls: how hard it is pushed for memory
+// and the share of memory it considers free. None where they can't be read, as in a sandbox.
+pub fn memory_pressure() -> Option<MemPressure> {
+    let level = match sysctl_int(c"kern.memorystatus_vm_pressure_level")? {
+        1 => PressureLevel::Normal,
+        2 => PressureLevel::Warning,
+        4 => PressureLevel::Critical,
+        _ => return None,
+    };
+    let free = sysctl_int(c"kern.memorystatus_level")?.clamp(0, 100) as u8;
+    Some(MemPressure { level, free })
+}
+
+// The physical footprint: the memory charged to the process, compressed pages included, the
+// figure Activity Monitor calls Memory. The resident size sysinfo reports counts every shared
+// library and framework in full for each process that maps them. None for other users'
+// processes without root.
+pub fn footprint(pid: u32) -> Option<u64> {
+    // SAFETY: rusage_info_v2 is plain integers, for which all zeros is a valid value
+    let mut info: libc::rusage_info_v2 = unsafe { mem::zeroed() };
+    // SAFETY: the flavor asks for exactly an rusage_info_v2, which is what `info` is
+    let result = unsafe { libc::proc_pid_rusage(pid as libc::c_int, libc::RUSAGE_INFO_V2, (&mut info as *mut libc::rusage_info_v2).cast()) };
+    (result == 0).then_some(info.ri_phys_footprint)
+}
+
+fn sysctl_int(name: &CStr) -> Option<i32> {
+    let mut value: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>();
+    // SAFETY: `value` is `len` bytes long and nothing is passed to be written
+    let result = unsafe { libc::sysctlbyname(name.as_ptr(), (&mut value as *mut libc::c_int).cast(), &mut len, ptr::null_mut(), 0) };
+    (result == 0 && len == mem::size_of::<libc::c_int>()).then_some(value)
+}
@@ -1,189 +1,589 @@
-use std::{error::Error, io, time::{Duration, Instant}};
-use crossterm::{event::{self, Event as CEvent, KeyCode}, execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
-use tui::{backend::CrosstermBackend, Terminal, widgets::{Block, Borders, Row, Table, TableState, Paragraph}, layout::{Constraint, Layout, Direction}, style::{Style, Modifier}};
-use sysinfo::{ProcessExt, System, SystemExt, NetworksExt};
-use serde::Deserialize;
-use std::fs;
-use sysinfo::NetworkExt;
-
-#[derive(Deserialize)]
-struct Config {
-    refresh_rate: u64,
-    default_sort: String,
-}
-
-enum SortBy {
-    Cpu,
-    Mem,
-    Name,
-}
-
-struct App {
-    sys: System,
-    last_updated: Instant,
-    refresh_rate: Duration,
-    search_query: String,
-    searching: bool,
-    sort_by: SortBy,
-    descending: bool,
-    table_state: TableState,
-}
-
-// Owned copy of process info to avoid borrow conflicts
-struct ProcInfo {
-    pid: sysinfo::Pid,
-    name: String,
-    cpu: f32,
-    mem: u64,
-}
-
-impl App {
-    fn new(config: Config) -> Self {
-        let sort_by = match config.default_sort.as_str() {
-            "mem" => SortBy::Mem,
-            "name" => SortBy::Name,
-            _ => SortBy::Cpu,
+use std::{error::Error, io::{self, IsTerminal, Write}, path::Path, process, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc}, thread, time::{Duration, Instant}};
+use clap::Parser;
+use crossterm::event::{self, Event as CEvent, KeyEvent};
+use tui::{backend::CrosstermBackend, Terminal};
+use htop::{alerts::{self, Sink}, app::{App, StatusKind}, columns::Column, keys::Action, netio, perf::Stage, batch::{self, BatchOptions, Format}, cli::{self, Cli, Command}, config::{self, Config}, csvlog::CsvLog, debuglog, diff, export, record::Recorder, remote::{Agent, RemoteSource}, replay::ReplaySource, snapshot::{SharedSnapshot, SystemSnapshot}, system::{SysinfoSource, SystemSource}, terminal::{self, TerminalGuard}, ui};
+
+// How often the tick producer wakes the main loop to check whether a refresh is due
+const TICK_RATE: Duration = Duration::from_millis(250);
+
+enum Event {
+    Input(KeyEvent),
+    Paste(String),
+    Resize,
+    Tick,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // clap prints usage errors and exits on its own, before the terminal is touched
+    let args = Cli::parse();
+
+    if let Some(Command::InitConfig { path, force }) = &args.command {
+        match path.as_deref() {
+            Some(p) if p.as_os_str() == "-" => print!("{}", config::template()),
+            _ => {
+                let path = path.clone().unwrap_or_else(config::default_path);
+                if let Err(e) = config::write_template(&path, *force) {
+                    eprintln!("rust-htop: {}", e);
+                    process::exit(1);
+                }
+                println!("wrote {}", path.display());
+            },
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Diff { before, after, threshold, format }) = &args.command {
+        let (before, after) = match (diff::load(before), diff::load(after)) {
+            (Ok(before), Ok(after)) => (before, after),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("rust-htop: {}", e);
+                process::exit(1);
+            },
         };
-        Self {
-            sys: System::new_all(),
-            last_updated: Instant::now(),
-            refresh_rate: Duration::from_millis(config.refresh_rate),
-            search_query: String::new(),
-            searching: false,
-            sort_by,
-            descending: true,
-            table_state: TableState::default(),
+        diff::write(&diff::diff(&before, &after, *threshold), *format, &mut io::stdout().lock())?;
+        return Ok(());
+    }
+
+    if let Some(path) = &args.debug_log {
+        let level = args.debug_level.parse().unwrap_or(log::LevelFilter::Debug);
+        if let Err(e) = debuglog::init(path, level) {
+            eprintln!("rust-htop: could not open the debug log {}: {}", path.display(), e);
+            process::exit(1);
         }
+        log::info!("rust-htop {} started with {:?}", env!("CARGO_PKG_VERSION"), std::env::args().collect::<Vec<_>>());
     }
-}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    let config: Config = toml::from_str(&fs::read_to_string("config.toml").unwrap_or_default()).unwrap_or(Config {
-        refresh_rate: 1000,
-        default_sort: "cpu".into(),
+    // Config problems are reported before the alternate screen would hide them
+    let loaded = match config::load_config(args.config.clone(), args.profile.as_deref()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            log::error!("{}", e);
+            eprintln!("rust-htop: {}", e);
+            process::exit(1);
+        },
+    };
+    match &loaded.path {
+        Some(path) => log::info!("config loaded from {} (profile {:?})", path.display(), args.profile),
+        None => log::info!("no config file found; using the defaults"),
+    }
+    for warning in &loaded.warnings {
+        log::warn!("{}", warning);
+    }
+
+    if args.print_config_path {
+        match &loaded.path {
+            Some(path) => println!("{}", path.display()),
+            None => {
+                println!("no config file found, searched:");
+                for path in config::search_paths() {
+                    println!("  {}", path.display());
+                }
+            },
+        }
+        return Ok(());
+    }
+
+    // Lets `kill <pid>` go through the normal cleanup instead of leaving the terminal raw
+    let terminate = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&terminate))?;
+
+    // With stdout going to a file, a pipe or a screen reader's capture there is nothing to draw
+    // the interface on, so the same figures are written out as sentences instead
+    let batch = args.batch || args.once || args.stream;
+    let accessible = args.accessible_output(io::stdout().is_terminal());
+    if batch || accessible {
+        // The log is off unless asked for, and scripts want to hear about a bad setting too
+        for warning in &loaded.warnings {
+            eprintln!("rust-htop: {}", warning);
+        }
+        let config = cli::merge(loaded.config, &args);
+        let format = if args.stream { Format::Json } else if accessible { Format::Lines } else { args.format };
+        let options = BatchOptions {
+            iterations: if args.once { Some(1) } else { args.iterations },
+            delay: args.delay.unwrap_or(Duration::from_millis(config.refresh_rate)),
+            format,
+            limit: args.limit,
+        };
+        let mut app = App::new(config, Box::new(SysinfoSource::new(false)));
+        return Ok(batch::run(&mut app, &options, &mut io::stdout().lock(), &terminate)?);
+    }
+
+    let mut csv_log = args.log_csv.clone().map(|path| CsvLog::new(path, args.log_interval));
+    let mut recorder = args.record.clone().map(|path| match Recorder::create(path.clone(), args.record_max_mb.saturating_mul(1 << 20)) {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            eprintln!("rust-htop: could not record to {}: {}", path.display(), e);
+            process::exit(1);
+        },
     });
 
-    let mut app = App::new(config);
+    // An agent is headless by definition; its output is the stream to the viewers
+    if args.headless || args.serve.is_some() {
+        // Without raw mode Ctrl+C arrives as SIGINT; stop the same way as for SIGTERM
+        #[cfg(unix)]
+        signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&terminate))?;
+        for warning in &loaded.warnings {
+            eprintln!("rust-htop: {}", warning);
+        }
+        let config = cli::merge(loaded.config, &args);
+        let source = open_source(&args, &config);
+        let mut app = App::new(config, source);
+        let exporters = start_exporters(&args, &app);
+        let (mut history, warning) = open_history(&args);
+        if let Some(warning) = warning {
+            eprintln!("rust-htop: {}", warning);
+        }
+        let mut desktop = true;
+        while !terminate.load(Ordering::Relaxed) {
+            if app.refresh_due() {
+                app.refresh();
+                exporters.publish(&app);
+                if let Some(log) = &mut csv_log && log.due() && let Err(e) = log.write(&app) {
+                    eprintln!("rust-htop: could not write {}: {}", log.path.display(), e);
+                }
+                if let Some(warning) = record(&mut recorder, &app) {
+                    eprintln!("rust-htop: {}", warning);
+                }
+                if let Some(warning) = write_history(&mut history, &app) {
+                    eprintln!("rust-htop: {}", warning);
+                }
+                for run in app.poll_commands() {
+                    eprintln!("rust-htop: command for {:?} ({}): {}", run.rule, run.command, htop::exec::describe(&run.state));
+                }
+                // The log gets every alert; a desktop, if there is one, gets the notify ones too
+                for alert in app.fired_alerts.drain(..) {
+                    eprintln!("rust-htop: alert: {}: {}", alert.title, alert.message);
+                    if alert.sink == Sink::Notify && desktop && alerts::notify(&alert).is_err() {
+                        desktop = false;
+                    }
+                }
+            }
+            thread::sleep(TICK_RATE);
+        }
+        return Ok(());
+    }
+
+    let mut watcher = loaded.path.clone().map(config::ConfigWatcher::new);
+    let config = cli::merge(loaded.config, &args);
+    let source = open_source(&args, &config);
+    let mut app = App::new(config, source);
+    // Started first so a port that is already taken is reported on the normal screen
+    let exporters = start_exporters(&args, &app);
+    let (mut history, history_warning) = open_history(&args);
+
+    terminal::install_panic_hook();
+    let _guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    app.config_path = loaded.path;
+    app.profile = args.profile.clone();
+    app.file_config = loaded.file;
+    let mut warnings = loaded.warnings;
+    warnings.extend(history_warning);
+    if let Some(replay) = &app.replay {
+        if replay.truncated {
+            warnings.push("the recording ends in a cut-off snapshot, which was skipped".to_string());
+        }
+        // Starting paused means the replay's own pause, which Space and the pause key undo
+        if app.paused {
+            app.paused = false;
+            app.perform(Action::Pause);
+        }
+    }
+    // Only the user's own processes' descriptors can be read, so the rest show "-"
+    if app.columns.contains(&Column::Net) && app.remote.is_none() && app.replay.is_none() && !netio::privileged(Path::new("/proc")) {
+        warnings.push("the net column can only see the sockets of your own processes; run as root for the rest".to_string());
+    }
+    if !warnings.is_empty() {
+        app.set_status(StatusKind::Warning, warnings.join("; "));
+    }
+    let events = spawn_event_threads();
+    let mut desktop = true;
+    // The title on the terminal, None before it is first set or after it was handed back
+    let host = export::host(&app);
+    let mut title = None;
 
     loop {
-        if event::poll(Duration::from_millis(100))? {
-            if let CEvent::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => break,
-                    KeyCode::Char('/') => {
-                        app.searching = true;
-                        app.search_query.clear();
-                    },
-                    KeyCode::Esc => {
-                        app.searching = false;
-                        app.search_query.clear();
-                    },
-                    KeyCode::Char(c) if app.searching => app.search_query.push(c),
-                    KeyCode::Backspace if app.searching => { app.search_query.pop(); },
-                    KeyCode::Char('c') => app.sort_by = SortBy::Cpu,
-                    KeyCode::Char('m') => app.sort_by = SortBy::Mem,
-                    KeyCode::Char('n') => app.sort_by = SortBy::Name,
-                    KeyCode::Char('r') => app.descending = !app.descending,
-                    KeyCode::Up => move_selection(&mut app, -1),
-                    KeyCode::Down => move_selection(&mut app, 1),
-                    _ => {},
-                }
+        // Everything that came in since the last frame is handled before the next one, so a
+        // held arrow key moves as fast as the terminal repeats it instead of a row per frame
+        let first = events.recv()?;
+        for (event, times) in coalesce(std::iter::once(first).chain(events.try_iter())) {
+            if app.quit {
+                break;
+            }
+            match event {
+                Event::Input(key) => app.handle_repeated_key(key, times),
+                Event::Paste(text) => app.paste(&text),
+                Event::Resize => app.dirty = true,
+                Event::Tick => {
+                    app.expire_flash(Instant::now());
+                    app.expire_toasts(Instant::now());
+                    app.expire_status(Instant::now());
+                    app.poll_commands();
+                    if let Some(watcher) = &mut watcher && watcher.changed() {
+                        reload_config(&mut app, &watcher.path, &args);
+                    }
+                    if !app.paused && app.refresh_due() {
+                        app.refresh();
+                        exporters.publish(&app);
+                        // Only fresh samples are logged, so a paused view doesn't repeat rows
+                        if let Some(log) = &mut csv_log && log.due() && let Err(e) = log.write(&app) {
+                            app.set_status(StatusKind::Warning, format!("Could not write {}: {}", log.path.display(), e));
+                        }
+                        if let Some(warning) = record(&mut recorder, &app) {
+                            app.set_status(StatusKind::Warning, warning);
+                        }
+                        if let Some(warning) = write_history(&mut history, &app) {
+                            app.set_status(StatusKind::Warning, warning);
+                        }
+                        deliver_alerts(&mut app, &mut desktop);
+                    }
+                },
             }
         }
 
-        if app.last_updated.elapsed() >= app.refresh_rate {
-            app.sys.refresh_all();
-            app.last_updated = Instant::now();
+        if app.history_wanted {
+            app.history_wanted = false;
+            show_history(&history, &mut app);
         }
 
-        terminal.draw(|f| {
-            let size = f.size();
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Length(3), Constraint::Length(3), Constraint::Min(0)].as_ref())
-                .split(size);
+        if app.quit || terminate.load(Ordering::Relaxed) {
+            break;
+        }
+        if app.suspend {
+            app.suspend = false;
+            #[cfg(unix)]
+            terminal::suspend(&mut terminal)?;
+            title = None;
+            app.dirty = true;
+        }
+        if !app.dirty {
+            continue;
+        }
+        app.dirty = false;
 
-            // Search bar
-            let search = Paragraph::new(if app.searching {
-                format!("Search: {}", app.search_query)
-            } else {
-                "Press '/' to search, 'q' to quit".to_string()
-            });
-            f.render_widget(search, chunks[0]);
-
-            // Network stats
-            let net = app.sys.networks();
-            let net_info = net.iter().map(|(iface, data)| {
-                format!("{} ↓{} KB ↑{} KB", iface, data.total_received() / 1024, data.total_transmitted() / 1024)
-            }).collect::<Vec<_>>().join(" | ");
-            f.render_widget(Paragraph::new(net_info), chunks[1]);
-
-            // Build owned process info vector
-            let mut processes: Vec<ProcInfo> = app.sys.processes().values().map(|p| ProcInfo {
-                pid: p.pid(),
-                name: p.name().to_string(),
-                cpu: p.cpu_usage(),
-                mem: p.memory(),
-            }).collect();
-
-            // Apply search filter
-            if !app.search_query.is_empty() {
-                let query = app.search_query.to_lowercase();
-                processes.retain(|p| p.name.to_lowercase().contains(&query) || p.pid.to_string().contains(&query));
+        let timing = app.perf.start();
+        terminal.draw(|f| ui::draw(f, &mut app))?;
+        app.perf.record(Stage::Draw, timing);
+        let wanted = app.config.set_terminal_title.then(|| app.terminal_title(&host));
+        if wanted != title {
+            match &wanted {
+                Some(text) => terminal::set_title(text)?,
+                None => terminal::restore_title()?,
             }
+            title = wanted;
+        }
+        if app.bell {
+            app.bell = false;
+            let mut stdout = io::stdout();
+            stdout.write_all(b"\x07")?;
+            stdout.flush()?;
+        }
+    }
 
-            // Sort processes using owned data
-            sort_processes(&app, &mut processes);
-
-            // Map to table rows
-            let rows: Vec<Row> = processes.iter().map(|p| {
-                Row::new(vec![
-                    p.pid.to_string(),
-                    p.name.clone(),
-                    format!("{:.2}%", p.cpu),
-                    format!("{:.2} MB", p.mem as f64 / 1024.0),
-                ])
-            }).collect();
-
-            let table = Table::new(rows)
-                .header(Row::new(vec!["PID", "Name", "CPU %", "Memory MB"]).style(Style::default().add_modifier(Modifier::BOLD)))
-                .block(Block::default().borders(Borders::ALL).title("Processes"))
-                .widths(&[
-                    Constraint::Length(10),
-                    Constraint::Length(25),
-                    Constraint::Length(10),
-                    Constraint::Length(15),
-                ])
-                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
-
-            f.render_stateful_widget(table, chunks[2], &mut app.table_state);
-        })?;
-    }
-
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     Ok(())
 }
 
-fn sort_processes(app: &App, processes: &mut Vec<ProcInfo>) {
-    match app.sort_by {
-        SortBy::Cpu => processes.sort_by(|a, b| a.cpu.partial_cmp(&b.cpu).unwrap()),
-        SortBy::Mem => processes.sort_by(|a, b| a.mem.cmp(&b.mem)),
-        SortBy::Name => processes.sort_by(|a, b| a.name.cmp(&b.name)),
+// Everything that is fed each refresh from outside the interface. The HTTP exporters answer
+// from a copy of the latest refresh, so a request never waits on sysinfo or the terminal.
+// Without any exporter nothing is copied. Dropping this stops the servers.
+struct Exporters {
+    snapshot: Option<SharedSnapshot>,
+    agent: Option<Agent>,
+    #[cfg(any(feature = "prometheus", feature = "http-api"))]
+    _servers: Vec<htop::server::Server>,
+}
+
+impl Exporters {
+    fn publish(&self, app: &App) {
+        if self.snapshot.is_none() && self.agent.is_none() {
+            return;
+        }
+        let latest = SystemSnapshot::capture_all(app);
+        // Encoding only fails past the frame size limit; the viewers keep the previous frame
+        if let Some(agent) = &self.agent && let Err(e) = agent.publish(&latest) {
+            log::warn!("could not send the snapshot to viewers: {}", e);
+        }
+        if let Some(shared) = &self.snapshot && let Ok(mut snapshot) = shared.write() {
+            *snapshot = latest;
+        }
     }
-    if app.descending {
-        processes.reverse();
+}
+
+// Stops recording after the first error rather than retrying every refresh; what was
+// written so far stays readable
+fn record(recorder: &mut Option<Recorder>, app: &App) -> Option<String> {
+    let active = recorder.as_mut()?;
+    let error = active.write(&SystemSnapshot::capture_all(app), chrono::Utc::now().timestamp_millis()).err()?;
+    let warning = format!("Recording stopped, could not write {}: {}", active.path.display(), error);
+    *recorder = None;
+    Some(warning)
+}
+
+#[cfg(feature = "history")]
+type HistoryDb = htop::history::History;
+// Without the feature there is never a database; --history-db stops at startup
+#[cfg(not(feature = "history"))]
+type HistoryDb = std::convert::Infallible;
+
+// How far back the history popup looks
+#[cfg(feature = "history")]
+const HISTORY_WINDOW_MS: i64 = 60 * 60 * 1000;
+
+// A database that can't be opened is a warning, not a reason to stop monitoring
+#[cfg(feature = "history")]
+fn open_history(args: &Cli) -> (Option<HistoryDb>, Option<String>) {
+    let Some(path) = &args.history_db else { return (None, None) };
+    let retention = Duration::from_secs(args.history_retention_days.saturating_mul(24 * 60 * 60));
+    let thresholds = htop::history::Thresholds { min_cpu: args.history_min_cpu as f32, min_mem: args.history_min_mem_mb.saturating_mul(1 << 20) };
+    match HistoryDb::open(path, retention, thresholds, chrono::Utc::now().timestamp_millis()) {
+        Ok(db) => (Some(db), None),
+        Err(e) => (None, Some(format!("Not keeping history, could not open {}: {}", path.display(), e))),
     }
 }
 
-fn move_selection(app: &mut App, delta: isize) {
-    let i = app.table_state.selected().unwrap_or(0) as isize + delta;
-    let len = app.sys.processes().len() as isize;
-    app.table_state.select(Some((i.max(0).min(len - 1)) as usize));
+#[cfg(not(feature = "history"))]
+fn open_history(args: &Cli) -> (Option<HistoryDb>, Option<String>) {
+    if args.history_db.is_some() {
+        missing_feature("--history-db", "history");
+    }
+    (None, None)
+}
+
+// Unlike the recorder the database stays in use after an error: a locked or full database
+// usually recovers, and the next refresh tries again
+#[cfg(feature = "history")]
+fn write_history(history: &mut Option<HistoryDb>, app: &App) -> Option<String> {
+    let error = history.as_mut()?.write(app, chrono::Utc::now().timestamp_millis()).err()?;
+    Some(format!("Could not write history: {}", error))
+}
+
+#[cfg(not(feature = "history"))]
+fn write_history(_history: &mut Option<HistoryDb>, _app: &App) -> Option<String> {
+    None
+}
+
+// The last hour of the selected process, for the popup
+fn show_history(history: &Option<HistoryDb>, app: &mut App) {
+    let Some(db) = history else {
+        app.set_status(StatusKind::Info, "Process history needs --history-db");
+        return;
+    };
+    #[cfg(feature = "history")]
+    {
+        let Some(p) = app.selected_process() else { return };
+        let until_ms = chrono::Utc::now().timestamp_millis();
+        let since_ms = until_ms - HISTORY_WINDOW_MS;
+        match db.process(p, since_ms) {
+            Ok(samples) => app.history = Some(htop::app::HistoryView { pid: p.pid, name: p.name.clone(), since_ms, until_ms, samples }),
+            Err(e) => app.set_status(StatusKind::Warning, format!("Could not read history: {}", e)),
+        }
+    }
+    #[cfg(not(feature = "history"))]
+    match *db {}
+}
+
+// Each alert goes where its rule says. Desktop notifications are tried until one fails,
+// which usually means there is no notification daemon; from then on those alerts are
+// banners on the status line too, and the first one says why.
+fn deliver_alerts(app: &mut App, desktop: &mut bool) {
+    for alert in std::mem::take(&mut app.fired_alerts) {
+        let text = format!("{}: {}", alert.title, alert.message);
+        match alert.sink {
+            Sink::Bell => {
+                app.ring(Instant::now());
+                app.set_status(StatusKind::Info, text);
+            },
+            Sink::Notify if *desktop => if let Err(e) = alerts::notify(&alert) {
+                *desktop = false;
+                app.set_status(StatusKind::Alert, format!("{} (no desktop notifications: {})", text, e));
+            },
+            Sink::Notify | Sink::Banner => app.set_status(StatusKind::Alert, text),
+        }
+    }
+}
+
+// Local sysinfo, the agent given with --connect or the recording given with --replay
+fn open_source(args: &Cli, config: &Config) -> Box<dyn SystemSource> {
+    if let Some(addr) = &args.connect {
+        return Box::new(RemoteSource::connect(addr));
+    }
+    if let Some(path) = &args.replay {
+        return match ReplaySource::open(path) {
+            Ok(replay) => Box::new(replay),
+            Err(e) => {
+                eprintln!("rust-htop: could not replay {}: {}", path.display(), e);
+                process::exit(1);
+            },
+        };
+    }
+    Box::new(SysinfoSource::new(config.full_refresh))
+}
+
+// Exits with an error when an address can't be bound, or the flags need a feature this
+// binary was built without
+fn start_exporters(args: &Cli, app: &App) -> Exporters {
+    #[cfg(not(feature = "prometheus"))]
+    if args.prometheus_listen.is_some() {
+        missing_feature("--prometheus-listen", "prometheus");
+    }
+    #[cfg(not(feature = "http-api"))]
+    if args.http_listen.is_some() {
+        missing_feature("--http-listen", "http-api");
+    }
+    let mut exporters = spawn_servers(args, app);
+    if let Some(addr) = &args.serve {
+        match Agent::spawn(addr.as_str()) {
+            Ok(agent) => {
+                if let Err(e) = agent.publish(&SystemSnapshot::capture_all(app)) {
+                    log::warn!("could not send the first snapshot to viewers: {}", e);
+                }
+                exporters.agent = Some(agent);
+            },
+            Err(e) => {
+                eprintln!("rust-htop: could not listen on {}: {}", addr, e);
+                process::exit(1);
+            },
+        }
+    }
+    exporters
+}
+
+#[cfg(any(feature = "prometheus", feature = "http-api"))]
+fn spawn_servers(args: &Cli, app: &App) -> Exporters {
+    use std::sync::RwLock;
+    use htop::server::{Handler, Server};
+    let snapshot: SharedSnapshot = Arc::new(RwLock::new(SystemSnapshot::capture_all(app)));
+    let mut handlers: Vec<(&str, Handler)> = Vec::new();
+    #[cfg(feature = "prometheus")]
+    if let Some(addr) = &args.prometheus_listen {
+        handlers.push((addr, htop::prometheus::handler(Arc::clone(&snapshot), args.prometheus_processes.clone())));
+    }
+    #[cfg(feature = "http-api")]
+    if let Some(addr) = &args.http_listen {
+        handlers.push((addr, htop::api::handler(Arc::clone(&snapshot))));
+    }
+    if handlers.is_empty() {
+        return Exporters { snapshot: None, agent: None, _servers: Vec::new() };
+    }
+    let servers = handlers.into_iter().map(|(addr, handler)| match Server::spawn(addr, handler) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("rust-htop: could not listen on {}: {}", addr, e);
+            process::exit(1);
+        },
+    }).collect();
+    Exporters { snapshot: Some(snapshot), agent: None, _servers: servers }
+}
+
+#[cfg(not(any(feature = "prometheus", feature = "http-api")))]
+fn spawn_servers(_args: &Cli, _app: &App) -> Exporters {
+    Exporters { snapshot: None, agent: None }
+}
+
+#[cfg(not(all(feature = "prometheus", feature = "http-api", feature = "history")))]
+fn missing_feature(flag: &str, feature: &str) -> ! {
+    eprintln!("rust-htop: {} needs a build with `--features {}`", flag, feature);
+    process::exit(1);
+}
+
+// A broken edit keeps the previous settings running and says why
+fn reload_config(app: &mut App, path: &Path, args: &Cli) {
+    match config::load(path, args.profile.as_deref()) {
+        Ok((config, mut warnings)) => {
+            app.file_config = config.clone();
+            let (config, env_warnings) = config::apply_env(config, std::env::vars());
+            warnings.extend(env_warnings);
+            let config = cli::merge(config, args);
+            if config == app.config {
+                return;
+            }
+            app.apply_config(config);
+            if warnings.is_empty() {
+                app.set_status(StatusKind::Info, format!("Reloaded {}", path.display()));
+            } else {
+                app.set_status(StatusKind::Warning, warnings.join("; "));
+            }
+        },
+        Err(e) => app.set_status(StatusKind::Warning, format!("{}; keeping the previous settings", e)),
+    }
+}
+
+// Runs of the same key, and of ticks or resizes, become one event with how many times it came
+fn coalesce(events: impl Iterator<Item = Event>) -> Vec<(Event, usize)> {
+    let mut out: Vec<(Event, usize)> = Vec::new();
+    for event in events {
+        if let Some((last, times)) = out.last_mut() {
+            let same = match (&*last, &event) {
+                (Event::Input(a), Event::Input(b)) => a == b,
+                (Event::Resize, Event::Resize) | (Event::Tick, Event::Tick) => true,
+                _ => false,
+            };
+            if same {
+                *times += 1;
+                continue;
+            }
+        }
+        out.push((event, 1));
+    }
+    out
+}
+
+// Input is read on its own thread so key handling never waits on a slow refresh or redraw
+fn spawn_event_threads() -> mpsc::Receiver<Event> {
+    let (tx, rx) = mpsc::channel();
+
+    let input_tx = tx.clone();
+    thread::spawn(move || {
+        while let Ok(ev) = event::read() {
+            let sent = match ev {
+                CEvent::Key(key) => input_tx.send(Event::Input(key)),
+                CEvent::Paste(text) => input_tx.send(Event::Paste(text)),
+                CEvent::Resize(..) => input_tx.send(Event::Resize),
+                _ => Ok(()),
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        while tx.send(Event::Tick).is_ok() {
+            thread::sleep(TICK_RATE);
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+
+    fn key(code: KeyCode) -> Event {
+        Event::Input(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    // Each event as something that can be compared, with how many times it came
+    fn names(events: Vec<(Event, usize)>) -> Vec<(String, usize)> {
+        events.into_iter().map(|(event, times)| (match event {
+            Event::Input(key) => format!("{:?} {:?}", key.kind, key.code),
+            Event::Paste(text) => format!("paste {}", text),
+            Event::Resize => "resize".into(),
+            Event::Tick => "tick".into(),
+        }, times)).collect()
+    }
+
+    #[test]
+    fn only_runs_of_the_same_event_are_coalesced() {
+        let release = Event::Input(KeyEvent::new_with_kind(KeyCode::Up, KeyModifiers::NONE, KeyEventKind::Release));
+        let events = [key(KeyCode::Up), key(KeyCode::Up), key(KeyCode::Up), release, key(KeyCode::Down), Event::Tick, Event::Tick, Event::Resize, Event::Paste("a".into()), Event::Paste("a".into()), key(KeyCode::Up)];
+        let expected = [("Press Up", 3), ("Release Up", 1), ("Press Down", 1), ("tick", 2), ("resize", 1), ("paste a", 1), ("paste a", 1), ("Press Up", 1)];
+        assert_eq!(names(coalesce(events.into_iter())), expected.map(|(name, times)| (name.to_string(), times)));
+        assert!(coalesce(std::iter::empty()).is_empty());
+    }
 }
@@ -0,0 +1,94 @@
+// The NET column: how many bytes are waiting in the send and receive queues of a process's
+// TCP and UDP sockets, as /proc/net/{tcp,tcp6,udp,udp6} print them. It is a sample taken every
+// fds::INTERVAL, not a count of what went over the wire, but a process that keeps the network
+// busy is one whose queues don't drain. Sockets are tied to processes by the same pass over
+// /proc/<pid>/fd that counts the CONN column, so a socket several processes share (forked
+// workers) counts for each of them, and another user's processes can't be read without root.
+// Built without the netio feature, and outside Linux, the column isn't offered.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::procfs;
+
+// CAP_SYS_PTRACE, which reading another user's /proc/<pid>/fd takes; root has it too
+const CAP_SYS_PTRACE: u32 = 19;
+
+// Bytes waiting in a socket, or in all of a process's
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Queues {
+    pub tx: u64,
+    pub rx: u64,
+}
+
+impl Queues {
+    pub fn total(self) -> u64 {
+        self.tx + self.rx
+    }
+}
+
+// By socket inode, every TCP and UDP socket on the machine. A table that can't be read (no
+// IPv6, or not Linux) contributes nothing.
+pub fn read_all(proc: &Path) -> HashMap<u64, Queues> {
+    ["tcp", "tcp6", "udp", "udp6"].iter().flat_map(|file| {
+        let text = fs::read_to_string(proc.join("net").join(file)).unwrap_or_default();
+        parse_table(&text)
+    }).collect()
+}
+
+// "0001F400:00000800" in the fifth field is 128000 bytes to send and 2048 received but not
+// read yet. A listening TCP socket's queues count connections waiting to be accepted rather
+// than bytes, so it is listed with none. The header line, rows that don't parse and sockets
+// without an inode (TIME_WAIT) are skipped.
+pub fn parse_table(text: &str) -> Vec<(u64, Queues)> {
+    text.lines().skip(1).filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let inode: u64 = fields.get(9)?.parse().ok()?;
+        let (tx, rx) = fields.get(4)?.split_once(':')?;
+        let queues = match *fields.get(3)? {
+            "0A" => Queues::default(),
+            _ => Queues { tx: u64::from_str_radix(tx, 16).ok()?, rx: u64::from_str_radix(rx, 16).ok()? },
+        };
+        (inode != 0).then_some((inode, queues))
+    }).collect()
+}
+
+// Whether every process's sockets can be seen, from this process's effective capabilities.
+// Without it the column only covers processes of the same user and shows "-" for the rest.
+pub fn privileged(proc: &Path) -> bool {
+    let Ok(text) = fs::read_to_string(proc.join("self").join("status")) else { return false };
+    procfs::parse_status(&text).capabilities.is_some_and(|caps| caps.effective & 1 << CAP_SYS_PTRACE != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queues_are_read_by_inode_without_listen_backlogs() {
+        let tcp: HashMap<u64, Queues> = parse_table(include_str!("../tests/data/net-tcp")).into_iter().collect();
+        assert_eq!(tcp.len(), 4);
+        assert_eq!(tcp[&30211], Queues::default());
+        assert_eq!(tcp[&30874], Queues::default());
+        assert_eq!(tcp[&41102], Queues { tx: 128000, rx: 0 });
+        assert_eq!(tcp[&41117].total(), 8192 + 2048);
+        let udp = parse_table(include_str!("../tests/data/net-udp"));
+        assert_eq!(udp, [(18923, Queues { tx: 0, rx: 76800 }), (17210, Queues::default())]);
+        assert!(parse_table("header\n   0: broken\n").is_empty());
+    }
+
+    #[test]
+    fn privilege_comes_from_the_effective_capabilities() {
+        let dir = std::env::temp_dir().join(format!("rust-htop-netio-{}", std::process::id()));
+        fs::create_dir_all(dir.join("self")).unwrap();
+        let status = |effective: &str| fs::write(dir.join("self/status"), format!("Name:\trust-htop\nCapPrm:\t{}\nCapEff:\t{}\n", effective, effective)).unwrap();
+        status("0000000000000000");
+        assert!(!privileged(&dir));
+        status("0000000000080000");
+        assert!(privileged(&dir));
+        // root
+        status("000001ffffffffff");
+        assert!(privileged(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!privileged(&dir));
+    }
+}
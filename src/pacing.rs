@@ -0,0 +1,71 @@
+// How often to refresh when refreshing takes longer than refresh_rate. Sampling runs on the
+// same thread as the keys and the drawing, so on a machine too loaded to read /proc in time,
+// refreshing again as soon as the interval is up would leave the UI frozen between refreshes.
+// Instead the wait grows to twice the time the last refresh took, giving the UI at least as
+// long as the sampling, and goes back to refresh_rate once a refresh fits in it again.
+
+use std::time::Duration;
+
+// The longest the wait grows to, however slow refreshing gets
+pub const MAX_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pacer {
+    // How long the last refresh took; None before the first
+    last: Option<Duration>,
+}
+
+impl Pacer {
+    pub fn record(&mut self, took: Duration) {
+        self.last = Some(took);
+    }
+
+    // How long the last refresh took, when that was longer than `configured`
+    pub fn lagging(&self, configured: Duration) -> Option<Duration> {
+        self.last.filter(|&took| took > configured)
+    }
+
+    // The wait between refreshes: `configured`, unless the last refresh didn't fit in it
+    pub fn interval(&self, configured: Duration) -> Duration {
+        match self.lagging(configured) {
+            Some(took) => (took * 2).min(MAX_INTERVAL).max(configured),
+            None => configured,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn a_slow_refresh_stretches_the_interval_until_one_fits_again() {
+        let mut pacer = Pacer::default();
+        assert_eq!((pacer.lagging(SECOND), pacer.interval(SECOND)), (None, SECOND));
+        pacer.record(Duration::from_millis(300));
+        assert_eq!((pacer.lagging(SECOND), pacer.interval(SECOND)), (None, SECOND));
+
+        pacer.record(Duration::from_millis(3200));
+        assert_eq!(pacer.lagging(SECOND), Some(Duration::from_millis(3200)));
+        assert_eq!(pacer.interval(SECOND), Duration::from_millis(6400));
+        // Exactly the interval still keeps up
+        pacer.record(SECOND);
+        assert_eq!((pacer.lagging(SECOND), pacer.interval(SECOND)), (None, SECOND));
+    }
+
+    #[test]
+    fn the_stretched_interval_is_capped_and_never_below_the_configured_one() {
+        let mut pacer = Pacer::default();
+        pacer.record(Duration::from_secs(40));
+        assert_eq!(pacer.interval(SECOND), MAX_INTERVAL);
+        // A configured interval past the cap is kept
+        pacer.record(Duration::from_secs(70));
+        assert_eq!(pacer.interval(Duration::from_secs(60)), Duration::from_secs(60));
+        // A lag is against the interval in effect, so raising refresh_rate ends it
+        pacer.record(Duration::from_millis(1500));
+        assert_eq!(pacer.interval(Duration::from_secs(2)), Duration::from_secs(2));
+        assert_eq!(pacer.lagging(Duration::from_secs(2)), None);
+    }
+}
@@ -0,0 +1,108 @@
+// What rust-htop itself spends per frame, for the overlay `--show-perf` or ctrl+p shows. Each
+// measurement is a `start` and a `record` around the work; while the overlay is off `start`
+// doesn't read the clock and `record` has nothing to add, so leaving the scopes in costs a
+// branch.
+
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+// How many frames the averages cover
+pub const FRAMES: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    // Sampling the system and rebuilding the view from it
+    Refresh,
+    // Turning the visible processes into table rows
+    Rows,
+    // The whole terminal.draw, rows and flushing included
+    Draw,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 3] = [Stage::Refresh, Stage::Rows, Stage::Draw];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Refresh => "refresh",
+            Stage::Rows => "rows",
+            Stage::Draw => "draw",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Perf {
+    enabled: bool,
+    // The latest FRAMES durations of each stage, oldest first, in Stage::ALL order
+    samples: [VecDeque<Duration>; 3],
+}
+
+impl Perf {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Turning it off forgets the samples, so turning it back on doesn't average in old frames
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.samples.iter_mut().for_each(VecDeque::clear);
+        }
+    }
+
+    #[inline]
+    pub fn start(&self) -> Option<Instant> {
+        self.enabled.then(Instant::now)
+    }
+
+    #[inline]
+    pub fn record(&mut self, stage: Stage, started: Option<Instant>) {
+        if let Some(started) = started {
+            self.add(stage, started.elapsed());
+        }
+    }
+
+    fn add(&mut self, stage: Stage, took: Duration) {
+        let samples = &mut self.samples[stage as usize];
+        samples.push_back(took);
+        if samples.len() > FRAMES {
+            samples.pop_front();
+        }
+    }
+
+    // None until the stage has run with the overlay on
+    pub fn average(&self, stage: Stage) -> Option<Duration> {
+        let samples = &self.samples[stage as usize];
+        (!samples.is_empty()).then(|| samples.iter().sum::<Duration>() / samples.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_cover_the_latest_frames() {
+        let mut perf = Perf::default();
+        perf.set_enabled(true);
+        for ms in 1..=FRAMES as u64 + 10 {
+            perf.add(Stage::Draw, Duration::from_millis(ms));
+        }
+        // 11 to 30
+        assert_eq!(perf.average(Stage::Draw), Some(Duration::from_micros(20_500)));
+        assert_eq!(perf.average(Stage::Refresh), None);
+    }
+
+    #[test]
+    fn nothing_is_measured_while_off() {
+        let mut perf = Perf::default();
+        assert_eq!(perf.start(), None);
+        perf.set_enabled(true);
+        let started = perf.start();
+        perf.record(Stage::Rows, started);
+        assert!(perf.average(Stage::Rows).is_some());
+        perf.set_enabled(false);
+        perf.record(Stage::Rows, perf.start());
+        assert_eq!(perf.average(Stage::Rows), None);
+    }
+}
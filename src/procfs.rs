@@ -0,0 +1,619 @@
+use std::{fs, io, path::Path};
+use sysinfo::{Pid, PidExt};
+
+use crate::sched::{Policy, Scheduling};
+
+// The fields rust-htop uses from /proc/<pid>/status. A field the kernel didn't print (kernel
+// threads have no memory lines) is None.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Status {
+    // VmSwap, in bytes
+    pub swap: Option<u64>,
+    // Context switches since the process started: waiting for something (a lock, I/O), and
+    // being preempted while still wanting the CPU
+    pub voluntary_switches: Option<u64>,
+    pub involuntary_switches: Option<u64>,
+    // None unless both the CapEff and CapPrm lines were there
+    pub capabilities: Option<Capabilities>,
+}
+
+// Capability sets as bit masks, bit n being capability n
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    // What the process can do right now
+    pub effective: u64,
+    // What it may turn on for itself
+    pub permitted: u64,
+}
+
+// The names from include/uapi/linux/capability.h, by bit
+const CAPABILITY_NAMES: [&str; 41] = [
+    "CAP_CHOWN", "CAP_DAC_OVERRIDE", "CAP_DAC_READ_SEARCH", "CAP_FOWNER", "CAP_FSETID", "CAP_KILL",
+    "CAP_SETGID", "CAP_SETUID", "CAP_SETPCAP", "CAP_LINUX_IMMUTABLE", "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST", "CAP_NET_ADMIN", "CAP_NET_RAW", "CAP_IPC_LOCK", "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE", "CAP_SYS_RAWIO", "CAP_SYS_CHROOT", "CAP_SYS_PTRACE", "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN", "CAP_SYS_BOOT", "CAP_SYS_NICE", "CAP_SYS_RESOURCE", "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG", "CAP_MKNOD", "CAP_LEASE", "CAP_AUDIT_WRITE", "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP", "CAP_MAC_OVERRIDE", "CAP_MAC_ADMIN", "CAP_SYSLOG", "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND", "CAP_AUDIT_READ", "CAP_PERFMON", "CAP_BPF", "CAP_CHECKPOINT_RESTORE",
+];
+
+// Every capability whose bit is set, lowest first. Bits newer than this table are shown by
+// number, as CAP_(41).
+pub fn capability_names(mask: u64) -> Vec<String> {
+    (0..64).filter(|bit| mask & (1 << bit) != 0).map(|bit| match CAPABILITY_NAMES.get(bit) {
+        Some(name) => name.to_string(),
+        None => format!("CAP_({})", bit),
+    }).collect()
+}
+
+// Whether `mask` holds every capability this table knows about, as root's usually does
+pub fn all_capabilities(mask: u64) -> bool {
+    let known = (1 << CAPABILITY_NAMES.len()) - 1;
+    mask & known == known
+}
+
+// The fields rust-htop uses from /proc/<pid>/stat: page faults since the process started,
+// the nice value, the CPU it last ran on and its scheduling policy. Minor faults were served
+// from memory; major ones had to wait for the disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stat {
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    pub nice: i32,
+    // None from kernels too old to report it
+    pub processor: Option<usize>,
+    // None, like processor, from kernels too old to report it
+    pub scheduling: Option<Scheduling>,
+}
+
+// Where a process's memory is, from smaps: resident and proportional set size (each shared
+// page divided among the processes mapping it), the resident pages split by whether others
+// map them too and whether they were written to, and what is swapped out. In bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemoryMap {
+    pub rss: u64,
+    pub pss: u64,
+    pub shared_clean: u64,
+    pub shared_dirty: u64,
+    pub private_clean: u64,
+    pub private_dirty: u64,
+    pub swap: u64,
+}
+
+// One row of /proc/<pid>/limits. A limit of None is unlimited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limit {
+    // As the kernel names it: "Max open files"
+    pub name: String,
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+    // "bytes", "files", "seconds", ...; empty for the priorities
+    pub unit: String,
+}
+
+pub fn read_status(proc: &Path, pid: Pid) -> Option<Status> {
+    fs::read_to_string(proc.join(pid.as_u32().to_string()).join("status")).ok().map(|text| parse_status(&text))
+}
+
+pub fn read_stat(proc: &Path, pid: Pid) -> Option<Stat> {
+    parse_stat(&fs::read_to_string(proc.join(pid.as_u32().to_string()).join("stat")).ok()?)
+}
+
+pub fn read_limits(proc: &Path, pid: Pid) -> io::Result<Vec<Limit>> {
+    Ok(parse_limits(&fs::read_to_string(proc.join(pid.as_u32().to_string()).join("limits"))?))
+}
+
+// The file is a fixed-width table whose names contain spaces, so the columns are cut where the
+// header puts them:
+//
+//   Limit                     Soft Limit           Hard Limit           Units
+//   Max open files            1024                 524288               files
+//
+// Rows whose limits aren't numbers or "unlimited" are skipped.
+pub fn parse_limits(text: &str) -> Vec<Limit> {
+    let mut lines = text.lines();
+    let header = lines.next().unwrap_or_default();
+    let offset = |title, default| header.find(title).unwrap_or(default);
+    let (soft, hard, unit) = (offset("Soft Limit", 26), offset("Hard Limit", 47), offset("Units", 68));
+    let cut = |line: &str, start: usize, end: usize| line.get(start.min(line.len())..end.min(line.len())).unwrap_or("").trim().to_string();
+    let limit = |text: String| match text.as_str() {
+        "unlimited" => Some(None),
+        n => n.parse().ok().map(Some),
+    };
+    lines.filter_map(|line| Some(Limit {
+        name: cut(line, 0, soft),
+        soft: limit(cut(line, soft, hard))?,
+        hard: limit(cut(line, hard, unit))?,
+        unit: cut(line, unit, line.len()),
+    })).filter(|l| !l.name.is_empty()).collect()
+}
+
+// smaps_rollup has the sums already; kernels before 4.14 only have smaps, one block of the
+// same fields per mapping. Walking every mapping is costly for a big process, so this is only
+// done for the one the details popup is open on.
+pub fn read_memory_map(proc: &Path, pid: Pid) -> io::Result<MemoryMap> {
+    let dir = proc.join(pid.as_u32().to_string());
+    let text = match fs::read_to_string(dir.join("smaps_rollup")) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && dir.exists() => fs::read_to_string(dir.join("smaps"))?,
+        text => text?,
+    };
+    Ok(parse_smaps(&text))
+}
+
+// Adds up the fields over every block, so it reads rollups and full smaps alike
+pub fn parse_smaps(text: &str) -> MemoryMap {
+    let mut map = MemoryMap::default();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let field = match key {
+            "Rss" => &mut map.rss,
+            "Pss" => &mut map.pss,
+            "Shared_Clean" => &mut map.shared_clean,
+            "Shared_Dirty" => &mut map.shared_dirty,
+            "Private_Clean" => &mut map.private_clean,
+            "Private_Dirty" => &mut map.private_dirty,
+            "Swap" => &mut map.swap,
+            _ => continue,
+        };
+        *field += kilobytes(value).unwrap_or(0);
+    }
+    map
+}
+
+// "1873 ((sd-pam)) S 1872 ...". The name in parentheses is the executable's and can hold
+// spaces and parentheses of its own, so the fields are counted from the last `)`: state,
+// ppid, pgrp, session, tty_nr, tpgid, flags, minflt, cminflt, majflt, cmajflt, utime, stime,
+// cutime, cstime, priority, nice, and twenty fields later processor, rt_priority and policy.
+pub fn parse_stat(text: &str) -> Option<Stat> {
+    let (_, rest) = text.rsplit_once(')')?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    Some(Stat {
+        minor_faults: fields.get(7)?.parse().ok()?,
+        major_faults: fields.get(9)?.parse().ok()?,
+        nice: fields.get(16)?.parse().ok()?,
+        processor: fields.get(36).and_then(|f| f.parse().ok()),
+        scheduling: fields.get(38).and_then(|f| Policy::from_number(f.parse().ok()?)).zip(fields.get(37).and_then(|f| f.parse().ok()))
+            .map(|(policy, priority)| Scheduling { policy, priority }),
+    })
+}
+
+// "Key:\tvalue" lines; sizes are printed as "20480 kB"
+pub fn parse_status(text: &str) -> Status {
+    let mut status = Status::default();
+    let (mut effective, mut permitted) = (None, None);
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        match key {
+            "VmSwap" => status.swap = kilobytes(value),
+            "voluntary_ctxt_switches" => status.voluntary_switches = value.trim().parse().ok(),
+            "nonvoluntary_ctxt_switches" => status.involuntary_switches = value.trim().parse().ok(),
+            "CapEff" => effective = u64::from_str_radix(value.trim(), 16).ok(),
+            "CapPrm" => permitted = u64::from_str_radix(value.trim(), 16).ok(),
+            _ => {},
+        }
+    }
+    if let (Some(effective), Some(permitted)) = (effective, permitted) {
+        status.capabilities = Some(Capabilities { effective, permitted });
+    }
+    status
+}
+
+// What /proc/meminfo adds to sysinfo's memory figures: the page cache, the kernel's buffers
+// for block devices, and data written but not on disk yet. In bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemInfo {
+    pub cached: u64,
+    pub buffers: u64,
+    pub dirty: u64,
+    // The pool of the default huge page size; None when none are set aside
+    pub huge_pages: Option<HugePages>,
+    // Transparent huge pages backing anonymous memory; None without THP support
+    pub anon_huge_pages: Option<u64>,
+}
+
+// A pool of persistent huge pages of one size, in pages. Reserved pages are promised to a
+// mapping but not faulted in yet, so they are free but can't be handed out again; surplus
+// pages were allocated past the pool size under overcommit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HugePages {
+    // In bytes
+    pub size: u64,
+    pub total: u64,
+    pub free: u64,
+    pub reserved: u64,
+    pub surplus: u64,
+}
+
+pub fn read_meminfo(proc: &Path) -> Option<MemInfo> {
+    parse_meminfo(&fs::read_to_string(proc.join("meminfo")).ok()?)
+}
+
+// "Cached:  5109848 kB" lines, and the huge page counts, which have no unit; None unless the
+// cache, buffers and dirty lines are all there
+pub fn parse_meminfo(text: &str) -> Option<MemInfo> {
+    let (mut cached, mut buffers, mut dirty, mut anon_huge_pages) = (None, None, None, None);
+    let mut huge = HugePages::default();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let count = || value.trim().parse().unwrap_or(0);
+        match key {
+            "Cached" => cached = kilobytes(value),
+            "Buffers" => buffers = kilobytes(value),
+            "Dirty" => dirty = kilobytes(value),
+            "AnonHugePages" => anon_huge_pages = kilobytes(value),
+            "HugePages_Total" => huge.total = count(),
+            "HugePages_Free" => huge.free = count(),
+            "HugePages_Rsvd" => huge.reserved = count(),
+            "HugePages_Surp" => huge.surplus = count(),
+            "Hugepagesize" => huge.size = kilobytes(value).unwrap_or(0),
+            _ => {},
+        }
+    }
+    let huge_pages = (huge.total > 0 && huge.size > 0).then_some(huge);
+    Some(MemInfo { cached: cached?, buffers: buffers?, dirty: dirty?, huge_pages, anon_huge_pages })
+}
+
+// Every huge page size with a pool set aside, smallest first, from the hugepages-<size>kB
+// directories under /sys/kernel/mm/hugepages. /proc/meminfo only counts the default size.
+pub fn read_huge_pages(dir: &Path) -> Vec<HugePages> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut pools: Vec<HugePages> = entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let kb: u64 = entry.file_name().to_str()?.strip_prefix("hugepages-")?.strip_suffix("kB")?.parse().ok()?;
+        let count = |file: &str| fs::read_to_string(entry.path().join(file)).ok().and_then(|n| n.trim().parse().ok()).unwrap_or(0);
+        let pool = HugePages { size: kb * 1024, total: count("nr_hugepages"), free: count("free_hugepages"), reserved: count("resv_hugepages"), surplus: count("surplus_hugepages") };
+        (pool.total > 0).then_some(pool)
+    }).collect();
+    pools.sort_by_key(|pool| pool.size);
+    pools
+}
+
+// The time all CPUs together spent since boot, in clock ticks, from the "cpu" line of
+// /proc/stat. Steal is time a virtual CPU wanted to run while the hypervisor ran something
+// else; guest is time this machine spent running the virtual CPUs of its own guests, which
+// the kernel also counts as user time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuTime {
+    pub total: u64,
+    pub steal: u64,
+    pub guest: u64,
+}
+
+pub fn read_cpu_time(proc: &Path) -> Option<CpuTime> {
+    parse_cpu_time(&fs::read_to_string(proc.join("stat")).ok()?)
+}
+
+// "cpu  683305 0 86380 473634 4113 0 61 3600 0 0": user, nice, system, idle, iowait, irq,
+// softirq and steal make up the total; guest and guest_nice follow from Linux 2.6.24 and 2.6.33
+pub fn parse_cpu_time(text: &str) -> Option<CpuTime> {
+    let line = text.lines().find_map(|line| line.strip_prefix("cpu "))?;
+    let ticks: Vec<u64> = line.split_whitespace().map(|field| field.parse().ok()).collect::<Option<_>>()?;
+    if ticks.len() < 8 {
+        return None;
+    }
+    Some(CpuTime { total: ticks[..8].iter().sum(), steal: ticks[7], guest: ticks[8..].iter().take(2).sum() })
+}
+
+// The cpufreq scaling governor, and whether the CPU may clock above its base frequency
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CpuFreq {
+    // "performance", "powersave", "schedutil", ..., or "mixed" when the CPUs disagree
+    pub governor: String,
+    // None where neither intel_pstate nor the cpufreq core offers a switch
+    pub boost: Option<bool>,
+}
+
+// From the cpu<N>/cpufreq directories under /sys/devices/system/cpu; None without cpufreq, as
+// in most virtual machines
+pub fn read_cpufreq(dir: &Path) -> Option<CpuFreq> {
+    let mut governors: Vec<String> = fs::read_dir(dir).ok()?.filter_map(|entry| {
+        let entry = entry.ok()?;
+        entry.file_name().to_str()?.strip_prefix("cpu")?.parse::<u32>().ok()?;
+        Some(fs::read_to_string(entry.path().join("cpufreq/scaling_governor")).ok()?.trim().to_string())
+    }).collect();
+    governors.sort();
+    governors.dedup();
+    let governor = match governors.len() {
+        0 => return None,
+        1 => governors.remove(0),
+        _ => "mixed".to_string(),
+    };
+    let flag = |file: &str| fs::read_to_string(dir.join(file)).ok().map(|text| text.trim() == "1");
+    // intel_pstate turns it off, acpi-cpufreq and amd-pstate turn it on
+    let boost = flag("intel_pstate/no_turbo").map(|off| !off).or_else(|| flag("cpufreq/boost"));
+    Some(CpuFreq { governor, boost })
+}
+
+// Pages read back in from swap and written out to it since boot, from /proc/vmstat's
+// "pswpin 18233" and "pswpout 402117" lines
+pub fn read_vmstat(proc: &Path) -> Option<(u64, u64)> {
+    parse_vmstat(&fs::read_to_string(proc.join("vmstat")).ok()?)
+}
+
+pub fn parse_vmstat(text: &str) -> Option<(u64, u64)> {
+    let (mut swapped_in, mut swapped_out) = (None, None);
+    for line in text.lines() {
+        match line.split_once(' ') {
+            Some(("pswpin", value)) => swapped_in = value.trim().parse().ok(),
+            Some(("pswpout", value)) => swapped_out = value.trim().parse().ok(),
+            _ => {},
+        }
+    }
+    swapped_in.zip(swapped_out)
+}
+
+// What /sys/class/net/<interface> adds to sysinfo's byte and error counts
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Link {
+    // None when operstate is "unknown", as it is for lo and many virtual interfaces
+    pub up: Option<bool>,
+    // Packets dropped on receive and transmit since the interface came up
+    pub dropped: Option<(u64, u64)>,
+}
+
+pub fn read_link(class_net: &Path, name: &str) -> Link {
+    let dir = class_net.join(name);
+    let count = |file: &str| fs::read_to_string(dir.join("statistics").join(file)).ok()?.trim().parse::<u64>().ok();
+    Link {
+        up: fs::read_to_string(dir.join("operstate")).ok().and_then(|state| parse_operstate(&state)),
+        dropped: count("rx_dropped").zip(count("tx_dropped")),
+    }
+}
+
+// "up"; "down", "dormant", "lowerlayerdown" and the rest of RFC 2863's states count as down
+pub fn parse_operstate(text: &str) -> Option<bool> {
+    match text.trim() {
+        "" | "unknown" => None,
+        state => Some(state == "up"),
+    }
+}
+
+// The files in /proc/pressure, in the order the header shows them
+pub const PRESSURE_RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
+
+// How much of the last 10 and 60 seconds tasks spent stalled waiting for a resource, in
+// percent: "some" is the share in which at least one task was waiting, "full" the share in
+// which every task that wanted to run was, so nothing got done.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pressure {
+    pub some: (f32, f32),
+    // Missing from the cpu file before Linux 5.13
+    pub full: Option<(f32, f32)>,
+}
+
+// None where the kernel has no pressure stall information: before 4.20, built without it, or
+// in a container that hides it
+pub fn read_pressure(dir: &Path, resource: &str) -> Option<Pressure> {
+    parse_pressure(&fs::read_to_string(dir.join(resource)).ok()?)
+}
+
+// "some avg10=12.48 avg60=3.07 avg300=0.84 total=98123456", then the same for "full"
+pub fn parse_pressure(text: &str) -> Option<Pressure> {
+    let (mut some, mut full) = (None, None);
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        let kind = words.next();
+        let (mut avg10, mut avg60) = (None, None);
+        for word in words {
+            match word.split_once('=') {
+                Some(("avg10", value)) => avg10 = value.parse().ok(),
+                Some(("avg60", value)) => avg60 = value.parse().ok(),
+                _ => {},
+            }
+        }
+        match kind {
+            Some("some") => some = avg10.zip(avg60),
+            Some("full") => full = avg10.zip(avg60),
+            _ => {},
+        }
+    }
+    Some(Pressure { some: some?, full })
+}
+
+fn kilobytes(value: &str) -> Option<u64> {
+    let kb: u64 = value.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_of_a_swapped_out_process() {
+        let status = parse_status(include_str!("../tests/data/status-postgres"));
+        assert_eq!(status.swap, Some(20480 * 1024));
+        assert_eq!((status.voluntary_switches, status.involuntary_switches), (Some(48213), Some(1022)));
+    }
+
+    #[test]
+    fn kernel_threads_have_no_swap_line() {
+        let status = parse_status(include_str!("../tests/data/status-kthreadd"));
+        assert_eq!(status.swap, None);
+        assert_eq!(status.involuntary_switches, Some(14));
+        assert_eq!(parse_status("VmSwap:\tlots\n").swap, None);
+    }
+
+    #[test]
+    fn capability_masks_are_read_and_named() {
+        let root = parse_status(include_str!("../tests/data/status-kthreadd")).capabilities.unwrap();
+        assert_eq!(root.effective, 0x1ff_ffff_ffff);
+        assert!(all_capabilities(root.effective));
+        let postgres = parse_status(include_str!("../tests/data/status-postgres")).capabilities.unwrap();
+        assert_eq!(postgres, Capabilities { effective: 0, permitted: 0 });
+        assert!(capability_names(postgres.effective).is_empty());
+        assert_eq!(parse_status("CapEff:\t0000000000003000\n").capabilities, None);
+
+        // Docker's default set
+        let names = capability_names(0xa80425fb);
+        assert_eq!(names.len(), 14);
+        assert_eq!(&names[..3], ["CAP_CHOWN", "CAP_DAC_OVERRIDE", "CAP_FOWNER"]);
+        assert!(names.contains(&"CAP_NET_BIND_SERVICE".to_string()) && !names.contains(&"CAP_SYS_ADMIN".to_string()));
+        assert!(!all_capabilities(0xa80425fb));
+        assert_eq!(capability_names(1 << 12 | 1 << 40 | 1 << 41 | 1 << 63), ["CAP_NET_ADMIN", "CAP_CHECKPOINT_RESTORE", "CAP_(41)", "CAP_(63)"]);
+    }
+
+    #[test]
+    fn memory_maps_are_summed_from_rollups_and_full_smaps() {
+        let rollup = parse_smaps(include_str!("../tests/data/smaps_rollup-gunicorn"));
+        assert_eq!(rollup, MemoryMap {
+            rss: 88116 * 1024,
+            pss: 31207 * 1024,
+            shared_clean: 11840 * 1024,
+            shared_dirty: 50112 * 1024,
+            private_clean: 1732 * 1024,
+            private_dirty: 24432 * 1024,
+            swap: 2048 * 1024,
+        });
+        // Three mappings, and the address lines with their colons are skipped
+        let full = parse_smaps(include_str!("../tests/data/smaps-old-kernel"));
+        assert_eq!((full.rss, full.pss, full.private_dirty, full.swap), (52 * 1024, 32 * 1024, 12 * 1024, 12 * 1024));
+        assert_eq!(parse_smaps(""), MemoryMap::default());
+    }
+
+    #[test]
+    fn full_smaps_stand_in_for_a_missing_rollup() {
+        let proc = std::env::temp_dir().join(format!("rust-htop-smaps-{}", std::process::id()));
+        fs::create_dir_all(proc.join("7")).unwrap();
+        fs::write(proc.join("7/smaps"), include_str!("../tests/data/smaps-old-kernel")).unwrap();
+        let map = read_memory_map(&proc, Pid::from_u32(7));
+        let missing = read_memory_map(&proc, Pid::from_u32(8));
+        fs::remove_dir_all(&proc).unwrap();
+        assert_eq!(map.unwrap().rss, 52 * 1024);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn links_are_read_from_sysfs() {
+        let class_net = std::env::temp_dir().join(format!("rust-htop-net-{}", std::process::id()));
+        fs::create_dir_all(class_net.join("eth0/statistics")).unwrap();
+        fs::write(class_net.join("eth0/operstate"), "down\n").unwrap();
+        fs::write(class_net.join("eth0/statistics/rx_dropped"), "12\n").unwrap();
+        fs::write(class_net.join("eth0/statistics/tx_dropped"), "0\n").unwrap();
+        fs::create_dir_all(class_net.join("lo")).unwrap();
+        fs::write(class_net.join("lo/operstate"), "unknown\n").unwrap();
+        let eth0 = read_link(&class_net, "eth0");
+        let lo = read_link(&class_net, "lo");
+        let gone = read_link(&class_net, "wlan0");
+        fs::remove_dir_all(&class_net).unwrap();
+        assert_eq!(eth0, Link { up: Some(false), dropped: Some((12, 0)) });
+        assert_eq!(lo, Link { up: None, dropped: None });
+        assert_eq!(gone, Link::default());
+        assert_eq!(parse_operstate("up\n"), Some(true));
+        assert_eq!(parse_operstate("lowerlayerdown"), Some(false));
+    }
+
+    #[test]
+    fn limits_are_cut_at_the_header_columns() {
+        let limits = parse_limits(include_str!("../tests/data/limits-nginx"));
+        assert_eq!(limits.len(), 16);
+        let find = |name: &str| limits.iter().find(|l| l.name == name).unwrap();
+        assert_eq!(find("Max open files"), &Limit { name: "Max open files".into(), soft: Some(1024), hard: Some(524288), unit: "files".into() });
+        assert_eq!((find("Max core file size").soft, find("Max core file size").hard), (Some(0), None));
+        assert_eq!(find("Max address space").soft, None);
+        assert_eq!(find("Max nice priority").unit, "");
+        assert_eq!(find("Max realtime timeout").unit, "us");
+        assert!(parse_limits("").is_empty());
+        // A row that doesn't line up with the header is dropped, not misread
+        let header = include_str!("../tests/data/limits-nginx").lines().next().unwrap();
+        assert!(parse_limits(&format!("{}\nMax open files 1024 4096 files\n", header)).is_empty());
+    }
+
+    #[test]
+    fn meminfo_gives_cache_buffers_and_dirty_pages() {
+        assert_eq!(parse_meminfo(include_str!("../tests/data/meminfo")), Some(MemInfo { cached: 5_109_868 * 1024, buffers: 40_836 * 1024, dirty: 240 * 1024, huge_pages: None, anon_huge_pages: Some(0) }));
+        // SwapCached and Active(file) are other keys that must not be mistaken for these
+        assert_eq!(parse_meminfo("SwapCached:  10 kB\nBuffers:  1 kB\nDirty:  0 kB\n"), None);
+        assert_eq!(parse_meminfo("Cached:  lots\nBuffers:  1 kB\nDirty:  0 kB\n"), None);
+    }
+
+    #[test]
+    fn huge_pages_are_read_from_meminfo_and_sysfs() {
+        let info = parse_meminfo(include_str!("../tests/data/meminfo-hugepages")).unwrap();
+        assert_eq!(info.huge_pages, Some(HugePages { size: 2 << 20, total: 64, free: 52, reserved: 3, surplus: 0 }));
+        assert_eq!(info.anon_huge_pages, Some(120 << 20));
+
+        let dir = std::env::temp_dir().join(format!("rust-htop-hugepages-{}", std::process::id()));
+        for (name, total, free) in [("hugepages-1048576kB", "4\n", "1\n"), ("hugepages-2048kB", "64\n", "52\n"), ("hugepages-64kB", "0\n", "0\n")] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(dir.join(name).join("nr_hugepages"), total).unwrap();
+            fs::write(dir.join(name).join("free_hugepages"), free).unwrap();
+        }
+        fs::write(dir.join("hugepages-2048kB/resv_hugepages"), "3\n").unwrap();
+        let pools = read_huge_pages(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        // The empty 64 kB pool is left out
+        assert_eq!(pools, [
+            HugePages { size: 2 << 20, total: 64, free: 52, reserved: 3, surplus: 0 },
+            HugePages { size: 1 << 30, total: 4, free: 1, reserved: 0, surplus: 0 },
+        ]);
+        assert!(read_huge_pages(&dir).is_empty());
+    }
+
+    #[test]
+    fn cpu_time_comes_from_the_all_cpus_line() {
+        assert_eq!(parse_cpu_time(include_str!("../tests/data/stat-vm")), Some(CpuTime { total: 683_503 + 86_424 + 474_769 + 4126 + 61 + 3601, steal: 3601, guest: 0 }));
+        // A host running guests; the per-core lines after it are not the total
+        assert_eq!(parse_cpu_time("cpu  900 0 100 1000 0 0 0 0 400 20\ncpu0 1 0 0 0 0 0 0 0 0 0\n"), Some(CpuTime { total: 2000, steal: 0, guest: 420 }));
+        // Kernels too old to count steal
+        assert_eq!(parse_cpu_time("cpu  900 0 100 1000 0 0 0\n"), None);
+        assert_eq!(parse_cpu_time("cpu0 1 0 0 0 0 0 0 0 0 0\n"), None);
+    }
+
+    #[test]
+    fn governors_are_read_per_cpu_and_mixed_ones_said_so() {
+        let dir = std::env::temp_dir().join(format!("rust-htop-cpufreq-{}", std::process::id()));
+        let governor = |cpu: &str, name: &str| {
+            fs::create_dir_all(dir.join(cpu).join("cpufreq")).unwrap();
+            fs::write(dir.join(cpu).join("cpufreq/scaling_governor"), format!("{}\n", name)).unwrap();
+        };
+        governor("cpu0", "powersave");
+        governor("cpu1", "powersave");
+        // Not a CPU
+        fs::create_dir_all(dir.join("cpuidle")).unwrap();
+        fs::create_dir_all(dir.join("intel_pstate")).unwrap();
+        fs::write(dir.join("intel_pstate/no_turbo"), "1\n").unwrap();
+        assert_eq!(read_cpufreq(&dir), Some(CpuFreq { governor: "powersave".into(), boost: Some(false) }));
+
+        governor("cpu12", "performance");
+        fs::remove_dir_all(dir.join("intel_pstate")).unwrap();
+        fs::create_dir_all(dir.join("cpufreq")).unwrap();
+        fs::write(dir.join("cpufreq/boost"), "1\n").unwrap();
+        assert_eq!(read_cpufreq(&dir), Some(CpuFreq { governor: "mixed".into(), boost: Some(true) }));
+        fs::remove_dir_all(dir.join("cpufreq")).unwrap();
+        assert_eq!(read_cpufreq(&dir).unwrap().boost, None);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read_cpufreq(&dir), None);
+    }
+
+    #[test]
+    fn vmstat_gives_the_pages_swapped_in_and_out() {
+        assert_eq!(parse_vmstat(include_str!("../tests/data/vmstat")), Some((18233, 402117)));
+        // pgpgin and pgpgout count all paging, not just swap
+        assert_eq!(parse_vmstat("pgpgin 3587590\npgpgout 91963644\npswpin 0\n"), None);
+        assert_eq!(parse_vmstat("pswpin 1\npswpout -1\n"), None);
+    }
+
+    #[test]
+    fn pressure_averages_are_read_for_some_and_full() {
+        assert_eq!(parse_pressure(include_str!("../tests/data/pressure-io")), Some(Pressure { some: (12.48, 3.07), full: Some((9.71, 2.15)) }));
+        // The cpu file of kernels before 5.13 has no full line
+        assert_eq!(parse_pressure(include_str!("../tests/data/pressure-cpu-old")), Some(Pressure { some: (0.35, 0.12), full: None }));
+        assert_eq!(parse_pressure("full avg10=1.00 avg60=1.00 avg300=1.00 total=1\n"), None);
+        assert_eq!(parse_pressure("some avg10=oops avg60=0.00\n"), None);
+        assert_eq!(parse_pressure(""), None);
+    }
+
+    #[test]
+    fn stat_fields_are_found_past_parentheses_in_the_name() {
+        assert_eq!(parse_stat(include_str!("../tests/data/stat-sd-pam")), Some(Stat { minor_faults: 36, major_faults: 0, nice: 0, processor: Some(3), scheduling: Some(Scheduling { policy: Policy::Normal, priority: 0 }) }));
+        // A name of "evil) S 1 (x" must not shift the fields
+        assert_eq!(parse_stat(include_str!("../tests/data/stat-tricky-name")), Some(Stat { minor_faults: 918273, major_faults: 3301, nice: 0, processor: Some(5), scheduling: Some(Scheduling { policy: Policy::Normal, priority: 0 }) }));
+        assert_eq!(parse_stat("9 (backup) D 1 9 9 0 -1 0 5 0 2 0 0 0 0 0 39 19 1").map(|s| (s.nice, s.processor, s.scheduling)), Some((19, None, None)));
+        let realtime = parse_stat(include_str!("../tests/data/stat-realtime")).and_then(|s| s.scheduling);
+        assert_eq!(realtime, Some(Scheduling { policy: Policy::Fifo, priority: 50 }));
+        assert_eq!(parse_stat("12 (a b) R 1 2 3 4 5 6 7"), None);
+        assert_eq!(parse_stat("no parentheses"), None);
+    }
+}
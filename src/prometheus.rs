@@ -0,0 +1,165 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Arc};
+
+use crate::{server::{Handler, Request, Response}, snapshot::{SharedSnapshot, SystemSnapshot}};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Answers /metrics from the shared snapshot. With `processes` set, processes matching it like
+// the search box does are added, summed per name: PIDs come and go and would make every
+// restart a new time series.
+pub fn handler(snapshot: SharedSnapshot, processes: Option<String>) -> Handler {
+    Arc::new(move |req: &Request| match req.path.as_str() {
+        "/metrics" => match snapshot.read() {
+            Ok(snapshot) => Response::ok(CONTENT_TYPE, render(&snapshot, processes.as_deref())),
+            Err(_) => Response::error(500, "snapshot unavailable"),
+        },
+        "/" => Response::ok("text/plain; charset=utf-8", "rust-htop exporter, metrics at /metrics\n".into()),
+        _ => Response::error(404, "not found"),
+    })
+}
+
+// The Prometheus text exposition format
+pub fn render(snapshot: &SystemSnapshot, processes: Option<&str>) -> String {
+    let mut out = String::new();
+
+    header(&mut out, "rust_htop_cpu_usage_percent", "gauge", "Usage of each CPU core in percent");
+    for (core, usage) in snapshot.cpus.iter().enumerate() {
+        sample(&mut out, "rust_htop_cpu_usage_percent", &[("core", &core.to_string())], percent(*usage as f64));
+    }
+
+    let memory = &snapshot.memory;
+    for (name, help, value) in [
+        ("rust_htop_memory_total_bytes", "Total memory", memory.total_bytes),
+        ("rust_htop_memory_used_bytes", "Memory in use", memory.used_bytes),
+        ("rust_htop_swap_total_bytes", "Total swap", memory.swap_total_bytes),
+        ("rust_htop_swap_used_bytes", "Swap in use", memory.swap_used_bytes),
+    ] {
+        header(&mut out, name, "gauge", help);
+        sample(&mut out, name, &[], value as f64);
+    }
+
+    header(&mut out, "rust_htop_load_average", "gauge", "Load average over the period");
+    let load = &snapshot.load;
+    for (period, value) in [("1m", load.one), ("5m", load.five), ("15m", load.fifteen)] {
+        sample(&mut out, "rust_htop_load_average", &[("period", period)], value);
+    }
+
+    header(&mut out, "rust_htop_network_received_bytes_total", "counter", "Bytes received since the interface came up");
+    for n in &snapshot.networks {
+        sample(&mut out, "rust_htop_network_received_bytes_total", &[("interface", &n.name)], n.received_bytes as f64);
+    }
+    header(&mut out, "rust_htop_network_transmitted_bytes_total", "counter", "Bytes sent since the interface came up");
+    for n in &snapshot.networks {
+        sample(&mut out, "rust_htop_network_transmitted_bytes_total", &[("interface", &n.name)], n.transmitted_bytes as f64);
+    }
+
+    if let Some(query) = processes {
+        write_processes(&mut out, snapshot, query);
+    }
+    out
+}
+
+fn write_processes(out: &mut String, snapshot: &SystemSnapshot, query: &str) {
+    // name -> (count, cpu, memory)
+    let mut groups: BTreeMap<&str, (u64, f64, u64)> = BTreeMap::new();
+    let query = query.to_lowercase();
+    for p in &snapshot.processes {
+        if p.matches(&query) {
+            let group = groups.entry(&p.name).or_default();
+            group.0 += 1;
+            group.1 += p.cpu_percent as f64;
+            group.2 += p.mem_bytes;
+        }
+    }
+    header(out, "rust_htop_process_count", "gauge", "Number of matching processes with this name");
+    for (name, (count, _, _)) in &groups {
+        sample(out, "rust_htop_process_count", &[("name", name)], *count as f64);
+    }
+    header(out, "rust_htop_process_cpu_usage_percent", "gauge", "CPU usage of the processes with this name, in percent of one core");
+    for (name, (_, cpu, _)) in &groups {
+        sample(out, "rust_htop_process_cpu_usage_percent", &[("name", name)], percent(*cpu));
+    }
+    header(out, "rust_htop_process_memory_bytes", "gauge", "Memory of the processes with this name");
+    for (name, (_, _, mem)) in &groups {
+        sample(out, "rust_htop_process_memory_bytes", &[("name", name)], *mem as f64);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v))).collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    // NaN is valid in the format, but a CPU reading that isn't a number is better left at 0
+    let value = if value.is_finite() { value } else { 0.0 };
+    let _ = writeln!(out, " {}", value);
+}
+
+// Two decimals like the table; more would only be f32 rounding noise
+fn percent(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+// Label values escape backslash, double quote and line feed
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{LoadRecord, MemoryRecord, NetworkRecord, ProcessRecord};
+
+    fn process(pid: u32, name: &str, cpu: f32, mem: u64) -> ProcessRecord {
+        ProcessRecord { pid, parent_pid: None, name: name.into(), user: None, state: "S".into(), cpu_percent: cpu, mem_bytes: mem, kernel_thread: false, start_time: 0, open_fds: None, connections: None, net_queued_bytes: None, swap_bytes: None, voluntary_ctxt_switches: None, nonvoluntary_ctxt_switches: None, minor_faults: None, major_faults: None, processor: None, container: None, exe: None, cmdline: Vec::new() }
+    }
+
+    fn snapshot() -> SystemSnapshot {
+        SystemSnapshot {
+            timestamp: "2024-01-01T00:00:00Z".into(),
+            cpus: vec![12.5, 50.0],
+            memory: MemoryRecord { total_bytes: 8192, used_bytes: 4096, available_bytes: 0, cached_bytes: None, buffers_bytes: None, dirty_bytes: None, anon_huge_pages_bytes: None, pressure: None, pressure_free_percent: None, huge_pages: Vec::new(), swap_total_bytes: 0, swap_used_bytes: 0, swapped_in_bytes: None, swapped_out_bytes: None },
+            load: LoadRecord { one: 1.5, five: 1.0, fifteen: 0.5 },
+            boot_time: 0,
+            cpu_time: None,
+            cpufreq: None,
+            pressure: Vec::new(),
+            networks: vec![NetworkRecord { name: "eth0".into(), received_bytes: 100, transmitted_bytes: 200, received_errors: 0, transmitted_errors: 0, received_dropped: None, transmitted_dropped: None, up: None }],
+            processes: vec![process(1, "init", 0.0, 10), process(10, "nginx", 1.5, 100), process(11, "nginx", 2.0, 200), process(20, "say \"hi\"", 0.0, 0)],
+        }
+    }
+
+    #[test]
+    fn system_gauges() {
+        let text = render(&snapshot(), None);
+        for line in [
+            "# TYPE rust_htop_cpu_usage_percent gauge",
+            "rust_htop_cpu_usage_percent{core=\"1\"} 50",
+            "rust_htop_memory_used_bytes 4096",
+            "rust_htop_memory_total_bytes 8192",
+            "rust_htop_load_average{period=\"1m\"} 1.5",
+            "# TYPE rust_htop_network_received_bytes_total counter",
+            "rust_htop_network_transmitted_bytes_total{interface=\"eth0\"} 200",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing `{}` in\n{}", line, text);
+        }
+        assert!(!text.contains("rust_htop_process"));
+    }
+
+    #[test]
+    fn processes_are_summed_by_name() {
+        let text = render(&snapshot(), Some("NGINX"));
+        assert!(text.contains("rust_htop_process_count{name=\"nginx\"} 2\n"), "{}", text);
+        assert!(text.contains("rust_htop_process_cpu_usage_percent{name=\"nginx\"} 3.5\n"));
+        assert!(text.contains("rust_htop_process_memory_bytes{name=\"nginx\"} 300\n"));
+        assert!(!text.contains("name=\"init\""));
+        let text = render(&snapshot(), Some(""));
+        assert!(text.contains("rust_htop_process_count{name=\"say \\\"hi\\\"\"} 1\n"), "{}", text);
+    }
+}
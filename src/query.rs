@@ -0,0 +1,144 @@
+// What the search box and watch patterns type. A word like "pid:101" or "user:www" only
+// matches that column; whatever else is typed is matched as before, against the name, path,
+// command line and PID, so "101" alone still finds both PID 101 and chrome-101.
+
+use crate::{app::ProcInfo, columns::Column};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Field {
+    Column(Column),
+    // Not a column of its own, but what the user filter picks by
+    User,
+}
+
+impl Field {
+    // What goes before the colon
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Column(column) => column.name(),
+            Field::User => "user",
+        }
+    }
+
+    fn all() -> impl Iterator<Item = Field> {
+        Column::ALL.into_iter().map(Field::Column).chain([Field::User])
+    }
+
+    fn from_name(name: &str) -> Option<Field> {
+        Field::all().find(|f| f.name() == name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Query {
+    // Each has to match, lowercased
+    fields: Vec<(Field, String)>,
+    // The rest of the words rejoined with single spaces, lowercased
+    text: String,
+}
+
+impl Query {
+    // Only a known name before the colon makes a field, so "postgres: checkpointer" is still
+    // plain text
+    pub fn parse(query: &str) -> Query {
+        let query = query.to_lowercase();
+        let mut parsed = Query::default();
+        let mut words = Vec::new();
+        for word in query.split_whitespace() {
+            match word.split_once(':').and_then(|(name, value)| Some((Field::from_name(name)?, value))) {
+                Some((field, value)) => parsed.fields.push((field, value.to_string())),
+                None => words.push(word),
+            }
+        }
+        parsed.text = words.join(" ");
+        parsed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.text.is_empty()
+    }
+
+    // A field with nothing after the colon yet matches everything, as an empty search does
+    pub fn matches(&self, p: &ProcInfo) -> bool {
+        p.matches(&self.text) && self.fields.iter().all(|(field, value)| match field {
+            Field::Column(Column::Name) => p.matches_name(value),
+            Field::Column(Column::Pid) => p.pid_text.contains(value.as_str()),
+            Field::Column(column) => column.text(p).to_lowercase().contains(value.as_str()),
+            Field::User => p.user.as_deref().is_some_and(|user| user.to_lowercase().contains(value.as_str())),
+        })
+    }
+}
+
+// Tab in the search box: the last word, when it is the start of one or more field names,
+// becomes as much of them as they share, and takes the colon once only one is left
+pub fn complete(query: &str) -> Option<String> {
+    let start = query.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &query[start..];
+    if word.is_empty() || word.contains(':') {
+        return None;
+    }
+    let lower = word.to_lowercase();
+    let candidates: Vec<&str> = Field::all().map(Field::name).filter(|name| name.starts_with(&lower)).collect();
+    let completed = match candidates.as_slice() {
+        [] => return None,
+        [only] => format!("{}:", only),
+        [first, rest @ ..] => {
+            let shared = rest.iter().fold(first.len(), |len, name| first.bytes().zip(name.bytes()).take(len).take_while(|(a, b)| a == b).count());
+            first[..shared].to_string()
+        },
+    };
+    (completed != word).then(|| format!("{}{}", &query[..start], completed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{FakeSource, SystemSource};
+
+    fn procs() -> Vec<ProcInfo> {
+        let mut source = FakeSource::with_processes(&[(101, "bash", 0.0, 0), (7, "chrome-101", 0.0, 0), (1010, "postgres", 0.0, 0)]);
+        source.processes[0].user = Some("alice".into());
+        source.processes[1].user = Some("bob".into());
+        source.processes[2].user = Some("postgres".into());
+        source.processes().iter().map(ProcInfo::new).collect()
+    }
+
+    fn names(query: &str) -> Vec<String> {
+        let query = Query::parse(query);
+        procs().into_iter().filter(|p| query.matches(p)).map(|p| p.name).collect()
+    }
+
+    #[test]
+    fn a_field_prefix_narrows_the_match_to_that_column() {
+        assert_eq!(names("101"), ["bash", "chrome-101", "postgres"]);
+        assert_eq!(names("pid:101"), ["bash", "postgres"]);
+        assert_eq!(names("name:101"), ["chrome-101"]);
+        assert_eq!(names("USER:Post"), ["postgres"]);
+        // Still being typed
+        assert_eq!(names("pid:"), ["bash", "chrome-101", "postgres"]);
+    }
+
+    #[test]
+    fn fields_combine_with_each_other_and_with_free_text() {
+        assert_eq!(names("pid:101 user:alice"), ["bash"]);
+        assert_eq!(names("user:o chrome"), ["chrome-101"]);
+        assert_eq!(names("bash pid:7"), Vec::<String>::new());
+        // The free text is the words left over, in order
+        let query = Query::parse("postgres:  pid:10 checkpointer");
+        assert_eq!(query, Query { fields: vec![(Field::Column(Column::Pid), "10".into())], text: "postgres: checkpointer".into() });
+        assert!(Query::parse("  ").is_empty());
+    }
+
+    #[test]
+    fn tab_completes_field_names() {
+        assert_eq!(complete("bash us").as_deref(), Some("bash user:"));
+        assert_eq!(complete("p").as_deref(), Some("pid:"));
+        // majflt, mem and minflt share only the m
+        assert_eq!(complete("m"), None);
+        assert_eq!(complete("cor").as_deref(), Some("core:"));
+        assert_eq!(complete("user:x conn").as_deref(), Some("user:x conn:"));
+        for done in ["", "pid:", "bash ", "zzz"] {
+            assert_eq!(complete(done), None, "{:?}", done);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::{ffi::OsString, fs::{self, File}, io::{self, BufReader, BufWriter, Read, Write}, path::{Path, PathBuf}};
+
+use crate::snapshot::SystemSnapshot;
+
+// Session files (.rhr) start with MAGIC and a big-endian u16 format version. Each frame that
+// follows is a u32 payload length, the snapshot time as i64 milliseconds since the Unix epoch,
+// and a zstd-compressed SystemSnapshot in JSON. Readers refuse versions newer than their own;
+// new information goes into the snapshot JSON, which already tolerates added fields.
+pub const MAGIC: &[u8; 8] = b"RHTOPREC";
+pub const VERSION: u16 = 1;
+const HEADER_LEN: u64 = 10;
+const FRAME_HEADER_LEN: u64 = 12;
+// Far above any real snapshot; a bigger length means the frame header is garbage
+const MAX_FRAME: usize = 64 << 20;
+const LEVEL: i32 = 3;
+
+// Appends a frame per refresh. Once the file would grow past `max_bytes` it is renamed to
+// `<path>.1`, replacing the previous one, and a new file is started, so a session never
+// takes more than about twice the limit. An existing file at `path` is rotated the same way
+// on start instead of being overwritten, which keeps the recording of a crashed run.
+pub struct Recorder {
+    pub path: PathBuf,
+    max_bytes: u64,
+    out: BufWriter<File>,
+    written: u64,
+}
+
+impl Recorder {
+    pub fn create(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        if fs::metadata(&path).is_ok_and(|m| m.len() > 0) {
+            fs::rename(&path, rotated_path(&path))?;
+        }
+        let out = start(&path)?;
+        Ok(Self { path, max_bytes, out, written: HEADER_LEN })
+    }
+
+    // Flushed per frame so a crash loses at most the frame being written
+    pub fn write(&mut self, snapshot: &SystemSnapshot, time_ms: i64) -> io::Result<()> {
+        let payload = zstd::bulk::compress(&serde_json::to_vec(snapshot)?, LEVEL)?;
+        if payload.len() > MAX_FRAME {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "snapshot too large to record"));
+        }
+        let size = FRAME_HEADER_LEN + payload.len() as u64;
+        log::debug!("recording a {} byte frame", size);
+        if self.written > HEADER_LEN && self.written + size > self.max_bytes {
+            self.out.flush()?;
+            log::info!("{} reached {} bytes; starting a new one", self.path.display(), self.written);
+            fs::rename(&self.path, rotated_path(&self.path))?;
+            self.out = start(&self.path)?;
+            self.written = HEADER_LEN;
+        }
+        self.out.write_all(&(payload.len() as u32).to_be_bytes())?;
+        self.out.write_all(&time_ms.to_be_bytes())?;
+        self.out.write_all(&payload)?;
+        self.out.flush()?;
+        self.written += size;
+        Ok(())
+    }
+}
+
+fn start(path: &Path) -> io::Result<BufWriter<File>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_be_bytes())?;
+    out.flush()?;
+    Ok(out)
+}
+
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".1");
+    name.into()
+}
+
+// One recorded refresh, still compressed; replays decode frames as they are shown
+pub struct Frame {
+    pub time_ms: i64,
+    data: Vec<u8>,
+}
+
+impl Frame {
+    pub fn snapshot(&self) -> io::Result<SystemSnapshot> {
+        let json = zstd::bulk::decompress(&self.data, MAX_FRAME * 16)?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+pub struct Session {
+    pub frames: Vec<Frame>,
+    // The file ended in the middle of a frame, as it does when the recorder was killed
+    pub truncated: bool,
+}
+
+pub fn open(path: &Path) -> io::Result<Session> {
+    read(BufReader::new(File::open(path)?))
+}
+
+pub fn read(mut input: impl Read) -> io::Result<Session> {
+    let mut header = [0; HEADER_LEN as usize];
+    input.read_exact(&mut header).map_err(|_| invalid("not a rust-htop recording"))?;
+    if &header[..8] != MAGIC {
+        return Err(invalid("not a rust-htop recording"));
+    }
+    let version = u16::from_be_bytes([header[8], header[9]]);
+    if version > VERSION {
+        return Err(invalid(&format!("recorded by a newer rust-htop (format version {}, this one reads up to {})", version, VERSION)));
+    }
+    let mut frames = Vec::new();
+    loop {
+        let mut head = [0; FRAME_HEADER_LEN as usize];
+        match read_full(&mut input, &mut head)? {
+            0 => return Ok(Session { frames, truncated: false }),
+            n if n < head.len() => return Ok(Session { frames, truncated: true }),
+            _ => {},
+        }
+        let len = u32::from_be_bytes(head[..4].try_into().unwrap()) as usize;
+        let time_ms = i64::from_be_bytes(head[4..].try_into().unwrap());
+        if len > MAX_FRAME {
+            return Ok(Session { frames, truncated: true });
+        }
+        let mut data = vec![0; len];
+        if read_full(&mut input, &mut data)? < len {
+            return Ok(Session { frames, truncated: true });
+        }
+        frames.push(Frame { time_ms, data });
+    }
+}
+
+// Like read_exact, but reports how much was read when the input ends early
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app::App, config::Config, system::FakeSource};
+
+    fn snapshot(procs: usize) -> SystemSnapshot {
+        let data: Vec<(u32, String)> = (0..procs).map(|i| (i as u32 + 1, format!("proc-{}", i))).collect();
+        let rows: Vec<(u32, &str, f32, u64)> = data.iter().map(|(pid, name)| (*pid, name.as_str(), 1.0, 4096)).collect();
+        SystemSnapshot::capture_all(&App::new(Config::default(), Box::new(FakeSource::with_processes(&rows))))
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rust-htop-{}-{}.rhr", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rotated_path(&path));
+        path
+    }
+
+    #[test]
+    fn frames_round_trip_with_their_times() {
+        let path = temp_path("round-trip");
+        let mut recorder = Recorder::create(path.clone(), 1 << 20).unwrap();
+        recorder.write(&snapshot(2), 1_000).unwrap();
+        recorder.write(&snapshot(3), 2_000).unwrap();
+        let session = open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!session.truncated);
+        let times: Vec<i64> = session.frames.iter().map(|f| f.time_ms).collect();
+        assert_eq!(times, vec![1_000, 2_000]);
+        assert_eq!(session.frames[1].snapshot().unwrap().processes, snapshot(3).processes);
+    }
+
+    #[test]
+    fn oversized_sessions_rotate() {
+        let path = temp_path("rotate");
+        let frame_size = {
+            let mut probe = Recorder::create(path.clone(), u64::MAX).unwrap();
+            probe.write(&snapshot(50), 0).unwrap();
+            fs::metadata(&path).unwrap().len() - HEADER_LEN
+        };
+        fs::remove_file(&path).unwrap();
+        let mut recorder = Recorder::create(path.clone(), HEADER_LEN + frame_size * 2).unwrap();
+        for time in 0..5 {
+            recorder.write(&snapshot(50), time).unwrap();
+        }
+        let current = open(&path).unwrap();
+        let rotated = open(&rotated_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(rotated_path(&path)).unwrap();
+        assert_eq!(current.frames.iter().map(|f| f.time_ms).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(rotated.frames.iter().map(|f| f.time_ms).collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn existing_recordings_are_kept_on_start() {
+        let path = temp_path("keep");
+        Recorder::create(path.clone(), 1 << 20).unwrap().write(&snapshot(1), 7).unwrap();
+        Recorder::create(path.clone(), 1 << 20).unwrap();
+        assert_eq!(open(&rotated_path(&path)).unwrap().frames[0].time_ms, 7);
+        assert!(open(&path).unwrap().frames.is_empty());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(rotated_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn a_cut_off_last_frame_is_dropped() {
+        let path = temp_path("truncated");
+        let mut recorder = Recorder::create(path.clone(), 1 << 20).unwrap();
+        recorder.write(&snapshot(2), 1).unwrap();
+        recorder.write(&snapshot(2), 2).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        for cut in [1, 5, FRAME_HEADER_LEN as usize + 3] {
+            let session = read(&bytes[..bytes.len() - cut]).unwrap();
+            assert!(session.truncated);
+            assert_eq!(session.frames.len(), 1);
+            assert!(session.frames[0].snapshot().is_ok());
+        }
+    }
+
+    #[test]
+    fn header_is_checked() {
+        assert!(read(&b"GIF89a..."[..]).is_err());
+        let mut newer = MAGIC.to_vec();
+        newer.extend_from_slice(&(VERSION + 1).to_be_bytes());
+        let err = read(newer.as_slice()).err().unwrap();
+        assert!(err.to_string().contains("newer rust-htop"), "{}", err);
+    }
+
+    #[test]
+    fn failures_are_errors_not_panics() {
+        assert!(Recorder::create("/nonexistent/rust-htop/session.rhr".into(), 1 << 20).is_err());
+    }
+}
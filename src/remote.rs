@@ -0,0 +1,303 @@
+use std::{io::{self, Read, Write}, net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs}, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex}, thread::{self, JoinHandle}, time::Duration};
+
+use crate::{app::ProcSample, snapshot::{SnapshotSource, SystemSnapshot}, system::{NetSample, SystemSource, SystemTotals}};
+
+// The agent protocol: every refresh the agent sends one frame per viewer, a 4-byte big-endian
+// length followed by a SystemSnapshot as JSON. Viewers only read. A new viewer gets the
+// latest snapshot straight away instead of waiting for the next refresh.
+
+// Far above any real process list; a bigger length means the stream is not a rust-htop agent
+const MAX_FRAME: usize = 64 << 20;
+// How often the accept loop checks whether it should stop
+const POLL: Duration = Duration::from_millis(50);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Agents send at their refresh rate; this much silence counts as a lost connection
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const RETRY: Duration = Duration::from_secs(2);
+
+pub fn encode(snapshot: &SystemSnapshot) -> io::Result<Vec<u8>> {
+    let json = serde_json::to_vec(snapshot)?;
+    let len = u32::try_from(json.len()).ok().filter(|&n| n as usize <= MAX_FRAME).ok_or(io::ErrorKind::InvalidData)?;
+    let mut frame = Vec::with_capacity(4 + json.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&json);
+    Ok(frame)
+}
+
+pub fn read_frame(input: &mut impl Read) -> io::Result<SystemSnapshot> {
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes is too large", len)));
+    }
+    let mut json = vec![0; len];
+    input.read_exact(&mut json)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+#[derive(Default)]
+struct Viewers {
+    latest: Option<Arc<Vec<u8>>>,
+    senders: Vec<mpsc::Sender<Arc<Vec<u8>>>>,
+}
+
+// `--serve`: accepts viewers on a background thread and hands every published snapshot to
+// each of them. A viewer has its own writer thread, so a slow one never holds up the others
+// or the refresh loop.
+pub struct Agent {
+    addr: SocketAddr,
+    viewers: Arc<Mutex<Viewers>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Agent {
+    pub fn spawn(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let viewers = Arc::new(Mutex::new(Viewers::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (thread_viewers, thread_stop) = (Arc::clone(&viewers), Arc::clone(&stop));
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => add_viewer(stream, &thread_viewers),
+                    Err(e) => {
+                        if e.kind() != io::ErrorKind::WouldBlock {
+                            log::warn!("could not accept a viewer: {}", e);
+                        }
+                        thread::sleep(POLL);
+                    },
+                }
+            }
+        });
+        Ok(Self { addr, viewers, stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // Viewers that went away are dropped here, when their writer thread has hung up
+    pub fn publish(&self, snapshot: &SystemSnapshot) -> io::Result<()> {
+        let frame = Arc::new(encode(snapshot)?);
+        if let Ok(mut viewers) = self.viewers.lock() {
+            viewers.senders.retain(|tx| tx.send(Arc::clone(&frame)).is_ok());
+            log::debug!("sent a {} byte frame to {} viewers", frame.len(), viewers.senders.len());
+            viewers.latest = Some(frame);
+        }
+        Ok(())
+    }
+
+    pub fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // Dropping the senders ends the writer threads, which closes the connections
+        if let Ok(mut viewers) = self.viewers.lock() {
+            viewers.senders.clear();
+        }
+    }
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn add_viewer(mut stream: TcpStream, viewers: &Mutex<Viewers>) {
+    if stream.set_nonblocking(false).and_then(|_| stream.set_write_timeout(Some(READ_TIMEOUT))).is_err() {
+        return;
+    }
+    let (tx, rx) = mpsc::channel::<Arc<Vec<u8>>>();
+    let Ok(mut viewers) = viewers.lock() else { return };
+    if let Some(latest) = &viewers.latest {
+        let _ = tx.send(Arc::clone(latest));
+    }
+    viewers.senders.push(tx);
+    thread::spawn(move || {
+        while let Ok(frame) = rx.recv() {
+            if stream.write_all(&frame).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+// Connection state of a remote source, for the banner
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteStatus {
+    pub addr: String,
+    pub connected: bool,
+    // Why the last connection attempt or connection failed
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Link {
+    // Newest snapshot not yet taken by `refresh`
+    latest: Option<SystemSnapshot>,
+    connected: bool,
+    error: Option<String>,
+}
+
+// `--connect`: a SystemSource fed by an agent. A background thread keeps the connection up,
+// reconnecting after errors, and `refresh` takes whatever snapshot arrived last. Until then
+// and while disconnected the previous data stays on screen.
+pub struct RemoteSource {
+    addr: String,
+    link: Arc<Mutex<Link>>,
+    stop: Arc<AtomicBool>,
+    current: SnapshotSource,
+}
+
+impl RemoteSource {
+    pub fn connect(addr: &str) -> Self {
+        let link = Arc::new(Mutex::new(Link::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (thread_link, thread_stop, thread_addr) = (Arc::clone(&link), Arc::clone(&stop), addr.to_string());
+        // Not joined: it may be blocked in a read, and it exits on its own once `stop` is seen
+        thread::spawn(move || receive(&thread_addr, &thread_link, &thread_stop));
+        Self { addr: addr.to_string(), link, stop, current: SnapshotSource::default() }
+    }
+}
+
+impl Drop for RemoteSource {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn receive(addr: &str, link: &Mutex<Link>, stop: &AtomicBool) {
+    let set = |connected: bool, error: Option<String>| {
+        if let Ok(mut link) = link.lock() {
+            link.connected = connected;
+            link.error = error;
+        }
+    };
+    while !stop.load(Ordering::Relaxed) {
+        match open(addr) {
+            Ok(mut stream) => {
+                set(true, None);
+                let error = loop {
+                    match read_frame(&mut stream) {
+                        Ok(snapshot) => {
+                            if stop.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            if let Ok(mut link) = link.lock() {
+                                link.latest = Some(snapshot);
+                            }
+                        },
+                        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break "the agent closed the connection".to_string(),
+                        Err(e) => break e.to_string(),
+                    }
+                };
+                log::warn!("lost the connection to {}: {}", addr, error);
+                set(false, Some(error));
+            },
+            Err(e) => {
+                log::debug!("could not connect to {}: {}", addr, e);
+                set(false, Some(e.to_string()));
+            },
+        }
+        thread::sleep(RETRY);
+    }
+}
+
+fn open(addr: &str) -> io::Result<TcpStream> {
+    let mut last = io::Error::new(io::ErrorKind::NotFound, "address did not resolve");
+    for resolved in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&resolved, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                return Ok(stream);
+            },
+            Err(e) => last = e,
+        }
+    }
+    Err(last)
+}
+
+impl SystemSource for RemoteSource {
+    fn refresh(&mut self) {
+        if let Ok(mut link) = self.link.lock() && let Some(snapshot) = link.latest.take() {
+            self.current.snapshot = Some(snapshot);
+        }
+    }
+
+    fn processes(&self) -> Vec<ProcSample<'_>> {
+        self.current.processes()
+    }
+
+    fn networks(&self) -> Vec<NetSample<'_>> {
+        self.current.networks()
+    }
+
+    fn totals(&self) -> SystemTotals {
+        self.current.totals()
+    }
+
+    fn remote(&self) -> Option<RemoteStatus> {
+        let link = self.link.lock().ok()?;
+        Some(RemoteStatus { addr: self.addr.clone(), connected: link.connected, error: link.error.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use crate::{app::App, config::Config, system::FakeSource};
+
+    fn snapshot(names: &[(u32, &str)]) -> SystemSnapshot {
+        let source = FakeSource::with_processes(&names.iter().map(|&(pid, name)| (pid, name, 1.0, 1024)).collect::<Vec<_>>());
+        SystemSnapshot::capture_all(&App::new(Config::default(), Box::new(source)))
+    }
+
+    // Refreshes until `done` holds or a few seconds pass
+    fn wait_for(source: &mut RemoteSource, done: impl Fn(&RemoteSource) -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            source.refresh();
+            if done(source) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn frames_round_trip_and_oversized_ones_are_refused() {
+        let snapshot = snapshot(&[(1, "init")]);
+        let frame = encode(&snapshot).unwrap();
+        assert_eq!(read_frame(&mut frame.as_slice()).unwrap(), snapshot);
+        let mut huge = (MAX_FRAME as u32 + 1).to_be_bytes().to_vec();
+        huge.extend_from_slice(b"{}");
+        assert_eq!(read_frame(&mut huge.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read_frame(&mut &frame[..10]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn viewer_follows_the_agent_and_notices_it_leaving() {
+        let mut agent = Agent::spawn("127.0.0.1:0").unwrap();
+        agent.publish(&snapshot(&[(1, "init"), (7, "sshd")])).unwrap();
+        let mut source = RemoteSource::connect(&agent.local_addr().to_string());
+        assert!(wait_for(&mut source, |s| s.processes().len() == 2));
+        assert!(source.remote().unwrap().connected);
+
+        agent.publish(&snapshot(&[(1, "init"), (7, "sshd"), (8, "bash")])).unwrap();
+        assert!(wait_for(&mut source, |s| s.processes().iter().any(|p| p.name == "bash")));
+
+        agent.shutdown();
+        assert!(wait_for(&mut source, |s| !s.remote().unwrap().connected));
+        // The last snapshot stays until the agent is back
+        assert_eq!(source.processes().len(), 3);
+        assert!(source.remote().unwrap().error.is_some());
+    }
+}
@@ -0,0 +1,225 @@
+use std::{io, path::Path, time::Instant};
+
+use crate::{app::ProcSample, record::{self, Session}, snapshot::SnapshotSource, system::{NetSample, SystemSource, SystemTotals}};
+
+// Playback speeds offered by '<' and '>'
+const SPEEDS: [f64; 9] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+const NORMAL_SPEED: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayCommand {
+    TogglePlay,
+    // Move by this many frames and pause
+    Step(isize),
+    Faster,
+    Slower,
+}
+
+// Where a replay is, for the timeline
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayStatus {
+    pub time_ms: i64,
+    // Zero-based frame on screen, out of `frames`
+    pub index: usize,
+    pub frames: usize,
+    pub speed: f64,
+    pub playing: bool,
+    // Set when the frame on screen could not be decoded; the previous one is still shown
+    pub error: Option<String>,
+    // The recording ended mid-frame, as it does when the recorder was killed; that frame is
+    // left out
+    pub truncated: bool,
+}
+
+// `--replay`: plays a recorded session back through the normal interface. The replay clock
+// runs at `speed` times real time between refreshes and each refresh shows the last frame
+// recorded at or before it, so playback keeps the recorded pacing whatever the refresh rate.
+pub struct ReplaySource {
+    session: Session,
+    index: usize,
+    clock_ms: f64,
+    speed: usize,
+    playing: bool,
+    last_tick: Instant,
+    current: SnapshotSource,
+    error: Option<String>,
+}
+
+impl ReplaySource {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(record::open(path)?)
+    }
+
+    pub fn new(session: Session) -> io::Result<Self> {
+        let Some(first) = session.frames.first() else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the recording has no snapshots"));
+        };
+        let clock_ms = first.time_ms as f64;
+        let mut replay = Self {
+            session,
+            index: 0,
+            clock_ms,
+            speed: NORMAL_SPEED,
+            playing: true,
+            last_tick: Instant::now(),
+            current: SnapshotSource::default(),
+            error: None,
+        };
+        replay.load();
+        Ok(replay)
+    }
+
+    fn load(&mut self) {
+        match self.session.frames[self.index].snapshot() {
+            Ok(snapshot) => {
+                self.current.snapshot = Some(snapshot);
+                self.error = None;
+            },
+            Err(e) => {
+                log::warn!("replay frame {} is damaged: {}", self.index + 1, e);
+                self.error = Some(format!("frame {} is damaged: {}", self.index + 1, e));
+            },
+        }
+    }
+
+    fn seek(&mut self, index: usize) {
+        if index != self.index {
+            self.index = index;
+            self.load();
+        }
+    }
+}
+
+impl SystemSource for ReplaySource {
+    fn refresh(&mut self) {
+        let elapsed = self.last_tick.elapsed();
+        self.last_tick = Instant::now();
+        if !self.playing {
+            return;
+        }
+        self.clock_ms += elapsed.as_secs_f64() * 1000.0 * SPEEDS[self.speed];
+        let frames = &self.session.frames;
+        let mut index = self.index;
+        while index + 1 < frames.len() && frames[index + 1].time_ms as f64 <= self.clock_ms {
+            index += 1;
+        }
+        if index + 1 == frames.len() {
+            self.playing = false;
+        }
+        self.seek(index);
+    }
+
+    fn processes(&self) -> Vec<ProcSample<'_>> {
+        self.current.processes()
+    }
+
+    fn networks(&self) -> Vec<NetSample<'_>> {
+        self.current.networks()
+    }
+
+    fn totals(&self) -> SystemTotals {
+        self.current.totals()
+    }
+
+    fn replay(&self) -> Option<ReplayStatus> {
+        Some(ReplayStatus {
+            time_ms: self.session.frames[self.index].time_ms,
+            index: self.index,
+            frames: self.session.frames.len(),
+            speed: SPEEDS[self.speed],
+            playing: self.playing,
+            error: self.error.clone(),
+            truncated: self.session.truncated,
+        })
+    }
+
+    fn control(&mut self, command: ReplayCommand) {
+        let last = self.session.frames.len() - 1;
+        match command {
+            ReplayCommand::TogglePlay => {
+                // Playing from the end starts over
+                if !self.playing && self.index == last {
+                    self.seek(0);
+                    self.clock_ms = self.session.frames[0].time_ms as f64;
+                }
+                self.playing = !self.playing;
+            },
+            ReplayCommand::Step(delta) => {
+                self.playing = false;
+                self.seek(self.index.saturating_add_signed(delta).min(last));
+                self.clock_ms = self.session.frames[self.index].time_ms as f64;
+            },
+            ReplayCommand::Faster => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            ReplayCommand::Slower => self.speed = self.speed.saturating_sub(1),
+        }
+        self.last_tick = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, time::Duration};
+    use crate::{app::App, config::Config, record::Recorder, snapshot::SystemSnapshot, system::FakeSource};
+
+    // A session with one more process per frame, recorded a second apart
+    fn session(frames: usize) -> Session {
+        let path = std::env::temp_dir().join(format!("rust-htop-replay-{}-{}.rhr", frames, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut recorder = Recorder::create(path.clone(), u64::MAX).unwrap();
+        for n in 1..=frames {
+            let rows: Vec<(u32, &str, f32, u64)> = (1..=n as u32).map(|pid| (pid, "worker", 1.0, 1024)).collect();
+            let snapshot = SystemSnapshot::capture_all(&App::new(Config::default(), Box::new(FakeSource::with_processes(&rows))));
+            recorder.write(&snapshot, n as i64 * 1000).unwrap();
+        }
+        let session = record::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        session
+    }
+
+    #[test]
+    fn stepping_pauses_and_stays_in_range() {
+        let mut replay = ReplaySource::new(session(3)).unwrap();
+        assert_eq!(replay.processes().len(), 1);
+        replay.control(ReplayCommand::Step(1));
+        let status = replay.replay().unwrap();
+        assert!(!status.playing);
+        assert_eq!((status.index, status.frames, status.time_ms), (1, 3, 2000));
+        assert_eq!(replay.processes().len(), 2);
+        replay.control(ReplayCommand::Step(5));
+        assert_eq!(replay.replay().unwrap().index, 2);
+        replay.control(ReplayCommand::Step(-5));
+        assert_eq!(replay.replay().unwrap().index, 0);
+        // A paused replay doesn't move on refresh
+        replay.refresh();
+        assert_eq!(replay.replay().unwrap().index, 0);
+    }
+
+    #[test]
+    fn playback_follows_the_recorded_times_at_the_chosen_speed() {
+        let mut replay = ReplaySource::new(session(4)).unwrap();
+        for _ in 0..6 {
+            replay.control(ReplayCommand::Faster);
+        }
+        assert_eq!(replay.replay().unwrap().speed, 64.0);
+        // 64x turns the three seconds between the first and last frame into under 50ms
+        std::thread::sleep(Duration::from_millis(60));
+        replay.refresh();
+        let status = replay.replay().unwrap();
+        assert_eq!(status.index, 3);
+        assert!(!status.playing, "stops at the end");
+        replay.control(ReplayCommand::TogglePlay);
+        assert_eq!(replay.replay().unwrap().index, 0);
+        for _ in 0..20 {
+            replay.control(ReplayCommand::Slower);
+        }
+        assert_eq!(replay.replay().unwrap().speed, 0.25);
+        replay.refresh();
+        assert_eq!(replay.replay().unwrap().index, 0);
+    }
+
+    #[test]
+    fn empty_recordings_are_refused() {
+        assert!(ReplaySource::new(Session { frames: Vec::new(), truncated: true }).is_err());
+    }
+}
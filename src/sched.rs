@@ -0,0 +1,341 @@
+// Scheduling settings of other processes: which CPUs they may run on, how their disk I/O is
+// scheduled and which CPU scheduling policy they run under. CPU lists are typed and shown the
+// way taskset and /sys/devices/system/cpu print them, "0-3,8"; I/O priorities the way ionice
+// does, "best-effort 4"; policies the way chrt does, "SCHED_FIFO".
+
+use std::{fmt, io};
+
+use crate::actions::ActionError;
+
+// The most CPUs a cpu_set_t holds, and so the most that can be read or set
+pub const MAX_CPUS: usize = 1024;
+
+// "0-3, 8" on a machine with `count` CPUs. The result is sorted and has no repeats.
+pub fn parse_cpu_list(text: &str, count: usize) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+    for part in text.split(',').map(str::trim) {
+        let number = |n: &str| match n.trim() {
+            "" => Err(format!("{:?} is missing a CPU number", part)),
+            n => n.parse::<usize>().map_err(|_| format!("{:?} is not a CPU number", n)),
+        };
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None if part.is_empty() => return Err("empty entry in the CPU list".into()),
+            None => (number(part)?, number(part)?),
+        };
+        if first > last {
+            return Err(format!("{:?} runs backwards", part));
+        }
+        if last >= count.min(MAX_CPUS) {
+            return Err(format!("there is no CPU {}; this machine has {}", last, format_cpu_list(&(0..count).collect::<Vec<_>>())));
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+// Runs of consecutive CPUs are collapsed into ranges; `cpus` must be sorted
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+    let mut parts = Vec::new();
+    let mut rest = cpus;
+    while let Some(&first) = rest.first() {
+        let run = rest.iter().enumerate().take_while(|&(i, &cpu)| cpu == first + i).count();
+        parts.push(match run {
+            1 => first.to_string(),
+            _ => format!("{}-{}", first, first + run - 1),
+        });
+        rest = &rest[run..];
+    }
+    parts.join(",")
+}
+
+// The I/O scheduling classes from include/uapi/linux/ioprio.h
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoClass {
+    // Never set: best-effort, at the level that follows from the nice value
+    None,
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+// A class and, for realtime and best-effort, a level from 0 (first served) to 7
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IoPriority {
+    pub class: IoClass,
+    pub level: u8,
+}
+
+// The class sits above the low 13 bits, which hold the level
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+impl IoPriority {
+    // The value ioprio_get returns; None for a class this kernel interface doesn't define
+    pub fn decode(value: i32) -> Option<Self> {
+        let class = match value >> IOPRIO_CLASS_SHIFT {
+            0 => IoClass::None,
+            1 => IoClass::Realtime,
+            2 => IoClass::BestEffort,
+            3 => IoClass::Idle,
+            _ => return None,
+        };
+        Some(IoPriority { class, level: (value & ((1 << IOPRIO_CLASS_SHIFT) - 1)) as u8 })
+    }
+
+    pub fn encode(self) -> i32 {
+        let class = match self.class {
+            IoClass::None => 0,
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        };
+        (class << IOPRIO_CLASS_SHIFT) | i32::from(self.level)
+    }
+
+    // A process without a class of its own gets best-effort at (nice + 20) / 5
+    pub fn effective(self, nice: i32) -> Self {
+        match self.class {
+            IoClass::None => IoPriority { class: IoClass::BestEffort, level: ((nice.clamp(-20, 19) + 20) / 5) as u8 },
+            _ => self,
+        }
+    }
+}
+
+// "best-effort 4", "idle", "none"
+impl fmt::Display for IoPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.class {
+            IoClass::None => write!(f, "none"),
+            IoClass::Realtime => write!(f, "realtime {}", self.level),
+            IoClass::BestEffort => write!(f, "best-effort {}", self.level),
+            IoClass::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+// The CPU scheduling policies from include/uapi/linux/sched.h
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    Normal,
+    Fifo,
+    RoundRobin,
+    Batch,
+    Idle,
+    Deadline,
+}
+
+impl Policy {
+    // The number in the policy field of /proc/<pid>/stat; None for one this list doesn't know
+    pub fn from_number(number: u32) -> Option<Self> {
+        match number {
+            0 => Some(Policy::Normal),
+            1 => Some(Policy::Fifo),
+            2 => Some(Policy::RoundRobin),
+            3 => Some(Policy::Batch),
+            5 => Some(Policy::Idle),
+            6 => Some(Policy::Deadline),
+            _ => None,
+        }
+    }
+
+    // Runs ahead of every normal process whenever it wants the CPU, so one that spins can
+    // starve the rest of the machine
+    pub fn realtime(self) -> bool {
+        matches!(self, Policy::Fifo | Policy::RoundRobin | Policy::Deadline)
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Policy::Normal => "SCHED_OTHER",
+            Policy::Fifo => "SCHED_FIFO",
+            Policy::RoundRobin => "SCHED_RR",
+            Policy::Batch => "SCHED_BATCH",
+            Policy::Idle => "SCHED_IDLE",
+            Policy::Deadline => "SCHED_DEADLINE",
+        })
+    }
+}
+
+// A policy and its real-time priority, from 1 (lowest) to 99; 0 under the other policies
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scheduling {
+    pub policy: Policy,
+    pub priority: u32,
+}
+
+// "SCHED_FIFO priority 50", or just the policy where priorities don't apply
+impl fmt::Display for Scheduling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.policy {
+            Policy::Fifo | Policy::RoundRobin => write!(f, "{} priority {}", self.policy, self.priority),
+            _ => write!(f, "{}", self.policy),
+        }
+    }
+}
+
+// "idle", "best-effort 3" or just "3" for a best-effort level. Realtime isn't offered: it can
+// starve every other process's disk access, which is not something to set by accident.
+pub fn parse_io_priority(text: &str) -> Result<IoPriority, String> {
+    let text = text.trim();
+    if text == "idle" {
+        return Ok(IoPriority { class: IoClass::Idle, level: 0 });
+    }
+    let level = text.strip_prefix("best-effort").unwrap_or(text).trim();
+    match level.parse::<u8>() {
+        Ok(level @ 0..=7) => Ok(IoPriority { class: IoClass::BestEffort, level }),
+        _ => Err(format!("{:?} is not \"idle\" or a best-effort level from 0 to 7", text)),
+    }
+}
+
+// What went wrong, in the words the status line uses
+pub fn describe(error: &io::Error) -> String {
+    ActionError::from_io(error).to_string()
+}
+
+#[cfg(target_os = "linux")]
+pub fn affinity(pid: u32) -> io::Result<Vec<usize>> {
+    let mut set = empty_set();
+    // SAFETY: `set` is a whole cpu_set_t and the kernel writes at most its size
+    if unsafe { libc::sched_getaffinity(pid as libc::pid_t, std::mem::size_of_val(&set), &mut set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: every index is below CPU_SETSIZE, so CPU_ISSET stays inside the set
+    Ok((0..MAX_CPUS).filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) }).collect())
+}
+
+// `cpus` come from parse_cpu_list, so they are below MAX_CPUS
+#[cfg(target_os = "linux")]
+pub fn set_affinity(pid: u32, cpus: &[usize]) -> io::Result<()> {
+    let mut set = empty_set();
+    for &cpu in cpus.iter().filter(|&&cpu| cpu < MAX_CPUS) {
+        // SAFETY: the index was checked against the size of the set
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    // SAFETY: `set` is a whole cpu_set_t and is only read
+    if unsafe { libc::sched_setaffinity(pid as libc::pid_t, std::mem::size_of_val(&set), &set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// ioprio_get and ioprio_set have no libc wrappers, so they are called by number
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+
+#[cfg(target_os = "linux")]
+pub fn io_priority(pid: u32) -> io::Result<IoPriority> {
+    // SAFETY: both arguments are plain integers; nothing is passed by pointer
+    let value = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, pid as libc::c_long) };
+    if value < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    IoPriority::decode(value as i32).ok_or_else(|| io::Error::other(format!("unknown I/O priority {:#x}", value)))
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_io_priority(pid: u32, priority: IoPriority) -> io::Result<()> {
+    // SAFETY: as for ioprio_get
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, pid as libc::c_long, priority.encode() as libc::c_long) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn empty_set() -> libc::cpu_set_t {
+    // SAFETY: a cpu_set_t is an array of bits, and all zeros is the empty set
+    unsafe { std::mem::zeroed() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_lists_are_parsed_and_checked() {
+        assert_eq!(parse_cpu_list("0-3,8", 16), Ok(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cpu_list(" 5, 1 - 2,2 ", 8), Ok(vec![1, 2, 5]));
+        assert_eq!(parse_cpu_list("7", 8), Ok(vec![7]));
+        assert_eq!(parse_cpu_list("8", 8), Err("there is no CPU 8; this machine has 0-7".into()));
+        assert_eq!(parse_cpu_list("3-1", 8), Err("\"3-1\" runs backwards".into()));
+        assert_eq!(parse_cpu_list("0,,1", 8), Err("empty entry in the CPU list".into()));
+        assert_eq!(parse_cpu_list("", 8), Err("empty entry in the CPU list".into()));
+        assert_eq!(parse_cpu_list("one", 8), Err("\"one\" is not a CPU number".into()));
+        assert_eq!(parse_cpu_list("-1", 8), Err("\"-1\" is missing a CPU number".into()));
+    }
+
+    #[test]
+    fn cpu_lists_are_formatted_as_ranges() {
+        assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8]), "0-3,8");
+        assert_eq!(format_cpu_list(&[1, 3, 4]), "1,3-4");
+        assert_eq!(format_cpu_list(&[]), "");
+        let all: Vec<usize> = (0..64).collect();
+        assert_eq!(parse_cpu_list(&format_cpu_list(&all), 64), Ok(all));
+    }
+
+    #[test]
+    fn io_priorities_round_trip_through_the_kernel_encoding() {
+        let idle = IoPriority { class: IoClass::Idle, level: 0 };
+        assert_eq!(idle.encode(), 0x6000);
+        assert_eq!(IoPriority::decode(0x4004), Some(IoPriority { class: IoClass::BestEffort, level: 4 }));
+        assert_eq!(IoPriority::decode(0x2000), Some(IoPriority { class: IoClass::Realtime, level: 0 }));
+        assert_eq!(IoPriority::decode(0).map(|p| p.class), Some(IoClass::None));
+        assert_eq!(IoPriority::decode(0x8000), None);
+        for class in [IoClass::None, IoClass::Realtime, IoClass::BestEffort, IoClass::Idle] {
+            for level in 0..8 {
+                let priority = IoPriority { class, level };
+                assert_eq!(IoPriority::decode(priority.encode()), Some(priority));
+            }
+        }
+    }
+
+    #[test]
+    fn io_priorities_are_parsed_and_shown_like_ionice() {
+        assert_eq!(parse_io_priority("idle").map(|p| p.to_string()), Ok("idle".into()));
+        assert_eq!(parse_io_priority(" 7 "), Ok(IoPriority { class: IoClass::BestEffort, level: 7 }));
+        assert_eq!(parse_io_priority("best-effort 2").map(|p| p.to_string()), Ok("best-effort 2".into()));
+        assert_eq!(parse_io_priority("8"), Err("\"8\" is not \"idle\" or a best-effort level from 0 to 7".into()));
+        assert!(parse_io_priority("realtime 0").is_err());
+        // Without a class of its own, a process at nice 10 is served at best-effort 6
+        let unset = IoPriority { class: IoClass::None, level: 0 };
+        assert_eq!(unset.effective(10).to_string(), "best-effort 6");
+        assert_eq!(unset.effective(-20).to_string(), "best-effort 0");
+    }
+
+    #[test]
+    fn policies_are_numbered_and_named_like_chrt() {
+        assert_eq!(Policy::from_number(1), Some(Policy::Fifo));
+        assert_eq!(Policy::from_number(4), None);
+        let realtime: Vec<Policy> = (0..8).filter_map(Policy::from_number).filter(|p| p.realtime()).collect();
+        assert_eq!(realtime, [Policy::Fifo, Policy::RoundRobin, Policy::Deadline]);
+        assert_eq!(Scheduling { policy: Policy::RoundRobin, priority: 50 }.to_string(), "SCHED_RR priority 50");
+        assert_eq!(Scheduling { policy: Policy::Batch, priority: 0 }.to_string(), "SCHED_BATCH");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn this_process_can_lower_its_own_io_priority() {
+        let me = std::process::id();
+        let before = io_priority(me).unwrap();
+        let lower = IoPriority { class: IoClass::BestEffort, level: 7 };
+        set_io_priority(me, lower).unwrap();
+        assert_eq!(io_priority(me).unwrap(), lower);
+        set_io_priority(me, before).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn this_process_can_be_pinned_to_its_own_cpus() {
+        let me = std::process::id();
+        let cpus = affinity(me).unwrap();
+        assert!(!cpus.is_empty());
+        set_affinity(me, &cpus).unwrap();
+        assert_eq!(affinity(me).unwrap(), cpus);
+        // No CPUs at all is refused by the kernel
+        assert!(set_affinity(me, &[]).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use std::{collections::HashMap, io::{self, BufRead, BufReader, Write}, net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs}, sync::{atomic::{AtomicBool, Ordering}, Arc}, thread::{self, JoinHandle}, time::Duration};
+
+// Just enough HTTP/1.1 for the exporters: GET requests, one per connection, answered from
+// memory. Anything fancier belongs behind a real reverse proxy.
+
+// How often the accept loop checks whether it should stop
+const POLL: Duration = Duration::from_millis(50);
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Self { status: 200, content_type, body }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: format!("{}\n", message) }
+    }
+}
+
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+// Serves `handler` on a background thread until `shutdown` or drop
+pub struct Server {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn spawn(addr: impl ToSocketAddrs, handler: Handler) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        // Non-blocking so the loop notices `stop` without waiting for another connection
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        // A client that errors out only affects its own connection
+                        if let Err(e) = serve(stream, &handler) {
+                            log::debug!("request failed: {}", e);
+                        }
+                    },
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+                    Err(e) => {
+                        log::warn!("could not accept a connection: {}", e);
+                        thread::sleep(POLL);
+                    },
+                }
+            }
+        });
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    // The bound address, which has the real port when spawned on port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn serve(stream: TcpStream, handler: &Handler) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let response = match read_request(&mut reader) {
+        Ok(request) if request.method == "GET" || request.method == "HEAD" => handler(&request),
+        Ok(_) => Response::error(405, "only GET is supported"),
+        Err(_) => Response::error(400, "bad request"),
+    };
+    write_response(&stream, &response)
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(io::ErrorKind::InvalidData.into());
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+    // Headers are read and ignored; the limit stops a client from feeding us forever
+    let mut total = line.len();
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line)?;
+        total += n;
+        if n == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        if total > MAX_HEADER_BYTES {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+    }
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Ok(Request { method, path: path.to_string(), query: parse_query(query) })
+}
+
+pub fn parse_query(query: &str) -> HashMap<String, String> {
+    query.split('&').filter(|p| !p.is_empty()).map(|pair| {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        (percent_decode(k), percent_decode(v))
+    }).collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|h| u8::from_str_radix(h, 16).ok());
+                match hex {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    },
+                    None => out.push(b'%'),
+                }
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, reason, response.content_type, response.body.len(),
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, target: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: test\r\n\r\n", target).unwrap();
+        let mut text = String::new();
+        stream.read_to_string(&mut text).unwrap();
+        let status = text.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let body = text.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    #[test]
+    fn parses_query_strings() {
+        let query = parse_query("sort=mem&filter=web%20server&limit=5&flag&q=a+b&bad=%zz");
+        assert_eq!(query["sort"], "mem");
+        assert_eq!(query["filter"], "web server");
+        assert_eq!(query["limit"], "5");
+        assert_eq!(query["flag"], "");
+        assert_eq!(query["q"], "a b");
+        assert_eq!(query["bad"], "%zz");
+        assert!(parse_query("").is_empty());
+    }
+
+    #[test]
+    fn serves_requests_until_shut_down() {
+        let handler: Handler = Arc::new(|req: &Request| match req.path.as_str() {
+            "/hello" => Response::ok("text/plain", format!("hello {}", req.query.get("name").map(String::as_str).unwrap_or("?"))),
+            _ => Response::error(404, "not found"),
+        });
+        let mut server = Server::spawn("127.0.0.1:0", handler).unwrap();
+        let addr = server.local_addr();
+        assert_eq!(get(addr, "/hello?name=bob"), (200, "hello bob".to_string()));
+        assert_eq!(get(addr, "/nope").0, 404);
+        server.shutdown();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
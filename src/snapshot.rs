@@ -0,0 +1,451 @@
+use std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, PidExt};
+
+use crate::{app::{App, ProcInfo, ProcSample}, procfs::{self, CpuFreq, CpuTime, HugePages, MemInfo, Pressure}, system::{MemPressure, NetSample, PressureLevel, SystemSource, SystemTotals}};
+
+// The machine-readable form of one refresh, written by `--once --format json` and the other
+// exporters. Field names are part of the output format: add new fields rather than renaming
+// existing ones. Sizes are in bytes and CPU usage is a percentage of one core.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemSnapshot {
+    // RFC 3339, UTC
+    pub timestamp: String,
+    // Usage of each core in percent
+    #[serde(default)]
+    pub cpus: Vec<f32>,
+    pub memory: MemoryRecord,
+    pub load: LoadRecord,
+    // Seconds since the epoch; 0 when unknown, as in recordings that predate it
+    #[serde(default)]
+    pub boot_time: u64,
+    // Only present on Linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_time: Option<CpuTimeRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpufreq: Option<CpuFreqRecord>,
+    // Only present where the kernel reports pressure stall information
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pressure: Vec<PressureRecord>,
+    pub networks: Vec<NetworkRecord>,
+    // In the requested sort order, after filtering and --limit
+    pub processes: Vec<ProcessRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryRecord {
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    // 0 in recordings that predate it
+    #[serde(default)]
+    pub available_bytes: u64,
+    // From /proc/meminfo, only present on Linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffers_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dirty_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anon_huge_pages_bytes: Option<u64>,
+    // macOS's memory pressure, "normal", "warning" or "critical", and the percentage of memory
+    // it counts as free; only present on macOS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure_free_percent: Option<u8>,
+    // Only present when huge pages are set aside
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub huge_pages: Vec<HugePagesRecord>,
+    pub swap_total_bytes: u64,
+    pub swap_used_bytes: u64,
+    // Since boot, from /proc/vmstat, only present on Linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swapped_in_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swapped_out_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoadRecord {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+// Clock ticks of all CPUs together since boot; steal and guest are the virtualization figures
+// of procfs::CpuTime
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CpuTimeRecord {
+    pub total_ticks: u64,
+    pub steal_ticks: u64,
+    pub guest_ticks: u64,
+}
+
+// The cpufreq governor ("mixed" when the CPUs disagree) and whether turbo/boost is allowed,
+// where the system has a switch for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CpuFreqRecord {
+    pub governor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boost: Option<bool>,
+}
+
+// One pool of persistent huge pages, counted in pages of `size_bytes`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HugePagesRecord {
+    pub size_bytes: u64,
+    pub total: u64,
+    pub free: u64,
+    pub reserved: u64,
+    pub surplus: u64,
+}
+
+// Percent of the last 10 and 60 seconds that some task, or every task, waited for the
+// resource ("cpu", "memory" or "io")
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PressureRecord {
+    pub resource: String,
+    pub some_avg10: f32,
+    pub some_avg60: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_avg10: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_avg60: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkRecord {
+    pub name: String,
+    // Totals since the interface came up
+    pub received_bytes: u64,
+    pub transmitted_bytes: u64,
+    #[serde(default)]
+    pub received_errors: u64,
+    #[serde(default)]
+    pub transmitted_errors: u64,
+    // Left out where the source doesn't know them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_dropped: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transmitted_dropped: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessRecord {
+    pub pid: u32,
+    pub parent_pid: Option<u32>,
+    pub name: String,
+    // None when the owner has no account name
+    pub user: Option<String>,
+    // One letter as shown by ps: R running, S sleeping, D disk wait, Z zombie, T stopped, ...
+    pub state: String,
+    pub cpu_percent: f32,
+    pub mem_bytes: u64,
+    // Lets a remote viewer hide kernel threads; older agents don't send it
+    #[serde(default)]
+    pub kernel_thread: bool,
+    // Seconds since the Unix epoch; with the PID it tells a process from a later one that
+    // reused its PID. 0 when unknown.
+    #[serde(default)]
+    pub start_time: u64,
+    // Open file descriptors, only present when they were counted for the FDS column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_fds: Option<u32>,
+    // Open TCP and UDP sockets, only present when they were counted for the CONN column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<u32>,
+    // Bytes queued in those sockets, only present when they were counted for the NET column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub net_queued_bytes: Option<u64>,
+    // Only present when it was read for the SWAP column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swap_bytes: Option<u64>,
+    // Totals since the process started, only present when read for the VCSW and IVCSW columns;
+    // the columns show the change between two snapshots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voluntary_ctxt_switches: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonvoluntary_ctxt_switches: Option<u64>,
+    // Page fault totals, only present when read for the MINFLT and MAJFLT columns
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minor_faults: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub major_faults: Option<u64>,
+    // The CPU it last ran on, only present when read for the CORE column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processor: Option<usize>,
+    // The container's name or short ID, only present when read for the CONTAINER column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    // The executable and the arguments, left out when they can't be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cmdline: Vec<String>,
+}
+
+impl ProcessRecord {
+    pub fn new(p: &ProcInfo) -> Self {
+        Self {
+            pid: p.pid.as_u32(),
+            parent_pid: p.parent.map(|pid| pid.as_u32()),
+            name: p.name.clone(),
+            user: p.user.clone(),
+            state: p.state.to_string(),
+            cpu_percent: p.cpu,
+            mem_bytes: p.mem,
+            kernel_thread: p.kernel_thread,
+            start_time: p.start_time,
+            open_fds: p.fds,
+            connections: p.conns,
+            net_queued_bytes: p.net,
+            swap_bytes: p.swap,
+            voluntary_ctxt_switches: p.voluntary_total,
+            nonvoluntary_ctxt_switches: p.involuntary_total,
+            minor_faults: p.minor_total,
+            major_faults: p.major_total,
+            processor: p.processor,
+            container: p.container.clone(),
+            exe: p.exe.clone(),
+            cmdline: p.cmd.clone(),
+        }
+    }
+
+    // The search box rule: a case-insensitive match on the name, executable path or command
+    // line, or part of the PID. `query` must already be lowercase.
+    pub fn matches(&self, query: &str) -> bool {
+        query.is_empty()
+            || self.name.to_lowercase().contains(query)
+            || self.exe.as_ref().is_some_and(|exe| exe.to_lowercase().contains(query))
+            || self.cmdline.join(" ").to_lowercase().contains(query)
+            || self.pid.to_string().contains(query)
+    }
+}
+
+// The latest snapshot of every process, shared with the exporter threads. The main loop
+// replaces it after each refresh, so serving a request never touches sysinfo.
+pub type SharedSnapshot = Arc<RwLock<SystemSnapshot>>;
+
+impl SystemSnapshot {
+    // The first `limit` visible rows of `app`, in display order
+    pub fn capture(app: &App, limit: Option<usize>) -> Self {
+        let rows = &app.visible[..limit.unwrap_or(usize::MAX).min(app.visible.len())];
+        Self::from_rows(app, rows)
+    }
+
+    // Every process regardless of the view, by PID
+    pub fn capture_all(app: &App) -> Self {
+        let mut rows: Vec<usize> = (0..app.processes.len()).collect();
+        rows.sort_by_key(|&i| app.processes[i].pid);
+        Self::from_rows(app, &rows)
+    }
+
+    fn from_rows(app: &App, rows: &[usize]) -> Self {
+        let totals = &app.totals;
+        let mut networks: Vec<NetworkRecord> = app.source.networks().iter().map(|n| NetworkRecord {
+            name: n.name.to_string(),
+            received_bytes: n.total_received,
+            transmitted_bytes: n.total_transmitted,
+            received_errors: n.errors.0,
+            transmitted_errors: n.errors.1,
+            received_dropped: n.dropped.map(|d| d.0),
+            transmitted_dropped: n.dropped.map(|d| d.1),
+            up: n.up,
+        }).collect();
+        // sysinfo keeps interfaces in a hash map
+        networks.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            cpus: totals.cpus.clone(),
+            memory: MemoryRecord {
+                total_bytes: totals.mem_total,
+                used_bytes: totals.mem_used,
+                available_bytes: totals.mem_available,
+                cached_bytes: totals.mem_detail.map(|m| m.cached),
+                buffers_bytes: totals.mem_detail.map(|m| m.buffers),
+                dirty_bytes: totals.mem_detail.map(|m| m.dirty),
+                anon_huge_pages_bytes: totals.mem_detail.and_then(|m| m.anon_huge_pages),
+                pressure: totals.mem_pressure.map(|p| p.level.name().to_string()),
+                pressure_free_percent: totals.mem_pressure.map(|p| p.free),
+                huge_pages: totals.huge_pages.iter().map(|h| HugePagesRecord { size_bytes: h.size, total: h.total, free: h.free, reserved: h.reserved, surplus: h.surplus }).collect(),
+                swap_total_bytes: totals.swap_total,
+                swap_used_bytes: totals.swap_used,
+                swapped_in_bytes: totals.swapped.map(|s| s.0),
+                swapped_out_bytes: totals.swapped.map(|s| s.1),
+            },
+            load: LoadRecord { one: totals.load[0], five: totals.load[1], fifteen: totals.load[2] },
+            boot_time: totals.boot_time,
+            cpu_time: totals.cpu_time.map(|t| CpuTimeRecord { total_ticks: t.total, steal_ticks: t.steal, guest_ticks: t.guest }),
+            cpufreq: totals.cpufreq.as_ref().map(|f| CpuFreqRecord { governor: f.governor.clone(), boost: f.boost }),
+            pressure: totals.pressure.iter().map(|&(resource, p)| PressureRecord {
+                resource: resource.to_string(),
+                some_avg10: p.some.0,
+                some_avg60: p.some.1,
+                full_avg10: p.full.map(|f| f.0),
+                full_avg60: p.full.map(|f| f.1),
+            }).collect(),
+            networks,
+            processes: rows.iter().map(|&i| ProcessRecord::new(&app.processes[i])).collect(),
+        }
+    }
+}
+
+// Serves a snapshot that was sampled elsewhere, by a remote agent or in a recording, the same
+// way sysinfo data is served. Empty until a snapshot is set; `refresh` leaves it alone.
+#[derive(Default)]
+pub struct SnapshotSource {
+    pub snapshot: Option<SystemSnapshot>,
+}
+
+impl SystemSource for SnapshotSource {
+    fn refresh(&mut self) {}
+
+    fn processes(&self) -> Vec<ProcSample<'_>> {
+        let Some(snapshot) = &self.snapshot else { return Vec::new() };
+        snapshot.processes.iter().map(|p| ProcSample {
+            pid: Pid::from_u32(p.pid),
+            name: &p.name,
+            cpu: p.cpu_percent,
+            mem: p.mem_bytes,
+            parent: p.parent_pid.map(Pid::from_u32),
+            kernel_thread: p.kernel_thread,
+            user: p.user.as_deref(),
+            state: p.state.chars().next().unwrap_or('?'),
+            start_time: p.start_time,
+            fds: p.open_fds,
+            conns: p.connections,
+            net: p.net_queued_bytes,
+            swap: p.swap_bytes,
+            voluntary_switches: p.voluntary_ctxt_switches,
+            involuntary_switches: p.nonvoluntary_ctxt_switches,
+            minor_faults: p.minor_faults,
+            major_faults: p.major_faults,
+            processor: p.processor,
+            scheduling: None,
+            nice: None,
+            container: p.container.as_deref(),
+            exe: p.exe.as_deref(),
+            cmd: &p.cmdline,
+        }).collect()
+    }
+
+    fn networks(&self) -> Vec<NetSample<'_>> {
+        let Some(snapshot) = &self.snapshot else { return Vec::new() };
+        snapshot.networks.iter().map(|n| NetSample {
+            name: &n.name,
+            total_received: n.received_bytes,
+            total_transmitted: n.transmitted_bytes,
+            errors: (n.received_errors, n.transmitted_errors),
+            dropped: n.received_dropped.zip(n.transmitted_dropped),
+            up: n.up,
+        }).collect()
+    }
+
+    fn totals(&self) -> SystemTotals {
+        let Some(snapshot) = &self.snapshot else { return SystemTotals::default() };
+        SystemTotals {
+            cpus: snapshot.cpus.clone(),
+            cpu_time: snapshot.cpu_time.as_ref().map(|t| CpuTime { total: t.total_ticks, steal: t.steal_ticks, guest: t.guest_ticks }),
+            cpufreq: snapshot.cpufreq.as_ref().map(|f| CpuFreq { governor: f.governor.clone(), boost: f.boost }),
+            mem_total: snapshot.memory.total_bytes,
+            mem_used: snapshot.memory.used_bytes,
+            mem_available: snapshot.memory.available_bytes,
+            mem_detail: match (snapshot.memory.cached_bytes, snapshot.memory.buffers_bytes, snapshot.memory.dirty_bytes) {
+                (Some(cached), Some(buffers), Some(dirty)) => Some(MemInfo {
+                    cached,
+                    buffers,
+                    dirty,
+                    huge_pages: None,
+                    anon_huge_pages: snapshot.memory.anon_huge_pages_bytes,
+                }),
+                _ => None,
+            },
+            mem_pressure: snapshot.memory.pressure.as_deref().and_then(PressureLevel::from_name).zip(snapshot.memory.pressure_free_percent).map(|(level, free)| MemPressure { level, free }),
+            huge_pages: snapshot.memory.huge_pages.iter().map(|h| HugePages { size: h.size_bytes, total: h.total, free: h.free, reserved: h.reserved, surplus: h.surplus }).collect(),
+            swap_total: snapshot.memory.swap_total_bytes,
+            swap_used: snapshot.memory.swap_used_bytes,
+            swapped: snapshot.memory.swapped_in_bytes.zip(snapshot.memory.swapped_out_bytes),
+            load: [snapshot.load.one, snapshot.load.five, snapshot.load.fifteen],
+            boot_time: snapshot.boot_time,
+            // Resources this build doesn't know of are left out
+            pressure: snapshot.pressure.iter().filter_map(|p| {
+                let resource = procfs::PRESSURE_RESOURCES.into_iter().find(|&r| r == p.resource)?;
+                Some((resource, Pressure { some: (p.some_avg10, p.some_avg60), full: p.full_avg10.zip(p.full_avg60) }))
+            }).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, system::{FakeNetwork, FakeSource, SystemTotals}};
+
+    fn app() -> App {
+        let mut source = FakeSource::with_processes(&[(1, "init", 0.5, 4096), (200, "postgres", 42.0, 1 << 20), (300, "bash", 1.0, 0)]);
+        source.processes[1].user = Some("postgres".into());
+        source.processes[1].parent = Some(1);
+        source.processes[1].state = 'R';
+        source.networks.push(FakeNetwork { name: "eth0".into(), total_received: 10, total_transmitted: 20, errors: (1, 0), dropped: Some((3, 4)), up: Some(true) });
+        source.totals = SystemTotals { cpus: vec![12.5, 3.0], cpu_time: Some(CpuTime { total: 90_000, steal: 3600, guest: 0 }), cpufreq: Some(CpuFreq { governor: "schedutil".into(), boost: Some(true) }), mem_total: 8 << 30, mem_used: 2 << 30, mem_available: 5 << 30, mem_detail: Some(MemInfo { cached: 3 << 30, buffers: 1 << 20, dirty: 4096, huge_pages: None, anon_huge_pages: Some(1 << 21) }), mem_pressure: Some(MemPressure { level: PressureLevel::Warning, free: 35 }), huge_pages: vec![HugePages { size: 2 << 20, total: 64, free: 52, reserved: 3, surplus: 0 }], swap_total: 0, swap_used: 0, swapped: Some((4096, 1 << 20)), load: [0.5, 0.25, 0.1], boot_time: 1_700_000_000, pressure: vec![("io", Pressure { some: (12.5, 3.0), full: None })] };
+        App::new(Config::default(), Box::new(source))
+    }
+
+    #[test]
+    fn json_round_trips_with_the_documented_fields() {
+        let snapshot = SystemSnapshot::capture(&app(), Some(2));
+        let text = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<SystemSnapshot>(&text).unwrap(), snapshot);
+
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        for key in ["timestamp", "cpus", "memory", "load", "networks", "processes"] {
+            assert!(value.get(key).is_some(), "missing `{}`", key);
+        }
+        assert_eq!(value["memory"]["total_bytes"], 8u64 << 30);
+        assert_eq!((&value["memory"]["pressure"], &value["memory"]["pressure_free_percent"]), (&"warning".into(), &35.into()));
+        assert_eq!(value["load"]["one"], 0.5);
+        assert_eq!(value["cpus"][0], 12.5);
+        assert_eq!(value["networks"][0]["received_bytes"], 10);
+        let procs = value["processes"].as_array().unwrap();
+        assert_eq!(procs.len(), 2);
+        let top = &procs[0];
+        for key in ["pid", "parent_pid", "name", "user", "state", "cpu_percent", "mem_bytes", "kernel_thread", "start_time"] {
+            assert!(top.get(key).is_some(), "missing process field `{}`", key);
+        }
+        assert_eq!(top["pid"], 200);
+        assert_eq!(top["parent_pid"], 1);
+        assert_eq!(top["user"], "postgres");
+        assert_eq!(top["state"], "R");
+        assert_eq!(procs[1]["user"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn follows_the_view_filter_and_sort() {
+        let mut app = app();
+        app.search_query = "s".into();
+        app.sort_by = crate::app::SortBy::Name;
+        app.descending = false;
+        app.update_view();
+        let names: Vec<String> = SystemSnapshot::capture(&app, None).processes.into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["bash", "postgres"]);
+        let pids: Vec<u32> = SystemSnapshot::capture_all(&app).processes.into_iter().map(|p| p.pid).collect();
+        assert_eq!(pids, vec![1, 200, 300]);
+    }
+
+    #[test]
+    fn a_snapshot_source_reproduces_the_captured_app() {
+        let original = app();
+        let source = SnapshotSource { snapshot: Some(SystemSnapshot::capture_all(&original)) };
+        let copy = App::new(Config::default(), Box::new(source));
+        let rows = |app: &App| app.visible.iter().map(|&i| {
+            let p = &app.processes[i];
+            (p.pid, p.name.clone(), p.cpu_text.clone(), p.mem, p.parent, p.user.clone(), p.state)
+        }).collect::<Vec<_>>();
+        assert_eq!(rows(&copy), rows(&original));
+        assert_eq!(copy.totals, original.totals);
+        assert_eq!(copy.net.interfaces, original.net.interfaces);
+    }
+}
@@ -0,0 +1,713 @@
+use sysinfo::{CpuExt, CpuRefreshKind, NetworkExt, NetworksExt, Pid, PidExt, ProcessExt, ProcessRefreshKind, ProcessStatus, RefreshKind, System, SystemExt, UserExt};
+
+use std::{collections::HashMap, path::Path, time::{Duration, Instant}};
+use crate::{actions::ActionError, app::ProcSample, columns::Column, conns::{self, Connection, Listener, SocketOwners}, containers::{self, Resolver}, fds::FdCounter, logins::{self, Login}, procfs::{self, Capabilities, CpuFreq, CpuTime, HugePages, Limit, Link, MemInfo, MemoryMap, Pressure, Stat, Status}, remote::RemoteStatus, replay::{ReplayCommand, ReplayStatus}, sched::{IoPriority, Scheduling}};
+#[cfg(target_os = "linux")]
+use crate::sched;
+#[cfg(target_os = "macos")]
+use crate::macos;
+
+// How often the cpufreq governor and boost switch are read again
+const CPUFREQ_INTERVAL: Duration = Duration::from_secs(30);
+
+// Per-interface counters as reported by the latest refresh. The pairs are (received,
+// transmitted), all since the interface came up.
+pub struct NetSample<'a> {
+    pub name: &'a str,
+    pub total_received: u64,
+    pub total_transmitted: u64,
+    pub errors: (u64, u64),
+    // None where the source doesn't know; see procfs::Link
+    pub dropped: Option<(u64, u64)>,
+    pub up: Option<bool>,
+}
+
+// System-wide figures as of the latest refresh. Memory is in bytes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemTotals {
+    // Usage of each core in percent
+    pub cpus: Vec<f32>,
+    // Since boot; None where the source has no /proc/stat
+    pub cpu_time: Option<CpuTime>,
+    // None where the source has no cpufreq
+    pub cpufreq: Option<CpuFreq>,
+    pub mem_total: u64,
+    pub mem_used: u64,
+    // What could be handed to programs without swapping, cache included; 0 when unknown
+    pub mem_available: u64,
+    // None where the source has no /proc/meminfo
+    pub mem_detail: Option<MemInfo>,
+    // macOS's own measure; None elsewhere
+    pub mem_pressure: Option<MemPressure>,
+    // Every huge page size with a pool set aside, smallest first; empty when none is
+    pub huge_pages: Vec<HugePages>,
+    pub swap_total: u64,
+    pub swap_used: u64,
+    // Bytes read back in from swap and written out to it since boot; None where the source
+    // has no /proc/vmstat
+    pub swapped: Option<(u64, u64)>,
+    // 1, 5 and 15 minute load averages
+    pub load: [f64; 3],
+    // Seconds since the epoch; 0 when the source doesn't know it
+    pub boot_time: u64,
+    // Stall information for the resources in procfs::PRESSURE_RESOURCES the kernel reports on
+    pub pressure: Vec<(&'static str, Pressure)>,
+}
+
+// How hard macOS is pushed for memory, the headline of Activity Monitor's memory tab. It
+// compresses and swaps long before "used" reaches the total, so used memory says little there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemPressure {
+    pub level: PressureLevel,
+    // The share of memory the kernel counts as free, in percent
+    pub free: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PressureLevel {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl PressureLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            PressureLevel::Normal => "normal",
+            PressureLevel::Warning => "warning",
+            PressureLevel::Critical => "critical",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [PressureLevel::Normal, PressureLevel::Warning, PressureLevel::Critical].into_iter().find(|l| l.name() == name)
+    }
+}
+
+// The per-process readings that cost extra /proc reads. The app turns on the ones its columns
+// and sort order need before each refresh; sources that can't provide them ignore this.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Extras {
+    pub fds: bool,
+    pub sockets: bool,
+    // The bytes queued in those sockets, for the net column
+    pub net: bool,
+    // /proc/<pid>/status, for the swap and context switch columns
+    pub status: bool,
+    // /proc/<pid>/stat, for the page fault and CORE columns and the RT badge
+    pub stat: bool,
+    // /proc/<pid>/cgroup and the container runtime, for the container column
+    pub containers: bool,
+}
+
+// Scheduling settings of one process for the details popup. Each is None where the source
+// can't read them at all (another machine, or not Linux), and an error when this process
+// couldn't be read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Details {
+    pub nice: Option<i32>,
+    pub scheduling: Option<Scheduling>,
+    // The CPUs it may run on
+    pub affinity: Option<Result<Vec<usize>, String>>,
+    pub io_priority: Option<Result<IoPriority, String>>,
+    // From /proc/<pid>/status; None when it couldn't be read
+    pub capabilities: Option<Capabilities>,
+    pub memory: Option<Result<MemoryMap, String>>,
+    pub limits: Option<Result<Vec<Limit>, String>>,
+}
+
+// Where the app gets its data from. The live implementation wraps sysinfo; tests inject
+// fixed data through `FakeSource`.
+pub trait SystemSource {
+    fn refresh(&mut self);
+    fn processes(&self) -> Vec<ProcSample<'_>>;
+    fn networks(&self) -> Vec<NetSample<'_>>;
+    fn totals(&self) -> SystemTotals;
+
+    // Set when the data comes from an agent on another machine
+    fn remote(&self) -> Option<RemoteStatus> {
+        None
+    }
+
+    // Set when playing back a recording
+    fn replay(&self) -> Option<ReplayStatus> {
+        None
+    }
+
+    // Playback controls; sources that aren't recordings ignore them
+    fn control(&mut self, _command: ReplayCommand) {}
+
+    fn want(&mut self, _extras: Extras) {}
+
+    // The TCP and UDP connections of one process, or why they can't be listed
+    fn connections(&self, _pid: Pid) -> Result<Vec<Connection>, String> {
+        Err("only processes on this machine can be listed".into())
+    }
+
+    // The sockets listening on the machine, with the processes holding them
+    fn listeners(&mut self) -> Result<Vec<Listener>, String> {
+        Err("only sockets on this machine can be listed".into())
+    }
+
+    // The login sessions in progress on the machine
+    fn logins(&self) -> Result<Vec<Login>, String> {
+        Err("only logins on this machine can be listed".into())
+    }
+
+    fn details(&self, _pid: Pid) -> Details {
+        Details::default()
+    }
+
+    // Whether the processes can carry the column's values at all. The table leaves the columns
+    // that never would out rather than showing "-" on every row.
+    fn has_column(&self, _column: Column) -> bool {
+        true
+    }
+
+    // Whether the process is still running, checked before asking what to change it to. A
+    // source that can't tell says yes, and the change itself finds out.
+    fn exists(&self, _pid: Pid) -> bool {
+        true
+    }
+
+    // Restricts a process to `cpus`, or says why it can't be
+    fn set_affinity(&mut self, _pid: Pid, _cpus: &[usize]) -> Result<(), ActionError> {
+        Err(ActionError::Unsupported)
+    }
+
+    fn set_io_priority(&mut self, _pid: Pid, _priority: IoPriority) -> Result<(), ActionError> {
+        Err(ActionError::Unsupported)
+    }
+}
+
+pub struct SysinfoSource {
+    sys: System,
+    full_refresh: bool,
+    extras: Extras,
+    fds: FdCounter,
+    // By PID, read on each refresh while `extras.status` is on
+    status: HashMap<Pid, Status>,
+    stat: HashMap<Pid, Stat>,
+    // The container ID of each process with its start time, read once per process
+    container_ids: HashMap<Pid, (u64, Option<String>)>,
+    // What the container column shows, by PID
+    containers: HashMap<Pid, String>,
+    resolver: Resolver,
+    // By interface name, read on each refresh
+    links: HashMap<String, Link>,
+    pressure: Vec<(&'static str, Pressure)>,
+    meminfo: Option<MemInfo>,
+    huge_pages: Vec<HugePages>,
+    swapped: Option<(u64, u64)>,
+    cpu_time: Option<CpuTime>,
+    // With when it was read; the governor and boost switch are changed by hand, if ever
+    cpufreq: Option<(Instant, Option<CpuFreq>)>,
+    mem_pressure: Option<MemPressure>,
+    sockets: SocketOwners,
+}
+
+impl SysinfoSource {
+    pub fn new(full_refresh: bool) -> Self {
+        let sys = if full_refresh {
+            System::new_all()
+        } else {
+            System::new_with_specifics(RefreshKind::new()
+                .with_processes(process_refresh_kind())
+                .with_cpu(CpuRefreshKind::new().with_cpu_usage())
+                .with_memory()
+                .with_users_list()
+                .with_networks()
+                .with_networks_list())
+        };
+        Self { sys, full_refresh, extras: Extras::default(), fds: FdCounter::default(), status: HashMap::new(), stat: HashMap::new(), container_ids: HashMap::new(), containers: HashMap::new(), resolver: Resolver::default(), links: HashMap::new(), pressure: Vec::new(), meminfo: None, huge_pages: Vec::new(), swapped: None, cpu_time: None, cpufreq: None, mem_pressure: None, sockets: SocketOwners::default() }
+    }
+}
+
+impl SysinfoSource {
+    // A process stays in its container, so its cgroup is read when it is first seen (or its
+    // PID is reused), and the names come from the resolver as its answers arrive
+    fn refresh_containers(&mut self) {
+        self.containers.clear();
+        if !self.extras.containers || !cfg!(target_os = "linux") {
+            self.container_ids.clear();
+            return;
+        }
+        let processes = self.sys.processes();
+        self.container_ids.retain(|pid, (start_time, _)| processes.get(pid).is_some_and(|p| p.start_time() == *start_time));
+        let now = Instant::now();
+        self.resolver.poll(now);
+        for (&pid, p) in processes {
+            let (_, id) = self.container_ids.entry(pid).or_insert_with(|| (p.start_time(), containers::read_container_id(Path::new("/proc"), pid)));
+            if let Some(id) = id {
+                self.containers.insert(pid, self.resolver.name(id, now));
+            }
+        }
+    }
+}
+
+fn process_refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::new().with_cpu().with_user()
+}
+
+impl SystemSource for SysinfoSource {
+    // Only refresh what is displayed or exported: processes, memory and the network counters.
+    // `full_refresh` keeps the old refresh_all behaviour around in case something is missing.
+    fn refresh(&mut self) {
+        if self.full_refresh {
+            self.sys.refresh_all();
+        } else {
+            self.sys.refresh_cpu();
+            self.sys.refresh_processes_specifics(process_refresh_kind());
+            self.sys.refresh_memory();
+            self.sys.refresh_networks();
+        }
+        self.fds.poll();
+        self.status.clear();
+        self.stat.clear();
+        if cfg!(target_os = "linux") {
+            let proc = Path::new("/proc");
+            let pids = self.sys.processes().keys();
+            if self.extras.status {
+                self.status.extend(pids.clone().filter_map(|&pid| Some((pid, procfs::read_status(proc, pid)?))));
+            }
+            if self.extras.stat {
+                self.stat.extend(pids.filter_map(|&pid| Some((pid, procfs::read_stat(proc, pid)?))));
+            }
+        }
+        self.refresh_containers();
+        if cfg!(target_os = "linux") {
+            let class_net = Path::new("/sys/class/net");
+            self.links = self.sys.networks().iter().map(|(name, _)| (name.clone(), procfs::read_link(class_net, name))).collect();
+            let dir = Path::new("/proc/pressure");
+            self.meminfo = procfs::read_meminfo(Path::new("/proc"));
+            self.huge_pages = procfs::read_huge_pages(Path::new("/sys/kernel/mm/hugepages"));
+            // Without sysfs only the default size is known
+            if self.huge_pages.is_empty() {
+                self.huge_pages.extend(self.meminfo.and_then(|m| m.huge_pages));
+            }
+            self.cpu_time = procfs::read_cpu_time(Path::new("/proc"));
+            if self.cpufreq.as_ref().is_none_or(|(read, _)| read.elapsed() >= CPUFREQ_INTERVAL) {
+                self.cpufreq = Some((Instant::now(), procfs::read_cpufreq(Path::new("/sys/devices/system/cpu"))));
+            }
+            let page = page_size();
+            self.swapped = procfs::read_vmstat(Path::new("/proc")).map(|(pages_in, pages_out)| (pages_in * page, pages_out * page));
+            self.pressure = procfs::PRESSURE_RESOURCES.iter().filter_map(|&resource| Some((resource, procfs::read_pressure(dir, resource)?))).collect();
+        }
+        #[cfg(target_os = "macos")]
+        {
+            self.mem_pressure = macos::memory_pressure();
+        }
+    }
+
+    fn processes(&self) -> Vec<ProcSample<'_>> {
+        self.sys.processes().values().map(|p| ProcSample {
+            pid: p.pid(),
+            name: p.name(),
+            cpu: p.cpu_usage(),
+            mem: process_memory(p),
+            parent: p.parent(),
+            kernel_thread: is_kernel_thread(p.pid(), p.parent()),
+            // The users list is read at startup; accounts created later show no name
+            user: p.user_id().and_then(|uid| self.sys.get_user_by_id(uid).map(|u| u.name()).or_else(|| service_account(uid))),
+            state: state_letter(p.status()),
+            start_time: p.start_time(),
+            fds: self.fds.counts.get(&p.pid()).copied(),
+            conns: self.fds.sockets.get(&p.pid()).copied(),
+            net: self.fds.queues.get(&p.pid()).map(|q| q.total()),
+            swap: self.status.get(&p.pid()).and_then(|s| s.swap),
+            voluntary_switches: self.status.get(&p.pid()).and_then(|s| s.voluntary_switches),
+            involuntary_switches: self.status.get(&p.pid()).and_then(|s| s.involuntary_switches),
+            minor_faults: self.stat.get(&p.pid()).map(|s| s.minor_faults),
+            major_faults: self.stat.get(&p.pid()).map(|s| s.major_faults),
+            processor: self.stat.get(&p.pid()).and_then(|s| s.processor),
+            scheduling: self.stat.get(&p.pid()).and_then(|s| s.scheduling),
+            nice: self.stat.get(&p.pid()).map(|s| s.nice),
+            container: self.containers.get(&p.pid()).map(String::as_str),
+            // Empty for kernel threads, and for other users' processes without root
+            exe: p.exe().to_str().filter(|exe| !exe.is_empty()),
+            cmd: p.cmd(),
+        }).collect()
+    }
+
+    fn networks(&self) -> Vec<NetSample<'_>> {
+        self.sys.networks().iter().map(|(iface, data)| {
+            let link = self.links.get(iface).copied().unwrap_or_default();
+            NetSample {
+                name: iface,
+                total_received: data.total_received(),
+                total_transmitted: data.total_transmitted(),
+                errors: (data.total_errors_on_received(), data.total_errors_on_transmitted()),
+                dropped: link.dropped,
+                up: link.up,
+            }
+        }).collect()
+    }
+
+    fn want(&mut self, extras: Extras) {
+        self.extras = extras;
+        self.fds.set_active(extras.fds, extras.sockets, extras.net);
+    }
+
+    fn connections(&self, pid: Pid) -> Result<Vec<Connection>, String> {
+        if !cfg!(target_os = "linux") {
+            return Err("connections are only listed on Linux".into());
+        }
+        conns::for_process(Path::new("/proc"), pid.as_u32()).ok_or_else(|| "its descriptors can't be read; try running as root".into())
+    }
+
+    fn listeners(&mut self) -> Result<Vec<Listener>, String> {
+        if !cfg!(target_os = "linux") {
+            return Err("listening sockets are only listed on Linux".into());
+        }
+        Ok(self.sockets.listeners(Path::new("/proc")))
+    }
+
+    fn logins(&self) -> Result<Vec<Login>, String> {
+        logins::read().map_err(|e| format!("the login records can't be read: {}", e))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn details(&self, pid: Pid) -> Details {
+        let stat = procfs::read_stat(Path::new("/proc"), pid);
+        Details {
+            nice: stat.map(|s| s.nice),
+            scheduling: stat.and_then(|s| s.scheduling),
+            affinity: Some(sched::affinity(pid.as_u32()).map_err(|e| sched::describe(&e))),
+            io_priority: Some(sched::io_priority(pid.as_u32()).map_err(|e| sched::describe(&e))),
+            capabilities: procfs::read_status(Path::new("/proc"), pid).and_then(|s| s.capabilities),
+            memory: Some(procfs::read_memory_map(Path::new("/proc"), pid).map_err(|e| describe_read_error(&e))),
+            limits: Some(procfs::read_limits(Path::new("/proc"), pid).map_err(|e| describe_read_error(&e))),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn exists(&self, pid: Pid) -> bool {
+        Path::new("/proc").join(pid.to_string()).exists()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_affinity(&mut self, pid: Pid, cpus: &[usize]) -> Result<(), ActionError> {
+        sched::set_affinity(pid.as_u32(), cpus).map_err(|e| ActionError::from_io(&e))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_io_priority(&mut self, pid: Pid, priority: IoPriority) -> Result<(), ActionError> {
+        sched::set_io_priority(pid.as_u32(), priority).map_err(|e| ActionError::from_io(&e))
+    }
+
+    // Every column after the memory one is read from /proc
+    fn has_column(&self, column: Column) -> bool {
+        match column {
+            Column::Pid | Column::Name | Column::Cpu | Column::Mem => true,
+            Column::Net => cfg!(all(target_os = "linux", feature = "netio")),
+            _ => cfg!(target_os = "linux"),
+        }
+    }
+
+    fn totals(&self) -> SystemTotals {
+        let load = self.sys.load_average();
+        SystemTotals {
+            cpus: self.sys.cpus().iter().map(|c| c.cpu_usage()).collect(),
+            cpu_time: self.cpu_time,
+            cpufreq: self.cpufreq.as_ref().and_then(|(_, freq)| freq.clone()),
+            mem_total: self.sys.total_memory(),
+            mem_used: self.sys.used_memory(),
+            mem_available: self.sys.available_memory(),
+            mem_detail: self.meminfo,
+            mem_pressure: self.mem_pressure,
+            huge_pages: self.huge_pages.clone(),
+            swap_total: self.sys.total_swap(),
+            swap_used: self.sys.used_swap(),
+            swapped: self.swapped,
+            load: [load.one, load.five, load.fifteen],
+            boot_time: self.sys.boot_time(),
+            pressure: self.pressure.clone(),
+        }
+    }
+}
+
+// On macOS the resident size counts shared frameworks in full for every process, which makes
+// the Memory column add up to several times the machine; the footprint doesn't
+#[cfg(target_os = "macos")]
+fn process_memory(process: &sysinfo::Process) -> u64 {
+    macos::footprint(process.pid().as_u32()).unwrap_or_else(|| process.memory())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn process_memory(process: &sysinfo::Process) -> u64 {
+    process.memory()
+}
+
+// /proc/vmstat counts swap in pages
+#[cfg(target_os = "linux")]
+fn page_size() -> u64 {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn page_size() -> u64 {
+    4096
+}
+
+#[cfg(target_os = "linux")]
+fn describe_read_error(error: &std::io::Error) -> String {
+    match error.kind() {
+        std::io::ErrorKind::PermissionDenied => "permission denied; another user's process needs root".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+// The letters ps and top use
+fn state_letter(status: ProcessStatus) -> char {
+    match status {
+        ProcessStatus::Run => 'R',
+        ProcessStatus::Sleep => 'S',
+        ProcessStatus::Idle => 'I',
+        ProcessStatus::UninterruptibleDiskSleep => 'D',
+        ProcessStatus::Stop => 'T',
+        ProcessStatus::Tracing => 't',
+        ProcessStatus::Zombie => 'Z',
+        ProcessStatus::Dead => 'X',
+        ProcessStatus::Wakekill => 'K',
+        ProcessStatus::Waking => 'W',
+        ProcessStatus::Parked => 'P',
+        ProcessStatus::LockBlocked => 'L',
+        _ => '?',
+    }
+}
+
+// What this build can show on this platform, for the help overlay. Most of the extras are
+// read from Linux's /proc and sysfs; elsewhere there is only what sysinfo reads portably, and
+// the rest is left out of the table and header rather than shown as zeros.
+pub fn features() -> Vec<(&'static str, bool)> {
+    let linux = cfg!(target_os = "linux");
+    vec![
+        ("fds, conn, swap, fault, core columns", linux),
+        ("Stall info (PSI) and steal time", linux),
+        ("cpufreq, swap I/O and huge pages", linux),
+        ("Memory pressure", cfg!(target_os = "macos")),
+        ("Listening sockets (l)", linux),
+        ("Logins (U)", logins::SUPPORTED),
+        ("CPU affinity (P), I/O priority (I)", linux),
+        ("Memory map, limits and capabilities", linux),
+        ("Container names", linux && cfg!(feature = "containers")),
+        ("Prometheus metrics", cfg!(feature = "prometheus")),
+        ("JSON API", cfg!(feature = "http-api")),
+        ("History database", cfg!(feature = "history")),
+        ("Desktop notifications", cfg!(feature = "notify")),
+    ]
+}
+
+// On Linux every kernel thread is kthreadd (PID 2) or one of its children. Windows has the
+// System Idle Process (PID 0), whose CPU use is the time nothing ran, and the kernel's System
+// process (PID 4) instead.
+fn is_kernel_thread(pid: Pid, parent: Option<Pid>) -> bool {
+    if cfg!(windows) {
+        return matches!(pid.as_u32(), 0 | 4);
+    }
+    let kthreadd = Pid::from_u32(2);
+    cfg!(target_os = "linux") && (pid == kthreadd || parent == Some(kthreadd))
+}
+
+// Windows services run as built-in accounts that aren't in the user list sysinfo reads, which
+// only has the machine's own accounts; without these most of the table would have no owner
+#[cfg(windows)]
+fn service_account(uid: &sysinfo::Uid) -> Option<&'static str> {
+    match uid.to_string().as_str() {
+        "S-1-5-18" => Some("SYSTEM"),
+        "S-1-5-19" => Some("LOCAL SERVICE"),
+        "S-1-5-20" => Some("NETWORK SERVICE"),
+        _ => None,
+    }
+}
+
+#[cfg(not(windows))]
+fn service_account(_uid: &sysinfo::Uid) -> Option<&'static str> {
+    None
+}
+
+pub struct FakeProcess {
+    pub pid: u32,
+    pub name: String,
+    pub cpu: f32,
+    pub mem: u64,
+    pub parent: Option<u32>,
+    pub kernel_thread: bool,
+    pub user: Option<String>,
+    pub state: char,
+    // Seconds since the Unix epoch
+    pub start_time: u64,
+    pub fds: Option<u32>,
+    pub conns: Option<u32>,
+    // In bytes
+    pub net: Option<u64>,
+    pub swap: Option<u64>,
+    // Totals since the process started
+    pub voluntary_switches: Option<u64>,
+    pub involuntary_switches: Option<u64>,
+    pub minor_faults: Option<u64>,
+    pub major_faults: Option<u64>,
+    // The CPU it last ran on
+    pub processor: Option<usize>,
+    pub scheduling: Option<Scheduling>,
+    pub nice: Option<i32>,
+    pub container: Option<String>,
+    pub exe: Option<String>,
+    pub cmd: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct FakeNetwork {
+    pub name: String,
+    pub total_received: u64,
+    pub total_transmitted: u64,
+    pub errors: (u64, u64),
+    pub dropped: Option<(u64, u64)>,
+    pub up: Option<bool>,
+}
+
+// Fixed data for tests; `refresh` is a no-op so the fields can be edited between refreshes
+#[derive(Default)]
+pub struct FakeSource {
+    pub processes: Vec<FakeProcess>,
+    pub networks: Vec<FakeNetwork>,
+    pub totals: SystemTotals,
+    // By PID; a process missing here can't be listed
+    pub connections: HashMap<u32, Vec<Connection>>,
+    pub listeners: Vec<Listener>,
+    pub logins: Vec<Login>,
+    // By PID; a process missing here has no affinity or I/O priority to show or change
+    pub affinity: HashMap<u32, Vec<usize>>,
+    pub io_priority: HashMap<u32, IoPriority>,
+    pub capabilities: HashMap<u32, Capabilities>,
+    // An error stands for a memory map that couldn't be read
+    pub memory: HashMap<u32, Result<MemoryMap, String>>,
+    pub limits: HashMap<u32, Vec<Limit>>,
+    // Columns it has no values for, as on a platform without /proc
+    pub missing_columns: Vec<Column>,
+}
+
+impl FakeSource {
+    pub fn with_processes(processes: &[(u32, &str, f32, u64)]) -> Self {
+        Self {
+            processes: processes.iter().map(|&(pid, name, cpu, mem)| FakeProcess {
+                pid,
+                name: name.to_string(),
+                cpu,
+                mem,
+                parent: None,
+                kernel_thread: false,
+                user: None,
+                state: 'S',
+                start_time: 0,
+                fds: None,
+                conns: None,
+                net: None,
+                swap: None,
+                voluntary_switches: None,
+                involuntary_switches: None,
+                minor_faults: None,
+                major_faults: None,
+                processor: None,
+                scheduling: None,
+                nice: None,
+                container: None,
+                exe: None,
+                cmd: Vec::new(),
+            }).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl SystemSource for FakeSource {
+    fn refresh(&mut self) {}
+
+    fn processes(&self) -> Vec<ProcSample<'_>> {
+        self.processes.iter().map(|p| ProcSample {
+            pid: Pid::from_u32(p.pid),
+            name: &p.name,
+            cpu: p.cpu,
+            mem: p.mem,
+            parent: p.parent.map(Pid::from_u32),
+            kernel_thread: p.kernel_thread,
+            user: p.user.as_deref(),
+            state: p.state,
+            start_time: p.start_time,
+            fds: p.fds,
+            conns: p.conns,
+            net: p.net,
+            swap: p.swap,
+            voluntary_switches: p.voluntary_switches,
+            involuntary_switches: p.involuntary_switches,
+            minor_faults: p.minor_faults,
+            major_faults: p.major_faults,
+            processor: p.processor,
+            scheduling: p.scheduling,
+            nice: p.nice,
+            container: p.container.as_deref(),
+            exe: p.exe.as_deref(),
+            cmd: &p.cmd,
+        }).collect()
+    }
+
+    fn networks(&self) -> Vec<NetSample<'_>> {
+        self.networks.iter().map(|n| NetSample {
+            name: &n.name,
+            total_received: n.total_received,
+            total_transmitted: n.total_transmitted,
+            errors: n.errors,
+            dropped: n.dropped,
+            up: n.up,
+        }).collect()
+    }
+
+    fn totals(&self) -> SystemTotals {
+        self.totals.clone()
+    }
+
+    fn connections(&self, pid: Pid) -> Result<Vec<Connection>, String> {
+        self.connections.get(&pid.as_u32()).cloned().ok_or_else(|| "no such process".into())
+    }
+
+    fn has_column(&self, column: Column) -> bool {
+        !self.missing_columns.contains(&column)
+    }
+
+    fn listeners(&mut self) -> Result<Vec<Listener>, String> {
+        Ok(self.listeners.clone())
+    }
+
+    fn logins(&self) -> Result<Vec<Login>, String> {
+        Ok(self.logins.clone())
+    }
+
+    fn details(&self, pid: Pid) -> Details {
+        let process = self.processes.iter().find(|p| p.pid == pid.as_u32());
+        Details {
+            nice: process.and_then(|p| p.nice),
+            scheduling: process.and_then(|p| p.scheduling),
+            affinity: self.affinity.get(&pid.as_u32()).cloned().map(Ok),
+            io_priority: self.io_priority.get(&pid.as_u32()).copied().map(Ok),
+            capabilities: self.capabilities.get(&pid.as_u32()).copied(),
+            memory: self.memory.get(&pid.as_u32()).cloned(),
+            limits: self.limits.get(&pid.as_u32()).cloned().map(Ok),
+        }
+    }
+
+    fn exists(&self, pid: Pid) -> bool {
+        self.processes.iter().any(|p| p.pid == pid.as_u32())
+    }
+
+    fn set_affinity(&mut self, pid: Pid, cpus: &[usize]) -> Result<(), ActionError> {
+        let current = self.affinity.get_mut(&pid.as_u32()).ok_or(ActionError::Denied)?;
+        *current = cpus.to_vec();
+        Ok(())
+    }
+
+    fn set_io_priority(&mut self, pid: Pid, priority: IoPriority) -> Result<(), ActionError> {
+        let current = self.io_priority.get_mut(&pid.as_u32()).ok_or(ActionError::Denied)?;
+        *current = priority;
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::{fmt, io, panic, sync::atomic::{AtomicBool, Ordering}};
+#[cfg(unix)]
+use tui::{backend::Backend, Terminal};
+use crossterm::{cursor::Show, event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste}, execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle}, Command};
+
+// Puts the terminal into raw mode on the alternate screen, with bracketed paste so a paste
+// arrives as one event rather than as keys, and undoes it on drop, so the user's shell is
+// restored on normal exit, on early `?` returns and while unwinding.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn enter() -> io::Result<Self> {
+        enable_raw_mode()?;
+        // Construct the guard first so a failure below still disables raw mode
+        let guard = TerminalGuard;
+        execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if let Err(e) = restore() {
+            log::error!("could not restore the terminal: {}", e);
+        }
+    }
+}
+
+// Safe to call more than once; the panic hook and the guard both run it on a panic
+pub fn restore() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableBracketedPaste, DisableMouseCapture, Show)?;
+    restore_title()
+}
+
+// Whether the title the terminal had before ours is on its title stack, to be popped back
+static TITLE_PUSHED: AtomicBool = AtomicBool::new(false);
+
+// There is no asking a terminal for its title, so the first change pushes the old one onto
+// the xterm title stack (which most terminals and tmux keep) for restore to pop
+pub fn set_title(title: &str) -> io::Result<()> {
+    if !TITLE_PUSHED.swap(true, Ordering::Relaxed) {
+        execute!(io::stdout(), TitleStack::Push)?;
+    }
+    execute!(io::stdout(), SetTitle(title))
+}
+
+pub fn restore_title() -> io::Result<()> {
+    if TITLE_PUSHED.swap(false, Ordering::Relaxed) {
+        execute!(io::stdout(), TitleStack::Pop)?;
+    }
+    Ok(())
+}
+
+// XTWINOPS 22 and 23, which crossterm has no commands for
+enum TitleStack {
+    Push,
+    Pop,
+}
+
+impl Command for TitleStack {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        match self {
+            TitleStack::Push => f.write_str("\x1b[22;0t"),
+            TitleStack::Pop => f.write_str("\x1b[23;0t"),
+        }
+    }
+
+    // The console keeps no stack; the title set last stays after exit
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Restore the terminal before the default hook prints the panic message, otherwise the
+// message lands on the alternate screen and disappears
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+        default_hook(info);
+    }));
+}
+
+// Shell job control for Ctrl+Z: hand the terminal back, stop until `fg` sends SIGCONT, then
+// take it over again. Clearing makes the next draw repaint every cell; tui picks up a size
+// change made while we were stopped on that same draw.
+#[cfg(unix)]
+pub fn suspend<B: Backend>(terminal: &mut Terminal<B>) -> io::Result<()> {
+    restore()?;
+    signal_hook::low_level::raise(signal_hook::consts::SIGTSTP)?;
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+    terminal.clear()
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use tui::style::{Color, Modifier, Style};
+
+// Every style the draw code uses. Widgets take their style from here rather than building
+// their own, so a theme or a [theme.colors] override reaches the whole screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub text: Style,
+    pub header: Style,
+    pub border: Style,
+    pub selected_row: Style,
+    // Rows matching a watch pattern
+    pub watch: Style,
+    pub cpu_warn: Style,
+    pub cpu_crit: Style,
+    pub gauge_fill: Style,
+    pub warning: Style,
+    pub error: Style,
+}
+
+// CPU cells switch to cpu_warn / cpu_crit at these percentages
+pub const CPU_WARN: f32 = 50.0;
+pub const CPU_CRIT: f32 = 90.0;
+
+// A pressure indicator turns to warning when some task stalled this share of the last ten
+// seconds, and to error at PSI_CRIT, or when every task did for PSI_WARN
+pub const PSI_WARN: f32 = 10.0;
+pub const PSI_CRIT: f32 = 40.0;
+
+// The memory breakdown turns to warning / error when no more than these percentages of
+// memory are available
+pub const MEM_AVAILABLE_WARN: f32 = 20.0;
+pub const MEM_AVAILABLE_CRIT: f32 = 10.0;
+
+// The steal indicator turns to warning / error when the hypervisor took this share of CPU time
+pub const STEAL_WARN: f32 = 5.0;
+pub const STEAL_CRIT: f32 = 20.0;
+
+pub const THEMES: &[&str] = &["dark", "light", "mono"];
+
+// Elements that can be overridden in [theme.colors]. The colour is the foreground, except for
+// the ones drawn as a filled bar where it is the background.
+const ELEMENTS: &[(&str, bool)] = &[
+    ("text", false),
+    ("header", false),
+    ("border", false),
+    ("selected_row", true),
+    ("watch", false),
+    ("cpu_warn", false),
+    ("cpu_crit", false),
+    ("gauge_fill", true),
+    ("warning", false),
+    ("error", false),
+];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::builtin("dark").expect("dark theme exists")
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let plain = Style::default();
+        let bold = plain.add_modifier(Modifier::BOLD);
+        let reversed = plain.add_modifier(Modifier::REVERSED);
+        match name {
+            "dark" => Some(Theme {
+                text: plain,
+                header: bold,
+                border: plain,
+                selected_row: reversed,
+                watch: plain.fg(Color::Cyan),
+                cpu_warn: plain.fg(Color::Yellow),
+                cpu_crit: plain.fg(Color::Red),
+                gauge_fill: plain.bg(Color::Green),
+                warning: plain.fg(Color::Yellow),
+                error: plain.fg(Color::Red),
+            }),
+            // Yellow is unreadable on a white background
+            "light" => Some(Theme {
+                text: plain,
+                header: bold.fg(Color::Blue),
+                border: plain.fg(Color::DarkGray),
+                selected_row: plain.bg(Color::Gray).fg(Color::Black),
+                watch: plain.fg(Color::Green),
+                cpu_warn: plain.fg(Color::Magenta),
+                cpu_crit: plain.fg(Color::Red),
+                gauge_fill: plain.bg(Color::Blue),
+                warning: plain.fg(Color::Magenta),
+                error: plain.fg(Color::Red),
+            }),
+            // Modifiers only, for terminals without colour
+            "mono" => Some(Theme {
+                text: plain,
+                header: bold,
+                border: plain,
+                selected_row: reversed,
+                watch: plain.add_modifier(Modifier::UNDERLINED),
+                cpu_warn: bold,
+                cpu_crit: bold.add_modifier(Modifier::UNDERLINED),
+                gauge_fill: reversed,
+                warning: bold,
+                error: bold.add_modifier(Modifier::UNDERLINED),
+            }),
+            _ => None,
+        }
+    }
+
+    fn element_mut(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "text" => &mut self.text,
+            "header" => &mut self.header,
+            "border" => &mut self.border,
+            "selected_row" => &mut self.selected_row,
+            "watch" => &mut self.watch,
+            "cpu_warn" => &mut self.cpu_warn,
+            "cpu_crit" => &mut self.cpu_crit,
+            "gauge_fill" => &mut self.gauge_fill,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            _ => return None,
+        })
+    }
+
+    // The named theme with the configured overrides applied. An unknown theme name falls
+    // back to "dark" and a bad override keeps the theme's own style; both are returned
+    // as warnings.
+    pub fn resolve(config: &ThemeConfig) -> (Theme, Vec<String>) {
+        let mut warnings = Vec::new();
+        let mut theme = Theme::builtin(&config.name).unwrap_or_else(|| {
+            warnings.push(format!("unknown theme \"{}\" (expected one of {}); using \"dark\"", config.name, THEMES.join(", ")));
+            Theme::default()
+        });
+        for (element, value) in &config.colors {
+            let Some(&(_, background)) = ELEMENTS.iter().find(|(e, _)| e == element) else {
+                warnings.push(format!("unknown theme element `{}` in [theme.colors]", element));
+                continue;
+            };
+            let color = match parse_color(value) {
+                Some(color) => color,
+                None => {
+                    warnings.push(format!("[theme.colors] {}: unknown colour \"{}\"", element, value));
+                    continue;
+                },
+            };
+            let style = theme.element_mut(element).expect("listed element");
+            *style = if background {
+                // A coloured bar replaces the reverse-video highlight
+                style.remove_modifier(Modifier::REVERSED).bg(color)
+            } else {
+                style.fg(color)
+            };
+        }
+        (theme, warnings)
+    }
+}
+
+// Accepts the terminal colour names ("red", "lightblue", "darkgray"), "#rrggbb" hex, and
+// 0-255 palette indexes
+pub fn parse_color(text: &str) -> Option<Color> {
+    let lower = text.trim().to_ascii_lowercase().replace(['_', '-', ' '], "");
+    if let Some(hex) = lower.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    if let Ok(index) = lower.parse::<u8>() {
+        return Some(Color::Indexed(index));
+    }
+    Some(match lower.as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    })
+}
+
+// `theme = "light"` on its own, or a [theme] table with `name` and a [theme.colors] table
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "ThemeSpec", into = "ThemeSpec")]
+pub struct ThemeConfig {
+    pub name: String,
+    pub colors: BTreeMap<String, String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self { name: "dark".into(), colors: BTreeMap::new() }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ThemeSpec {
+    Name(String),
+    Table {
+        #[serde(default = "default_theme_name")]
+        name: String,
+        #[serde(default)]
+        colors: BTreeMap<String, String>,
+    },
+}
+
+fn default_theme_name() -> String {
+    ThemeConfig::default().name
+}
+
+impl From<ThemeSpec> for ThemeConfig {
+    fn from(spec: ThemeSpec) -> Self {
+        match spec {
+            ThemeSpec::Name(name) => ThemeConfig { name, colors: BTreeMap::new() },
+            ThemeSpec::Table { name, colors } => ThemeConfig { name, colors },
+        }
+    }
+}
+
+// Written back in the short form unless there are overrides to keep
+impl From<ThemeConfig> for ThemeSpec {
+    fn from(config: ThemeConfig) -> Self {
+        if config.colors.is_empty() {
+            ThemeSpec::Name(config.name)
+        } else {
+            ThemeSpec::Table { name: config.name, colors: config.colors }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme_config(name: &str, colors: &[(&str, &str)]) -> ThemeConfig {
+        ThemeConfig { name: name.into(), colors: colors.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect() }
+    }
+
+    #[test]
+    fn parses_colours() {
+        assert_eq!(parse_color("red"), Some(Color::Red));
+        assert_eq!(parse_color("Light_Blue"), Some(Color::LightBlue));
+        assert_eq!(parse_color("dark-grey"), Some(Color::DarkGray));
+        assert_eq!(parse_color("#3a3A3a"), Some(Color::Rgb(0x3a, 0x3a, 0x3a)));
+        assert_eq!(parse_color("208"), Some(Color::Indexed(208)));
+        for bad in ["", "#12345", "#gggggg", "256", "purpleish"] {
+            assert_eq!(parse_color(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn overrides_apply_on_top_of_the_named_theme() {
+        let (theme, warnings) = Theme::resolve(&theme_config("dark", &[("header", "cyan"), ("selected_row", "#203040")]));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(theme.header, Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan));
+        assert_eq!(theme.selected_row.bg, Some(Color::Rgb(0x20, 0x30, 0x40)));
+        assert!(!theme.selected_row.add_modifier.contains(Modifier::REVERSED));
+        assert_eq!(theme.cpu_crit, Theme::default().cpu_crit);
+    }
+
+    #[test]
+    fn invalid_entries_fall_back_to_the_builtin_theme() {
+        let (theme, warnings) = Theme::resolve(&theme_config("solarized", &[("header", "blurple"), ("sidebar", "red"), ("error", "lightred")]));
+        assert_eq!(warnings, vec![
+            "unknown theme \"solarized\" (expected one of dark, light, mono); using \"dark\"".to_string(),
+            "[theme.colors] header: unknown colour \"blurple\"".to_string(),
+            "unknown theme element `sidebar` in [theme.colors]".to_string(),
+        ]);
+        assert_eq!(theme.header, Theme::default().header);
+        assert_eq!(theme.error.fg, Some(Color::LightRed));
+    }
+
+    #[test]
+    fn every_builtin_theme_resolves() {
+        for name in THEMES {
+            assert!(Theme::builtin(name).is_some(), "{}", name);
+            assert!(Theme::resolve(&theme_config(name, &[])).1.is_empty());
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// The last minute of CPU use of the processes someone is looking at, for the graph in the
+// details popup. Keeping it for every process would cost too much on a big machine, so a
+// process's trail starts when it is first selected or opened in the popup, and is dropped once
+// it has gone unlooked-at for KEEP_MS or has exited.
+
+use std::collections::{HashMap, VecDeque};
+use sysinfo::Pid;
+
+use crate::app::ProcInfo;
+
+// How far back a trail reaches
+pub const WINDOW_MS: i64 = 60_000;
+
+// How long a trail is kept recording after its process was last looked at, so stepping to the
+// next row and back doesn't lose the graph
+pub const KEEP_MS: i64 = 30_000;
+
+// A ceiling for very short refresh intervals
+const MAX_SAMPLES: usize = 600;
+
+pub struct Trail {
+    // Tells a reused PID from the process the samples belong to
+    start_time: u64,
+    // (time in ms, CPU %), oldest first
+    samples: VecDeque<(i64, f32)>,
+    looked_at_ms: i64,
+}
+
+impl Trail {
+    pub fn cpu(&self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().map(|&(_, cpu)| cpu)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // (min, average, max); None before the first sample
+    pub fn stats(&self) -> Option<(f32, f32, f32)> {
+        let first = self.samples.front()?.1;
+        let (min, max, sum) = self.cpu().fold((first, first, 0.0), |(min, max, sum), cpu| (min.min(cpu), max.max(cpu), sum + cpu));
+        Some((min, sum / self.samples.len() as f32, max))
+    }
+}
+
+#[derive(Default)]
+pub struct Trails {
+    trails: HashMap<Pid, Trail>,
+}
+
+impl Trails {
+    pub fn get(&self, pid: Pid) -> Option<&Trail> {
+        self.trails.get(&pid)
+    }
+
+    // Adds the latest sample of every kept process, starting trails for the processes in
+    // `looked_at`. `now_ms` is the data's clock, so a replay graphs its recorded times; the
+    // same time again (a paused replay) adds nothing, and a time before the last sample (a
+    // seek back) starts the trail over.
+    pub fn update(&mut self, procs: &[ProcInfo], looked_at: &[Pid], now_ms: i64) {
+        for &pid in looked_at {
+            self.trails.entry(pid).or_insert_with(|| Trail { start_time: 0, samples: VecDeque::new(), looked_at_ms: now_ms }).looked_at_ms = now_ms;
+        }
+        if self.trails.is_empty() {
+            return;
+        }
+        let mut seen = Vec::with_capacity(self.trails.len());
+        for p in procs {
+            let Some(trail) = self.trails.get_mut(&p.pid) else { continue };
+            if now_ms - trail.looked_at_ms > KEEP_MS {
+                continue;
+            }
+            seen.push(p.pid);
+            if trail.start_time != p.start_time || trail.samples.back().is_some_and(|&(ms, _)| ms > now_ms) {
+                trail.start_time = p.start_time;
+                trail.samples.clear();
+            }
+            if trail.samples.back().is_some_and(|&(ms, _)| ms == now_ms) {
+                continue;
+            }
+            trail.samples.push_back((now_ms, p.cpu));
+            while trail.samples.len() > MAX_SAMPLES || trail.samples.front().is_some_and(|&(ms, _)| now_ms - ms > WINDOW_MS) {
+                trail.samples.pop_front();
+            }
+        }
+        self.trails.retain(|pid, _| seen.contains(pid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{FakeSource, SystemSource};
+
+    fn procs(list: &[(u32, u64, f32)]) -> Vec<ProcInfo> {
+        let mut source = FakeSource::with_processes(&list.iter().map(|&(pid, _, cpu)| (pid, "x", cpu, 0)).collect::<Vec<_>>());
+        for (p, &(_, start_time, _)) in source.processes.iter_mut().zip(list) {
+            p.start_time = start_time;
+        }
+        source.processes().iter().map(ProcInfo::new).collect()
+    }
+
+    fn cpu(trails: &Trails, pid: u32) -> Option<Vec<f32>> {
+        trails.get(Pid::from(pid as usize)).map(|t| t.cpu().collect())
+    }
+
+    #[test]
+    fn only_looked_at_processes_are_recorded() {
+        let mut trails = Trails::default();
+        let db = Pid::from(7);
+        trails.update(&procs(&[(7, 100, 10.0), (8, 100, 50.0)]), &[db], 1_000);
+        trails.update(&procs(&[(7, 100, 30.0), (8, 100, 50.0)]), &[db], 2_000);
+        // The same replay frame again
+        trails.update(&procs(&[(7, 100, 30.0), (8, 100, 50.0)]), &[db], 2_000);
+        assert_eq!(cpu(&trails, 7), Some(vec![10.0, 30.0]));
+        assert_eq!(cpu(&trails, 8), None);
+        assert_eq!(trails.get(db).unwrap().stats(), Some((10.0, 20.0, 30.0)));
+
+        // Samples older than the window fall off the front
+        trails.update(&procs(&[(7, 100, 5.0)]), &[db], 61_500);
+        assert_eq!(cpu(&trails, 7), Some(vec![30.0, 5.0]));
+    }
+
+    #[test]
+    fn trails_go_when_nobody_looks_or_the_process_is_gone() {
+        let mut trails = Trails::default();
+        trails.update(&procs(&[(7, 100, 10.0), (8, 100, 1.0)]), &[Pid::from(7), Pid::from(8)], 0);
+        // Still recorded a while after the selection moved on
+        trails.update(&procs(&[(7, 100, 20.0), (8, 100, 2.0)]), &[Pid::from(8)], KEEP_MS);
+        assert_eq!(cpu(&trails, 7), Some(vec![10.0, 20.0]));
+        trails.update(&procs(&[(7, 100, 30.0), (8, 100, 3.0)]), &[Pid::from(8)], KEEP_MS + 1);
+        assert_eq!(cpu(&trails, 7), None);
+        // 8 exited, and the PID now belongs to a process that started later
+        trails.update(&procs(&[(8, 900, 4.0)]), &[Pid::from(8)], KEEP_MS + 2);
+        assert_eq!(cpu(&trails, 8), Some(vec![4.0]));
+        trails.update(&procs(&[]), &[], KEEP_MS + 3);
+        assert_eq!(cpu(&trails, 8), None);
+    }
+}
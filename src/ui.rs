@@ -0,0 +1,1205 @@
+use std::{borrow::Cow, collections::VecDeque, time::{Duration, Instant}};
+use tui::{backend::Backend, Frame, widgets::{Block, Borders, Cell, Clear, Row, Sparkline, Table, TableState, Paragraph}, layout::{Constraint, Layout, Direction, Rect}, style::{Modifier, Style}, text::{Span, Spans}};
+use unicode_segmentation::UnicodeSegmentation;
+use sysinfo::{Pid, PidExt};
+
+use crate::{actions::{ActionError, ActionLog, ActionResult}, alerts::Alerts, app::{self, Aggregate, App, ConnectionsView, DetailsView, HistorySample, HistoryView, ListenersView, LoginsView, NameDisplay, ProcInfo, Setting, SortBy, SortMenu, Status, StatusKind, UserPicker}, columns::Column, editor::LineEditor, exec::{self, Run, RunState}, exits::Exit, handle::ProcessHandle, keys::{self, Action, Keymap}, perf::{self, Stage}, procfs, query::Query, replay::ReplayStatus, sched::{self, IoClass}, system::{self, PressureLevel, SystemTotals}, theme::{self, Theme}, trails::Trail, width};
+
+// Width of the progress bar in the replay timeline
+const TIMELINE_WIDTH: usize = 30;
+
+// Output lines shown per run in the command log
+const COMMAND_OUTPUT_LINES: usize = 3;
+
+// Turn the prepared state on `App` into widgets; no data collection happens here. Every
+// style comes from `app.theme`.
+pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let size = f.size();
+    let theme = app.theme;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        // A replay adds its timeline to the header
+        .constraints([Constraint::Length(3), Constraint::Length(3 + app.replay.is_some() as u16 + memory_lines(app)), Constraint::Min(0), Constraint::Length(app.footer.is_some() as u16)].as_ref())
+        .split(size);
+
+    // Search bar, or the latest status message until it is dismissed
+    let search = if let Some(status) = &app.status {
+        match status.kind {
+            StatusKind::Info => Paragraph::new(status_text(status)),
+            _ => Paragraph::new(format!("{} (press any key to dismiss)", status_text(status))),
+        }.style(status_style(status.kind, &theme))
+    } else if let Some(input) = &app.watch_input {
+        Paragraph::new(prompt("Watch: ".into(), input, " (Enter to add, Esc to cancel)".into())).style(theme.text)
+    } else if let Some(input) = &app.setting_input {
+        let example = match input.setting {
+            Setting::Affinity => "like 0-3,8",
+            Setting::IoPriority => "idle or best-effort 0-7",
+        };
+        let label = format!("{} of {}: ", input.setting.label(), process_label(&input.name, &input.process, app.has_exited(&input.process)));
+        Paragraph::new(prompt(label, &input.text, format!(" ({}; Enter to apply, Esc to cancel)", example))).style(theme.text)
+    } else {
+        let user = match &app.user_filter {
+            Some(user) => format!("User: {} (Esc for everyone)  ", user),
+            None => String::new(),
+        };
+        let text = if app.searching {
+            prompt(format!("{}Search{}: ", user, if app.search_inverted { " (inverted)" } else { "" }), &app.search_query, String::new())
+        } else {
+            Spans::from(format!("{}Press {} to search, {} for help, {} to quit", user, key_hint(&app.keymap, Action::Search), key_hint(&app.keymap, Action::Help), key_hint(&app.keymap, Action::Quit)))
+        };
+        Paragraph::new(text).style(theme.text)
+    };
+    f.render_widget(search, chunks[0]);
+
+    // The clock and network stats, or why a remote agent isn't sending any
+    let info = match &app.remote {
+        Some(remote) if !remote.connected => {
+            let text = match &remote.error {
+                Some(error) => format!("Lost connection to {} ({}); reconnecting...", remote.addr, error),
+                None => format!("Connecting to {}...", remote.addr),
+            };
+            Paragraph::new(text).style(theme.warning)
+        },
+        _ => {
+            let mut header = clock(app, chrono::Local::now(), &theme);
+            header.0.extend(interfaces(app, &theme));
+            let mut system = Spans::from(uptime(app));
+            system.0.extend(pressure(app, &theme));
+            system.0.extend(mem_pressure(app, &theme));
+            if let Some(freq) = &app.totals.cpufreq {
+                system.0.push(Span::raw(cpufreq(freq)));
+            }
+            system.0.extend(virtualization(app, &theme));
+            system.0.extend(swap_io(app, &theme));
+            if app.config.show_huge_pages {
+                system.0.extend(app.totals.huge_pages.iter().map(|h| Span::raw(format!("  HP {}/{} × {}", h.total - h.free.min(h.total), h.total, huge_page_size(h.size)))));
+            }
+            let mut lines = vec![header];
+            if let Some(replay) = &app.replay {
+                lines.push(Spans::from(Span::styled(timeline(replay), if replay.error.is_some() { theme.warning } else { theme.text })));
+            }
+            lines.extend([system, Spans::from(traffic(app))]);
+            if app.memory_detail {
+                lines.push(Spans::from(memory(&app.totals, &theme)));
+                if !app.totals.huge_pages.is_empty() {
+                    lines.push(Spans::from(huge_pages(&app.totals)));
+                }
+            }
+            Paragraph::new(lines).style(theme.text)
+        },
+    };
+    f.render_widget(info, chunks[1]);
+
+    let name_width = name_width(app, chunks[2].width);
+    let timing = app.perf.start();
+    let rows = process_rows(&app.processes, &app.visible, &app.depths, &app.watched, &app.columns, NameLayout { display: app.name_display, width: name_width, sum: TreeSum::new(app), realtime: app.realtime_badge }, &theme);
+    app.perf.record(Stage::Rows, timing);
+    let widths: Vec<Constraint> = app.columns.iter().map(|&c| match c {
+        Column::Name => Constraint::Length(name_width),
+        _ => Constraint::Length(c.width()),
+    }).collect();
+
+    // A bell alert flashes the header
+    let header = if app.flash_until.is_some() { theme.header.add_modifier(Modifier::REVERSED) } else { theme.header };
+    let table = Table::new(rows)
+        .header(Row::new(app.columns.iter().map(|c| c.title())).style(header))
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(table_title(app)))
+        .style(theme.text)
+        .widths(&widths)
+        .highlight_style(theme.selected_row);
+
+    f.render_stateful_widget(table, chunks[2], &mut app.table_state);
+    // Below the header, so a filter that leaves nothing doesn't look like a broken table
+    if let Some(text) = empty_text(app) && chunks[2].height > 3 {
+        let area = Rect::new(chunks[2].x + 1, chunks[2].y + 2, chunks[2].width.saturating_sub(2), 1);
+        f.render_widget(Paragraph::new(text).style(theme.text), area);
+    }
+    // Under the last row, or on the bottom border once the rows fill the table
+    if app.beyond_limit > 0 && chunks[2].height > 2 {
+        let text = format!("… and {} more", app.beyond_limit);
+        let y = (chunks[2].y + 2 + app.visible.len().min(u16::MAX as usize) as u16).min(chunks[2].y + chunks[2].height - 1);
+        let area = Rect::new(chunks[2].x + 1, y, (width::width(&text) as u16).min(chunks[2].width.saturating_sub(2)), 1);
+        f.render_widget(Paragraph::new(text).style(theme.text.add_modifier(Modifier::DIM)), area);
+    }
+    if let Some(footer) = &app.footer {
+        f.render_widget(Paragraph::new(footer_text(footer)).style(theme.header), chunks[3]);
+    }
+
+    if app.show_help {
+        draw_help(f, &app.keymap, app.replay.is_some(), &theme, size);
+    }
+    if let Some(history) = &app.history {
+        draw_history(f, history, &theme, size);
+    }
+    if let Some(view) = &app.connections {
+        draw_connections(f, view, app.has_exited(&view.process), &theme, size);
+    }
+    if let Some(view) = &app.details {
+        // A trail under the same PID after the process exited belongs to whatever has it now
+        let exited = app.has_exited(&view.process);
+        draw_details(f, view, app.trails.get(view.process.pid).filter(|_| !exited), exited, &theme, size);
+    }
+    if app.show_alerts {
+        draw_alerts(f, &app.alerts, &theme, size);
+    }
+    if app.show_realtime {
+        draw_realtime(f, &app.boosted(), &theme, size);
+    }
+    if app.show_commands {
+        draw_commands(f, &app.commands.log, &theme, size);
+    }
+    if let Some(row) = app.watch_list {
+        draw_watches(f, &app.watches, &app.processes, row, &theme, size);
+    }
+    if let Some(picker) = &app.user_picker {
+        draw_users(f, picker, &theme, size);
+    }
+    if let Some(menu) = &app.sort_menu {
+        draw_sort_menu(f, menu, app.sort_by, app.descending, &theme, size);
+    }
+    if let Some(view) = &app.listeners {
+        draw_listeners(f, view, &app.processes, &theme, size);
+    }
+    if let Some(view) = &app.logins {
+        draw_logins(f, view, &app.processes, &theme, size);
+    }
+    if let Some(back) = app.action_log {
+        draw_action_log(f, app.actions.results(), back, &theme, size);
+    }
+    if let Some(back) = app.message_log {
+        draw_message_log(f, &app.messages, back, &theme, size);
+    }
+    if let Some(back) = app.exit_log {
+        draw_exit_log(f, app.exits.exits(), back, &theme, size);
+    }
+    draw_toasts(f, &app.actions, &theme, size);
+    if app.perf.enabled() {
+        draw_perf(f, app, &theme, size);
+    }
+}
+
+fn action_style(result: &ActionResult, theme: &Theme) -> Style {
+    match result.outcome {
+        Ok(_) => theme.text,
+        Err(ActionError::Gone) => theme.warning,
+        Err(_) => theme.error,
+    }
+}
+
+// "12 rows  CPU Σ 84.00% avg 7.00% max 42.00%  Memory Σ ...", with the cells' formatting
+fn footer_text(footer: &Aggregate) -> String {
+    let rows = match footer.rows {
+        1 => "1 row".to_string(),
+        n => format!("{} rows", n),
+    };
+    format!(" {}  CPU Σ {} avg {} max {}  Memory Σ {} avg {} max {}", rows,
+        app::format_cpu(footer.cpu_sum), app::format_cpu(footer.cpu_average()), app::format_cpu(footer.cpu_max),
+        app::format_mem(footer.mem_sum), app::format_mem(footer.mem_average()), app::format_mem(footer.mem_max))
+}
+
+// The latest action results in the bottom left corner, newest at the bottom, until they expire
+fn draw_toasts<B: Backend>(f: &mut Frame<B>, actions: &ActionLog, theme: &Theme, size: Rect) {
+    let lines: Vec<Spans> = actions.toasts().map(|result| Spans::from(Span::styled(result.text(), action_style(result, theme)))).collect();
+    if lines.is_empty() {
+        return;
+    }
+    let longest = lines.iter().map(Spans::width).max().unwrap_or(0) as u16;
+    let width = (longest + 2).min(size.width);
+    let height = (lines.len() as u16 + 2).min(size.height);
+    let area = Rect::new(size.x, size.y + size.height - height, width, height);
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).border_style(theme.border)), area);
+}
+
+// Every kept result with its time, newest first, starting `back` results from the newest
+fn draw_action_log<B: Backend>(f: &mut Frame<B>, results: &VecDeque<ActionResult>, back: usize, theme: &Theme, size: Rect) {
+    let width = size.width.min(100);
+    let height = size.height.min(results.len() as u16 + 2);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let lines: Vec<Spans> = results.iter().rev().skip(back).map(|result| Spans::from(vec![
+        Span::styled(format!("{} ", result.time.format("%H:%M:%S")), theme.header),
+        Span::styled(result.text(), action_style(result, theme)),
+    ])).collect();
+    let title = format!("Process actions, {} of {} (↑↓ to scroll, any other key to close)", back + 1, results.len());
+    let text = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(title))
+        .style(theme.text);
+    f.render_widget(Clear, area);
+    f.render_widget(text, area);
+}
+
+// A status as the status line and the message log word it
+fn status_text(status: &Status) -> String {
+    match status.kind {
+        StatusKind::Info => status.text.clone(),
+        StatusKind::Warning => format!("Warning: {}", status.text),
+        StatusKind::Error => format!("Error: {}", status.text),
+        StatusKind::Alert => format!("Alert: {}", status.text),
+    }
+}
+
+fn status_style(kind: StatusKind, theme: &Theme) -> Style {
+    match kind {
+        StatusKind::Info => theme.text,
+        StatusKind::Warning => theme.warning,
+        StatusKind::Error => theme.error,
+        StatusKind::Alert => theme.error.add_modifier(Modifier::BOLD),
+    }
+}
+
+fn draw_message_log<B: Backend>(f: &mut Frame<B>, messages: &VecDeque<Status>, back: usize, theme: &Theme, size: Rect) {
+    let width = size.width.min(100);
+    let height = size.height.min(messages.len().max(1) as u16 + 2);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let lines: Vec<Spans> = match messages.is_empty() {
+        true => vec![Spans::from("No messages yet")],
+        false => messages.iter().rev().skip(back).map(|status| Spans::from(vec![
+            Span::styled(format!("{} ", status.time.format("%H:%M:%S")), theme.header),
+            Span::styled(status_text(status), status_style(status.kind, theme)),
+        ])).collect(),
+    };
+    let title = format!("Messages, {} of {} (↑↓ to scroll, any other key to close)", (back + 1).min(messages.len()), messages.len());
+    let text = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(title))
+        .style(theme.text);
+    f.render_widget(Clear, area);
+    f.render_widget(text, area);
+}
+
+// How long an exited process ran; most of those worth looking up ran for seconds
+fn format_lifetime(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{} s", secs),
+        _ => app::format_duration(secs),
+    }
+}
+
+// Every kept exit with the time it was noticed, newest first, starting `back` exits from the newest
+fn draw_exit_log<B: Backend>(f: &mut Frame<B>, exits: &VecDeque<Exit>, back: usize, theme: &Theme, size: Rect) {
+    let width = size.width.min(100);
+    let height = size.height.min(exits.len() as u16 + 2);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let lines: Vec<Spans> = exits.iter().rev().skip(back).map(|exit| {
+        let time = chrono::DateTime::from_timestamp_millis(exit.time_ms).map_or_else(String::new, |t| t.with_timezone(&chrono::Local).format("%H:%M:%S").to_string());
+        let lifetime = exit.lifetime.map_or_else(|| "-".to_string(), format_lifetime);
+        Spans::from(vec![
+            Span::styled(format!("{} ", time), theme.header),
+            Span::raw(format!("{:>7} {:<20} {:<10} {:>7} {:>9}  ran {}", exit.pid, exit.name, exit.user.as_deref().unwrap_or("-"), app::format_cpu(exit.cpu), app::format_mem(exit.mem), lifetime)),
+        ])
+    }).collect();
+    let title = format!("Exited processes, {} of {} (↑↓ to scroll, any other key to close)", back + 1, exits.len());
+    let text = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(title))
+        .style(theme.text);
+    f.render_widget(Clear, area);
+    f.render_widget(text, area);
+}
+
+// rust-htop's own costs in the bottom right corner, over the table rather than beside it so
+// turning it on doesn't move anything it measures
+fn draw_perf<B: Backend>(f: &mut Frame<B>, app: &App, theme: &Theme, size: Rect) {
+    let mut lines: Vec<Spans> = Stage::ALL.into_iter().map(|stage| Spans::from(match app.perf.average(stage) {
+        Some(took) => format!("{:<10}{:>8.2} ms", stage.label(), took.as_secs_f64() * 1000.0),
+        None => format!("{:<10}{:>11}", stage.label(), "-"),
+    })).collect();
+    lines.push(Spans::from(format!("{:<10}{:>11}", "processes", app.processes.len())));
+    // Only a local source lists this process; a remote or recorded one has another's PID
+    let own = (app.remote.is_none() && app.replay.is_none()).then(|| app.processes.iter().find(|p| p.pid == app.self_pid)).flatten();
+    if let Some(own) = own {
+        lines.push(Spans::from(format!("{:<10}{:>10.1}%", "own CPU", own.cpu)));
+        lines.push(Spans::from(format!("{:<10}{:>11}", "own RSS", app::format_bytes(own.mem as f64, "").trim())));
+    }
+    let width = PERF_WIDTH.min(size.width);
+    let height = (lines.len() as u16 + 2).min(size.height);
+    let area = Rect::new(size.x + size.width - width, size.y + size.height - height, width, height);
+    let block = Block::default().borders(Borders::ALL).border_style(theme.border).title(format!("Perf, {} frames", perf::FRAMES));
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block).style(theme.text), area);
+}
+
+// A label and a figure, 21 columns, and the borders
+const PERF_WIDTH: u16 = 23;
+
+// A line being typed between its label and hint, with the cluster under the cursor reversed;
+// past the end of the text the cursor is the space in front of the hint
+fn prompt(label: String, line: &LineEditor, hint: String) -> Spans<'static> {
+    let (before, after) = line.split();
+    let (under, rest) = match after.graphemes(true).next() {
+        Some(under) => (under, format!("{}{}", &after[under.len()..], hint)),
+        None => (" ", hint.strip_prefix(' ').unwrap_or(&hint).to_string()),
+    };
+    Spans::from(vec![
+        Span::raw(label + before),
+        Span::styled(under.to_string(), Style::default().add_modifier(Modifier::REVERSED)),
+        Span::raw(rest),
+    ])
+}
+
+// Paths and command lines widen the Name column to the longest one shown, as far as the other
+// columns leave room
+fn name_width(app: &App, table_width: u16) -> u16 {
+    let width = Column::Name.width();
+    if app.name_display == NameDisplay::Basename {
+        return width;
+    }
+    let longest = app.visible.iter().enumerate().map(|(row, &i)| {
+        let indent = app.depths.get(row).map_or(0, |&depth| if depth > 0 { 2 * depth + 1 } else { 0 });
+        width::width(app.processes[i].display_name(app.name_display)) + indent
+    }).max().unwrap_or(0);
+    // The borders, and a space between columns
+    let others: u16 = app.columns.iter().filter(|&&c| c != Column::Name).map(|c| c.width() + 1).sum();
+    let room = table_width.saturating_sub(2 + others);
+    (longest.min(u16::MAX as usize) as u16).min(room).max(width)
+}
+
+fn table_title(app: &App) -> String {
+    let mut title = match (&app.remote, &app.replay) {
+        (Some(remote), _) => format!("Processes on {}", remote.addr),
+        (None, Some(_)) => "Processes (replay)".to_string(),
+        (None, None) => "Processes".to_string(),
+    };
+    if let Some(state) = app.state_filter {
+        title.push_str(&format!(" [{} only]", state.label()));
+    }
+    if app.hide_idle {
+        title.push_str(&format!(" [{} idle hidden]", app.hidden_idle));
+    }
+    if app.self_hidden {
+        title.push_str(" (+self hidden)");
+    }
+    if app.paused {
+        title.push_str(" [paused]");
+    }
+    title
+}
+
+// Why the table is empty when there are processes but the filters leave none
+fn empty_text(app: &App) -> Option<String> {
+    if !app.visible.is_empty() || app.processes.is_empty() {
+        return None;
+    }
+    let mut filters = Vec::new();
+    if let Some(state) = app.state_filter {
+        filters.push(format!("{} only", state.label()));
+    }
+    if let Some(user) = &app.user_filter {
+        filters.push(format!("user {}", user));
+    }
+    if !app.search_query.is_empty() {
+        filters.push(format!("search {}{:?}", if app.search_inverted { "not " } else { "" }, app.search_query.as_str()));
+    }
+    if app.hide_kernel_threads {
+        filters.push("kernel threads hidden".to_string());
+    }
+    if app.hidden_idle > 0 {
+        filters.push(format!("{} idle hidden", app.hidden_idle));
+    }
+    Some(match filters.len() {
+        0 => "No processes match".to_string(),
+        _ => format!("No processes match ({})", filters.join(", ")),
+    })
+}
+
+// The time now and the time of the snapshot on screen, which while paused or replaying can
+// be long ago; a replayed one stands out in the warning style
+fn clock<'a>(app: &App, now: chrono::DateTime<chrono::Local>, theme: &Theme) -> Spans<'a> {
+    let format = app.config.time_format.as_str();
+    let mut spans = vec![Span::raw(format!("{}  ", now.format(format)))];
+    if let Some(sampled) = chrono::DateTime::from_timestamp_millis(app.sampled_ms).filter(|_| app.sampled_ms > 0) {
+        let sampled = sampled.with_timezone(&chrono::Local).format(format);
+        match app.replay {
+            Some(_) => spans.push(Span::styled(format!("replaying {}", sampled), theme.warning)),
+            None => spans.push(Span::raw(format!("snapshot {}", sampled))),
+        }
+        spans.push(Span::raw("  "));
+    }
+    // The interval in effect, which is the configured one unless refreshing is lagging
+    if app.replay.is_none() && !app.paused {
+        let interval = app.pacer.interval(app.refresh_rate);
+        spans.push(Span::raw(format!("every {}  ", format_interval(interval))));
+    }
+    if let Some(took) = app.pacer.lagging(app.refresh_rate) {
+        spans.push(Span::styled(format!("refresh lagging: last cycle took {:.1}s  ", took.as_secs_f64()), theme.warning));
+    }
+    Spans::from(spans)
+}
+
+// "500ms", "1s", "2.5s"
+fn format_interval(interval: Duration) -> String {
+    match interval.as_millis() {
+        ms if ms < 1000 => format!("{}ms", ms),
+        ms if ms % 1000 == 0 => format!("{}s", ms / 1000),
+        _ => format!("{:.1}s", interval.as_secs_f64()),
+    }
+}
+
+// "Up 6 h 12 m, booted 2024-05-01 08:32" as of the snapshot on screen; empty when the source
+// doesn't know the boot time
+fn uptime(app: &App) -> String {
+    let booted = chrono::DateTime::from_timestamp(app.totals.boot_time as i64, 0).filter(|_| app.totals.boot_time > 0);
+    match booted {
+        Some(booted) => format!(
+            "Up {}, booted {}",
+            app::format_duration((app.sampled_ms / 1000).saturating_sub(app.totals.boot_time as i64).max(0) as u64),
+            booted.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+        ),
+        None => String::new(),
+    }
+}
+
+// "  PSI cpu 0%/0% mem 1%/0% io 12%/3%": the share of the last 10 and 60 seconds some task
+// waited for each resource. Nothing where the kernel doesn't report it.
+fn pressure<'a>(app: &App, theme: &Theme) -> Vec<Span<'a>> {
+    if app.totals.pressure.is_empty() {
+        return Vec::new();
+    }
+    let mut spans = vec![Span::raw("  PSI")];
+    for &(resource, p) in &app.totals.pressure {
+        let full = p.full.map_or(0.0, |f| f.0);
+        let style = if p.some.0 >= theme::PSI_CRIT || full >= theme::PSI_WARN {
+            theme.error
+        } else if p.some.0 >= theme::PSI_WARN {
+            theme.warning
+        } else {
+            theme.text
+        };
+        let name = if resource == "memory" { "mem" } else { resource };
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(format!("{} {:.0}%/{:.0}%", name, p.some.0, p.some.1), style));
+    }
+    spans
+}
+
+// "  mem pressure warning": macOS's stand-in for PSI, in the style memory() gives it
+fn mem_pressure<'a>(app: &App, theme: &Theme) -> Option<Span<'a>> {
+    let pressure = app.totals.mem_pressure?;
+    Some(Span::styled(format!("  mem pressure {}", pressure.level.name()), pressure_style(pressure.level, theme)))
+}
+
+fn pressure_style(level: PressureLevel, theme: &Theme) -> Style {
+    match level {
+        PressureLevel::Normal => theme.text,
+        PressureLevel::Warning => theme.warning,
+        PressureLevel::Critical => theme.error,
+    }
+}
+
+// "  governor powersave, turbo off"; laptops on battery often run the powersave governor, which is the
+// whole answer to why everything is slow
+fn cpufreq(freq: &procfs::CpuFreq) -> String {
+    match freq.boost {
+        Some(boost) => format!("  governor {}, turbo {}", freq.governor, if boost { "on" } else { "off" }),
+        None => format!("  governor {}", freq.governor),
+    }
+}
+
+// "  steal 7%  guest 25%" where either has been seen on this machine
+fn virtualization<'a>(app: &App, theme: &Theme) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    if let Some(steal) = app.virtualization.steal {
+        let style = if steal >= theme::STEAL_CRIT {
+            theme.error
+        } else if steal >= theme::STEAL_WARN {
+            theme.warning
+        } else {
+            theme.text
+        };
+        spans.push(Span::styled(format!("  steal {:.0}%", steal), style));
+    }
+    if let Some(guest) = app.virtualization.guest {
+        spans.push(Span::raw(format!("  guest {:.0}%", guest)));
+    }
+    spans
+}
+
+// "  swap I/O 0 B/s in, 4.2 MB/s out", red once pages have moved for several refreshes in a
+// row; nothing where the source doesn't report swap activity
+fn swap_io<'a>(app: &App, theme: &Theme) -> Option<Span<'a>> {
+    let (rate_in, rate_out) = app.swap.rate?;
+    let text = format!("  swap I/O {} in, {} out", app::format_bytes(rate_in, "/s").trim(), app::format_bytes(rate_out, "/s").trim());
+    Some(Span::styled(text, if app.swap.swapping() { theme.error } else { theme.text }))
+}
+
+// "Mem total 7.8 GB  used 2.1 GB  available 5.2 GB  cached 4.9 GB  buffers 39.9 MB  dirty
+// 216.0 KB", in the warning or error style when little is available. Cache is handed back
+// when programs want it, so it is available memory rather than used memory pressure. macOS
+// rates its pressure itself, so there "Mem pressure normal, 62% free" leads and sets the style.
+fn memory<'a>(totals: &SystemTotals, theme: &Theme) -> Span<'a> {
+    let bytes = |n: u64| app::format_bytes(n as f64, "").trim_start().to_string();
+    let mut text = match totals.mem_pressure {
+        Some(pressure) => format!("Mem pressure {}, {}% free  total {}  used {}", pressure.level.name(), pressure.free, bytes(totals.mem_total), bytes(totals.mem_used)),
+        None => format!("Mem total {}  used {}", bytes(totals.mem_total), bytes(totals.mem_used)),
+    };
+    // Recordings from before it was kept don't know what was available
+    let mut style = theme.text;
+    if totals.mem_available > 0 {
+        text.push_str(&format!("  available {}", bytes(totals.mem_available)));
+    }
+    if let Some(pressure) = totals.mem_pressure {
+        style = pressure_style(pressure.level, theme);
+    } else if totals.mem_available > 0 {
+        let percent = totals.mem_available as f32 * 100.0 / totals.mem_total.max(1) as f32;
+        if percent <= theme::MEM_AVAILABLE_CRIT {
+            style = theme.error;
+        } else if percent <= theme::MEM_AVAILABLE_WARN {
+            style = theme.warning;
+        }
+    }
+    if let Some(detail) = totals.mem_detail {
+        text.push_str(&format!("  cached {}  buffers {}  dirty {}", bytes(detail.cached), bytes(detail.buffers), bytes(detail.dirty)));
+    }
+    Span::styled(text, style)
+}
+
+// The breakdown, and a second line for huge pages where any are set aside
+fn memory_lines(app: &App) -> u16 {
+    match app.memory_detail {
+        true => 1 + !app.totals.huge_pages.is_empty() as u16,
+        false => 0,
+    }
+}
+
+// "Huge pages 2MiB 12/64 used, 3 reserved, 0 surplus  THP 120.0 MB"
+fn huge_pages(totals: &SystemTotals) -> String {
+    let mut text = "Huge pages".to_string();
+    for h in &totals.huge_pages {
+        text.push_str(&format!(" {} {}/{} used, {} reserved, {} surplus ", huge_page_size(h.size), h.total - h.free.min(h.total), h.total, h.reserved, h.surplus));
+    }
+    if let Some(thp) = totals.mem_detail.and_then(|m| m.anon_huge_pages) {
+        text.push_str(&format!(" THP {}", app::format_bytes(thp as f64, "").trim_start()));
+    }
+    text.trim_end().to_string()
+}
+
+// Page sizes are powers of two: "64KiB", "2MiB", "1GiB"
+fn huge_page_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{}GiB", b >> 30),
+        b if b >= 1 << 20 => format!("{}MiB", b >> 20),
+        b => format!("{}KiB", b >> 10),
+    }
+}
+
+// All interfaces together: the rate now, the highest this session and what moved since start
+fn traffic(app: &App) -> String {
+    let (rate, peak, total) = (app.net.rate, app.net.peak, app.net.total);
+    format!(
+        "Net ↓{} ↑{}  peak ↓{} ↑{}  session ↓{} ↑{}",
+        app::format_bytes(rate.0, "/s"), app::format_bytes(rate.1, "/s"),
+        app::format_bytes(peak.0, "/s"), app::format_bytes(peak.1, "/s"),
+        app::format_bytes(total.0 as f64, ""), app::format_bytes(total.1 as f64, ""),
+    )
+}
+
+// "eth0 ↓1234 KB ↑56 KB | wlan0 ↓0 KB ↑0 KB down", with each interface's errors and drops
+// since the previous refresh when show_net_errors is on. One with new errors is drawn in the
+// error style.
+fn interfaces<'a>(app: &App, theme: &Theme) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    for (i, n) in app.net.interfaces.iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(" | "));
+        }
+        let style = if n.errors != (0, 0) { theme.error } else { theme.text };
+        let mut text = format!("{} ↓{} KB ↑{} KB", n.name, n.received / 1024, n.transmitted / 1024);
+        if app.config.show_net_errors {
+            text.push_str(&format!(" err {}/{}", n.errors.0, n.errors.1));
+            if let Some((received, transmitted)) = n.dropped {
+                text.push_str(&format!(" drop {}/{}", received, transmitted));
+            }
+        }
+        spans.push(Span::styled(text, style));
+        if n.up == Some(false) {
+            spans.push(Span::styled(" down", theme.warning));
+        }
+    }
+    spans
+}
+
+// "▶ 2024-05-01 03:14:05  4x  [#######-----]  120/480", in local time
+fn timeline(replay: &ReplayStatus) -> String {
+    let time = chrono::DateTime::from_timestamp_millis(replay.time_ms)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "?".to_string());
+    let done = if replay.frames > 1 { replay.index * TIMELINE_WIDTH / (replay.frames - 1) } else { TIMELINE_WIDTH };
+    let mut text = format!(
+        "{} {}  {}x  [{}{}]  {}/{}",
+        if replay.playing { "▶" } else { "⏸" }, time, replay.speed,
+        "#".repeat(done), "-".repeat(TIMELINE_WIDTH - done), replay.index + 1, replay.frames,
+    );
+    if let Some(error) = &replay.error {
+        text.push_str(&format!("  ({})", error));
+    }
+    text
+}
+
+// The first key bound to `action`, quoted, for inline hints
+fn key_hint(keymap: &Keymap, action: Action) -> String {
+    match keymap.bindings().iter().find(|(a, _)| *a == action).and_then(|(_, keys)| keys.first()) {
+        Some(key) => format!("'{}'", keys::format_key(key)),
+        None => "(unbound)".to_string(),
+    }
+}
+
+// Every action with its effective keys, drawn over the middle of the screen
+fn draw_help<B: Backend>(f: &mut Frame<B>, keymap: &Keymap, replaying: bool, theme: &Theme, size: Rect) {
+    let mut rows: Vec<Row> = keymap.bindings().iter().filter(|(action, _)| action.listed()).map(|(action, keys)| {
+        let keys = if keys.is_empty() { "(unbound)".to_string() } else { keys.iter().map(keys::format_key).collect::<Vec<_>>().join(", ") };
+        Row::new(vec![keys, action.description().to_string()])
+    }).collect();
+    rows.push(Row::new(vec!["Esc".to_string(), "Clear the search and the user filter".to_string()]));
+    if cfg!(target_os = "linux") {
+        rows.push(Row::new(vec!["PSI io 12%/3%".to_string(), "Share of time tasks waited, 10 s/60 s".to_string()]));
+    }
+    if replaying {
+        for (keys, description) in [("Space", "Play or pause the replay"), ("Left, Right", "Step one snapshot"), ("<, >", "Slower or faster playback")] {
+            rows.push(Row::new(vec![keys.to_string(), description.to_string()]));
+        }
+    }
+
+    // What works on this platform goes beside the bindings where there is room, and after them
+    // where there isn't
+    let features = system::features();
+    let feature_rows = || features.iter().map(|&(name, on)| Row::new(vec![Cell::from(if on { "yes" } else { "no" }), Cell::from(name)]).style(if on { theme.text } else { theme.text.add_modifier(Modifier::DIM) }));
+    let platform = format!("On {}", std::env::consts::OS);
+    let beside = size.width >= 60 + FEATURES_WIDTH;
+    if !beside {
+        rows.push(Row::new(vec![String::new(), String::new()]));
+        rows.push(Row::new(vec![platform.clone(), String::new()]).style(theme.header));
+        rows.extend(feature_rows());
+    }
+
+    let width = size.width.min(60 + if beside { FEATURES_WIDTH } else { 0 });
+    let height = size.height.min(rows.len() as u16 + 2);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let (keys_area, features_area) = match beside {
+        true => (Rect { width: 60, ..area }, Some(Rect { x: area.x + 60, width: FEATURES_WIDTH, height: area.height.min(features.len() as u16 + 2), ..area })),
+        false => (area, None),
+    };
+    let widths = [Constraint::Length(20), Constraint::Length(keys_area.width.saturating_sub(23))];
+    let table = Table::new(rows)
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title("Help (any key to close)"))
+        .style(theme.text)
+        .widths(&widths);
+    f.render_widget(Clear, area);
+    f.render_widget(table, keys_area);
+    if let Some(features_area) = features_area {
+        let widths = [Constraint::Length(3), Constraint::Length(FEATURES_WIDTH - 6)];
+        let table = Table::new(feature_rows())
+            .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(platform))
+            .style(theme.text)
+            .widths(&widths);
+        f.render_widget(table, features_area);
+    }
+}
+
+// Wide enough for the longest feature name
+const FEATURES_WIDTH: u16 = 42;
+
+// Every rule with where it stands: firing, holding but not for long enough yet, or quiet
+fn draw_alerts<B: Backend>(f: &mut Frame<B>, alerts: &Alerts, theme: &Theme, size: Rect) {
+    let rows: Vec<Row> = alerts.status(Instant::now()).into_iter().map(|status| {
+        let (state, style) = match (status.firing, status.pending) {
+            (0, None) => ("ok".to_string(), theme.text),
+            (0, Some(held)) => (format!("holding {} of {}", short_duration(held), short_duration(status.rule.condition.duration)), theme.warning),
+            (1, _) => ("firing".to_string(), theme.error),
+            (n, _) => (format!("firing ({})", n), theme.error),
+        };
+        let last = match status.last_fired {
+            Some(ago) => format!("fired {} ago", short_duration(ago)),
+            None => "never fired".to_string(),
+        };
+        Row::new(vec![Cell::from(status.rule.name.clone()), Cell::from(state).style(style), Cell::from(last)])
+    }).collect();
+    let width = size.width.min(90);
+    let height = size.height.min(rows.len() as u16 + 2);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let widths = [Constraint::Length(width.saturating_sub(43)), Constraint::Length(20), Constraint::Length(18)];
+    let title = if alerts.enabled { "Alerts (any key to close)" } else { "Alerts, turned off (any key to close)" };
+    let table = Table::new(rows)
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(title))
+        .style(theme.text)
+        .widths(&widths);
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
+
+// The processes that can keep the rest off the CPU, as of the latest refresh
+fn draw_realtime<B: Backend>(f: &mut Frame<B>, procs: &[&ProcInfo], theme: &Theme, size: Rect) {
+    let rows: Vec<Row> = procs.iter().map(|p| {
+        let policy = p.scheduling.map_or_else(|| "-".to_string(), |s| s.to_string());
+        let nice = p.nice.map_or_else(|| "-".to_string(), |nice| nice.to_string());
+        let style = if p.realtime() { theme.warning } else { theme.text };
+        Row::new(vec![Cell::from(p.pid_text.as_str()), Cell::from(p.name.as_str()), Cell::from(policy).style(style), Cell::from(nice)])
+    }).collect();
+    let width = size.width.min(80);
+    let height = size.height.min(rows.len() as u16 + 3);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let widths = [Constraint::Length(8), Constraint::Length(width.saturating_sub(47)), Constraint::Length(26), Constraint::Length(6)];
+    let table = Table::new(rows)
+        .header(Row::new(vec!["PID", "Name", "Policy", "Nice"]).style(theme.header))
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title("Real-time and boosted processes (any key to close)"))
+        .style(theme.text)
+        .widths(&widths);
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
+
+// One row per socket, listening ones first, the way ss lists them
+// What popups and prompts call their process; one that has exited keeps what was last read
+// and says so here
+fn process_label(name: &str, process: &ProcessHandle, exited: bool) -> String {
+    if exited {
+        format!("{} ({}, exited)", name, process.pid)
+    } else {
+        format!("{} ({})", name, process.pid)
+    }
+}
+
+fn draw_connections<B: Backend>(f: &mut Frame<B>, view: &ConnectionsView, exited: bool, theme: &Theme, size: Rect) {
+    let mut list: Vec<_> = view.list.iter().collect();
+    list.sort_by_key(|c| (c.state != "LISTEN", c.protocol, c.local));
+    let mut rows: Vec<Row> = list.iter().map(|c| {
+        Row::new(vec![c.protocol.to_string(), c.local.to_string(), c.remote.to_string(), c.state.to_string()])
+    }).collect();
+    if rows.is_empty() {
+        rows.push(Row::new(vec!["No TCP or UDP sockets open".to_string()]));
+    }
+    let width = size.width.min(100);
+    let height = size.height.min(rows.len() as u16 + 3);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let address = width.saturating_sub(23) / 2;
+    let widths = [Constraint::Length(5), Constraint::Length(address), Constraint::Length(address), Constraint::Length(12)];
+    let table = Table::new(rows)
+        .header(Row::new(vec!["Proto", "Local", "Remote", "State"]).style(theme.header))
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(format!("Connections of {}, any key to close", process_label(&view.name, &view.process, exited))))
+        .style(theme.text)
+        .widths(&widths);
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
+
+// One row per listening socket, by port, with the process holding it where it can be seen
+fn draw_listeners<B: Backend>(f: &mut Frame<B>, view: &ListenersView, procs: &[ProcInfo], theme: &Theme, size: Rect) {
+    let rows: Vec<Row> = view.list.iter().map(|l| {
+        let owner = match l.pid {
+            Some(pid) => match procs.iter().find(|p| p.pid == Pid::from_u32(pid)) {
+                Some(p) => format!("{} ({})", p.name, pid),
+                None => pid.to_string(),
+            },
+            None => "-".to_string(),
+        };
+        Row::new(vec![l.protocol.to_string(), l.local.to_string(), owner])
+    }).collect();
+    let width = size.width.min(80);
+    let height = size.height.min(rows.len() as u16 + 3);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let address = width.saturating_sub(9) / 2;
+    let widths = [Constraint::Length(5), Constraint::Length(address), Constraint::Length(address)];
+    let table = Table::new(rows)
+        .header(Row::new(vec!["Proto", "Local", "Process"]).style(theme.header))
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title("Listening (Enter to select the process, any other key to close)"))
+        .style(theme.text)
+        .widths(&widths)
+        .highlight_style(theme.selected_row);
+    let mut state = TableState::default();
+    state.select(Some(view.row));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+// One row per session with how many processes its user has, so a heavy job can be told from
+// someone's work in progress before it is killed
+fn draw_logins<B: Backend>(f: &mut Frame<B>, view: &LoginsView, procs: &[ProcInfo], theme: &Theme, size: Rect) {
+    let rows: Vec<Row> = view.list.iter().map(|l| {
+        let since = match chrono::DateTime::from_timestamp(l.since as i64, 0) {
+            Some(t) => t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string(),
+            None => "unknown".to_string(),
+        };
+        let count = procs.iter().filter(|p| p.user.as_deref() == Some(l.user.as_str())).count();
+        Row::new(vec![l.user.clone(), l.terminal.clone(), since, l.host.clone().unwrap_or_else(|| "local".to_string()), format!("{} processes", count)])
+    }).collect();
+    let width = size.width.min(90);
+    let height = size.height.min(rows.len() as u16 + 3);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let widths = [Constraint::Length(14), Constraint::Length(8), Constraint::Length(16), Constraint::Length(width.saturating_sub(60)), Constraint::Length(14)];
+    let table = Table::new(rows)
+        .header(Row::new(vec!["User", "Terminal", "Since", "From", "Running"]).style(theme.header))
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title("Logged in (Enter to show the user's processes, any other key to close)"))
+        .style(theme.text)
+        .widths(&widths)
+        .highlight_style(theme.selected_row);
+    let mut state = TableState::default();
+    state.select(Some(view.row));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+// One labelled line per field; settings the source can't read are left out
+// Rows of the CPU graph in the details popup
+const TRAIL_HEIGHT: u16 = 3;
+
+fn draw_details<B: Backend>(f: &mut Frame<B>, view: &DetailsView, trail: Option<&Trail>, exited: bool, theme: &Theme, size: Rect) {
+    let start_time = view.process.start_time;
+    let started = match chrono::DateTime::from_timestamp(start_time as i64, 0) {
+        Some(t) if start_time > 0 && exited => t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+        Some(t) if start_time > 0 => {
+            let running = (chrono::Utc::now() - t).num_seconds().max(0) as u64;
+            format!("{} (running {})", t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"), app::format_duration(running))
+        },
+        _ => "unknown".to_string(),
+    };
+    let mut fields = vec![
+        ("PID", view.process.pid.to_string()),
+        ("Parent", view.parent.map_or_else(|| "none".to_string(), |p| p.to_string())),
+        ("User", view.user.clone().unwrap_or_else(|| "unknown".to_string())),
+        ("State", view.state.to_string()),
+        ("Started", started),
+    ];
+    match &view.details.affinity {
+        Some(Ok(cpus)) => fields.push(("CPU affinity", sched::format_cpu_list(cpus))),
+        Some(Err(e)) => fields.push(("CPU affinity", format!("can't be read: {}", e))),
+        None => {},
+    }
+    if let Some(nice) = view.details.nice {
+        fields.push(("Nice", nice.to_string()));
+    }
+    match view.details.scheduling {
+        Some(scheduling) if scheduling.policy.realtime() => fields.push(("Scheduling", format!("{} (real-time: runs ahead of every normal process)", scheduling))),
+        Some(scheduling) => fields.push(("Scheduling", scheduling.to_string())),
+        None => {},
+    }
+    match &view.details.io_priority {
+        // Without a class of its own the process is served by its nice value
+        Some(Ok(io)) if io.class == IoClass::None => fields.push(("I/O priority", format!("{} (from nice)", io.effective(view.details.nice.unwrap_or(0))))),
+        Some(Ok(io)) => fields.push(("I/O priority", io.to_string())),
+        Some(Err(e)) => fields.push(("I/O priority", format!("can't be read: {}", e))),
+        None => {},
+    }
+    match &view.details.memory {
+        Some(Ok(map)) => fields.extend([
+            ("RSS", app::format_mem(map.rss)),
+            ("PSS", app::format_mem(map.pss)),
+            ("Shared", format!("{} clean, {} dirty", app::format_mem(map.shared_clean), app::format_mem(map.shared_dirty))),
+            ("Private", format!("{} clean, {} dirty", app::format_mem(map.private_clean), app::format_mem(map.private_dirty))),
+            ("Swapped", app::format_mem(map.swap)),
+        ]),
+        Some(Err(e)) => fields.push(("Memory map", format!("can't be read: {}", e))),
+        None => {},
+    }
+    match &view.details.limits {
+        Some(Ok(limits)) => {
+            fields.push(("Limits", format!("{:<18}{}", "soft", "hard")));
+            for (name, label) in LIMITS {
+                let Some(limit) = limits.iter().find(|l| l.name == name) else { continue };
+                let mut soft = limit_text(limit.soft, &limit.unit);
+                // Next to the FDS count, how close the process is to running out
+                if name == "Max open files" && let Some(fds) = view.fds {
+                    soft.push_str(&format!(" ({} open)", fds));
+                }
+                fields.push((label, format!("{:<18}{}", soft, limit_text(limit.hard, &limit.unit))));
+            }
+        },
+        Some(Err(e)) => fields.push(("Limits", format!("can't be read: {}", e))),
+        None => {},
+    }
+    let width = size.width.min(70);
+    // Long capability lists go on as many lines as they take, under a blank label
+    if let Some(caps) = view.details.capabilities {
+        for (label, mask) in [("Effective caps", caps.effective), ("Permitted caps", caps.permitted)] {
+            for (i, line) in capability_lines(mask, width.saturating_sub(18) as usize).into_iter().enumerate() {
+                fields.push((if i == 0 { label } else { "" }, line));
+            }
+        }
+    }
+    let lines: Vec<Spans> = fields.into_iter().map(|(label, value)| {
+        Spans::from(vec![Span::styled(format!("{:<16}", label), theme.header), Span::raw(value)])
+    }).collect();
+    // The CPU graph goes under the fields, with a line of figures above it
+    let stats = trail.and_then(Trail::stats);
+    let (label, graph) = if stats.is_some() { (1, TRAIL_HEIGHT) } else { (0, 0) };
+    let height = size.height.min(lines.len() as u16 + label + graph + 2);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let block = Block::default().borders(Borders::ALL).border_style(theme.border).title(format!("{}, any key to close", process_label(&view.name, &view.process, exited)));
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(label), Constraint::Length(graph)].as_ref())
+        .split(inner);
+    f.render_widget(Paragraph::new(lines).style(theme.text), chunks[0]);
+    if let (Some(trail), Some((min, avg, max))) = (trail, stats) {
+        let text = format!("CPU, last minute: min {}  avg {}  max {}", app::format_cpu(min), app::format_cpu(avg), app::format_cpu(max));
+        f.render_widget(Paragraph::new(text).style(theme.header), chunks[1]);
+        // The newest samples that fit, in hundredths of a percent so low usage still shows
+        let cpu: Vec<u64> = trail.cpu().skip(trail.len().saturating_sub(chunks[2].width as usize)).map(|cpu| (cpu.max(0.0) * 100.0) as u64).collect();
+        f.render_widget(Sparkline::default().data(&cpu).style(theme.text), chunks[2]);
+    }
+}
+
+
+// The rows of /proc/<pid>/limits worth showing, with their labels in the details popup
+const LIMITS: [(&str, &str); 4] = [
+    ("Max open files", "  open files"),
+    ("Max processes", "  processes"),
+    ("Max address space", "  address space"),
+    ("Max core file size", "  core file"),
+];
+
+// Sizes in MB like the memory figures, counts as they are
+fn limit_text(limit: Option<u64>, unit: &str) -> String {
+    match limit {
+        None => "unlimited".to_string(),
+        Some(bytes) if unit == "bytes" => app::format_mem(bytes),
+        Some(n) => n.to_string(),
+    }
+}
+
+// "none", "all" for a full set, or the names packed into lines of at most `width`
+fn capability_lines(mask: u64, width: usize) -> Vec<String> {
+    if mask == 0 {
+        return vec!["none".to_string()];
+    }
+    if procfs::all_capabilities(mask) {
+        return vec!["all".to_string()];
+    }
+    let mut lines: Vec<String> = Vec::new();
+    for name in procfs::capability_names(mask) {
+        match lines.last_mut() {
+            Some(line) if line.len() + name.len() < width => {
+                line.push(' ');
+                line.push_str(&name);
+            },
+            _ => lines.push(name),
+        }
+    }
+    lines
+}
+
+// The latest `on_fire` runs first: when, which rule, how it ended, the command and its output
+fn draw_commands<B: Backend>(f: &mut Frame<B>, log: &VecDeque<Run>, theme: &Theme, size: Rect) {
+    let width = size.width.min(100);
+    let height = size.height.min(24);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let mut lines = Vec::new();
+    for run in log.iter().rev() {
+        let style = match run.state {
+            RunState::Running | RunState::Exited(Some(0)) => theme.text,
+            RunState::Skipped => theme.warning,
+            _ => theme.error,
+        };
+        lines.push(Spans::from(vec![
+            Span::styled(format!("{} {}: ", run.started.format("%H:%M:%S"), run.rule), theme.header),
+            Span::styled(exec::describe(&run.state), style),
+        ]));
+        lines.push(Spans::from(format!("  $ {}", run.command)));
+        // A few lines of output each, so one chatty command doesn't push the rest off
+        let output: Vec<&str> = run.output.lines().collect();
+        for line in output.iter().take(COMMAND_OUTPUT_LINES) {
+            lines.push(Spans::from(format!("  {}", line)));
+        }
+        if output.len() > COMMAND_OUTPUT_LINES {
+            lines.push(Spans::from(format!("  ({} more lines)", output.len() - COMMAND_OUTPUT_LINES)));
+        }
+    }
+    let text = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title("Alert commands (any key to close)"))
+        .style(theme.text);
+    f.render_widget(Clear, area);
+    f.render_widget(text, area);
+}
+
+// "45s", "12m" or "3h"
+fn short_duration(d: Duration) -> String {
+    match d.as_secs() {
+        secs if secs < 60 => format!("{}s", secs),
+        secs if secs < 3600 => format!("{}m", secs / 60),
+        secs => format!("{}h", secs / 3600),
+    }
+}
+
+// The watch patterns with how many processes each matches now
+fn draw_watches<B: Backend>(f: &mut Frame<B>, watches: &[String], procs: &[ProcInfo], selected: usize, theme: &Theme, size: Rect) {
+    let rows: Vec<(String, String)> = watches.iter().map(|pattern| {
+        let query = Query::parse(pattern);
+        let count = procs.iter().filter(|p| query.matches(p)).count();
+        (pattern.clone(), format!("{} processes", count))
+    }).collect();
+    draw_list(f, "Watches (d to remove, any other key to close)", rows, selected, theme.watch, theme, size);
+}
+
+fn draw_users<B: Backend>(f: &mut Frame<B>, picker: &UserPicker, theme: &Theme, size: Rect) {
+    let rows = picker.users.iter().map(|(user, count)| (user.clone(), format!("{} processes", count))).collect();
+    draw_list(f, "Users (Enter to filter, any other key to close)", rows, picker.row, theme.text, theme, size);
+}
+
+fn draw_sort_menu<B: Backend>(f: &mut Frame<B>, menu: &SortMenu, sort_by: SortBy, descending: bool, theme: &Theme, size: Rect) {
+    let rows = menu.columns.iter().map(|&c| {
+        let note = match c.sort() == sort_by {
+            true if descending => "▼ descending",
+            true => "▲ ascending",
+            false => "",
+        };
+        (c.title().to_string(), note.to_string())
+    }).collect();
+    draw_list(f, "Sort by (Enter sorts, again reverses)", rows, menu.row, theme.text, theme, size);
+}
+
+// A popup list of names with a count or note beside each, one of them highlighted
+fn draw_list<B: Backend>(f: &mut Frame<B>, title: &str, rows: Vec<(String, String)>, selected: usize, style: Style, theme: &Theme, size: Rect) {
+    let width = size.width.min(60);
+    let height = size.height.min(rows.len() as u16 + 2);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let widths = [Constraint::Length(width.saturating_sub(18)), Constraint::Length(14)];
+    let rows: Vec<Row> = rows.into_iter().map(|(name, note)| Row::new(vec![name, note])).collect();
+    let table = Table::new(rows)
+        .block(Block::default().borders(Borders::ALL).border_style(theme.border).title(title))
+        .style(style)
+        .widths(&widths)
+        .highlight_style(theme.selected_row);
+    let mut state = TableState::default();
+    state.select(Some(selected));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+// CPU and memory sparklines of one process, drawn over the middle of the screen
+fn draw_history<B: Backend>(f: &mut Frame<B>, view: &HistoryView, theme: &Theme, size: Rect) {
+    let width = size.width.min(80);
+    let height = size.height.min(12);
+    let area = Rect::new(size.x + (size.width - width) / 2, size.y + (size.height - height) / 2, width, height);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.border)
+        .title(format!("{} ({}), last hour (any key to close)", view.name, view.pid));
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    if view.samples.is_empty() {
+        let text = "No samples in the last hour. Only processes above the --history-min-cpu or --history-min-mem-mb thresholds are kept.";
+        f.render_widget(Paragraph::new(text).style(theme.text).wrap(tui::widgets::Wrap { trim: true }), inner);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(4), Constraint::Length(1), Constraint::Length(4)].as_ref())
+        .split(inner);
+    let columns = inner.width as usize;
+    // Hundredths of a percent, so low CPU usage still shows
+    let cpu = buckets(view, columns, |s| (s.cpu.max(0.0) * 100.0) as u64);
+    let mem = buckets(view, columns, |s| s.mem);
+    let peak_cpu = view.samples.iter().map(|s| s.cpu).fold(0.0, f32::max);
+    let peak_mem = view.samples.iter().map(|s| s.mem).max().unwrap_or(0);
+    f.render_widget(Paragraph::new(format!("CPU, peak {}", app::format_cpu(peak_cpu))).style(theme.header), chunks[0]);
+    f.render_widget(Sparkline::default().data(&cpu).style(theme.text), chunks[1]);
+    f.render_widget(Paragraph::new(format!("Memory, peak {}", app::format_mem(peak_mem))).style(theme.header), chunks[2]);
+    f.render_widget(Sparkline::default().data(&mem).style(theme.text), chunks[3]);
+}
+
+// Splits the window into `columns` equal slices and keeps the highest value of each, so a
+// short spike stays visible however many samples share its column. Slices without samples,
+// when the process was idle or not running, are 0.
+fn buckets(view: &HistoryView, columns: usize, value: impl Fn(&HistorySample) -> u64) -> Vec<u64> {
+    let mut out = vec![0; columns];
+    let span = (view.until_ms - view.since_ms).max(1) as i128;
+    for sample in &view.samples {
+        let offset = (sample.time_ms - view.since_ms).max(0) as i128;
+        let column = ((offset * columns as i128 / span) as usize).min(columns.saturating_sub(1));
+        if let Some(slot) = out.get_mut(column) {
+            *slot = (*slot).max(value(sample));
+        }
+    }
+    out
+}
+
+// Table rows borrow the cached cell text, so no per-frame string formatting happens here
+// outside of tree view, where the name needs its indentation. `depths` is empty when flat and
+// `watched` when nothing is watched. A watched row takes the watch style over the CPU
+// thresholds; the selection is drawn over both.
+// What the Name column shows and how many cells it has for it
+#[derive(Clone, Copy)]
+pub struct NameLayout {
+    pub display: NameDisplay,
+    pub width: u16,
+    pub sum: TreeSum,
+    // Put RT_BADGE before the names of real-time processes
+    pub realtime: bool,
+}
+
+pub const RT_BADGE: &str = "RT ";
+
+// Which subtree total parent rows show after their name, dimmed
+#[derive(Clone, Copy, PartialEq)]
+pub enum TreeSum {
+    Off,
+    Cpu,
+    Mem,
+}
+
+impl TreeSum {
+    pub fn new(app: &App) -> Self {
+        match app.sort_by {
+            _ if !app.tree_view || !app.tree_totals => TreeSum::Off,
+            SortBy::Mem => TreeSum::Mem,
+            _ => TreeSum::Cpu,
+        }
+    }
+
+    fn text(self, p: &ProcInfo) -> Option<String> {
+        let (cpu, mem) = p.subtree?;
+        match self {
+            TreeSum::Off => None,
+            TreeSum::Cpu => Some(format!(" Σ {:.0}%", cpu)),
+            TreeSum::Mem => Some(format!(" Σ {}", app::format_bytes(mem as f64, "").trim())),
+        }
+    }
+}
+
+pub fn process_rows<'a>(procs: &'a [ProcInfo], order: &[usize], depths: &[usize], watched: &[bool], columns: &[Column], name: NameLayout, theme: &Theme) -> Vec<Row<'a>> {
+    order.iter().enumerate().map(|(row, &i)| {
+        let p = &procs[i];
+        let watch = watched.get(i).copied().unwrap_or(false);
+        let cells = Row::new(columns.iter().map(|&column| match column {
+            Column::Name => {
+                let label = match depths.get(row) {
+                    Some(&depth) if depth > 0 => Cow::Owned(format!("{}└─ {}", "  ".repeat(depth - 1), p.display_name(name.display))),
+                    _ => Cow::Borrowed(p.display_name(name.display)),
+                };
+                match (name.sum.text(p), name.realtime && p.realtime()) {
+                    (None, false) => match label {
+                        Cow::Borrowed(label) => Cell::from(width::fit(label, name.width.into())),
+                        Cow::Owned(label) => Cell::from(width::fit(&label, name.width.into()).into_owned()),
+                    },
+                    (sum, badge) => {
+                        let mut spans = Vec::new();
+                        let mut room = usize::from(name.width);
+                        if badge {
+                            spans.push(Span::styled(RT_BADGE, theme.warning));
+                            room = room.saturating_sub(RT_BADGE.len());
+                        }
+                        let sum = sum.map(|sum| Span::styled(sum, Style::default().add_modifier(Modifier::DIM)));
+                        spans.push(Span::raw(width::fit(&label, room.saturating_sub(sum.as_ref().map_or(0, Span::width))).into_owned()));
+                        spans.extend(sum);
+                        Cell::from(Spans::from(spans))
+                    },
+                }
+            },
+            Column::Cpu if watch => Cell::from(p.cpu_text.as_str()),
+            Column::Cpu => Cell::from(p.cpu_text.as_str()).style(cpu_style(p.cpu, theme)),
+            _ => Cell::from(column.text(p)),
+        }).collect::<Vec<_>>());
+        if watch { cells.style(theme.watch) } else { cells }
+    }).collect()
+}
+
+fn cpu_style(cpu: f32, theme: &Theme) -> Style {
+    if cpu >= theme::CPU_CRIT {
+        theme.cpu_crit
+    } else if cpu >= theme::CPU_WARN {
+        theme.cpu_warn
+    } else {
+        Style::default()
+    }
+}
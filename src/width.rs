@@ -0,0 +1,72 @@
+// Terminal cell widths of process names and command lines. CJK characters and most emoji take
+// two cells, combining marks none, and an emoji joined with zero-width joiners is one glyph
+// however many code points it has, so lengths are counted in grapheme clusters and cells
+// rather than chars.
+
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const ELLIPSIS: &str = "…";
+
+pub fn width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+// `text` cut to at most `cells` with an ellipsis at the end when it doesn't fit. Clusters are
+// never split, so a wide character that would straddle the edge is dropped and a space takes
+// its place: the result is always exactly as wide as the column or narrower than it.
+pub fn fit(text: &str, cells: usize) -> Cow<'_, str> {
+    if width(text) <= cells {
+        return Cow::Borrowed(text);
+    }
+    let Some(room) = cells.checked_sub(width(ELLIPSIS)) else { return Cow::Borrowed("") };
+    let mut out = String::with_capacity(text.len().min(cells * 4));
+    let mut used = 0;
+    for cluster in text.graphemes(true) {
+        let w = width(cluster);
+        if used + w > room {
+            break;
+        }
+        out.push_str(cluster);
+        used += w;
+    }
+    out.extend(std::iter::repeat_n(' ', room - used));
+    out.push_str(ELLIPSIS);
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_and_zero_width_characters_are_measured_in_cells() {
+        assert_eq!(width("postgres"), 8);
+        assert_eq!(width("微信"), 4);
+        assert_eq!(width("cafe\u{301}"), 4);
+    }
+
+    #[test]
+    fn names_are_cut_on_cluster_boundaries_to_the_column() {
+        assert_eq!(fit("postgres", 8), "postgres");
+        assert_eq!(fit("postgres: checkpointer", 10), "postgres:…");
+        // The third CJK character would need cells 5 and 6 of 6; a space fills the gap
+        assert_eq!(fit("微信开发者工具", 6), "微信 …");
+        assert_eq!(width(&fit("微信开发者工具", 6)), 6);
+        // A combining accent stays on its letter
+        assert_eq!(fit("cafe\u{301}-latte", 5), "cafe\u{301}…");
+        assert_eq!(fit("abc", 0), "");
+    }
+
+    #[test]
+    fn joined_emoji_are_never_split() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let name = format!("{}-sync", family);
+        for cells in 1..width(&name) {
+            let cut = fit(&name, cells);
+            assert_eq!(width(&cut), cells, "{:?}", cut);
+            assert!(!cut.contains('\u{200d}') || cut.starts_with(family), "{:?} in {} cells", cut, cells);
+        }
+    }
+}
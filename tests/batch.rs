@@ -0,0 +1,39 @@
+// The binary run the way scripts run it, with stdout captured. Warnings about the settings
+// have to reach stderr there, since nothing is drawn to show them in and the log is off by
+// default.
+
+use std::{fs, path::PathBuf, process::{Command, Output}};
+
+// A config file of its own, and an empty XDG_CONFIG_HOME so no real one is picked up
+fn run(name: &str, config: &str, env: &[(&str, &str)]) -> Output {
+    let dir: PathBuf = std::env::temp_dir().join(format!("rust-htop-batch-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    fs::write(&path, config).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_htop"))
+        .args(["--once", "--limit", "1", "--config"])
+        .arg(&path)
+        .env("XDG_CONFIG_HOME", &dir)
+        .envs(env.iter().copied())
+        .output()
+        .expect("rust-htop runs");
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn a_clamped_refresh_rate_is_reported_on_stderr() {
+    let output = run("clamped", "refresh_rate = 5\n", &[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("refresh_rate = 5 is outside 100-60000 milliseconds; using 100"), "{}", stderr);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("refresh_rate"));
+}
+
+#[test]
+fn a_skipped_environment_variable_is_reported_on_stderr_with_once() {
+    let output = run("env", "", &[("RUST_HTOP_SORT", "bogus")]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rust-htop: RUST_HTOP_SORT=\"bogus\": ") && stderr.contains("; ignored"), "{}", stderr);
+}
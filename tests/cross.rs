@@ -0,0 +1,29 @@
+// Outside Linux there is only what sysinfo reads portably, so everything that reads /proc or
+// sysfs or calls Linux-only libc functions has to be behind a cfg or a runtime check. This
+// builds the crate for FreeBSD to catch Linux-only code creeping in. It is skipped where that
+// target's standard library isn't installed (`rustup target add x86_64-unknown-freebsd`).
+
+use std::{path::Path, process::Command};
+
+const TARGET: &str = "x86_64-unknown-freebsd";
+
+#[test]
+fn builds_for_freebsd() {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let libdir = Command::new(rustc).args(["--print", "target-libdir", "--target", TARGET]).output().expect("rustc runs");
+    let libdir = String::from_utf8_lossy(&libdir.stdout).trim().to_string();
+    if !Path::new(&libdir).is_dir() {
+        eprintln!("skipped: the {} standard library isn't installed", TARGET);
+        return;
+    }
+    let manifest = env!("CARGO_MANIFEST_DIR");
+    // Its own target directory, since the one running this test is locked. The default
+    // features only: history's bundled SQLite would need a C compiler for the target.
+    let status = Command::new(env!("CARGO"))
+        .args(["check", "--lib", "--bins", "--target", TARGET, "--target-dir"])
+        .arg(Path::new(manifest).join("target").join("cross"))
+        .current_dir(manifest)
+        .status()
+        .expect("cargo runs");
+    assert!(status.success(), "cargo check --target {} failed", TARGET);
+}
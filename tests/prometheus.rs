@@ -0,0 +1,38 @@
+#![cfg(feature = "prometheus")]
+
+use std::{io::{Read, Write}, net::{SocketAddr, TcpStream}, sync::{Arc, RwLock}};
+use htop::{app::App, config::Config, prometheus, server::Server, snapshot::{SharedSnapshot, SystemSnapshot}, system::{FakeNetwork, FakeSource, SystemTotals}};
+
+fn scrape(addr: SocketAddr, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut text = String::new();
+    stream.read_to_string(&mut text).unwrap();
+    let (head, body) = text.split_once("\r\n\r\n").unwrap();
+    (head.to_string(), body.to_string())
+}
+
+#[test]
+fn serves_the_latest_snapshot() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 4096), (10, "nginx", 2.0, 1000), (11, "nginx", 1.0, 500)]);
+    source.networks.push(FakeNetwork { name: "lo".into(), total_received: 42, total_transmitted: 42, ..FakeNetwork::default() });
+    source.totals = SystemTotals { cpus: vec![25.0], cpu_time: None, cpufreq: None, mem_total: 1 << 30, mem_used: 1 << 29, mem_available: 0, mem_detail: None, mem_pressure: None, huge_pages: Vec::new(), swap_total: 0, swap_used: 0, swapped: None, load: [0.5, 0.5, 0.5], boot_time: 0, pressure: Vec::new() };
+    let app = App::new(Config::default(), Box::new(source));
+    let snapshot: SharedSnapshot = Arc::new(RwLock::new(SystemSnapshot::capture_all(&app)));
+
+    let mut server = Server::spawn("127.0.0.1:0", prometheus::handler(Arc::clone(&snapshot), Some("nginx".into()))).unwrap();
+    let addr = server.local_addr();
+    let (head, body) = scrape(addr, "/metrics");
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+    assert!(head.contains("Content-Type: text/plain; version=0.0.4"), "{}", head);
+    assert!(body.contains("rust_htop_cpu_usage_percent{core=\"0\"} 25\n"), "{}", body);
+    assert!(body.contains("rust_htop_memory_used_bytes 536870912\n"));
+    assert!(body.contains("rust_htop_network_received_bytes_total{interface=\"lo\"} 42\n"));
+    assert!(body.contains("rust_htop_process_count{name=\"nginx\"} 2\n"));
+
+    // A refresh published by the main loop is what the next scrape sees
+    snapshot.write().unwrap().memory.used_bytes = 7;
+    assert!(scrape(addr, "/metrics").1.contains("rust_htop_memory_used_bytes 7\n"));
+    assert!(scrape(addr, "/other").0.starts_with("HTTP/1.1 404"));
+    server.shutdown();
+}
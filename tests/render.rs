@@ -0,0 +1,1033 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use htop::{app::{App, HistorySample, HistoryView, Interface, StatusKind}, config::Config, conns::{Connection, Listener}, keys::{Action, KeySpec}, logins::Login, procfs::{self, Capabilities, CpuFreq, HugePages, MemInfo, MemoryMap, Pressure}, record::Recorder, remote::RemoteStatus, replay::ReplaySource, sched::{IoClass, IoPriority, Policy, Scheduling}, snapshot::SystemSnapshot, system::{FakeSource, MemPressure, PressureLevel}, ui};
+use tui::{backend::TestBackend, buffer::Buffer, style::{Color, Modifier}, Terminal};
+
+fn app() -> App {
+    let source = FakeSource::with_processes(&[
+        (1, "systemd", 0.5, 12 * 1024 * 1024),
+        (200, "postgres", 42.0, 512 * 1024 * 1024),
+        (3000, "bash", 1.25, 4 * 1024 * 1024),
+    ]);
+    App::new(Config::default(), Box::new(source))
+}
+
+fn render(app: &mut App, width: u16, height: u16) -> Buffer {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|f| ui::draw(f, app)).unwrap();
+    terminal.backend().buffer().clone()
+}
+
+fn lines(buffer: &Buffer) -> Vec<String> {
+    let width = buffer.area.width as usize;
+    buffer.content.chunks(width).map(|row| row.iter().map(|c| c.symbol.as_str()).collect()).collect()
+}
+
+fn press(app: &mut App, code: KeyCode) {
+    app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+}
+
+fn process_lines(buffer: &Buffer) -> Vec<String> {
+    lines(buffer).into_iter().filter(|l| l.contains("%") && !l.contains("CPU %")).collect()
+}
+
+#[test]
+fn header_and_rows_are_rendered() {
+    let buffer = render(&mut app(), 80, 20);
+    let lines = lines(&buffer);
+    assert!(lines[0].starts_with("Press '/' to search, '?' for help, 'q' to quit"));
+    let header = lines.iter().find(|l| l.contains("PID")).expect("header row");
+    for title in ["PID", "Name", "CPU %", "Memory MB"] {
+        assert!(header.contains(title), "missing {:?} in {:?}", title, header);
+    }
+    // Default sort is CPU descending
+    let rows = process_lines(&buffer);
+    assert_eq!(rows.len(), 3);
+    assert!(rows[0].contains("postgres") && rows[1].contains("bash") && rows[2].contains("systemd"));
+}
+
+#[test]
+fn columns_are_aligned() {
+    let buffer = render(&mut app(), 80, 20);
+    let lines = lines(&buffer);
+    let header = lines.iter().find(|l| l.contains("PID")).unwrap();
+    let name_col = header.find("Name").unwrap();
+    let cpu_col = header.find("CPU %").unwrap();
+    let mem_col = header.find("Memory MB").unwrap();
+    for row in process_lines(&buffer) {
+        assert_ne!(row.chars().nth(name_col), Some(' '), "name cell not aligned in {:?}", row);
+        assert_ne!(row.chars().nth(cpu_col), Some(' '), "cpu cell not aligned in {:?}", row);
+        assert_ne!(row.chars().nth(mem_col), Some(' '), "memory cell not aligned in {:?}", row);
+    }
+    assert!(process_lines(&buffer)[0].contains("512.00 MB"));
+}
+
+#[test]
+fn filter_reduces_rows() {
+    let mut app = app();
+    press(&mut app, KeyCode::Char('/'));
+    for c in "post".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    let buffer = render(&mut app, 80, 20);
+    assert!(lines(&buffer)[0].starts_with("Search: post"));
+    let rows = process_lines(&buffer);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].contains("postgres"));
+}
+
+#[test]
+fn selected_row_is_reversed() {
+    let mut app = app();
+    press(&mut app, KeyCode::Down);
+    assert_eq!(app.table_state.selected(), Some(1));
+    let buffer = render(&mut app, 80, 20);
+    let lines = lines(&buffer);
+    let y = lines.iter().position(|l| l.contains("bash")).unwrap() as u16;
+    let x = lines[y as usize].find("bash").unwrap() as u16;
+    assert!(buffer.get(x, y).modifier.contains(Modifier::REVERSED));
+    let other = lines.iter().position(|l| l.contains("postgres")).unwrap() as u16;
+    assert!(!buffer.get(x, other).modifier.contains(Modifier::REVERSED));
+}
+
+#[test]
+fn watched_rows_beat_cpu_colours_and_sit_under_the_selection() {
+    let source = FakeSource::with_processes(&[(1, "systemd", 95.0, 0), (200, "postgres", 99.0, 0), (3000, "bash", 1.25, 0)]);
+    let mut app = App::new(Config::default(), Box::new(source));
+    press(&mut app, KeyCode::Char('w'));
+    for c in "POST".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    assert!(lines(&render(&mut app, 80, 20))[0].starts_with("Watch: POST (Enter to add, Esc to cancel)"));
+    press(&mut app, KeyCode::Enter);
+    press(&mut app, KeyCode::Down);
+    let buffer = render(&mut app, 80, 20);
+    let text = lines(&buffer);
+    let cpu_cell = |name: &str| {
+        let y = text.iter().position(|l| l.contains(name)).unwrap();
+        buffer.get(text[y].find("9").unwrap() as u16, y as u16).clone()
+    };
+    // postgres is on top and watched, systemd below it is selected
+    assert_eq!(cpu_cell("postgres").fg, Color::Cyan);
+    assert!(!cpu_cell("postgres").modifier.contains(Modifier::REVERSED));
+    assert_eq!(cpu_cell("systemd").fg, Color::Red);
+    assert!(cpu_cell("systemd").modifier.contains(Modifier::REVERSED));
+    press(&mut app, KeyCode::Up);
+    let buffer = render(&mut app, 80, 20);
+    let y = lines(&buffer).iter().position(|l| l.contains("postgres")).unwrap() as u16;
+    assert!(buffer.get(1, y).modifier.contains(Modifier::REVERSED));
+
+    press(&mut app, KeyCode::Char('L'));
+    let text = lines(&render(&mut app, 80, 20));
+    assert!(text.iter().any(|l| l.contains("Watches (d to remove")));
+    assert!(text.iter().any(|l| l.contains("POST") && l.contains("1 processes")));
+    press(&mut app, KeyCode::Char('d'));
+    assert!(app.watches.is_empty() && app.watch_list.is_none());
+    let buffer = render(&mut app, 80, 20);
+    let text = lines(&buffer);
+    let y = text.iter().position(|l| l.contains("postgres")).unwrap();
+    assert_eq!(buffer.get(text[y].find("99").unwrap() as u16, y as u16).fg, Color::Red);
+}
+
+#[test]
+fn small_terminals_do_not_panic() {
+    for (width, height) in [(0, 0), (1, 1), (10, 3), (20, 7), (80, 2), (3, 40)] {
+        render(&mut app(), width, height);
+    }
+}
+
+#[test]
+fn warning_banner_is_dismissed_by_a_key() {
+    let mut app = app();
+    app.set_status(StatusKind::Warning, "config.toml: unknown key `refesh_rate` ignored");
+    let buffer = render(&mut app, 100, 20);
+    assert!(lines(&buffer)[0].starts_with("Warning: config.toml: unknown key `refesh_rate` ignored"));
+    press(&mut app, KeyCode::Down);
+    let buffer = render(&mut app, 100, 20);
+    assert!(lines(&buffer)[0].starts_with("Press '/' to search"));
+}
+
+#[test]
+fn alert_banner_stands_out_until_a_key() {
+    let mut app = app();
+    app.set_status(StatusKind::Alert, "process cpu > 90%: postgres (PID 200) at 95.0% CPU");
+    let buffer = render(&mut app, 100, 20);
+    assert!(lines(&buffer)[0].starts_with("Alert: process cpu > 90%: postgres (PID 200) at 95.0% CPU (press any key to dismiss)"));
+    assert!(buffer.get(0, 0).modifier.contains(Modifier::BOLD));
+    press(&mut app, KeyCode::Down);
+    assert!(lines(&render(&mut app, 100, 20))[0].starts_with("Press '/' to search"));
+}
+
+#[test]
+fn a_bell_alert_inverts_the_table_header() {
+    let mut app = app();
+    let header_cell = |buffer: &Buffer| {
+        let row = lines(buffer).iter().position(|l| l.contains("CPU %")).unwrap() as u16;
+        buffer.get(1, row).modifier
+    };
+    assert!(!header_cell(&render(&mut app, 80, 20)).contains(Modifier::REVERSED));
+    let now = std::time::Instant::now();
+    app.ring(now);
+    assert!(header_cell(&render(&mut app, 80, 20)).contains(Modifier::REVERSED));
+    app.expire_flash(now + htop::app::FLASH_TIME);
+    assert!(!header_cell(&render(&mut app, 80, 20)).contains(Modifier::REVERSED));
+}
+
+#[test]
+fn alerts_popup_shows_each_rule_and_whether_it_fires() {
+    let rule = |name: &str, when: &str| htop::alerts::AlertConfig { name: name.into(), when: when.into(), ..Default::default() };
+    let config = Config { alerts: vec![rule("Hot database", "process:postgres cpu > 40%"), rule("", "memory > 99%"), rule("", "process cpu > 1 for 1h")], ..Config::default() };
+    let mut app = App::new(config, Box::new(FakeSource::with_processes(&[(200, "postgres", 42.0, 0), (3000, "bash", 1.25, 0)])));
+    app.refresh();
+    press(&mut app, KeyCode::Char('a'));
+    let text = lines(&render(&mut app, 100, 20));
+    assert!(text.iter().any(|l| l.contains("Alerts (any key to close)")));
+    assert!(text.iter().any(|l| l.contains("Hot database") && l.contains("firing") && l.contains("fired 0s ago")));
+    assert!(text.iter().any(|l| l.contains("memory > 99%") && l.contains("ok") && l.contains("never fired")));
+    assert!(text.iter().any(|l| l.contains("process cpu > 1 for 1h") && l.contains("holding 0s of 1h")));
+    press(&mut app, KeyCode::Char('a'));
+    assert!(!app.show_alerts);
+}
+
+#[cfg(unix)]
+#[test]
+fn alert_commands_need_allow_exec_and_land_in_the_log() {
+    let rule = htop::alerts::AlertConfig { name: "Hot".into(), when: "process cpu > 40".into(), on_fire: "echo restarting; exit 2".into(), ..Default::default() };
+    let source = || Box::new(FakeSource::with_processes(&[(200, "postgres", 42.0, 0)]));
+    let mut app = App::new(Config { alerts: vec![rule.clone()], ..Config::default() }, source());
+    app.refresh();
+    assert!(app.commands.log.is_empty());
+
+    let mut app = App::new(Config { alerts: vec![rule], allow_exec: true, ..Config::default() }, source());
+    app.refresh();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while app.poll_commands().is_empty() && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(lines(&render(&mut app, 100, 30))[0].starts_with("Warning: Command for \"Hot\": exit 2"));
+    press(&mut app, KeyCode::Char('X'));
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("Alert commands (any key to close)")));
+    assert!(text.iter().any(|l| l.contains("Hot: exit 2")));
+    assert!(text.iter().any(|l| l.contains("$ echo restarting; exit 2")));
+    assert!(text.iter().any(|l| l.contains("  restarting ")));
+}
+
+#[test]
+fn tree_view_indents_children() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 0), (50, "sshd", 0.0, 0), (60, "bash", 0.0, 0)]);
+    source.processes[1].parent = Some(1);
+    source.processes[2].parent = Some(50);
+    let config = Config { tree_view: true, ..Config::default() };
+    let mut app = App::new(config, Box::new(source));
+    let rows = process_lines(&render(&mut app, 80, 20));
+    assert!(rows[0].contains(" init "));
+    assert!(rows[1].contains(" └─ sshd "));
+    assert!(rows[2].contains("   └─ bash "));
+    assert!(rows[2].find("└─") > rows[1].find("└─"));
+}
+
+#[test]
+fn tree_totals_order_siblings_and_show_dimmed_on_parents() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 0), (50, "sshd", 10.0, 0), (60, "make", 2.0, 0), (61, "cc", 40.0, 0)]);
+    for (i, parent) in [(1, 1), (2, 1), (3, 60)] {
+        source.processes[i].parent = Some(parent);
+    }
+    let config = Config { tree_view: true, tree_totals: true, ..Config::default() };
+    let mut app = App::new(config, Box::new(source));
+    let buffer = render(&mut app, 80, 20);
+    let rows = process_lines(&buffer);
+    assert!(rows[1].contains("└─ make Σ 42%"), "{:?}", rows);
+    assert!(rows[2].contains("└─ cc ") && !rows[2].contains('Σ'));
+    assert!(rows[3].contains("└─ sshd "));
+    let (y, line) = lines(&buffer).into_iter().enumerate().find(|(_, l)| l.contains("make")).unwrap();
+    let x = line.chars().position(|c| c == 'Σ').unwrap() as u16;
+    assert!(buffer.get(x, y as u16).modifier.contains(Modifier::DIM));
+
+    // Off again, sshd's own CPU puts it first and the totals go
+    press(&mut app, KeyCode::Char('T'));
+    let rows = process_lines(&render(&mut app, 80, 20));
+    assert!(rows[1].contains("└─ sshd "), "{:?}", rows);
+    assert!(!rows.iter().any(|r| r.contains('Σ')));
+}
+
+#[test]
+fn help_overlay_lists_effective_bindings() {
+    let mut config = Config::default();
+    config.keys.insert("toggle_tree".into(), KeySpec::Many(vec!["F5".into(), "ctrl+t".into()]));
+    let mut app = App::new(config, Box::new(FakeSource::default()));
+    press(&mut app, KeyCode::Char('?'));
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("Help (any key to close)")));
+    assert!(text.iter().any(|l| l.contains("F5, ctrl+t") && l.contains("Toggle tree view")));
+    assert!(text.iter().any(|l| l.contains("q, ctrl+c") && l.contains("Quit")));
+    for (width, height) in [(1, 1), (10, 3), (30, 8)] {
+        render(&mut app, width, height);
+    }
+    press(&mut app, KeyCode::Esc);
+    assert!(!lines(&render(&mut app, 100, 30)).iter().any(|l| l.contains("Help")));
+}
+
+#[test]
+fn action_results_stack_as_toasts_and_stay_in_the_log() {
+    let mut source = FakeSource::with_processes(&[(42, "postgres", 9.0, 0), (7, "sshd", 1.0, 0)]);
+    source.totals.cpus = vec![0.0; 4];
+    source.affinity.insert(42, vec![0, 1, 2, 3]);
+    let mut app = App::new(Config::default(), Box::new(source));
+    app.table_state.select(Some(0));
+    for text in ["1", "9", "0-1", "2"] {
+        // Through the action, since 'P' is only bound on Linux
+        app.perform(Action::SetAffinity);
+        app.setting_input.as_mut().unwrap().text = text.into();
+        press(&mut app, KeyCode::Enter);
+    }
+    let text = lines(&render(&mut app, 100, 20));
+    // The three newest, oldest at the top, in the bottom left corner
+    assert!(text[15].starts_with("┌"), "{:?}", text[15]);
+    assert!(text[16].starts_with("│Could not set the CPU affinity of postgres (42): there is no CPU 9; this machine has 0-3│"), "{:?}", text[16]);
+    assert!(text[17].starts_with("│postgres (42) now has CPU affinity 0-1 "));
+    assert!(text[18].starts_with("│postgres (42) now has CPU affinity 2 "));
+    assert!(!text.iter().any(|l| l.contains("CPU affinity 1 ")));
+
+    app.perform(Action::ActionLog);
+    press(&mut app, KeyCode::Down);
+    let text = lines(&render(&mut app, 100, 20));
+    let title = text.iter().position(|l| l.contains("Process actions, 2 of 4")).expect("the log");
+    assert!(text[title + 1].contains("postgres (42) now has CPU affinity 0-1"), "{:?}", text[title + 1]);
+    assert!(text[title + 3].contains("postgres (42) now has CPU affinity 1"));
+    assert!(text[title + 1][..12].contains(":"));
+    press(&mut app, KeyCode::Esc);
+    assert!(app.action_log.is_none());
+}
+
+#[test]
+fn a_row_limit_counts_the_rest_under_the_last_row() {
+    let mut app = App::new(Config { row_limit: 2, ..Config::default() }, Box::new(FakeSource::with_processes(&[
+        (1, "systemd", 0.5, 12 * 1024 * 1024),
+        (200, "postgres", 42.0, 512 * 1024 * 1024),
+        (3000, "bash", 1.25, 4 * 1024 * 1024),
+    ])));
+    let text = lines(&render(&mut app, 80, 20));
+    let last = text.iter().position(|l| l.contains("bash")).unwrap();
+    assert!(text[last - 1].contains("postgres"));
+    assert!(text[last + 1].starts_with("│… and 1 more "), "{:?}", text[last + 1]);
+    // With no room below the rows it goes on the border
+    let text = lines(&render(&mut app, 80, 9));
+    assert!(text[8].starts_with("└… and 1 more─"), "{:?}", text);
+}
+
+#[test]
+fn perf_overlay_sits_in_the_corner_without_moving_the_table() {
+    let mut app = app();
+    let before = lines(&render(&mut app, 80, 20));
+    app.handle_key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL));
+    app.refresh();
+    let text = lines(&render(&mut app, 80, 20));
+    // The box covers the bottom right and nothing else
+    let top = text.len() - 6;
+    assert!(text[top].ends_with("┌Perf, 20 frames──────┐"), "{:?}", text[top]);
+    assert!(text[top + 1].contains("│refresh") && text[top + 1].ends_with(" ms│"), "{:?}", text[top + 1]);
+    assert!(text[top + 2].contains("│rows") && text[top + 2].ends_with(" ms│"));
+    // terminal.draw is timed by the caller, around this
+    assert!(text[top + 3].ends_with("│draw                -│"), "{:?}", text[top + 3]);
+    assert!(text[top + 4].ends_with("│processes           3│"));
+    assert_eq!(text[..top], before[..top]);
+    for (before, after) in before[top..].iter().zip(&text[top..]) {
+        assert_eq!(after.chars().take(80 - 23).collect::<String>(), before.chars().take(80 - 23).collect::<String>());
+    }
+    // Hidden from the help, but it turns off again
+    press(&mut app, KeyCode::Char('?'));
+    assert!(!lines(&render(&mut app, 100, 40)).iter().any(|l| l.contains("own timings")));
+    press(&mut app, KeyCode::Esc);
+    app.handle_key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL));
+    assert_eq!(lines(&render(&mut app, 80, 20)), before);
+}
+
+#[test]
+fn help_overlay_reports_what_this_platform_supports() {
+    let mut app = App::new(Config::default(), Box::new(FakeSource::default()));
+    press(&mut app, KeyCode::Char('?'));
+    let platform = format!("On {}", std::env::consts::OS);
+    let supported = if cfg!(target_os = "linux") { "yes" } else { "no" };
+    // Beside the bindings on a wide terminal
+    let text = lines(&render(&mut app, 120, 30));
+    let top = text.iter().find(|l| l.contains("Help (any key to close)")).unwrap();
+    assert!(top.contains(&platform), "{:?}", top);
+    assert!(text.iter().any(|l| l.contains("Quit") && l.contains(&format!("{} fds, conn, swap, fault, core columns", supported))), "{:#?}", text);
+    // After them on a narrow one
+    let text = lines(&render(&mut app, 80, 100));
+    let at = text.iter().position(|l| l.contains(&platform)).unwrap();
+    assert!(at > text.iter().position(|l| l.contains("Quit")).unwrap());
+    assert!(text[at + 1].contains(&format!("{}                  fds, conn, swap, fault, core columns", supported)), "{:?}", text[at + 1]);
+}
+
+#[test]
+fn theme_colours_reach_the_table() {
+    let mut config = Config::default();
+    config.theme.colors.insert("selected_row".into(), "#102030".into());
+    config.theme.colors.insert("cpu_crit".into(), "magenta".into());
+    let source = FakeSource::with_processes(&[(1, "spin", 99.0, 0), (2, "idle", 0.0, 0)]);
+    let mut app = App::new(config, Box::new(source));
+    press(&mut app, KeyCode::Down);
+    let buffer = render(&mut app, 80, 20);
+    let lines = lines(&buffer);
+    let spin = lines.iter().position(|l| l.contains("spin")).unwrap() as u16;
+    let cpu_x = lines[spin as usize].find("99.00%").unwrap() as u16;
+    assert_eq!(buffer.get(cpu_x, spin).fg, Color::Magenta);
+    let idle = lines.iter().position(|l| l.contains("idle")).unwrap() as u16;
+    let name_x = lines[idle as usize].find("idle").unwrap() as u16;
+    assert_eq!(buffer.get(name_x, idle).bg, Color::Rgb(0x10, 0x20, 0x30));
+    assert!(!buffer.get(name_x, idle).modifier.contains(Modifier::REVERSED));
+}
+
+#[test]
+fn remote_source_is_named_and_a_lost_link_is_shown() {
+    let mut app = app();
+    app.remote = Some(RemoteStatus { addr: "db1:7070".into(), connected: true, error: None });
+    let text = lines(&render(&mut app, 80, 20)).join("\n");
+    assert!(text.contains("Processes on db1:7070"), "{}", text);
+    assert!(!text.contains("reconnecting"));
+
+    app.remote = Some(RemoteStatus { addr: "db1:7070".into(), connected: false, error: Some("connection refused".into()) });
+    let lines = lines(&render(&mut app, 80, 20));
+    assert!(lines[3].starts_with("Lost connection to db1:7070 (connection refused); reconnecting..."), "{:?}", lines);
+    // The last rows received stay on screen
+    assert_eq!(process_lines(&render(&mut app, 80, 20)).len(), 3);
+}
+
+#[test]
+fn replay_shows_a_timeline_and_steps_with_the_arrow_keys() {
+    let path = std::env::temp_dir().join(format!("rust-htop-render-replay-{}.rhr", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut recorder = Recorder::create(path.clone(), u64::MAX).unwrap();
+    for n in 1..=3u32 {
+        let rows: Vec<(u32, &str, f32, u64)> = (1..=n).map(|pid| (pid, "worker", 1.0, 1024)).collect();
+        let snapshot = SystemSnapshot::capture_all(&App::new(Config::default(), Box::new(FakeSource::with_processes(&rows))));
+        recorder.write(&snapshot, 1_700_000_000_000 + n as i64 * 1000).unwrap();
+    }
+    let source = ReplaySource::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut app = App::new(Config::default(), Box::new(source));
+
+    let text = lines(&render(&mut app, 100, 20)).join("\n");
+    assert!(text.contains("Processes (replay)"), "{}", text);
+    assert!(text.contains("▶") && text.contains(" 1x ") && text.contains("1/3"), "{}", text);
+    press(&mut app, KeyCode::Right);
+    press(&mut app, KeyCode::Char('>'));
+    let buffer = render(&mut app, 100, 20);
+    let text = lines(&buffer).join("\n");
+    assert!(text.contains("⏸") && text.contains(" 2x ") && text.contains("2/3"), "{}", text);
+    assert_eq!(process_lines(&buffer).len(), 2);
+    // The header tells the replayed time apart from the clock
+    let recorded = chrono::DateTime::from_timestamp_millis(1_700_000_002_000).unwrap().with_timezone(&chrono::Local).format("%H:%M:%S").to_string();
+    let header = &lines(&buffer)[3];
+    let at = header.find(&format!("replaying {}", recorded)).unwrap_or_else(|| panic!("{:?}", header));
+    assert_eq!(buffer.get(at as u16, 3).fg, Color::Yellow);
+    // Sorting and searching work on the replayed rows
+    press(&mut app, KeyCode::Char('/'));
+    press(&mut app, KeyCode::Char('2'));
+    assert_eq!(process_lines(&render(&mut app, 100, 20)).len(), 1);
+}
+
+#[test]
+fn the_header_shows_the_clock_the_snapshot_time_and_the_uptime() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 0)]);
+    let booted = chrono::Local::now() - chrono::Duration::seconds(6 * 3600 + 12 * 60 + 30);
+    source.totals.boot_time = booted.timestamp() as u64;
+    let mut app = App::new(Config { time_format: "[%Y]".into(), ..Config::default() }, Box::new(source));
+    let year = chrono::Local::now().format("[%Y]").to_string();
+    let lines = lines(&render(&mut app, 120, 20));
+    assert!(lines[3].starts_with(&format!("{}  snapshot {}  ", year, year)), "{:?}", lines[3]);
+    assert!(lines[4].starts_with(&format!("Up 6 h 12 m, booted {}", booted.format("%Y-%m-%d %H:%M"))), "{:?}", lines[4]);
+    assert!(lines[5].starts_with("Net ↓   0.0 B/s  ↑   0.0 B/s   peak ↓   0.0 B/s  ↑   0.0 B/s   session ↓   0.0 B  ↑   0.0 B"), "{:?}", lines[5]);
+}
+
+#[test]
+fn macos_memory_pressure_leads_the_memory_figures() {
+    let mut source = FakeSource::with_processes(&[(1, "launchd", 0.0, 0)]);
+    source.totals.mem_total = 16 << 30;
+    // Compressed and swapped as it is, nearly all of it shows as used
+    source.totals.mem_used = 15 << 30;
+    source.totals.mem_available = 3 << 30;
+    source.totals.mem_pressure = Some(MemPressure { level: PressureLevel::Warning, free: 18 });
+    let mut app = App::new(Config { memory_detail: true, ..Config::default() }, Box::new(source));
+    let buffer = render(&mut app, 120, 20);
+    let text = lines(&buffer);
+    let at = text[4].find("mem pressure warning").unwrap_or_else(|| panic!("{:?}", text[4]));
+    assert_eq!(buffer.get(at as u16 + 2, 4).fg, Color::Yellow);
+    assert_eq!(text[6].trim_end(), "Mem pressure warning, 18% free  total 16.0 GB  used 15.0 GB  available 3.0 GB");
+    assert_eq!(buffer.get(0, 6).fg, Color::Yellow);
+
+    app.totals.mem_pressure = Some(MemPressure { level: PressureLevel::Normal, free: 40 });
+    assert_ne!(render(&mut app, 120, 20).get(0, 6).fg, Color::Yellow);
+}
+
+#[test]
+fn the_memory_breakdown_is_toggled_and_colored_by_what_is_available() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 0)]);
+    source.totals.mem_total = 8 << 30;
+    // Nearly all of it "used", but most of that is cache
+    source.totals.mem_used = 7 << 30;
+    source.totals.mem_available = 6 << 30;
+    source.totals.mem_detail = Some(MemInfo { cached: 5 << 30, buffers: 40 << 20, dirty: 216 << 10, ..MemInfo::default() });
+    let mut app = App::new(Config::default(), Box::new(source));
+    assert!(!lines(&render(&mut app, 120, 20)).iter().any(|l| l.contains("Mem total")));
+    press(&mut app, KeyCode::Char('M'));
+    let buffer = render(&mut app, 120, 20);
+    let text = lines(&buffer);
+    assert_eq!(text[6].trim_end(), "Mem total 8.0 GB  used 7.0 GB  available 6.0 GB  cached 5.0 GB  buffers 40.0 MB  dirty 216.0 KB");
+    assert_ne!(buffer.get(0, 6).fg, Color::Yellow);
+    // The table moved down to make room
+    assert!(text[7].contains("Processes"), "{:?}", text);
+
+    app.totals.mem_available = 1 << 30;
+    assert_eq!(render(&mut app, 120, 20).get(0, 6).fg, Color::Yellow);
+    app.totals.mem_available = 512 << 20;
+    assert_eq!(render(&mut app, 120, 20).get(0, 6).fg, Color::Red);
+}
+
+#[test]
+fn swap_activity_turns_red_once_it_keeps_up() {
+    let mut app = app();
+    assert!(!lines(&render(&mut app, 120, 20))[4].contains("swap I/O"));
+    app.swap.rate = Some((0.0, 4.2 * 1024.0 * 1024.0));
+    app.swap.busy = 1;
+    let buffer = render(&mut app, 120, 20);
+    let line = &lines(&buffer)[4];
+    assert!(line.contains("  swap I/O 0.0 B/s in, 4.2 MB/s out"), "{:?}", line);
+    let x = line.find("swap I/O").unwrap() as u16;
+    assert_ne!(buffer.get(x, 4).fg, Color::Red);
+    app.swap.busy = htop::app::SWAP_BUSY_REFRESHES;
+    assert_eq!(render(&mut app, 120, 20).get(x, 4).fg, Color::Red);
+}
+
+#[test]
+fn steal_time_shows_on_virtual_machines() {
+    let mut app = app();
+    assert!(!lines(&render(&mut app, 120, 20))[4].contains("steal"));
+    app.virtualization.steal = Some(7.0);
+    let buffer = render(&mut app, 120, 20);
+    let line = &lines(&buffer)[4];
+    assert!(line.contains("  steal 7%") && !line.contains("guest"), "{:?}", line);
+    assert_eq!(buffer.get(line.find("steal").unwrap() as u16, 4).fg, Color::Yellow);
+    app.virtualization.steal = None;
+    app.virtualization.guest = Some(25.0);
+    assert!(lines(&render(&mut app, 120, 20))[4].contains("  guest 25%"));
+}
+
+#[test]
+fn the_cpufreq_governor_shows_where_there_is_one() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 0)]);
+    source.totals.cpufreq = Some(CpuFreq { governor: "powersave".into(), boost: Some(false) });
+    let mut app = App::new(Config::default(), Box::new(source));
+    assert!(lines(&render(&mut app, 120, 20))[4].contains("  governor powersave, turbo off"));
+    app.totals.cpufreq = Some(CpuFreq { governor: "mixed".into(), boost: None });
+    assert!(lines(&render(&mut app, 120, 20))[4].trim_end().ends_with("  governor mixed"));
+    app.totals.cpufreq = None;
+    assert!(!lines(&render(&mut app, 120, 20))[4].contains("governor"));
+}
+
+#[test]
+fn huge_pages_show_only_where_a_pool_is_set_aside() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 0)]);
+    source.totals.mem_total = 64 << 30;
+    source.totals.mem_detail = Some(MemInfo { anon_huge_pages: Some(120 << 20), ..MemInfo::default() });
+    source.totals.huge_pages = vec![
+        HugePages { size: 2 << 20, total: 64, free: 52, reserved: 3, surplus: 0 },
+        HugePages { size: 1 << 30, total: 4, free: 4, reserved: 0, surplus: 0 },
+    ];
+    let mut app = App::new(Config::default(), Box::new(source));
+    let line = lines(&render(&mut app, 120, 20))[4].clone();
+    assert!(line.trim_end().ends_with("HP 12/64 × 2MiB  HP 0/4 × 1GiB"), "{:?}", line);
+    press(&mut app, KeyCode::Char('M'));
+    let text = lines(&render(&mut app, 160, 20));
+    assert_eq!(text[7].trim_end(), "Huge pages 2MiB 12/64 used, 3 reserved, 0 surplus  1GiB 0/4 used, 0 reserved, 0 surplus  THP 120.0 MB");
+    assert!(text[8].contains("Processes"), "{:?}", text);
+
+    app.config.show_huge_pages = false;
+    assert!(!lines(&render(&mut app, 120, 20))[4].contains("HP"));
+    // No pools: no header item and no line, THP or not
+    app.totals.huge_pages.clear();
+    let text = lines(&render(&mut app, 120, 20));
+    assert!(!text.iter().any(|l| l.contains("Huge pages")) && text[7].contains("Processes"), "{:?}", text);
+}
+
+#[test]
+fn pressure_shows_after_the_uptime_when_the_kernel_reports_it() {
+    let mut source = FakeSource::with_processes(&[(1, "init", 0.0, 0)]);
+    source.totals.pressure = vec![
+        ("cpu", Pressure { some: (0.4, 0.1), full: None }),
+        ("memory", Pressure { some: (12.0, 3.0), full: Some((0.0, 0.0)) }),
+        ("io", Pressure { some: (15.0, 8.0), full: Some((11.0, 6.0)) }),
+    ];
+    let mut stalled = App::new(Config::default(), Box::new(source));
+    let buffer = render(&mut stalled, 120, 20);
+    let line = &lines(&buffer)[4];
+    let at = line.find("PSI cpu 0%/0% mem 12%/3% io 15%/8%").unwrap_or_else(|| panic!("{:?}", line)) as u16;
+    assert_ne!(buffer.get(at + 4, 4).fg, Color::Yellow);
+    assert_eq!(buffer.get(at + 14, 4).fg, Color::Yellow);
+    assert_eq!(buffer.get(at + 25, 4).fg, Color::Red);
+
+    // Nothing at all without /proc/pressure
+    let mut app = app();
+    assert!(!lines(&render(&mut app, 120, 20))[4].contains("PSI"));
+}
+
+#[test]
+fn interfaces_show_their_link_state_and_new_errors() {
+    let mut app = app();
+    let interface = |name: &str, up, errors| Interface { name: name.into(), received: 2048, transmitted: 1024, up, errors, dropped: Some((0, 5)) };
+    app.net.interfaces = vec![interface("eth0", Some(true), (3, 0)), interface("wlan0", Some(false), (0, 0))];
+    let buffer = render(&mut app, 160, 20);
+    let header = &lines(&buffer)[3];
+    let eth0 = header.find("eth0 ↓2 KB ↑1 KB err 3/0 drop 0/5 | ").unwrap_or_else(|| panic!("{:?}", header));
+    assert_eq!(buffer.get(header[..eth0].chars().count() as u16, 3).fg, Color::Red);
+    let down = header.find("wlan0 ↓2 KB ↑1 KB err 0/0 drop 0/5 down").unwrap_or_else(|| panic!("{:?}", header));
+    let down = header[..down].chars().count() as u16;
+    assert_ne!(buffer.get(down, 3).fg, Color::Red);
+    assert_eq!(buffer.get(down + 35, 3).fg, Color::Yellow);
+
+    app.config.show_net_errors = false;
+    let header = lines(&render(&mut app, 160, 20))[3].clone();
+    assert!(header.contains("eth0 ↓2 KB ↑1 KB | wlan0 ↓2 KB ↑1 KB down"), "{:?}", header);
+}
+
+#[test]
+fn history_popup_shows_sparklines_of_the_selected_process() {
+    let mut app = app();
+    press(&mut app, KeyCode::Char('H'));
+    assert!(!app.history_wanted, "nothing is selected yet");
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Char('H'));
+    assert!(app.history_wanted);
+    let pid = app.selected_process().unwrap().pid;
+    assert_eq!(app.selected_process().unwrap().name, "bash");
+
+    // What the main loop fills in from the database: a spike near the end of the hour
+    let samples = (0..60).map(|minute| HistorySample { time_ms: minute * 60_000, cpu: if minute > 50 { 80.0 } else { 2.0 }, mem: 50 << 20 }).collect();
+    app.history = Some(HistoryView { pid, name: "bash".into(), since_ms: 0, until_ms: 3_600_000, samples });
+    let text = lines(&render(&mut app, 100, 30));
+    let title = text.iter().position(|l| l.contains("bash (3000), last hour (any key to close)")).expect("popup title");
+    assert!(text[title + 1].contains("CPU, peak 80.00%"), "{:?}", text);
+    let cpu_line = &text[title + 5];
+    assert!(cpu_line.trim_end_matches(['│', ' ']).ends_with('█'), "{:?}", cpu_line);
+    assert!(text[title + 6].contains("Memory, peak 50.00 MB"));
+    for (width, height) in [(1, 1), (10, 3), (30, 8)] {
+        render(&mut app, width, height);
+    }
+
+    press(&mut app, KeyCode::Char('x'));
+    assert!(app.history.is_none());
+    app.history = Some(HistoryView { pid, name: "bash".into(), since_ms: 0, until_ms: 3_600_000, samples: Vec::new() });
+    assert!(lines(&render(&mut app, 100, 30)).iter().any(|l| l.contains("No samples in the last hour")));
+}
+
+#[test]
+fn configured_columns_drive_the_table_and_the_export() {
+    let dir = std::env::temp_dir().join(format!("rust-htop-render-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = Config { columns: vec!["name".into(), "pid".into()], export_dir: dir.display().to_string(), ..Config::default() };
+    let source = FakeSource::with_processes(&[(1, "systemd", 0.5, 1024), (200, "postgres", 42.0, 2048)]);
+    let mut app = App::new(config, Box::new(source));
+    let text = lines(&render(&mut app, 80, 20));
+    let header = text.iter().find(|l| l.contains("PID")).unwrap();
+    assert!(header.find("Name") < header.find("PID"), "{}", header);
+    assert!(!header.contains("CPU %"));
+
+    press(&mut app, KeyCode::Char('E'));
+    let status = lines(&render(&mut app, 200, 20))[0].clone();
+    assert!(status.starts_with(&format!("Exported 2 processes to {}", dir.display())), "{}", status);
+    let file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let exported = std::fs::read_to_string(&file).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(exported.ends_with("name,pid\npostgres,200\nsystemd,1\n"), "{}", exported);
+
+    press(&mut app, KeyCode::Char('E'));
+    assert!(lines(&render(&mut app, 200, 20))[0].starts_with("Error: Could not export"));
+}
+
+#[test]
+fn the_fds_column_shows_a_dash_until_counted() {
+    let config = Config { columns: vec!["pid".into(), "cpu".into(), "fds".into()], ..Config::default() };
+    let mut source = FakeSource::with_processes(&[(1, "systemd", 0.5, 1024), (200, "postgres", 42.0, 2048)]);
+    source.processes[1].fds = Some(431);
+    let mut app = App::new(config, Box::new(source));
+    press(&mut app, KeyCode::Char('f'));
+    let rows = process_lines(&render(&mut app, 80, 20));
+    assert!(rows[0].contains("200") && rows[0].contains("431"), "{:?}", rows);
+    assert!(rows[1].split_whitespace().any(|cell| cell == "-"), "{:?}", rows);
+}
+
+#[test]
+fn connections_popup_lists_the_selected_process_sockets() {
+    let mut source = FakeSource::with_processes(&[(200, "postgres", 42.0, 2048), (300, "bash", 1.0, 1024)]);
+    source.processes[0].conns = Some(2);
+    let socket = |local: &str, remote: &str, state| Connection { protocol: "tcp", local: local.parse().unwrap(), remote: remote.parse().unwrap(), state, inode: 1 };
+    source.connections.insert(200, vec![socket("10.0.0.5:5432", "10.0.0.9:40112", "ESTABLISHED"), socket("0.0.0.0:5432", "0.0.0.0:0", "LISTEN")]);
+    let config = Config { columns: vec!["name".into(), "cpu".into(), "conn".into()], ..Config::default() };
+    let mut app = App::new(config, Box::new(source));
+    assert!(process_lines(&render(&mut app, 80, 20))[0].contains(" 2 "));
+
+    app.table_state.select(Some(0));
+    press(&mut app, KeyCode::Char('C'));
+    let text = lines(&render(&mut app, 100, 20));
+    assert!(text.iter().any(|l| l.contains("Connections of postgres (200)")), "{:?}", text);
+    let listen = text.iter().position(|l| l.contains("0.0.0.0:5432") && l.contains("LISTEN")).unwrap();
+    let established = text.iter().position(|l| l.contains("10.0.0.9:40112") && l.contains("ESTABLISHED")).unwrap();
+    assert!(listen < established);
+
+    press(&mut app, KeyCode::Char('x'));
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Char('C'));
+    assert!(app.connections.is_none());
+    assert!(lines(&render(&mut app, 100, 20))[0].contains("Can't list the connections of bash (300)"));
+}
+
+#[test]
+fn listeners_panel_jumps_to_the_process_holding_a_port() {
+    let mut source = FakeSource::with_processes(&[(200, "postgres", 42.0, 2048), (300, "bash", 1.0, 1024), (410, "nginx", 0.5, 512)]);
+    for (p, user) in source.processes.iter_mut().zip(["postgres", "alice", "www-data"]) {
+        p.user = Some(user.into());
+    }
+    let listener = |protocol, local: &str, pid| Listener { protocol, local: local.parse().unwrap(), pid };
+    source.listeners = vec![listener("udp", "0.0.0.0:53", None), listener("tcp", "0.0.0.0:5432", Some(200)), listener("tcp", "[::]:8080", Some(410))];
+    let mut app = App::new(Config::default(), Box::new(source));
+    press(&mut app, KeyCode::Char('l'));
+    let text = lines(&render(&mut app, 100, 20));
+    assert!(text.iter().any(|l| l.contains("Listening")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("[::]:8080") && l.contains("nginx (410)")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("0.0.0.0:53") && l.contains(" - ")), "{:?}", text);
+
+    // A user filter that hides nginx is cleared to get to it
+    press(&mut app, KeyCode::Char('x'));
+    app.user_filter = Some("postgres".into());
+    app.update_view();
+    assert_eq!(app.visible.len(), 1);
+    press(&mut app, KeyCode::Char('l'));
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Enter);
+    assert!(app.listeners.is_none());
+    assert!(app.user_filter.is_none());
+    assert_eq!(app.selected_process().map(|p| p.name.as_str()), Some("nginx"));
+
+    // Nobody to jump to for a socket whose owner can't be read
+    press(&mut app, KeyCode::Char('l'));
+    press(&mut app, KeyCode::Enter);
+    assert!(lines(&render(&mut app, 100, 20))[0].contains("can't be seen"));
+    assert_eq!(app.selected_process().map(|p| p.name.as_str()), Some("nginx"));
+}
+
+#[test]
+fn logins_panel_filters_on_the_selected_user() {
+    let mut source = FakeSource::with_processes(&[(200, "postgres", 42.0, 2048), (300, "bash", 1.0, 1024), (301, "make", 90.0, 1024)]);
+    for (p, user) in source.processes.iter_mut().zip(["postgres", "alice", "alice"]) {
+        p.user = Some(user.into());
+    }
+    source.logins = vec![
+        Login { user: "bob".into(), terminal: "tty1".into(), host: None, since: 1_700_000_000, pid: 900 },
+        Login { user: "alice".into(), terminal: "pts/3".into(), host: Some("10.0.0.7".into()), since: 1_700_000_900, pid: 901 },
+    ];
+    let mut app = App::new(Config::default(), Box::new(source));
+    press(&mut app, KeyCode::Char('U'));
+    let text = lines(&render(&mut app, 100, 20));
+    assert!(text.iter().any(|l| l.contains("Logged in")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("alice") && l.contains("pts/3") && l.contains("10.0.0.7") && l.contains("2 processes")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("bob") && l.contains("local") && l.contains("0 processes")), "{:?}", text);
+
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Enter);
+    assert!(app.logins.is_none());
+    assert_eq!(app.user_filter.as_deref(), Some("alice"));
+    assert_eq!(app.visible.len(), 2);
+
+    // An empty utmp opens nothing
+    let mut app = App::new(Config::default(), Box::new(FakeSource::default()));
+    press(&mut app, KeyCode::Char('U'));
+    assert!(app.logins.is_none());
+    assert!(lines(&render(&mut app, 100, 20))[0].contains("Nobody is logged in"));
+}
+
+#[test]
+fn details_popup_shows_the_selected_process_and_its_scheduling() {
+    let mut source = FakeSource::with_processes(&[(200, "postgres", 42.0, 2048), (300, "bash", 1.0, 1024)]);
+    source.processes[0].parent = Some(1);
+    source.processes[0].user = Some("postgres".into());
+    source.affinity.insert(200, vec![0, 1, 2, 3, 8]);
+    source.io_priority.insert(200, IoPriority { class: IoClass::Idle, level: 0 });
+    source.capabilities.insert(200, Capabilities { effective: 1 << 10 | 1 << 12, permitted: 0 });
+    source.memory.insert(200, Ok(MemoryMap { rss: 80 << 20, pss: 30 << 20, shared_dirty: 50 << 20, private_dirty: 24 << 20, ..MemoryMap::default() }));
+    source.processes[0].fds = Some(412);
+    source.limits.insert(200, procfs::parse_limits(include_str!("data/limits-nginx")));
+    source.memory.insert(300, Err("permission denied; another user's process needs root".into()));
+    let mut app = App::new(Config::default(), Box::new(source));
+    app.table_state.select(Some(0));
+    press(&mut app, KeyCode::Enter);
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("postgres (200), any key to close")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("Parent") && l.contains(" 1 ")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("CPU affinity") && l.contains("0-3,8")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("I/O priority") && l.contains("idle")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("Effective caps") && l.contains("CAP_NET_BIND_SERVICE CAP_NET_ADMIN")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("Permitted caps") && l.contains("none")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("PSS") && l.contains("30.00 MB")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("Shared") && l.contains("0.00 MB clean, 50.00 MB dirty")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("open files") && l.contains("1024 (412 open)   524288")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("core file") && l.contains("0.00 MB") && l.contains("unlimited")), "{:?}", text);
+    // Its CPU graph starts with the popup, so far a single sample
+    assert!(text.iter().any(|l| l.contains("CPU, last minute: min 42.00%  avg 42.00%  max 42.00%")), "{:?}", text);
+
+    // Without settings to read their lines are left out, and a refusal is shown
+    press(&mut app, KeyCode::Char('x'));
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Enter);
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("bash (300), any key to close")), "{:?}", text);
+    assert!(!text.iter().any(|l| l.contains("CPU affinity") || l.contains("I/O priority") || l.contains("caps")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("Memory map") && l.contains("permission denied")), "{:?}", text);
+}
+
+#[test]
+fn the_sort_menu_marks_the_current_sort_and_its_direction() {
+    let mut app = app();
+    press(&mut app, KeyCode::F(6));
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("Sort by")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("CPU") && l.contains("▼ descending")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("Container")), "{:?}", text);
+    press(&mut app, KeyCode::Enter);
+    press(&mut app, KeyCode::F(6));
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("CPU") && l.contains("▲ ascending")), "{:?}", text);
+}
+
+#[test]
+fn the_message_log_lists_statuses_newest_first() {
+    let mut app = app();
+    app.set_status(StatusKind::Info, "Settings saved to /tmp/htop.toml");
+    app.set_status(StatusKind::Error, "Could not export to /nonexistent: not found");
+    app.perform(Action::Messages);
+    let text = lines(&render(&mut app, 100, 20));
+    assert!(text.iter().any(|l| l.contains("Messages, 1 of 2")), "{:?}", text);
+    let error = text.iter().position(|l| l.contains("Error: Could not export to /nonexistent")).unwrap();
+    let saved = text.iter().position(|l| l.contains("Settings saved")).unwrap();
+    assert!(error < saved);
+}
+
+#[test]
+fn popups_of_a_process_that_exits_keep_what_was_read_and_say_so() {
+    let socket = Connection { protocol: "tcp", local: "10.0.0.5:5432".parse().unwrap(), remote: "10.0.0.9:40112".parse().unwrap(), state: "ESTABLISHED", inode: 1 };
+    let mut source = FakeSource::with_processes(&[(200, "postgres", 42.0, 2048), (300, "bash", 1.0, 1024)]);
+    source.processes[0].start_time = 1_700_000_000;
+    source.connections.insert(200, vec![socket]);
+    let mut app = App::new(Config::default(), Box::new(source));
+    app.table_state.select(Some(0));
+    press(&mut app, KeyCode::Enter);
+    press(&mut app, KeyCode::Char('x'));
+    press(&mut app, KeyCode::Char('C'));
+    app.perform(Action::Details);
+
+    // PID 200 now belongs to another process, with no sockets of its own
+    let mut source = FakeSource::with_processes(&[(200, "sleep", 0.0, 512), (300, "bash", 1.0, 1024)]);
+    source.processes[0].start_time = 1_700_000_900;
+    app.source = Box::new(source);
+    app.connections.as_mut().unwrap().updated -= std::time::Duration::from_secs(60);
+    app.refresh();
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("postgres (200, exited), any key to close")), "{:?}", text);
+    assert!(!text.iter().any(|l| l.contains("CPU, last minute")), "{:?}", text);
+    app.details = None;
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("Connections of postgres (200, exited)")), "{:?}", text);
+    assert!(text.iter().any(|l| l.contains("10.0.0.9:40112")), "{:?}", text);
+}
+
+#[test]
+fn a_lagging_refresh_is_called_out_in_the_header() {
+    let mut app = app();
+    let text = lines(&render(&mut app, 100, 20)).join("\n");
+    assert!(text.contains("every 1s") && !text.contains("lagging"), "{}", text);
+    app.pacer.record(std::time::Duration::from_millis(3200));
+    let buffer = render(&mut app, 100, 20);
+    let text = lines(&buffer);
+    assert!(text.iter().any(|l| l.contains("every 6.4s")), "{:?}", text);
+    let y = text.iter().position(|l| l.contains("refresh lagging: last cycle took 3.2s")).unwrap_or_else(|| panic!("{:?}", text));
+    let x = text[y].find("refresh lagging").unwrap();
+    assert_eq!(buffer.get(x as u16, y as u16).fg, Color::Yellow);
+}
+
+#[test]
+fn the_exit_log_lists_what_went_newest_first() {
+    let mut app = app();
+    let names: Vec<String> = app.processes.iter().map(|p| p.name.clone()).collect();
+    app.source = Box::new(FakeSource::with_processes(&[]));
+    app.refresh();
+    press(&mut app, KeyCode::Char('e'));
+    let text = lines(&render(&mut app, 100, 30));
+    let title = text.iter().position(|l| l.contains(&format!("Exited processes, 1 of {}", names.len()))).unwrap_or_else(|| panic!("{:?}", text));
+    assert!(text[title + 1].contains(names.last().unwrap().as_str()) && text[title + 1].contains("ran -"), "{:?}", text);
+}
+
+#[test]
+fn the_footer_sits_below_the_table_and_follows_the_search() {
+    let mut app = app();
+    press(&mut app, KeyCode::Char('='));
+    let text = lines(&render(&mut app, 100, 20));
+    let last = text.last().unwrap();
+    assert!(last.contains("3 rows  CPU Σ 43.75% avg 14.58% max 42.00%  Memory Σ 528.00 MB avg 176.00 MB max 512.00 MB"), "{:?}", text);
+    assert!(text[text.len() - 2].starts_with('└'), "{:?}", text);
+    press(&mut app, KeyCode::Char('/'));
+    for c in "bash".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    let text = lines(&render(&mut app, 100, 20));
+    assert!(text.last().unwrap().contains("1 row  CPU Σ 1.25% avg 1.25% max 1.25%"), "{:?}", text);
+}
+
+#[test]
+fn real_time_processes_are_badged_listed_and_explained() {
+    let mut source = FakeSource::with_processes(&[(20, "pipewire", 42.0, 2048), (40, "Xorg", 1.0, 1024), (60, "bash", 0.5, 1024)]);
+    source.processes[0].scheduling = Some(Scheduling { policy: Policy::Fifo, priority: 50 });
+    source.processes[1].scheduling = Some(Scheduling { policy: Policy::Normal, priority: 0 });
+    source.processes[1].nice = Some(-10);
+    let mut app = App::new(Config::default(), Box::new(source));
+    let buffer = render(&mut app, 100, 20);
+    let text = lines(&buffer);
+    let y = text.iter().position(|l| l.contains("pipewire")).unwrap();
+    let x = text[y].find("RT pipewire").unwrap_or_else(|| panic!("{:?}", text[y]));
+    assert_eq!(buffer.get(x as u16, y as u16).fg, Color::Yellow);
+    assert!(!text.iter().any(|l| l.contains("RT Xorg") || l.contains("RT bash")), "{:?}", text);
+
+    app.table_state.select(Some(0));
+    press(&mut app, KeyCode::Enter);
+    let text = lines(&render(&mut app, 100, 30));
+    assert!(text.iter().any(|l| l.contains("Scheduling") && l.contains("SCHED_FIFO priority 50 (real-time")), "{:?}", text);
+    press(&mut app, KeyCode::Esc);
+
+    app.handle_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+    let text = lines(&render(&mut app, 100, 20));
+    let listed: Vec<&String> = text.iter().filter(|l| l.contains("pipewire") || l.contains("Xorg") || l.contains("bash")).collect();
+    assert!(text.iter().any(|l| l.contains("Real-time and boosted processes")), "{:?}", text);
+    let popup: Vec<&&String> = listed.iter().filter(|l| l.contains("SCHED_")).collect();
+    assert_eq!(popup.len(), 2, "{:?}", text);
+    assert!(popup[0].contains("pipewire") && popup[1].contains("Xorg") && popup[1].contains("-10"), "{:?}", popup);
+}
+
+#[test]
+fn the_net_column_shows_queued_bytes_and_a_dash_where_unread() {
+    let mut source = FakeSource::with_processes(&[(200, "rsync", 42.0, 2048), (300, "bash", 1.0, 1024)]);
+    source.processes[0].net = Some(3 << 20);
+    let config = Config { columns: vec!["name".into(), "cpu".into(), "net".into()], default_sort: "net".into(), descending: false, ..Config::default() };
+    let mut app = App::new(config, Box::new(source));
+    let rows = process_lines(&render(&mut app, 80, 20));
+    assert!(rows[0].contains("bash") && rows[0].split_whitespace().any(|cell| cell == "-"), "{:?}", rows);
+    assert!(rows[1].contains("3.00 MB"), "{:?}", rows);
+}
+
+#[test]
+fn the_container_column_shows_names_and_a_dash_outside_containers() {
+    let mut source = FakeSource::with_processes(&[(200, "nginx", 42.0, 2048), (300, "bash", 1.0, 1024)]);
+    source.processes[0].container = Some("nginx-prod".into());
+    let config = Config { columns: vec!["name".into(), "cpu".into(), "container".into()], ..Config::default() };
+    let mut app = App::new(config, Box::new(source));
+    let rows = process_lines(&render(&mut app, 80, 20));
+    assert!(rows[0].contains("nginx-prod"), "{:?}", rows);
+    assert!(rows[1].split_whitespace().any(|cell| cell == "-"), "{:?}", rows);
+}
+
+#[test]
+fn the_user_filter_is_named_on_the_status_line() {
+    let mut source = FakeSource::with_processes(&[(200, "nginx", 42.0, 2048), (300, "bash", 1.0, 1024)]);
+    source.processes[0].user = Some("www-data".into());
+    let mut app = App::new(Config::default(), Box::new(source));
+    press(&mut app, KeyCode::Char('u'));
+    let screen = lines(&render(&mut app, 80, 20)).join("\n");
+    assert!(screen.contains("Users (Enter to filter"), "{}", screen);
+    assert!(screen.contains("www-data") && screen.contains("1 processes"), "{}", screen);
+    press(&mut app, KeyCode::Enter);
+    let buffer = render(&mut app, 80, 20);
+    assert!(lines(&buffer)[0].starts_with("User: www-data (Esc for everyone)  Press '/' to search"), "{:?}", lines(&buffer)[0]);
+    assert_eq!(process_lines(&buffer).len(), 1);
+}
+
+#[test]
+fn a_state_filter_is_named_in_the_title_and_explains_an_empty_table() {
+    let mut app = app();
+    press(&mut app, KeyCode::Char('S'));
+    press(&mut app, KeyCode::Char('S'));
+    let buffer = render(&mut app, 80, 20);
+    let lines = lines(&buffer);
+    assert!(lines.iter().any(|l| l.contains("Processes [D-state only]")), "{:?}", lines);
+    assert!(process_lines(&buffer).is_empty());
+    let header = lines.iter().position(|l| l.contains("PID")).unwrap();
+    assert!(lines[header + 1].contains("No processes match (D-state only)"), "{:?}", lines[header + 1]);
+}
+
+#[test]
+fn an_inverted_search_is_marked_in_the_search_bar() {
+    let mut app = app();
+    press(&mut app, KeyCode::Char('/'));
+    for c in "postgres".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    app.handle_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL));
+    let buffer = render(&mut app, 80, 20);
+    assert!(lines(&buffer)[0].starts_with("Search (inverted): postgres"), "{:?}", lines(&buffer)[0]);
+    let rows = process_lines(&buffer);
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|row| !row.contains("postgres")), "{:?}", rows);
+}
+
+#[test]
+fn the_prompt_shows_where_the_cursor_is() {
+    let mut app = app();
+    press(&mut app, KeyCode::Char('/'));
+    app.paste("postgres");
+    let reversed = |buffer: &Buffer| -> Vec<(u16, String)> {
+        (0..buffer.area.width).map(|x| buffer.get(x, 0)).enumerate().filter(|(_, c)| c.modifier.contains(Modifier::REVERSED)).map(|(x, c)| (x as u16, c.symbol.clone())).collect()
+    };
+    // After the text the cursor is a space of its own
+    let buffer = render(&mut app, 80, 20);
+    assert!(lines(&buffer)[0].starts_with("Search: postgres "));
+    assert_eq!(reversed(&buffer), vec![(16, " ".to_string())]);
+    press(&mut app, KeyCode::Home);
+    press(&mut app, KeyCode::Right);
+    let buffer = render(&mut app, 80, 20);
+    assert!(lines(&buffer)[0].starts_with("Search: postgres "));
+    assert_eq!(reversed(&buffer), vec![(9, "o".to_string())]);
+}
+
+#[test]
+fn long_names_widen_the_name_column() {
+    let mut source = FakeSource::with_processes(&[(200, "java", 42.0, 2048)]);
+    source.processes[0].cmd = vec!["/usr/bin/java".into(), "-Xmx4g".into(), "-jar".into(), "/opt/app/server.jar".into()];
+    let config = Config { name_display: "cmdline".into(), ..Config::default() };
+    let mut app = App::new(config, Box::new(source));
+    let rows = process_lines(&render(&mut app, 120, 20));
+    assert!(rows[0].contains("/usr/bin/java -Xmx4g -jar /opt/app/server.jar"), "{:?}", rows);
+    assert!(rows[0].contains("42.00%"));
+}
+
+#[test]
+fn wide_names_are_cut_to_exactly_the_column_width() {
+    let source = FakeSource::with_processes(&[
+        (1, "微信开发者工具微信开发者工具微信开发者工具", 3.0, 0),
+        (2, "👨\u{200d}👩\u{200d}👧 family-photo-sync-agent", 2.0, 0),
+        (3, "cafe\u{301}", 1.0, 0),
+    ]);
+    let mut app = App::new(Config::default(), Box::new(source));
+    let buffer = render(&mut app, 80, 20);
+    // Cell positions, which the strings from `lines` don't give once a symbol is wider than a byte
+    let row = |y: u16| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol.clone()).collect::<Vec<_>>();
+    let header = (0..buffer.area.height).map(row).find(|cells| cells.concat().contains("CPU %")).unwrap();
+    let cpu_x = header.iter().position(|c| c == "C").unwrap();
+    let name_x = header.iter().position(|c| c == "N").unwrap();
+    let names: Vec<String> = (0..buffer.area.height).map(row).filter(|cells| cells[cpu_x + 1] == ".").map(|cells| {
+        // Nothing of the name spills into the gap in front of the CPU column
+        assert_eq!(cells[cpu_x - 1], " ", "{:?}", cells.concat());
+        cells[name_x..cpu_x - 1].concat()
+    }).collect();
+    assert_eq!(names.len(), 3);
+    assert!(names[0].starts_with("微") && names[0].trim_end().ends_with('…'), "{:?}", names[0]);
+    assert!(names[1].starts_with("👨\u{200d}👩\u{200d}👧") && names[1].trim_end().ends_with('…'), "{:?}", names[1]);
+    assert_eq!(names[2].trim_end(), "cafe\u{301}");
+}